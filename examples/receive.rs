@@ -7,9 +7,8 @@ fn main() {
     let frame = socket.receive().unwrap();
     print!("{:?}#", frame.id());
 
-    let data = frame.data();
-    for i in 0..data.len() {
-        print!("{:X}", data[i]);
+    for byte in frame.data() {
+        print!("{:X}", byte);
     }
     println!();
 }
@@ -0,0 +1,303 @@
+use crate::{Clock, SpnValue, SystemClock};
+use std::time::{Duration, Instant};
+
+/// Limit violated by a decoded parameter
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AlarmKind {
+    /// Value below the minimum
+    BelowMin,
+    /// Value above the maximum
+    AboveMax,
+    /// Value changing faster than the allowed rate
+    RateOfChange,
+}
+
+/// Change of an alarm reported by `AlarmMonitor`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlarmEvent {
+    /// The limit was violated for the whole debounce time
+    Raised {
+        /// Suspect parameter number
+        spn: u32,
+        /// Violated limit
+        kind: AlarmKind,
+        /// Value which raised the alarm
+        value: f64,
+    },
+
+    /// The value stayed within the limit for the whole debounce time
+    Cleared {
+        /// Suspect parameter number
+        spn: u32,
+        /// Limit which is no longer violated
+        kind: AlarmKind,
+        /// Value which cleared the alarm
+        value: f64,
+    },
+}
+
+/// Limits of a single parameter
+///
+/// ```
+/// # use candev::Threshold;
+/// # use std::time::Duration;
+/// // coolant temperature above 110 °C for 5 s
+/// let threshold = Threshold::new(110)
+///     .max(110.0)
+///     .debounce(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    spn: u32,
+    min: Option<f64>,
+    max: Option<f64>,
+    rate: Option<f64>,
+    debounce: Duration,
+}
+
+impl Threshold {
+    /// Thresholds of parameter `spn`, initially without limits and
+    /// debouncing
+    pub fn new(spn: u32) -> Threshold {
+        Threshold {
+            spn,
+            min: None,
+            max: None,
+            rate: None,
+            debounce: Duration::ZERO,
+        }
+    }
+
+    /// Alarm on values below `min`
+    pub fn min(mut self, min: f64) -> Threshold {
+        self.min = Some(min);
+        self
+    }
+
+    /// Alarm on values above `max`
+    pub fn max(mut self, max: f64) -> Threshold {
+        self.max = Some(max);
+        self
+    }
+
+    /// Alarm if the value changes by more than `rate` units per second, in
+    /// either direction
+    pub fn max_rate(mut self, rate: f64) -> Threshold {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Only raise or clear alarms once the condition held for `debounce`
+    pub fn debounce(mut self, debounce: Duration) -> Threshold {
+        self.debounce = debounce;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Monitored {
+    threshold: Threshold,
+    last: Option<(Instant, f64)>,
+    alarms: [AlarmState; 3],
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct AlarmState {
+    active: bool,
+    /// Since when the condition differs from `active`
+    pending: Option<Instant>,
+}
+
+impl AlarmState {
+    /// Track `violated` and report whether the alarm toggled
+    fn update(&mut self, violated: bool, time: Instant, debounce: Duration) -> bool {
+        if violated == self.active {
+            self.pending = None;
+            return false;
+        }
+        let since = *self.pending.get_or_insert(time);
+        if time.saturating_duration_since(since) < debounce {
+            return false;
+        }
+        self.active = violated;
+        self.pending = None;
+        true
+    }
+}
+
+/// Debounced alarms on decoded J1939 parameters
+///
+/// Checks the values decoded by `PgnDatabase::decode` against the minimum,
+/// maximum and rate-of-change limits of a `Threshold`. An alarm is raised
+/// once a limit was violated for the debounce time of its threshold, and
+/// cleared once the value stayed within the limit for as long. Values the
+/// sender marked as not available are ignored. The time of an update is
+/// taken from a `Clock`.
+#[derive(Debug, Clone, Default)]
+pub struct AlarmMonitor<C = SystemClock> {
+    clock: C,
+    monitored: Vec<Monitored>,
+}
+
+impl AlarmMonitor {
+    /// Create a monitor without thresholds
+    pub fn new() -> AlarmMonitor {
+        AlarmMonitor::default()
+    }
+}
+
+impl<C: Clock> AlarmMonitor<C> {
+    /// Use `clock` to time the debouncing and rate of change
+    pub fn with_clock<D: Clock>(self, clock: D) -> AlarmMonitor<D> {
+        AlarmMonitor {
+            clock,
+            monitored: self.monitored,
+        }
+    }
+
+    /// Monitor a parameter
+    pub fn threshold(mut self, threshold: Threshold) -> AlarmMonitor<C> {
+        self.monitored.push(Monitored {
+            threshold,
+            last: None,
+            alarms: Default::default(),
+        });
+        self
+    }
+
+    /// Check values decoded just now
+    pub fn update(&mut self, values: &[SpnValue<'_>]) -> Vec<AlarmEvent> {
+        let time = self.clock.now();
+        let mut events = Vec::new();
+        for value in values {
+            let current = match value.value {
+                Some(current) => current,
+                None => continue,
+            };
+            for monitored in self
+                .monitored
+                .iter_mut()
+                .filter(|m| m.threshold.spn == value.spn.spn)
+            {
+                monitored.check(current, time, &mut events);
+            }
+        }
+        events
+    }
+
+    /// Currently raised alarms
+    pub fn active(&self) -> Vec<(u32, AlarmKind)> {
+        let mut active = Vec::new();
+        for monitored in &self.monitored {
+            for (kind, state) in KINDS.iter().zip(&monitored.alarms) {
+                if state.active {
+                    active.push((monitored.threshold.spn, *kind));
+                }
+            }
+        }
+        active
+    }
+}
+
+const KINDS: [AlarmKind; 3] = [
+    AlarmKind::BelowMin,
+    AlarmKind::AboveMax,
+    AlarmKind::RateOfChange,
+];
+
+impl Monitored {
+    fn check(&mut self, value: f64, time: Instant, events: &mut Vec<AlarmEvent>) {
+        let threshold = &self.threshold;
+        let rate = self.last.and_then(|(last_time, last)| {
+            let dt = time.saturating_duration_since(last_time).as_secs_f64();
+            if dt > 0.0 {
+                Some((value - last).abs() / dt)
+            } else {
+                None
+            }
+        });
+        self.last = Some((time, value));
+
+        let violated = [
+            threshold.min.map(|min| value < min),
+            threshold.max.map(|max| value > max),
+            threshold.rate.and_then(|max| rate.map(|rate| rate > max)),
+        ];
+        for ((kind, state), violated) in KINDS.iter().zip(&mut self.alarms).zip(violated) {
+            let violated = match violated {
+                Some(violated) => violated,
+                None => continue,
+            };
+            if state.update(violated, time, threshold.debounce) {
+                let spn = threshold.spn;
+                let kind = *kind;
+                events.push(if violated {
+                    AlarmEvent::Raised { spn, kind, value }
+                } else {
+                    AlarmEvent::Cleared { spn, kind, value }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlarmEvent, AlarmKind, AlarmMonitor, Threshold};
+    use crate::{Frame, ManualClock, PgnDatabase};
+    use std::time::Duration;
+
+    const DB: &str = "65262,110,Engine Coolant Temperature,1,1 byte,1 °C/bit,-40,°C";
+
+    #[test]
+    fn test_debounced_alarm() {
+        let db: PgnDatabase = DB.parse().unwrap();
+        let clock = ManualClock::new();
+        let mut monitor = AlarmMonitor::new()
+            .threshold(
+                Threshold::new(110)
+                    .max(110.0)
+                    .max_rate(20.0)
+                    .debounce(Duration::from_secs(5)),
+            )
+            .with_clock(clock.clone());
+        let frame = |temp: u8| Frame::new(0x18FEEE00, &[temp + 40], false, false).unwrap();
+        let mut update = |secs, temp| {
+            clock.advance(Duration::from_secs(secs));
+            monitor.update(&db.decode(&frame(temp)))
+        };
+
+        assert!(update(0, 100).is_empty());
+        // too short to raise an alarm
+        assert!(update(1, 115).is_empty());
+        assert!(update(1, 105).is_empty());
+        assert!(update(1, 112).is_empty());
+        assert!(update(4, 113).is_empty());
+        assert_eq!(
+            update(1, 114),
+            [AlarmEvent::Raised {
+                spn: 110,
+                kind: AlarmKind::AboveMax,
+                value: 114.0
+            }]
+        );
+        assert!(update(1, 100).is_empty());
+        assert_eq!(
+            update(5, 100),
+            [AlarmEvent::Cleared {
+                spn: 110,
+                kind: AlarmKind::AboveMax,
+                value: 100.0
+            }]
+        );
+
+        let clock = ManualClock::new();
+        let mut monitor = AlarmMonitor::new()
+            .threshold(Threshold::new(110).max_rate(20.0))
+            .with_clock(clock.clone());
+        assert!(monitor.update(&db.decode(&frame(80))).is_empty());
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(monitor.update(&db.decode(&frame(95))).len(), 1);
+        assert_eq!(monitor.active(), [(110, AlarmKind::RateOfChange)]);
+    }
+}
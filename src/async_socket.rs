@@ -0,0 +1,150 @@
+use crate::{socket::BACKPRESSURE_RETRY, Frame, Socket, SocketError};
+use async_io::{Async, Timer};
+use libc::ENOBUFS;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// Asynchronous CAN socket.
+///
+/// Built on `async-io`, so it works with any executor (smol, async-std,
+/// futures' `block_on`, ...) without depending on tokio. The wrapped socket
+/// is switched to non-blocking mode.
+///
+/// Besides the async methods, transmission is available as `poll_ready`,
+/// `start_send` and `poll_flush` following the contract of the `Sink` trait
+/// from `futures`. A single frame is buffered, so a full transmit queue
+/// (`ENOBUFS`) or socket buffer holds back further frames instead of
+/// queueing them in userspace.
+#[derive(Debug)]
+pub struct AsyncSocket {
+    inner: Async<Socket>,
+    pending: Option<Frame>,
+    retry: Option<Timer>,
+}
+
+impl AsyncSocket {
+    /// Open a named CAN device.
+    pub fn open(ifname: &str) -> Result<AsyncSocket, SocketError> {
+        Ok(AsyncSocket::new(Socket::new(ifname)?)?)
+    }
+
+    /// Wrap an existing socket.
+    pub fn new(socket: Socket) -> io::Result<AsyncSocket> {
+        Ok(AsyncSocket {
+            inner: Async::new(socket)?,
+            pending: None,
+            retry: None,
+        })
+    }
+
+    /// Receive a frame, waiting until one is available.
+    pub async fn receive(&self) -> Result<Frame, SocketError> {
+        Ok(self.inner.read_with(|s| s.recv_one(0)).await?)
+    }
+
+    /// Transmit a frame, waiting until the socket is writable and the
+    /// transmit queue has room.
+    pub async fn transmit(&self, frame: &Frame) -> Result<(), SocketError> {
+        loop {
+            match self.inner.write_with(|s| s.send_frame(frame, 0)).await {
+                Err(e) if e.raw_os_error() == Some(ENOBUFS) => {
+                    Timer::after(BACKPRESSURE_RETRY).await;
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Check whether a frame can be passed to `start_send`, transmitting
+    /// the previously buffered frame first.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SocketError>> {
+        self.poll_flush(cx)
+    }
+
+    /// Buffer a frame for transmission. `poll_ready` has to return ready
+    /// before each call.
+    pub fn start_send(&mut self, frame: Frame) -> Result<(), SocketError> {
+        if self.pending.is_some() {
+            return Err(SocketError::WouldBlock);
+        }
+        self.pending = Some(frame);
+        Ok(())
+    }
+
+    /// Transmit the buffered frame.
+    ///
+    /// Waits for the socket to become writable, and retries while the
+    /// transmit queue of the device is full. A frame failing with another
+    /// error is dropped and the error returned.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SocketError>> {
+        loop {
+            if let Some(retry) = self.retry.as_mut() {
+                ready!(Pin::new(retry).poll(cx));
+                self.retry = None;
+            }
+
+            let frame = match self.pending {
+                Some(frame) => frame,
+                None => return Poll::Ready(Ok(())),
+            };
+            match self.inner.get_ref().send_frame(&frame, 0) {
+                Ok(()) => {
+                    self.pending = None;
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.inner.poll_writable(cx))?;
+                }
+                Err(e) if e.raw_os_error() == Some(ENOBUFS) => {
+                    self.retry = Some(Timer::after(BACKPRESSURE_RETRY));
+                }
+                Err(e) => {
+                    self.pending = None;
+                    return Poll::Ready(Err(e.into()));
+                }
+            }
+        }
+    }
+
+    /// Access the underlying socket, e.g. to change socket options.
+    pub fn get_ref(&self) -> &Socket {
+        self.inner.get_ref()
+    }
+
+    /// Return the underlying socket, switched back to blocking mode.
+    pub fn into_inner(self) -> io::Result<Socket> {
+        let socket = self.inner.into_inner()?;
+        socket.set_nonblocking(false)?;
+        Ok(socket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncSocket;
+    use crate::{Frame, Socket};
+    use embedded_can::blocking::Can;
+    use std::future::poll_fn;
+
+    #[test]
+    fn test_poll_transmit() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let mut socket = AsyncSocket::new(a).unwrap();
+
+        async_io::block_on(async {
+            for id in [0x100, 0x101] {
+                poll_fn(|cx| socket.poll_ready(cx)).await.unwrap();
+                let frame = Frame::new(id, &[1, 2], false, false).unwrap();
+                socket.start_send(frame).unwrap();
+            }
+            poll_fn(|cx| socket.poll_flush(cx)).await.unwrap();
+        });
+
+        assert_eq!(b.receive().unwrap().raw_id(), 0x100);
+        assert_eq!(b.receive().unwrap().raw_id(), 0x101);
+    }
+}
@@ -0,0 +1,679 @@
+use crate::{frame::fd_dlc, AnyFrame, FdFrame, Frame, ShutdownToken};
+use embedded_can::Frame as _;
+use flate2::read::ZlibDecoder;
+use libc::{CAN_EFF_FLAG, CAN_RTR_FLAG};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Size of the file header
+const FILE_HEADER_SIZE: usize = 144;
+
+/// Size of an object header: base header and version 1 header
+const OBJ_HEADER_SIZE: usize = 32;
+
+/// Size of the header of a log container, including the base header
+const CONTAINER_HEADER_SIZE: usize = 32;
+
+/// Uncompressed bytes collected before a log container is written
+const CONTAINER_SIZE: usize = 128 * 1024;
+
+const CAN_MESSAGE: u32 = 1;
+const LOG_CONTAINER: u32 = 10;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+/// Object header flag: timestamp in 10 µs units
+const TIME_TEN_MICS: u32 = 1;
+/// Object header flag: timestamp in nanoseconds
+const TIME_ONE_NANS: u32 = 2;
+
+/// `CAN_MESSAGE` flags
+const MSG_TX: u8 = 0x01;
+const MSG_REMOTE: u8 = 0x80;
+
+/// Marks extended ids in the arbitration id field
+const MSG_EXT: u32 = 0x8000_0000;
+
+/// `CAN_FD_MESSAGE` flags
+const FD_EDL: u8 = 0x01;
+const FD_BRS: u8 = 0x02;
+const FD_ESI: u8 = 0x04;
+
+/// `CAN_FD_MESSAGE_64` flags
+const FD64_REMOTE: u32 = 0x0010;
+const FD64_EDL: u32 = 0x1000;
+const FD64_BRS: u32 = 0x2000;
+const FD64_ESI: u32 = 0x4000;
+
+/// Log container compression methods
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+
+/// Frame stored in a BLF file
+#[derive(Debug, Copy, Clone)]
+pub struct BlfMessage {
+    /// Time since the start of the measurement
+    pub timestamp: Duration,
+    /// Channel the frame was logged on, starting at 1
+    pub channel: u16,
+    /// `true` for frames transmitted by the logging node
+    pub tx: bool,
+    /// The logged frame
+    pub frame: AnyFrame,
+}
+
+/// Iterator over the CAN and CAN FD messages of a Vector BLF file
+///
+/// Other objects, e.g. error frames or statistics, are skipped. Log
+/// containers may be stored uncompressed or zlib compressed, other
+/// compression methods are reported as `Unsupported` errors.
+pub struct BlfReader<R> {
+    reader: R,
+    start: SystemTime,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl BlfReader<BufReader<File>> {
+    /// Open a BLF file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<BlfReader<BufReader<File>>> {
+        BlfReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> BlfReader<R> {
+    /// Read the file header from `reader`
+    pub fn new(mut reader: R) -> io::Result<BlfReader<R>> {
+        let mut header = [0; FILE_HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"LOGG" {
+            return Err(invalid("not a BLF file"));
+        }
+        let header_size = u32_at(&header, 4) as usize;
+        if header_size > FILE_HEADER_SIZE {
+            io::copy(
+                &mut (&mut reader).take((header_size - FILE_HEADER_SIZE) as u64),
+                &mut io::sink(),
+            )?;
+        }
+
+        Ok(BlfReader {
+            reader,
+            start: from_system_time(&header[40..56]),
+            buf: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// Start of the measurement, the reference of the message timestamps
+    pub fn start_time(&self) -> SystemTime {
+        self.start
+    }
+
+    /// Read the next top level object into the buffer. `false` at the end of
+    /// the file.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut header = [0; 16];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        if &header[..4] != b"LOBJ" {
+            return Err(invalid("invalid object signature"));
+        }
+        let size = u32_at(&header, 8) as usize;
+        if size < header.len() {
+            return Err(invalid("invalid object size"));
+        }
+        let mut data = vec![0; size - header.len()];
+        self.reader.read_exact(&mut data)?;
+        // top level objects are padded
+        io::copy(
+            &mut (&mut self.reader).take((size % 4) as u64),
+            &mut io::sink(),
+        )?;
+
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        if u32_at(&header, 12) == LOG_CONTAINER {
+            if data.len() < CONTAINER_HEADER_SIZE - header.len() {
+                return Err(invalid("invalid log container"));
+            }
+            let content = &data[CONTAINER_HEADER_SIZE - header.len()..];
+            match u16_at(&data, 0) {
+                NO_COMPRESSION => self.buf.extend_from_slice(content),
+                ZLIB_DEFLATE => {
+                    let size = u32_at(&data, 8) as usize;
+                    let start = self.buf.len();
+                    ZlibDecoder::new(content)
+                        .take(size as u64)
+                        .read_to_end(&mut self.buf)?;
+                    if self.buf.len() - start != size {
+                        return Err(invalid("truncated log container"));
+                    }
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "unsupported log container compression",
+                    ))
+                }
+            }
+        } else {
+            self.buf.extend_from_slice(&header);
+            self.buf.extend_from_slice(&data);
+        }
+        Ok(true)
+    }
+
+    /// Parse the next object from the buffer. `None` if it is incomplete,
+    /// `Some(None)` for objects other than CAN messages.
+    fn parse(&mut self) -> io::Result<Option<Option<BlfMessage>>> {
+        let rest = &self.buf[self.pos..];
+        if rest.len() < 16 {
+            return Ok(None);
+        }
+        if &rest[..4] != b"LOBJ" {
+            return Err(invalid("invalid object signature"));
+        }
+        let header_size = u16_at(rest, 4) as usize;
+        let version = u16_at(rest, 6);
+        let size = u32_at(rest, 8) as usize;
+        let ty = u32_at(rest, 12);
+        if size < header_size || header_size < OBJ_HEADER_SIZE {
+            return Err(invalid("invalid object size"));
+        }
+        if rest.len() < size {
+            return Ok(None);
+        }
+        self.pos += size;
+
+        if version != 1 && version != 2 {
+            return Ok(Some(None));
+        }
+        // flags and timestamp are at the same place in both header versions
+        let timestamp = u64_at(rest, 24);
+        let timestamp = if u32_at(rest, 16) == TIME_TEN_MICS {
+            Duration::from_micros(timestamp * 10)
+        } else {
+            Duration::from_nanos(timestamp)
+        };
+        let data = &rest[header_size..size];
+
+        let message = match ty {
+            CAN_MESSAGE | CAN_MESSAGE2 if data.len() >= 16 => {
+                let flags = data[2];
+                let dlc = (data[3] as usize).min(8);
+                let frame = Frame::new(0, &data[8..8 + dlc], false, false)
+                    .unwrap()
+                    .with_can_id(can_id(u32_at(data, 4), flags & MSG_REMOTE != 0));
+                Some(BlfMessage {
+                    timestamp,
+                    channel: u16_at(data, 0),
+                    tx: flags & MSG_TX != 0,
+                    frame: AnyFrame::Classic(frame),
+                })
+            }
+            CAN_FD_MESSAGE if data.len() >= 84 => {
+                let flags = data[2];
+                let id = u32_at(data, 4);
+                let fd_flags = data[13];
+                let frame = if fd_flags & FD_EDL != 0 {
+                    let len = (data[14] as usize).min(64);
+                    let frame = FdFrame::new(
+                        0,
+                        &data[20..20 + len],
+                        fd_flags & FD_BRS != 0,
+                        fd_flags & FD_ESI != 0,
+                    )
+                    .unwrap();
+                    AnyFrame::Fd(frame.with_can_id(can_id(id, false)))
+                } else {
+                    let dlc = (data[3] as usize).min(8);
+                    let frame = Frame::new(0, &data[20..20 + dlc], false, false).unwrap();
+                    AnyFrame::Classic(frame.with_can_id(can_id(id, flags & MSG_REMOTE != 0)))
+                };
+                Some(BlfMessage {
+                    timestamp,
+                    channel: u16_at(data, 0),
+                    tx: flags & MSG_TX != 0,
+                    frame,
+                })
+            }
+            CAN_FD_MESSAGE_64 if data.len() >= 40 => {
+                let len = data[2] as usize;
+                let id = u32_at(data, 4);
+                let fd_flags = u32_at(data, 12);
+                let payload = data.get(40..40 + len).unwrap_or(&[]);
+                let frame = if fd_flags & FD64_EDL != 0 {
+                    let frame = FdFrame::new(
+                        0,
+                        &payload[..payload.len().min(64)],
+                        fd_flags & FD64_BRS != 0,
+                        fd_flags & FD64_ESI != 0,
+                    )
+                    .unwrap();
+                    AnyFrame::Fd(frame.with_can_id(can_id(id, false)))
+                } else {
+                    let dlc = (data[1] as usize).min(8);
+                    let mut payload = payload.to_vec();
+                    payload.resize(dlc, 0);
+                    let frame = Frame::new(0, &payload, false, false).unwrap();
+                    AnyFrame::Classic(frame.with_can_id(can_id(id, fd_flags & FD64_REMOTE != 0)))
+                };
+                Some(BlfMessage {
+                    timestamp,
+                    channel: data[0] as u16,
+                    tx: data[34] != 0,
+                    frame,
+                })
+            }
+            _ => None,
+        };
+        Ok(Some(message))
+    }
+}
+
+impl<R: Read> Iterator for BlfReader<R> {
+    type Item = io::Result<BlfMessage>;
+
+    fn next(&mut self) -> Option<io::Result<BlfMessage>> {
+        loop {
+            match self.parse() {
+                Ok(Some(Some(message))) => return Some(Ok(message)),
+                Ok(Some(None)) => {}
+                Ok(None) => match self.fill() {
+                    Ok(true) => {}
+                    Ok(false) if self.pos == self.buf.len() => return None,
+                    Ok(false) => {
+                        self.pos = self.buf.len();
+                        return Some(Err(invalid("truncated object")));
+                    }
+                    Err(e) => return Some(Err(e)),
+                },
+                Err(e) => {
+                    // skip the rest, the object boundaries are lost
+                    self.buf.clear();
+                    self.pos = 0;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Writer of Vector BLF files
+///
+/// Messages are collected in uncompressed log containers. `finish` has to be
+/// called at the end to write the file header with the object count and the
+/// measurement duration.
+pub struct BlfWriter<W> {
+    writer: W,
+    start: SystemTime,
+    last: Duration,
+    container: Vec<u8>,
+    objects: u32,
+    uncompressed: u64,
+}
+
+impl BlfWriter<BufWriter<File>> {
+    /// Create a BLF file for a measurement starting now, replacing an
+    /// existing file
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<BlfWriter<BufWriter<File>>> {
+        BlfWriter::new(BufWriter::new(File::create(path)?), SystemTime::now())
+    }
+}
+
+impl<W: Write + Seek> BlfWriter<W> {
+    /// Write BLF data to `writer`, for a measurement started at `start`
+    pub fn new(mut writer: W, start: SystemTime) -> io::Result<BlfWriter<W>> {
+        // the header is written by `finish`
+        writer.write_all(&[0; FILE_HEADER_SIZE])?;
+        Ok(BlfWriter {
+            writer,
+            start,
+            last: Duration::ZERO,
+            container: Vec::new(),
+            objects: 0,
+            uncompressed: FILE_HEADER_SIZE as u64,
+        })
+    }
+
+    /// Append a message
+    pub fn write(&mut self, message: &BlfMessage) -> io::Result<()> {
+        let tx = if message.tx { MSG_TX } else { 0 };
+        let mut data = Vec::with_capacity(84);
+        data.extend_from_slice(&message.channel.to_le_bytes());
+        let ty = match message.frame {
+            AnyFrame::Classic(frame) => {
+                let remote = if frame.is_remote_frame() {
+                    MSG_REMOTE
+                } else {
+                    0
+                };
+                data.push(tx | remote);
+                data.push(frame.dlc() as u8);
+                data.extend_from_slice(&arbitration_id(&frame).to_le_bytes());
+                let mut payload = [0; 8];
+                payload[..frame.data().len()].copy_from_slice(frame.data());
+                data.extend_from_slice(&payload);
+                CAN_MESSAGE
+            }
+            AnyFrame::Fd(frame) => {
+                let len = frame.data().len();
+                data.push(tx);
+                data.push(fd_dlc(len));
+                data.extend_from_slice(&arbitration_id(&frame).to_le_bytes());
+                data.extend_from_slice(&[0; 5]); // frame length and bit count
+                let brs = if frame.is_brs() { FD_BRS } else { 0 };
+                let esi = if frame.is_esi() { FD_ESI } else { 0 };
+                data.push(FD_EDL | brs | esi);
+                data.push(len as u8);
+                data.extend_from_slice(&[0; 5]);
+                let mut payload = [0; 64];
+                payload[..len].copy_from_slice(frame.data());
+                data.extend_from_slice(&payload);
+                CAN_FD_MESSAGE
+            }
+        };
+
+        let size = (OBJ_HEADER_SIZE + data.len()) as u32;
+        self.container.extend_from_slice(b"LOBJ");
+        self.container
+            .extend_from_slice(&(OBJ_HEADER_SIZE as u16).to_le_bytes());
+        self.container.extend_from_slice(&1u16.to_le_bytes());
+        self.container.extend_from_slice(&size.to_le_bytes());
+        self.container.extend_from_slice(&ty.to_le_bytes());
+        self.container
+            .extend_from_slice(&TIME_ONE_NANS.to_le_bytes());
+        self.container.extend_from_slice(&[0; 4]); // client index, version
+        self.container
+            .extend_from_slice(&(message.timestamp.as_nanos() as u64).to_le_bytes());
+        self.container.extend_from_slice(&data);
+
+        self.objects += 1;
+        self.last = self.last.max(message.timestamp);
+        if self.container.len() >= CONTAINER_SIZE {
+            self.write_container()?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining messages and the file header, returning the
+    /// underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_container()?;
+        let file_size = self.writer.stream_position()?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(b"LOGG");
+        header.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // application id and version, BLF version 2.6.8.1
+        header.extend_from_slice(&[5, 0, 0, 0, 2, 6, 8, 1]);
+        header.extend_from_slice(&file_size.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed.to_le_bytes());
+        header.extend_from_slice(&self.objects.to_le_bytes());
+        header.extend_from_slice(&self.objects.to_le_bytes());
+        header.extend_from_slice(&to_system_time(self.start));
+        header.extend_from_slice(&to_system_time(self.start + self.last));
+        header.resize(FILE_HEADER_SIZE, 0);
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// `finish` the file once shutting down. The messages are only complete
+    /// with the file header, so all of them are reported to `token` as
+    /// flushed by subsystem `blf`, or as dropped if finishing failed.
+    pub fn finish_on(self, token: &ShutdownToken) -> io::Result<W> {
+        let pending = self.objects as u64;
+        let rv = self.finish();
+        token.report_flush("blf", pending, &rv);
+        rv
+    }
+
+    fn write_container(&mut self) -> io::Result<()> {
+        if self.container.is_empty() {
+            return Ok(());
+        }
+        let size = CONTAINER_HEADER_SIZE + self.container.len();
+        let mut header = Vec::with_capacity(CONTAINER_HEADER_SIZE);
+        header.extend_from_slice(b"LOBJ");
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&(size as u32).to_le_bytes());
+        header.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        header.extend_from_slice(&NO_COMPRESSION.to_le_bytes());
+        header.extend_from_slice(&[0; 6]);
+        header.extend_from_slice(&(self.container.len() as u32).to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.container)?;
+        self.writer.write_all(&[0; 3][..size % 4])?;
+        self.uncompressed += size as u64;
+        self.container.clear();
+        Ok(())
+    }
+}
+
+/// CAN_ID with flags from a BLF arbitration id
+fn can_id(id: u32, remote: bool) -> u32 {
+    let eff = if id & MSG_EXT != 0 { CAN_EFF_FLAG } else { 0 };
+    let rtr = if remote { CAN_RTR_FLAG } else { 0 };
+    (id & !MSG_EXT) | eff | rtr
+}
+
+/// BLF arbitration id of a frame
+fn arbitration_id(frame: &impl embedded_can::Frame) -> u32 {
+    match frame.id() {
+        embedded_can::Id::Standard(id) => id.as_raw() as u32,
+        embedded_can::Id::Extended(id) => id.as_raw() | MSG_EXT,
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Convert a Windows `SYSTEMTIME` (UTC) to a `SystemTime`
+fn from_system_time(raw: &[u8]) -> SystemTime {
+    let field = |n: usize| u16_at(raw, 2 * n) as i64;
+    let (year, month, day) = (field(0), field(1), field(3));
+    if year == 0 || month == 0 || day == 0 {
+        return UNIX_EPOCH;
+    }
+    let secs =
+        days_from_civil(year, month, day) * 86400 + field(4) * 3600 + field(5) * 60 + field(6);
+    if secs < 0 {
+        return UNIX_EPOCH;
+    }
+    UNIX_EPOCH + Duration::from_secs(secs as u64) + Duration::from_millis(field(7) as u64)
+}
+
+/// Convert a `SystemTime` to a Windows `SYSTEMTIME` (UTC)
+fn to_system_time(time: SystemTime) -> [u8; 16] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let days = (since_epoch.as_secs() / 86400) as i64;
+    let secs = since_epoch.as_secs() % 86400;
+    let (year, month, day) = civil_from_days(days);
+    let fields = [
+        year as u16,
+        month as u16,
+        ((days + 4) % 7) as u16, // 1970-01-01 was a Thursday
+        day as u16,
+        (secs / 3600) as u16,
+        (secs / 60 % 60) as u16,
+        (secs % 60) as u16,
+        since_epoch.subsec_millis() as u16,
+    ];
+    let mut raw = [0; 16];
+    for (n, field) in fields.iter().enumerate() {
+        raw[2 * n..2 * n + 2].copy_from_slice(&field.to_le_bytes());
+    }
+    raw
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Date of a day counted from 1970-01-01, inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{civil_from_days, days_from_civil, BlfMessage, BlfReader, BlfWriter};
+    use crate::{AnyFrame, FdFrame, Frame, ShutdownToken};
+    use embedded_can::Frame as _;
+    use std::{
+        io::Cursor,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn test_blf_roundtrip() {
+        let start = UNIX_EPOCH + Duration::from_millis(1_436_509_052_250);
+        let mut writer = BlfWriter::new(Cursor::new(Vec::new()), start).unwrap();
+        let messages = [
+            BlfMessage {
+                timestamp: Duration::from_micros(10),
+                channel: 1,
+                tx: false,
+                frame: AnyFrame::Classic(Frame::new_extended(0x100, &[1, 2, 3])),
+            },
+            BlfMessage {
+                timestamp: Duration::from_micros(20),
+                channel: 2,
+                tx: true,
+                frame: AnyFrame::Fd(FdFrame::new(0x123, &[0xAA; 20], true, false).unwrap()),
+            },
+        ];
+        for message in &messages {
+            writer.write(message).unwrap();
+        }
+        let token = ShutdownToken::new();
+        let file = writer.finish_on(&token).unwrap().into_inner();
+        assert_eq!(&file[..4], b"LOGG");
+        assert_eq!(
+            token.reports()[0].to_string(),
+            "blf: 2 frames flushed, 0 dropped"
+        );
+
+        let mut reader = BlfReader::new(&file[..]).unwrap();
+        assert_eq!(reader.start_time(), start);
+        let read = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].timestamp, Duration::from_micros(10));
+        match read[0].frame {
+            AnyFrame::Classic(frame) => {
+                assert!(frame.is_extended());
+                assert_eq!(frame.to_string(), "00000100#010203");
+            }
+            other => panic!("unexpected frame: {:?}", other),
+        }
+        assert_eq!(read[1].channel, 2);
+        assert!(read[1].tx);
+        match read[1].frame {
+            AnyFrame::Fd(frame) => {
+                assert!(frame.is_brs());
+                assert_eq!(frame.dlc(), 20);
+            }
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compressed_container() {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+
+        let mut writer = BlfWriter::new(Cursor::new(Vec::new()), UNIX_EPOCH).unwrap();
+        let frame = Frame::new(0x123, &[1, 2, 3], false, false).unwrap();
+        for n in 0..100 {
+            writer
+                .write(&BlfMessage {
+                    timestamp: Duration::from_millis(n),
+                    channel: 1,
+                    tx: false,
+                    frame: AnyFrame::Classic(frame),
+                })
+                .unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        // compress the single log container of the file
+        let (head, container) = file.split_at(144);
+        let size = u32::from_le_bytes(container[24..28].try_into().unwrap()) as usize;
+        let content = &container[32..32 + size];
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut file = head.to_vec();
+        file.extend_from_slice(&container[..32]);
+        file[144 + 8..144 + 12].copy_from_slice(&(32 + compressed.len() as u32).to_le_bytes());
+        file[144 + 16] = 2;
+        file.extend_from_slice(&compressed);
+        file.extend_from_slice(&[0; 3][..compressed.len() % 4]);
+
+        let read = BlfReader::new(&file[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 100);
+        assert_eq!(read[99].timestamp, Duration::from_millis(99));
+        assert!(matches!(read[99].frame, AnyFrame::Classic(f) if f == frame));
+    }
+
+    #[test]
+    fn test_civil_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in [0, 11017, 19782, -1] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{
+    frame::bytes_of, gateway::crc8_table, interface::Message, Filter, Interface, InterfaceError,
+};
+use libc::{AF_CAN, NLM_F_ACK, NLM_F_REQUEST, RTM_DELROUTE, RTM_NEWROUTE};
+
+/// `CGW_TYPE_CAN_CAN` from `linux/can/gw.h`
+const CGW_TYPE_CAN_CAN: u8 = 1;
+
+/// `CGW_MOD_AND` from `linux/can/gw.h`, followed by OR, XOR and SET
+const CGW_MOD_AND: u16 = 1;
+
+/// `CGW_CS_XOR` from `linux/can/gw.h`
+const CGW_CS_XOR: u16 = 5;
+
+/// `CGW_CS_CRC8` from `linux/can/gw.h`
+const CGW_CS_CRC8: u16 = 6;
+
+/// `CGW_SRC_IF` from `linux/can/gw.h`
+const CGW_SRC_IF: u16 = 9;
+
+/// `CGW_DST_IF` from `linux/can/gw.h`
+const CGW_DST_IF: u16 = 10;
+
+/// `CGW_FILTER` from `linux/can/gw.h`
+const CGW_FILTER: u16 = 11;
+
+/// `CGW_LIM_HOPS` from `linux/can/gw.h`
+const CGW_LIM_HOPS: u16 = 13;
+
+/// `CGW_FLAGS_CAN_ECHO` from `linux/can/gw.h`
+const CGW_FLAGS_CAN_ECHO: u16 = 0x01;
+
+/// `CGW_FLAGS_CAN_SRC_TSTAMP` from `linux/can/gw.h`
+const CGW_FLAGS_CAN_SRC_TSTAMP: u16 = 0x02;
+
+/// `CGW_FLAGS_CAN_IIF_TX_OK` from `linux/can/gw.h`
+const CGW_FLAGS_CAN_IIF_TX_OK: u16 = 0x04;
+
+const CGW_MOD_ID: u8 = 0x01;
+const CGW_MOD_DLC: u8 = 0x02;
+const CGW_MOD_DATA: u8 = 0x04;
+
+/// Size of `struct cgw_frame_mod`, a `can_frame` followed by the modified
+/// fields
+const FRAME_MOD_SIZE: usize = 17;
+
+/// Family header of can-gw requests, `struct rtcanmsg`
+#[repr(C)]
+struct RtCanMsg {
+    can_family: u8,
+    gwtype: u8,
+    flags: u16,
+}
+
+/// Operation of a `GwRule` frame modification
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GwModOp {
+    /// Binary AND with the value
+    And,
+    /// Binary OR with the value
+    Or,
+    /// Binary XOR with the value
+    Xor,
+    /// Replace by the value
+    Set,
+}
+
+/// Routing rule of the kernel CAN gateway
+///
+/// Installs a route between two interfaces in the `can-gw` kernel module,
+/// like the `cangw` tool does. Frames are forwarded within the kernel,
+/// without copies to userspace, optionally modifying them and updating a
+/// checksum in the data:
+///
+/// ```no_run
+/// use candev::{Filter, GwModOp, GwRule, Interface};
+///
+/// let can0 = Interface::new("can0").unwrap();
+/// let can1 = Interface::new("can1").unwrap();
+/// GwRule::new(&can0, &can1)
+///     .filter(Filter::standard(0x123))
+///     .modify_id(GwModOp::Set, 0x321)
+///     .modify_data(GwModOp::Or, [0, 0, 0, 0, 0, 0, 0, 0x80])
+///     .xor_checksum(0, 6, 7, 0)
+///     .install()
+///     .unwrap();
+/// ```
+///
+/// Installing rules requires `CAP_NET_ADMIN` and the `can-gw` module.
+/// Modifications are applied in the order AND, OR, XOR, SET before the
+/// checksums are calculated.
+#[derive(Debug, Clone)]
+pub struct GwRule {
+    src: u32,
+    dst: u32,
+    flags: u16,
+    filter: Option<Filter>,
+    mods: [Option<[u8; FRAME_MOD_SIZE]>; 4],
+    xor: Option<[u8; 4]>,
+    crc8: Option<Vec<u8>>,
+    hops: Option<u8>,
+}
+
+impl GwRule {
+    /// Forward all frames received on `src` to `dst`
+    pub fn new(src: &Interface, dst: &Interface) -> GwRule {
+        GwRule {
+            src: src.index(),
+            dst: dst.index(),
+            flags: 0,
+            filter: None,
+            mods: [None; 4],
+            xor: None,
+            crc8: None,
+            hops: None,
+        }
+    }
+
+    /// Only forward frames accepted by `filter`
+    pub fn filter(mut self, filter: Filter) -> GwRule {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Deliver forwarded frames to the local sockets on `dst` as well
+    pub fn echo(mut self, enabled: bool) -> GwRule {
+        self.set_flag(CGW_FLAGS_CAN_ECHO, enabled);
+        self
+    }
+
+    /// Keep the timestamp of the received frame
+    pub fn source_timestamp(mut self, enabled: bool) -> GwRule {
+        self.set_flag(CGW_FLAGS_CAN_SRC_TSTAMP, enabled);
+        self
+    }
+
+    /// Allow `src` and `dst` to be the same interface
+    pub fn allow_same_interface(mut self, enabled: bool) -> GwRule {
+        self.set_flag(CGW_FLAGS_CAN_IIF_TX_OK, enabled);
+        self
+    }
+
+    /// Limit the number of gateway hops of a frame
+    pub fn limit_hops(mut self, hops: u8) -> GwRule {
+        self.hops = Some(hops);
+        self
+    }
+
+    /// Modify the raw id, including the `CAN_EFF_FLAG` and `CAN_RTR_FLAG`
+    pub fn modify_id(mut self, op: GwModOp, id: u32) -> GwRule {
+        self.frame_mod(op, CGW_MOD_ID)[..4].copy_from_slice(&id.to_ne_bytes());
+        self
+    }
+
+    /// Modify the data length code
+    pub fn modify_dlc(mut self, op: GwModOp, dlc: u8) -> GwRule {
+        self.frame_mod(op, CGW_MOD_DLC)[4] = dlc;
+        self
+    }
+
+    /// Modify the data
+    pub fn modify_data(mut self, op: GwModOp, data: [u8; 8]) -> GwRule {
+        self.frame_mod(op, CGW_MOD_DATA)[8..16].copy_from_slice(&data);
+        self
+    }
+
+    /// Store the XOR of the data bytes `from` to `to` in byte `result`,
+    /// starting with `init`.
+    ///
+    /// Negative indices count from the end of the data, -1 is the last
+    /// byte.
+    pub fn xor_checksum(mut self, from: i8, to: i8, result: i8, init: u8) -> GwRule {
+        self.xor = Some([from as u8, to as u8, result as u8, init]);
+        self
+    }
+
+    /// Store the CRC8 with the generator `polynomial` of the data bytes
+    /// `from` to `to` in byte `result`.
+    ///
+    /// The CRC starts with `init` and is XORed with `final_xor`, e.g.
+    /// polynomial 0x1D, 0xFF and 0xFF for SAE J1850. Negative indices count
+    /// from the end of the data.
+    pub fn crc8_checksum(
+        mut self,
+        from: i8,
+        to: i8,
+        result: i8,
+        polynomial: u8,
+        init: u8,
+        final_xor: u8,
+    ) -> GwRule {
+        // struct cgw_csum_crc8 without profile
+        let mut crc8 = vec![from as u8, to as u8, result as u8, init, final_xor];
+        crc8.extend(crc8_table(polynomial));
+        crc8.extend_from_slice(&[0; 21]);
+        self.crc8 = Some(crc8);
+        self
+    }
+
+    /// Install the rule
+    pub fn install(&self) -> Result<(), InterfaceError> {
+        Ok(self.rule_message(RTM_NEWROUTE).request()?)
+    }
+
+    /// Remove the installed rule.
+    ///
+    /// The kernel only removes a rule matching all settings of `self`.
+    pub fn remove(&self) -> Result<(), InterfaceError> {
+        Ok(self.rule_message(RTM_DELROUTE).request()?)
+    }
+
+    /// Remove all rules of the kernel gateway
+    pub fn flush() -> Result<(), InterfaceError> {
+        let mut msg = GwRule::message(RTM_DELROUTE, 0);
+        msg.attr(CGW_SRC_IF, &0u32.to_ne_bytes());
+        msg.attr(CGW_DST_IF, &0u32.to_ne_bytes());
+        Ok(msg.request()?)
+    }
+
+    fn message(ty: u16, flags: u16) -> Message {
+        let header = RtCanMsg {
+            can_family: AF_CAN as u8,
+            gwtype: CGW_TYPE_CAN_CAN,
+            flags,
+        };
+        Message::new(ty, NLM_F_REQUEST | NLM_F_ACK, bytes_of(&header))
+    }
+
+    /// Request of type `ty` with all attributes of the rule
+    fn rule_message(&self, ty: u16) -> Message {
+        let mut msg = GwRule::message(ty, self.flags);
+        for (i, frame_mod) in self.mods.iter().enumerate() {
+            if let Some(frame_mod) = frame_mod {
+                msg.attr(CGW_MOD_AND + i as u16, frame_mod);
+            }
+        }
+        if let Some(xor) = &self.xor {
+            msg.attr(CGW_CS_XOR, xor);
+        }
+        if let Some(crc8) = &self.crc8 {
+            msg.attr(CGW_CS_CRC8, crc8);
+        }
+        msg.attr(CGW_SRC_IF, &self.src.to_ne_bytes());
+        msg.attr(CGW_DST_IF, &self.dst.to_ne_bytes());
+        if let Some(filter) = &self.filter {
+            let mut raw = filter.id().to_ne_bytes().to_vec();
+            raw.extend_from_slice(&filter.mask().to_ne_bytes());
+            msg.attr(CGW_FILTER, &raw);
+        }
+        if let Some(hops) = self.hops {
+            msg.attr(CGW_LIM_HOPS, &[hops]);
+        }
+        msg
+    }
+
+    fn set_flag(&mut self, flag: u16, enabled: bool) {
+        if enabled {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// `struct cgw_frame_mod` of `op`, with `field` marked as modified
+    fn frame_mod(&mut self, op: GwModOp, field: u8) -> &mut [u8; FRAME_MOD_SIZE] {
+        let frame_mod = self.mods[op as usize].get_or_insert([0; FRAME_MOD_SIZE]);
+        frame_mod[FRAME_MOD_SIZE - 1] |= field;
+        frame_mod
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        GwModOp, GwRule, CGW_DST_IF, CGW_FILTER, CGW_MOD_AND, CGW_MOD_DATA, CGW_MOD_ID, CGW_SRC_IF,
+        FRAME_MOD_SIZE,
+    };
+    use crate::{gateway::crc8_table, interface::find_attr, Filter, Interface};
+
+    #[test]
+    fn test_crc8_table() {
+        let table: Vec<u8> = crc8_table(0x1D).collect();
+        assert_eq!(table.len(), 256);
+        assert_eq!(table[1], 0x1D);
+
+        // CRC-8/SAE-J1850 check value
+        let crc = b"123456789"
+            .iter()
+            .fold(0xFF, |crc, byte| table[(crc ^ byte) as usize]);
+        assert_eq!(crc ^ 0xFF, 0x4B);
+    }
+
+    #[test]
+    fn test_rule_message() {
+        let lo = Interface::new("lo").unwrap();
+        let rule = GwRule::new(&lo, &lo)
+            .filter(Filter::standard(0x123))
+            .modify_id(GwModOp::Set, 0x321)
+            .modify_data(GwModOp::Set, [1; 8]);
+        let frame_mod = rule.mods[GwModOp::Set as usize].unwrap();
+        assert_eq!(frame_mod[FRAME_MOD_SIZE - 1], CGW_MOD_ID | CGW_MOD_DATA);
+        assert_eq!(&frame_mod[..4], &0x321u32.to_ne_bytes());
+        assert!(rule.mods[GwModOp::And as usize].is_none());
+
+        let buf = rule.rule_message(libc::RTM_NEWROUTE).finish();
+        // nlmsghdr (16) + rtcanmsg (4)
+        let attrs = &buf[20..];
+        let index = lo.index().to_ne_bytes();
+        assert_eq!(find_attr(attrs, CGW_SRC_IF), Some(&index[..]));
+        assert_eq!(find_attr(attrs, CGW_DST_IF), Some(&index[..]));
+        assert_eq!(find_attr(attrs, CGW_FILTER).unwrap().len(), 8);
+        assert_eq!(
+            find_attr(attrs, CGW_MOD_AND + GwModOp::Set as u16),
+            Some(&frame_mod[..])
+        );
+    }
+
+    #[test]
+    fn test_not_can() {
+        let lo = Interface::new("lo").unwrap();
+        assert!(GwRule::new(&lo, &lo).install().is_err());
+    }
+}
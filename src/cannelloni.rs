@@ -0,0 +1,488 @@
+use crate::{
+    shutdown::POLL_INTERVAL, Frame, Interest, Poller, ShutdownReport, ShutdownToken, Socket,
+    SocketError,
+};
+use embedded_can::{blocking::Can, Frame as _};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+/// Protocol version of the cannelloni data packets
+const CANNELLONI_VERSION: u8 = 2;
+
+/// Operation code of data packets
+const OP_DATA: u8 = 0;
+
+/// Length of the packet header: version, op code, sequence number and the
+/// 16 bit frame count
+const HEADER_LEN: usize = 5;
+
+/// Flag in the length byte marking a CAN FD frame
+const CANFD_FRAME: u8 = 0x80;
+
+/// Largest payload sent in a single datagram, to stay below the Ethernet MTU
+const MAX_PACKET_LEN: usize = 1472;
+
+/// Frame tunnel to a cannelloni peer over UDP
+///
+/// Exchanges frames with another machine running cannelloni, or a second
+/// `Cannelloni`, and implements the blocking `Can` trait, so the remote bus
+/// can be used like a local `Socket`:
+///
+/// ```no_run
+/// use candev::{Cannelloni, Frame};
+/// use embedded_can::blocking::Can;
+///
+/// let mut tunnel = Cannelloni::bind("0.0.0.0:20000", "192.168.1.10:20000").unwrap();
+/// tunnel.transmit(&Frame::new(0x123, &[1, 2, 3], false, false).unwrap()).unwrap();
+/// let frame = tunnel.receive().unwrap();
+/// ```
+///
+/// `bridge` connects the tunnel to a local bus, `bridge_until` does so until
+/// a `ShutdownToken` requests shutdown. To plug it into a `Gateway`, bridge
+/// it to one end of a `Socket::pair` and add the other end as port.
+///
+/// UDP silently drops and reorders datagrams. The sequence numbers of the
+/// received packets are tracked, `stats` reports the packets lost and
+/// reordered on the way from the peer.
+///
+/// Only classic frames are tunneled, received CAN FD frames are skipped.
+/// The SCTP transport of cannelloni is not supported, the peer has to use
+/// UDP (the default of cannelloni).
+#[derive(Debug)]
+pub struct Cannelloni {
+    socket: UdpSocket,
+    sequence: u8,
+    received: VecDeque<Frame>,
+    /// Sequence number expected from the peer next
+    expected: Option<u8>,
+    stats: TunnelStats,
+}
+
+/// Statistics of the packets received through a tunnel, derived from their
+/// sequence numbers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TunnelStats {
+    /// Data packets received
+    pub packets: u64,
+    /// Packets skipped in the sequence. Packets arriving late are counted
+    /// as reordered instead once they arrive.
+    pub lost: u64,
+    /// Packets arriving after a packet with a later sequence number, or
+    /// duplicates
+    pub reordered: u64,
+}
+
+impl Cannelloni {
+    /// Listen on `local` and exchange frames with the peer at `remote`
+    pub fn bind<A: ToSocketAddrs, B: ToSocketAddrs>(local: A, remote: B) -> io::Result<Cannelloni> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(remote)?;
+        Ok(Cannelloni {
+            socket,
+            sequence: 0,
+            received: VecDeque::new(),
+            expected: None,
+            stats: TunnelStats::default(),
+        })
+    }
+
+    /// Statistics of the packets received from the peer
+    pub fn stats(&self) -> TunnelStats {
+        self.stats
+    }
+
+    /// Sets the read timeout on the socket
+    pub fn set_read_timeout(&self, duration: Duration) -> io::Result<()> {
+        self.socket.set_read_timeout(Some(duration))
+    }
+
+    /// Transmit several frames, packing as many into each datagram as fit
+    pub fn transmit_batch(&mut self, frames: &[Frame]) -> Result<(), SocketError> {
+        let mut packet = Vec::with_capacity(MAX_PACKET_LEN);
+        let mut count = 0;
+        for frame in frames {
+            if packet.len() + HEADER_LEN + 13 > MAX_PACKET_LEN {
+                self.send_packet(&packet, count)?;
+                packet.clear();
+                count = 0;
+            }
+            encode_frame(frame, &mut packet);
+            count += 1;
+        }
+        if count > 0 {
+            self.send_packet(&packet, count)?;
+        }
+        Ok(())
+    }
+
+    /// Forward frames between the tunnel and `bus` in both directions.
+    ///
+    /// Runs until a socket fails and returns that error.
+    pub fn bridge(&mut self, bus: &mut Socket) -> SocketError {
+        match self.forward(bus, None) {
+            Ok(_) => unreachable!("bridging without shutdown token ended"),
+            Err(e) => e,
+        }
+    }
+
+    /// Forward frames between the tunnel and `bus` until `token` requests
+    /// shutdown, then forward the frames still queued in both directions
+    /// until none is left or the grace period ends. The frames forwarded
+    /// and dropped meanwhile are reported to the token as subsystem
+    /// `cannelloni`.
+    pub fn bridge_until(
+        &mut self,
+        bus: &mut Socket,
+        token: &ShutdownToken,
+    ) -> Result<(), SocketError> {
+        let flushed = self.forward(bus, Some(token))?;
+        let dropped = self.received.len() as u64;
+        self.received.clear();
+        token.report(ShutdownReport {
+            subsystem: "cannelloni".to_string(),
+            flushed,
+            dropped,
+        });
+        Ok(())
+    }
+
+    /// Forward frames until an error occurs or, with a token, until the
+    /// queues are empty after shutdown was requested. Returns the number of
+    /// frames forwarded after the request.
+    fn forward(
+        &mut self,
+        bus: &mut Socket,
+        token: Option<&ShutdownToken>,
+    ) -> Result<u64, SocketError> {
+        let mut poller = Poller::new()?;
+        poller.add(bus, 0, Interest::Readable)?;
+        poller.add(&self.socket, 1, Interest::Readable)?;
+        self.socket.set_nonblocking(true)?;
+        let rv = self.forward_ready(bus, &mut poller, token);
+        self.socket.set_nonblocking(false).ok();
+        rv
+    }
+
+    fn forward_ready(
+        &mut self,
+        bus: &mut Socket,
+        poller: &mut Poller,
+        token: Option<&ShutdownToken>,
+    ) -> Result<u64, SocketError> {
+        let mut flushed = 0;
+        loop {
+            let (timeout, requested) = match token {
+                Some(token) if token.is_expired() => return Ok(flushed),
+                Some(token) if token.is_requested() => (Some(Duration::ZERO), true),
+                Some(_) => (Some(POLL_INTERVAL), false),
+                None => (None, false),
+            };
+            let ready = poller.wait(timeout)?;
+            if requested && ready.is_empty() {
+                return Ok(flushed);
+            }
+
+            let mut forwarded = 0;
+            for ready in ready {
+                if ready.key == 0 {
+                    let mut frames = Vec::new();
+                    while let Some(frame) = bus.try_receive()? {
+                        frames.push(frame);
+                    }
+                    forwarded += frames.len() as u64;
+                    self.transmit_batch(&frames)?;
+                    continue;
+                }
+                loop {
+                    match self.receive_packet() {
+                        Ok(()) => {}
+                        Err(SocketError::WouldBlock) => break,
+                        Err(e) => return Err(e),
+                    }
+                    while let Some(frame) = self.received.pop_front() {
+                        bus.transmit(&frame)?;
+                        forwarded += 1;
+                    }
+                }
+            }
+            if requested {
+                flushed += forwarded;
+            }
+        }
+    }
+
+    fn send_packet(&mut self, frames: &[u8], count: u16) -> Result<(), SocketError> {
+        let mut packet = Vec::with_capacity(HEADER_LEN + frames.len());
+        packet.extend_from_slice(&[CANNELLONI_VERSION, OP_DATA, self.sequence]);
+        packet.extend_from_slice(&count.to_be_bytes());
+        packet.extend_from_slice(frames);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.socket.send(&packet)?;
+        Ok(())
+    }
+
+    /// Receive a datagram and queue the frames it contains
+    fn receive_packet(&mut self) -> Result<(), SocketError> {
+        let mut buf = [0u8; 65535];
+        let len = self.socket.recv(&mut buf).map_err(|e| {
+            match SocketError::from(e) {
+                // read timeout on the blocking socket
+                SocketError::WouldBlock if !self.is_nonblocking() => SocketError::Timeout,
+                e => e,
+            }
+        })?;
+        if let Some(sequence) = decode_packet(&buf[..len], &mut self.received) {
+            self.track(sequence);
+        }
+        Ok(())
+    }
+
+    /// Count the packets lost or reordered before the packet `sequence`
+    fn track(&mut self, sequence: u8) {
+        self.stats.packets += 1;
+        let expected = match self.expected {
+            Some(expected) => expected,
+            None => sequence,
+        };
+        // sequence numbers wrap, packets up to half the range ahead are new
+        let gap = sequence.wrapping_sub(expected);
+        if gap < 0x80 {
+            self.stats.lost += gap as u64;
+            self.expected = Some(sequence.wrapping_add(1));
+        } else {
+            self.stats.reordered += 1;
+            self.stats.lost = self.stats.lost.saturating_sub(1);
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        let flags = unsafe { libc::fcntl(self.socket.as_raw_fd(), libc::F_GETFL) };
+        flags != -1 && flags & libc::O_NONBLOCK != 0
+    }
+}
+
+impl Can for Cannelloni {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        self.transmit_batch(std::slice::from_ref(frame))
+    }
+
+    fn receive(&mut self) -> Result<Frame, SocketError> {
+        loop {
+            if let Some(frame) = self.received.pop_front() {
+                return Ok(frame);
+            }
+            self.receive_packet()?;
+        }
+    }
+}
+
+impl AsRawFd for Cannelloni {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// Append a frame: the CAN_ID with flags, the length and the data, which is
+/// omitted for remote frames
+fn encode_frame(frame: &Frame, packet: &mut Vec<u8>) {
+    packet.extend_from_slice(&frame.can_id().to_be_bytes());
+    packet.push(frame.dlc() as u8);
+    if !frame.is_remote_frame() {
+        packet.extend_from_slice(frame.data());
+    }
+}
+
+/// Decode the classic frames of a data packet, returning its sequence
+/// number. Malformed packets are decoded up to the first invalid frame.
+fn decode_packet(packet: &[u8], frames: &mut VecDeque<Frame>) -> Option<u8> {
+    if packet.len() < HEADER_LEN {
+        return None;
+    }
+    let (header, mut data) = packet.split_at(HEADER_LEN);
+    if header[0] != CANNELLONI_VERSION || header[1] != OP_DATA {
+        return None;
+    }
+    let sequence = Some(header[2]);
+
+    for _ in 0..u16::from_be_bytes([header[3], header[4]]) {
+        let (can_id, len) = match data {
+            [a, b, c, d, len, ..] => (u32::from_be_bytes([*a, *b, *c, *d]), *len),
+            _ => return sequence,
+        };
+        data = &data[5..];
+
+        let fd = len & CANFD_FRAME != 0;
+        if fd {
+            // skip the CAN FD flags
+            data = match data.get(1..) {
+                Some(data) => data,
+                None => return sequence,
+            };
+        }
+        let len = (len & !CANFD_FRAME) as usize;
+        let remote = can_id & libc::CAN_RTR_FLAG != 0;
+        let payload_len = if remote { 0 } else { len };
+        let payload = match data.get(..payload_len) {
+            Some(payload) => payload,
+            None => return sequence,
+        };
+        data = &data[payload_len..];
+
+        if fd || len > 8 {
+            continue;
+        }
+        let frame = if remote {
+            Frame::builder().data(&[0; 8][..len]).rtr().build()
+        } else {
+            Frame::new(0, payload, false, false)
+        };
+        if let Ok(frame) = frame {
+            frames.push_back(frame.with_can_id(can_id));
+        }
+    }
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_packet, Cannelloni, TunnelStats};
+    use crate::{Frame, ShutdownToken, Socket};
+    use embedded_can::{blocking::Can, Frame as _};
+    use std::{collections::VecDeque, time::Duration};
+
+    fn tunnel_pair() -> (Cannelloni, Cannelloni) {
+        let a = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (addr_a, addr_b) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        drop((a, b));
+        (
+            Cannelloni::bind(addr_a, addr_b).unwrap(),
+            Cannelloni::bind(addr_b, addr_a).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let (mut a, mut b) = tunnel_pair();
+        b.set_read_timeout(Duration::from_secs(1)).unwrap();
+
+        let frames = [
+            Frame::new(0x123, &[1, 2, 3], false, false).unwrap(),
+            Frame::builder().id(0x42).extended().build().unwrap(),
+            Frame::new(0x7FF, &[0; 4], true, false).unwrap(),
+        ];
+        a.transmit_batch(&frames).unwrap();
+        a.transmit(&frames[0]).unwrap();
+        for frame in frames.iter().chain(&frames[..1]) {
+            let received = b.receive().unwrap();
+            assert_eq!(&received, frame);
+            assert_eq!(received.is_extended(), frame.is_extended());
+            assert_eq!(received.is_remote_frame(), frame.is_remote_frame());
+        }
+    }
+
+    #[test]
+    fn test_skip_fd_frames() {
+        let packet = [
+            2,
+            0,
+            7,
+            0,
+            2, // header with 2 frames
+            0,
+            0,
+            0x01,
+            0x23,
+            0x80 | 12,
+            0x01,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0, // CAN FD
+            0,
+            0,
+            0x01,
+            0x24,
+            1,
+            0xAA, // classic
+        ];
+        let mut frames = VecDeque::new();
+        assert_eq!(decode_packet(&packet, &mut frames), Some(7));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].raw_id(), 0x124);
+        assert_eq!(frames[0].data(), &[0xAA]);
+    }
+
+    #[test]
+    fn test_sequence_tracking() {
+        let (mut a, mut b) = tunnel_pair();
+        b.set_read_timeout(Duration::from_secs(1)).unwrap();
+
+        // 254 is lost and 0 arrives after 1, wrapping around is no loss
+        for sequence in [252, 253, 255, 1, 0, 2] {
+            a.sequence = sequence;
+            a.send_packet(&[], 0).unwrap();
+        }
+        for _ in 0..6 {
+            b.receive_packet().unwrap();
+        }
+        assert_eq!(
+            b.stats(),
+            TunnelStats {
+                packets: 6,
+                lost: 1,
+                reordered: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_bridge() {
+        let (mut tunnel, mut remote) = tunnel_pair();
+        remote.set_read_timeout(Duration::from_secs(1)).unwrap();
+        let (mut bus, mut app) = Socket::pair().unwrap();
+        std::thread::spawn(move || tunnel.bridge(&mut bus));
+
+        let frame = Frame::new(0x123, &[1, 2], false, false).unwrap();
+        app.transmit(&frame).unwrap();
+        assert_eq!(remote.receive().unwrap(), frame);
+
+        let frame = Frame::new(0x321, &[3], false, false).unwrap();
+        remote.transmit(&frame).unwrap();
+        app.set_read_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(app.receive().unwrap(), frame);
+    }
+
+    #[test]
+    fn test_bridge_until_shutdown() {
+        let (mut tunnel, mut remote) = tunnel_pair();
+        remote.set_read_timeout(Duration::from_secs(1)).unwrap();
+        let (mut bus, mut app) = Socket::pair().unwrap();
+
+        // queued before the request, forwarded during the grace period
+        let frame = Frame::new(0x123, &[1, 2], false, false).unwrap();
+        app.transmit(&frame).unwrap();
+        let token = ShutdownToken::new();
+        token.shutdown(Duration::from_secs(1));
+        tunnel.bridge_until(&mut bus, &token).unwrap();
+        assert_eq!(remote.receive().unwrap(), frame);
+        assert_eq!(token.reports()[0].subsystem, "cannelloni");
+        assert_eq!(token.reports()[0].flushed, 1);
+    }
+}
@@ -0,0 +1,1041 @@
+use crate::{Clock, Frame, ShutdownToken, Socket, SocketError, SystemClock};
+use embedded_can::blocking::Can;
+use std::{
+    collections::HashMap,
+    error, fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// COB-ID of the SYNC message
+const COB_SYNC: u32 = 0x080;
+/// Base COB-ID of emergency messages
+const COB_EMCY: u32 = 0x080;
+/// Base COB-ID of heartbeat messages
+const COB_HEARTBEAT: u32 = 0x700;
+/// Base COB-ID of SDO responses, server to client
+const COB_SDO_TX: u32 = 0x580;
+/// Base COB-ID of SDO requests, client to server
+const COB_SDO_RX: u32 = 0x600;
+
+/// Time a server has to answer an SDO request
+const SDO_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// SDO abort: toggle bit not alternated
+const ABORT_TOGGLE: u32 = 0x0503_0000;
+/// SDO abort: protocol timed out
+const ABORT_TIMEOUT: u32 = 0x0504_0000;
+/// SDO abort: command specifier not valid or unknown
+const ABORT_COMMAND: u32 = 0x0504_0001;
+/// SDO abort: object does not exist in the object dictionary
+const ABORT_NO_OBJECT: u32 = 0x0602_0000;
+/// SDO abort: length of service parameter does not match
+const ABORT_LENGTH: u32 = 0x0607_0010;
+
+/// NMT state reported in heartbeat messages
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NmtState {
+    /// Boot-up, sent once after initialization
+    BootUp,
+    /// Stopped
+    Stopped,
+    /// Operational
+    Operational,
+    /// Pre-operational
+    PreOperational,
+}
+
+impl NmtState {
+    fn to_u8(self) -> u8 {
+        match self {
+            NmtState::BootUp => 0x00,
+            NmtState::Stopped => 0x04,
+            NmtState::Operational => 0x05,
+            NmtState::PreOperational => 0x7F,
+        }
+    }
+
+    fn from_u8(val: u8) -> Option<NmtState> {
+        Some(match val {
+            0x00 => NmtState::BootUp,
+            0x04 => NmtState::Stopped,
+            0x05 => NmtState::Operational,
+            0x7F => NmtState::PreOperational,
+            _ => return None,
+        })
+    }
+}
+
+/// Heartbeat message (COB-ID 0x700 + node id)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// Node id (1..=127)
+    pub node_id: u8,
+    /// State of the node
+    pub state: NmtState,
+}
+
+impl Heartbeat {
+    /// Encode into a frame
+    pub fn to_frame(&self) -> Frame {
+        let id = COB_HEARTBEAT + (self.node_id & 0x7F) as u32;
+        Frame::new(id, &[self.state.to_u8()], false, false).unwrap()
+    }
+
+    /// Decode from a frame, `None` if it is not a heartbeat message
+    pub fn from_frame(frame: &Frame) -> Option<Heartbeat> {
+        let id = match embedded_can::Frame::id(frame) {
+            embedded_can::Id::Standard(id) => id.as_raw() as u32,
+            embedded_can::Id::Extended(_) => return None,
+        };
+        if !(COB_HEARTBEAT + 1..COB_HEARTBEAT + 0x80).contains(&id) || frame.data().len() != 1 {
+            return None;
+        }
+        Some(Heartbeat {
+            node_id: (id - COB_HEARTBEAT) as u8,
+            state: NmtState::from_u8(frame.data()[0] & 0x7F)?,
+        })
+    }
+}
+
+/// SYNC message (COB-ID 0x80)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SyncMessage {
+    /// Optional counter value
+    pub counter: Option<u8>,
+}
+
+impl SyncMessage {
+    /// Encode into a frame
+    pub fn to_frame(&self) -> Frame {
+        match self.counter {
+            Some(counter) => Frame::new(COB_SYNC, &[counter], false, false),
+            None => Frame::new(COB_SYNC, &[], false, false),
+        }
+        .unwrap()
+    }
+}
+
+/// Emergency message (COB-ID 0x80 + node id)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Emergency {
+    /// Node id of the sender (1..=127)
+    pub node_id: u8,
+    /// Emergency error code
+    pub error_code: u16,
+    /// Error register (object 0x1001) of the sender
+    pub error_register: u8,
+    /// Manufacturer specific error information
+    pub manufacturer: [u8; 5],
+}
+
+impl Emergency {
+    /// Encode into a frame
+    pub fn to_frame(&self) -> Frame {
+        let mut data = [0; 8];
+        data[..2].copy_from_slice(&self.error_code.to_le_bytes());
+        data[2] = self.error_register;
+        data[3..].copy_from_slice(&self.manufacturer);
+        Frame::new(COB_EMCY + (self.node_id & 0x7F) as u32, &data, false, false).unwrap()
+    }
+
+    /// Decode from a frame, `None` if it is not an emergency message
+    pub fn from_frame(frame: &Frame) -> Option<Emergency> {
+        let id = match embedded_can::Frame::id(frame) {
+            embedded_can::Id::Standard(id) => id.as_raw() as u32,
+            embedded_can::Id::Extended(_) => return None,
+        };
+        if !(COB_EMCY + 1..COB_EMCY + 0x80).contains(&id) || frame.data().len() != 8 {
+            return None;
+        }
+        let data = frame.data();
+        let mut manufacturer = [0; 5];
+        manufacturer.copy_from_slice(&data[3..]);
+        Some(Emergency {
+            node_id: (id - COB_EMCY) as u8,
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: data[2],
+            manufacturer,
+        })
+    }
+
+    /// Check whether the message signals that all errors were reset
+    pub fn is_reset(&self) -> bool {
+        self.error_code == 0
+    }
+
+    /// Description of the error code as defined by CiA 301.
+    ///
+    /// Codes without a dedicated description are described by their error
+    /// class, e.g. `0x2310` as "current, device output side".
+    pub fn description(&self) -> &'static str {
+        let code = self.error_code;
+        [code, code & 0xFFF0, code & 0xFF00, code & 0xF000]
+            .iter()
+            .find_map(|&c| describe_error_code(c))
+            .unwrap_or("unknown")
+    }
+}
+
+/// Standard emergency error codes of CiA 301
+fn describe_error_code(code: u16) -> Option<&'static str> {
+    Some(match code {
+        0x0000 => "error reset or no error",
+        0x1000 => "generic error",
+        0x2000 => "current",
+        0x2100 => "current, device input side",
+        0x2200 => "current inside the device",
+        0x2300 => "current, device output side",
+        0x3000 => "voltage",
+        0x3100 => "mains voltage",
+        0x3200 => "voltage inside the device",
+        0x3300 => "output voltage",
+        0x4000 => "temperature",
+        0x4100 => "ambient temperature",
+        0x4200 => "device temperature",
+        0x5000 => "device hardware",
+        0x6000 => "device software",
+        0x6100 => "internal software",
+        0x6200 => "user software",
+        0x6300 => "data set",
+        0x7000 => "additional modules",
+        0x8000 => "monitoring",
+        0x8100 => "communication",
+        0x8110 => "CAN overrun (objects lost)",
+        0x8120 => "CAN in error passive mode",
+        0x8130 => "life guard error or heartbeat error",
+        0x8140 => "recovered from bus off",
+        0x8150 => "CAN-ID collision",
+        0x8200 => "protocol error",
+        0x8210 => "PDO not processed due to length error",
+        0x8220 => "PDO length exceeded",
+        0x8230 => "DAM MPDO not processed, destination object not available",
+        0x8240 => "unexpected SYNC data length",
+        0x8250 => "RPDO timeout",
+        0x9000 => "external error",
+        0xF000 => "additional functions",
+        0xFF00 => "device specific",
+        _ => return None,
+    })
+}
+
+/// Errors of SDO transfers
+#[derive(Debug)]
+pub enum SdoError {
+    /// The socket failed
+    Socket(SocketError),
+
+    /// The server did not answer in time
+    Timeout,
+
+    /// The transfer was aborted with the contained abort code
+    Abort(u32),
+
+    /// The response does not match the request
+    InvalidResponse,
+}
+
+impl From<SocketError> for SdoError {
+    fn from(e: SocketError) -> SdoError {
+        SdoError::Socket(e)
+    }
+}
+
+impl fmt::Display for SdoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SdoError::Socket(e) => write!(f, "SDO transfer failed: {}", e),
+            SdoError::Abort(code) => match abort_description(*code) {
+                Some(description) => write!(f, "SDO abort 0x{:08X}: {}", code, description),
+                None => write!(f, "SDO abort 0x{:08X}", code),
+            },
+            SdoError::InvalidResponse => write!(f, "invalid SDO response"),
+            SdoError::Timeout => write!(f, "SDO server did not respond in time"),
+        }
+    }
+}
+
+impl error::Error for SdoError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SdoError::Socket(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Description of common SDO abort codes of CiA 301
+fn abort_description(code: u32) -> Option<&'static str> {
+    Some(match code {
+        ABORT_TOGGLE => "toggle bit not alternated",
+        ABORT_TIMEOUT => "SDO protocol timed out",
+        ABORT_COMMAND => "command specifier not valid or unknown",
+        0x0601_0000 => "unsupported access to an object",
+        0x0601_0001 => "attempt to read a write only object",
+        0x0601_0002 => "attempt to write a read only object",
+        ABORT_NO_OBJECT => "object does not exist in the object dictionary",
+        ABORT_LENGTH => "length of service parameter does not match",
+        0x0609_0011 => "sub-index does not exist",
+        0x0609_0030 => "invalid value for parameter",
+        0x0800_0000 => "general error",
+        0x0800_0020 => "data cannot be transferred or stored to the application",
+        _ => return None,
+    })
+}
+
+/// SDO client
+///
+/// Reads (upload) and writes (download) objects of the object dictionary
+/// of a node, using expedited transfers for up to 4 bytes and segmented
+/// transfers otherwise.
+///
+/// ```no_run
+/// use candev::{SdoClient, Socket};
+///
+/// let mut sdo = SdoClient::new(Socket::new("can0").unwrap(), 5);
+/// let device_type = sdo.upload(0x1000, 0).unwrap();
+/// sdo.download(0x1017, 0, &1000u16.to_le_bytes()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SdoClient<C = SystemClock> {
+    socket: Socket,
+    node_id: u8,
+    timeout: Duration,
+    clock: C,
+}
+
+impl SdoClient {
+    /// Transfer objects of `node_id` (1..=127) using `socket`
+    pub fn new(socket: Socket, node_id: u8) -> SdoClient {
+        SdoClient {
+            socket,
+            node_id: node_id & 0x7F,
+            timeout: SDO_TIMEOUT,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> SdoClient<C> {
+    /// Use `clock` to measure the response timeout
+    pub fn with_clock<D: Clock>(self, clock: D) -> SdoClient<D> {
+        SdoClient {
+            socket: self.socket,
+            node_id: self.node_id,
+            timeout: self.timeout,
+            clock,
+        }
+    }
+
+    /// Set the time the server has to answer each request, 1 s by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Read the object at `index` and `subindex`
+    pub fn upload(&mut self, index: u16, subindex: u8) -> Result<Vec<u8>, SdoError> {
+        let mux = multiplexer(index, subindex);
+        let response = self.request([0x40, mux[0], mux[1], mux[2], 0, 0, 0, 0])?;
+        if response[0] >> 5 != 2 || response[1..4] != mux {
+            return Err(SdoError::InvalidResponse);
+        }
+
+        let size_indicated = response[0] & 0x01 != 0;
+        if response[0] & 0x02 != 0 {
+            // expedited
+            let len = if size_indicated {
+                4 - (response[0] >> 2 & 0x3) as usize
+            } else {
+                4
+            };
+            return Ok(response[4..4 + len].to_vec());
+        }
+
+        let size = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+        let mut data = Vec::new();
+        let mut toggle = 0;
+        loop {
+            let response = self.request([0x60 | toggle << 4, 0, 0, 0, 0, 0, 0, 0])?;
+            if response[0] >> 5 != 0 || response[0] >> 4 & 1 != toggle {
+                return Err(SdoError::InvalidResponse);
+            }
+            let unused = (response[0] >> 1 & 0x7) as usize;
+            data.extend_from_slice(&response[1..8 - unused]);
+            if response[0] & 0x01 != 0 {
+                break;
+            }
+            toggle ^= 1;
+        }
+        if size_indicated && data.len() != size as usize {
+            return Err(SdoError::InvalidResponse);
+        }
+        Ok(data)
+    }
+
+    /// Write `data` to the object at `index` and `subindex`
+    pub fn download(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoError> {
+        let mux = multiplexer(index, subindex);
+        let mut request = [0; 8];
+        request[1..4].copy_from_slice(&mux);
+
+        if (1..=4).contains(&data.len()) {
+            // expedited, size indicated
+            request[0] = 0x23 | ((4 - data.len()) as u8) << 2;
+            request[4..4 + data.len()].copy_from_slice(data);
+            let response = self.request(request)?;
+            if response[0] != 0x60 || response[1..4] != mux {
+                return Err(SdoError::InvalidResponse);
+            }
+            return Ok(());
+        }
+
+        request[0] = 0x21;
+        request[4..].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        let response = self.request(request)?;
+        if response[0] != 0x60 || response[1..4] != mux {
+            return Err(SdoError::InvalidResponse);
+        }
+
+        let segments = data.chunks(7).count().max(1);
+        let mut toggle = 0;
+        for n in 0..segments {
+            let segment = &data[n * 7..data.len().min(n * 7 + 7)];
+            let last = (n + 1 == segments) as u8;
+            let mut request = [0; 8];
+            request[0] = toggle << 4 | ((7 - segment.len()) as u8) << 1 | last;
+            request[1..1 + segment.len()].copy_from_slice(segment);
+            let response = self.request(request)?;
+            if response[0] != 0x20 | toggle << 4 {
+                return Err(SdoError::InvalidResponse);
+            }
+            toggle ^= 1;
+        }
+        Ok(())
+    }
+
+    /// Access the socket
+    pub fn get_ref(&self) -> &Socket {
+        &self.socket
+    }
+
+    /// Return the socket
+    pub fn into_inner(self) -> Socket {
+        self.socket
+    }
+
+    /// Send a request and wait for the response of the server, failing with
+    /// `SdoError::Abort` if the server aborts the transfer. The read timeout
+    /// of the socket is restored afterwards.
+    fn request(&mut self, request: [u8; 8]) -> Result<[u8; 8], SdoError> {
+        let previous = self.socket.read_timeout().map_err(SocketError::from)?;
+        let response = self.exchange(request);
+        self.socket
+            .set_read_timeout(previous.unwrap_or(Duration::ZERO))
+            .map_err(SocketError::from)?;
+        response
+    }
+
+    fn exchange(&mut self, request: [u8; 8]) -> Result<[u8; 8], SdoError> {
+        let request_id = COB_SDO_RX + self.node_id as u32;
+        let response_id = COB_SDO_TX + self.node_id as u32;
+        self.socket
+            .transmit(&Frame::new(request_id, &request, false, false).unwrap())?;
+
+        let deadline = self.clock.now() + self.timeout;
+        loop {
+            // a zero read timeout would block indefinitely
+            let remaining = deadline
+                .checked_duration_since(self.clock.now())
+                .filter(|remaining| *remaining >= Duration::from_millis(1));
+            let remaining = match remaining {
+                Some(remaining) => remaining,
+                None => {
+                    // tell the server to give up as well
+                    let abort = abort_frame(
+                        request_id,
+                        [request[1], request[2], request[3]],
+                        ABORT_TIMEOUT,
+                    );
+                    self.socket.transmit(&abort).ok();
+                    return Err(SdoError::Timeout);
+                }
+            };
+            self.socket
+                .set_read_timeout(remaining)
+                .map_err(SocketError::from)?;
+            let frame = match self.socket.receive() {
+                Err(SocketError::Timeout) => continue,
+                frame => frame?,
+            };
+            if frame.raw_id() != response_id || frame.data().len() != 8 {
+                continue;
+            }
+
+            let mut response = [0; 8];
+            response.copy_from_slice(frame.data());
+            if response[0] == 0x80 {
+                let code = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+                return Err(SdoError::Abort(code));
+            }
+            return Ok(response);
+        }
+    }
+}
+
+/// SDO server
+///
+/// Serves uploads and downloads of the objects inserted into it, like the
+/// object dictionary of a device. Every received frame should be passed to
+/// `handle`, which returns the response to transmit, if any. Downloads are
+/// only accepted for existing objects.
+#[derive(Debug, Clone)]
+pub struct SdoServer {
+    node_id: u8,
+    objects: HashMap<(u16, u8), Vec<u8>>,
+    transfer: Option<SdoTransfer>,
+}
+
+/// Segmented transfer in progress on a server
+#[derive(Debug, Clone)]
+struct SdoTransfer {
+    mux: [u8; 3],
+    upload: bool,
+    size: Option<usize>,
+    data: Vec<u8>,
+    offset: usize,
+    toggle: u8,
+}
+
+impl SdoServer {
+    /// Serve the objects of `node_id` (1..=127)
+    pub fn new(node_id: u8) -> SdoServer {
+        SdoServer {
+            node_id: node_id & 0x7F,
+            objects: HashMap::new(),
+            transfer: None,
+        }
+    }
+
+    /// Insert or replace the object at `index` and `subindex`
+    pub fn insert(&mut self, index: u16, subindex: u8, data: &[u8]) {
+        self.objects.insert((index, subindex), data.to_vec());
+    }
+
+    /// Current value of the object at `index` and `subindex`
+    pub fn get(&self, index: u16, subindex: u8) -> Option<&[u8]> {
+        self.objects.get(&(index, subindex)).map(Vec::as_slice)
+    }
+
+    /// Process a received frame, returning the response to transmit
+    pub fn handle(&mut self, frame: &Frame) -> Option<Frame> {
+        if frame.raw_id() != COB_SDO_RX + self.node_id as u32 || frame.data().len() != 8 {
+            return None;
+        }
+        let request = frame.data();
+        let mux = [request[1], request[2], request[3]];
+        let key = (u16::from_le_bytes([mux[0], mux[1]]), mux[2]);
+
+        let mut response = [0; 8];
+        match request[0] >> 5 {
+            // initiate download
+            1 => {
+                if !self.objects.contains_key(&key) {
+                    return Some(self.abort(mux, ABORT_NO_OBJECT));
+                }
+                let size_indicated = request[0] & 0x01 != 0;
+                if request[0] & 0x02 != 0 {
+                    let len = if size_indicated {
+                        4 - (request[0] >> 2 & 0x3) as usize
+                    } else {
+                        4
+                    };
+                    self.objects.insert(key, request[4..4 + len].to_vec());
+                } else {
+                    let size = u32::from_le_bytes([request[4], request[5], request[6], request[7]]);
+                    self.transfer = Some(SdoTransfer {
+                        mux,
+                        upload: false,
+                        size: size_indicated.then_some(size as usize),
+                        data: Vec::new(),
+                        offset: 0,
+                        toggle: 0,
+                    });
+                }
+                response[0] = 0x60;
+                response[1..4].copy_from_slice(&mux);
+            }
+            // download segment
+            0 => {
+                let transfer = match self.transfer.as_mut() {
+                    Some(transfer) if !transfer.upload => transfer,
+                    _ => return Some(self.abort(mux, ABORT_COMMAND)),
+                };
+                let toggle = request[0] >> 4 & 1;
+                if toggle != transfer.toggle {
+                    let mux = transfer.mux;
+                    return Some(self.abort(mux, ABORT_TOGGLE));
+                }
+                let unused = (request[0] >> 1 & 0x7) as usize;
+                transfer.data.extend_from_slice(&request[1..8 - unused]);
+                transfer.toggle ^= 1;
+
+                if request[0] & 0x01 != 0 {
+                    let transfer = self.transfer.take().unwrap();
+                    if transfer
+                        .size
+                        .map_or(false, |size| size != transfer.data.len())
+                    {
+                        return Some(self.abort(transfer.mux, ABORT_LENGTH));
+                    }
+                    let key = (
+                        u16::from_le_bytes([transfer.mux[0], transfer.mux[1]]),
+                        transfer.mux[2],
+                    );
+                    self.objects.insert(key, transfer.data);
+                }
+                response[0] = 0x20 | toggle << 4;
+            }
+            // initiate upload
+            2 => {
+                let data = match self.objects.get(&key) {
+                    Some(data) => data,
+                    None => return Some(self.abort(mux, ABORT_NO_OBJECT)),
+                };
+                response[1..4].copy_from_slice(&mux);
+                if (1..=4).contains(&data.len()) {
+                    response[0] = 0x43 | ((4 - data.len()) as u8) << 2;
+                    response[4..4 + data.len()].copy_from_slice(data);
+                } else {
+                    response[0] = 0x41;
+                    response[4..].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                    self.transfer = Some(SdoTransfer {
+                        mux,
+                        upload: true,
+                        size: Some(data.len()),
+                        data: data.clone(),
+                        offset: 0,
+                        toggle: 0,
+                    });
+                }
+            }
+            // upload segment
+            3 => {
+                let transfer = match self.transfer.as_mut() {
+                    Some(transfer) if transfer.upload => transfer,
+                    _ => return Some(self.abort(mux, ABORT_COMMAND)),
+                };
+                let toggle = request[0] >> 4 & 1;
+                if toggle != transfer.toggle {
+                    let mux = transfer.mux;
+                    return Some(self.abort(mux, ABORT_TOGGLE));
+                }
+                let segment =
+                    &transfer.data[transfer.offset..transfer.data.len().min(transfer.offset + 7)];
+                transfer.offset += segment.len();
+                transfer.toggle ^= 1;
+                let last = transfer.offset == transfer.data.len();
+                response[0] = toggle << 4 | ((7 - segment.len()) as u8) << 1 | last as u8;
+                response[1..1 + segment.len()].copy_from_slice(segment);
+                if last {
+                    self.transfer = None;
+                }
+            }
+            // abort
+            4 => {
+                self.transfer = None;
+                return None;
+            }
+            _ => return Some(self.abort(mux, ABORT_COMMAND)),
+        }
+        Some(Frame::new(COB_SDO_TX + self.node_id as u32, &response, false, false).unwrap())
+    }
+
+    fn abort(&mut self, mux: [u8; 3], code: u32) -> Frame {
+        self.transfer = None;
+        abort_frame(COB_SDO_TX + self.node_id as u32, mux, code)
+    }
+}
+
+/// Index and subindex as transmitted in SDO messages
+fn multiplexer(index: u16, subindex: u8) -> [u8; 3] {
+    let index = index.to_le_bytes();
+    [index[0], index[1], subindex]
+}
+
+fn abort_frame(id: u32, mux: [u8; 3], code: u32) -> Frame {
+    let mut data = [0x80, mux[0], mux[1], mux[2], 0, 0, 0, 0];
+    data[4..].copy_from_slice(&code.to_le_bytes());
+    Frame::new(id, &data, false, false).unwrap()
+}
+
+/// Periodically transmits heartbeat messages of a node.
+///
+/// Messages are sent from a background thread which is stopped when the
+/// producer is dropped or `stop` is called.
+pub struct HeartbeatProducer<T> {
+    state: Arc<AtomicU8>,
+    producer: Producer<T>,
+}
+
+impl<T> HeartbeatProducer<T>
+where
+    T: embedded_can::blocking::Can<Frame = Frame> + Send + 'static,
+{
+    /// Start producing heartbeats for `node_id` every `period` on `can`,
+    /// beginning immediately.
+    pub fn start(can: T, node_id: u8, period: Duration, state: NmtState) -> HeartbeatProducer<T> {
+        HeartbeatProducer::start_with_clock(can, node_id, period, state, SystemClock)
+    }
+
+    /// Like `start`, taking the time from `clock`
+    pub fn start_with_clock<C: Clock + Send + 'static>(
+        can: T,
+        node_id: u8,
+        period: Duration,
+        state: NmtState,
+        clock: C,
+    ) -> HeartbeatProducer<T> {
+        let state = Arc::new(AtomicU8::new(state.to_u8()));
+        let current = state.clone();
+        let producer = Producer::start(can, period, clock, move || {
+            let state = NmtState::from_u8(current.load(Ordering::Relaxed)).unwrap();
+            Heartbeat { node_id, state }.to_frame()
+        });
+        HeartbeatProducer { state, producer }
+    }
+
+    /// Change the state reported by the following heartbeats
+    pub fn set_state(&self, state: NmtState) {
+        self.state.store(state.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Number of heartbeats that could not be transmitted
+    pub fn failed(&self) -> usize {
+        self.producer.failed()
+    }
+
+    /// Stop producing once `token` requests shutdown
+    pub fn stop_on(&self, token: &ShutdownToken) {
+        self.producer.stop_on(token);
+    }
+
+    /// Stop producing and return the CAN interface
+    pub fn stop(self) -> T {
+        self.producer.stop()
+    }
+}
+
+/// Periodically transmits SYNC messages.
+///
+/// Messages are sent from a background thread which is stopped when the
+/// producer is dropped or `stop` is called.
+pub struct SyncProducer<T> {
+    producer: Producer<T>,
+}
+
+impl<T> SyncProducer<T>
+where
+    T: embedded_can::blocking::Can<Frame = Frame> + Send + 'static,
+{
+    /// Start producing SYNC messages every `period` on `can`.
+    ///
+    /// If `overflow` is set (2..=240), every message carries a counter
+    /// running from 1 to `overflow`.
+    pub fn start(can: T, period: Duration, overflow: Option<u8>) -> SyncProducer<T> {
+        SyncProducer::start_with_clock(can, period, overflow, SystemClock)
+    }
+
+    /// Like `start`, taking the time from `clock`
+    pub fn start_with_clock<C: Clock + Send + 'static>(
+        can: T,
+        period: Duration,
+        overflow: Option<u8>,
+        clock: C,
+    ) -> SyncProducer<T> {
+        let mut counter = 0;
+        let producer = Producer::start(can, period, clock, move || match overflow {
+            Some(overflow) => {
+                counter = counter % overflow.clamp(2, 240) + 1;
+                SyncMessage {
+                    counter: Some(counter),
+                }
+                .to_frame()
+            }
+            None => SyncMessage { counter: None }.to_frame(),
+        });
+        SyncProducer { producer }
+    }
+
+    /// Number of SYNC messages that could not be transmitted
+    pub fn failed(&self) -> usize {
+        self.producer.failed()
+    }
+
+    /// Stop producing once `token` requests shutdown
+    pub fn stop_on(&self, token: &ShutdownToken) {
+        self.producer.stop_on(token);
+    }
+
+    /// Stop producing and return the CAN interface
+    pub fn stop(self) -> T {
+        self.producer.stop()
+    }
+}
+
+/// Background thread transmitting a frame every period
+struct Producer<T> {
+    stop: Arc<AtomicBool>,
+    failed: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<T>>,
+}
+
+impl<T> Producer<T>
+where
+    T: embedded_can::blocking::Can<Frame = Frame> + Send + 'static,
+{
+    fn start<C, F>(mut can: T, period: Duration, clock: C, mut next_frame: F) -> Producer<T>
+    where
+        C: Clock + Send + 'static,
+        F: FnMut() -> Frame + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let failed = Arc::new(AtomicUsize::new(0));
+        let (stopped, failures) = (stop.clone(), failed.clone());
+
+        let thread = thread::spawn(move || {
+            let mut ticker = Ticker::new(clock, period);
+            while !stopped.load(Ordering::Relaxed) {
+                if can.transmit(&next_frame()).is_err() {
+                    failures.fetch_add(1, Ordering::Relaxed);
+                }
+                ticker.wait();
+            }
+            can
+        });
+
+        Producer {
+            stop,
+            failed,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T> Producer<T> {
+    fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    fn stop_on(&self, token: &ShutdownToken) {
+        let stop = self.stop.clone();
+        token.on_shutdown(move || stop.store(true, Ordering::Relaxed));
+    }
+
+    fn stop(mut self) -> T {
+        self.join().unwrap()
+    }
+
+    fn join(&mut self) -> Option<T> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.take().map(|t| t.join().unwrap())
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Sleeps until deadlines derived from the start time, so that periods
+/// do not drift with the time spent transmitting.
+struct Ticker<C> {
+    clock: C,
+    next: Instant,
+    period: Duration,
+}
+
+impl<C: Clock> Ticker<C> {
+    fn new(clock: C, period: Duration) -> Ticker<C> {
+        Ticker {
+            next: clock.now(),
+            clock,
+            period,
+        }
+    }
+
+    fn wait(&mut self) {
+        self.next += self.period;
+
+        // skip missed deadlines instead of sending a burst to catch up
+        let now = self.clock.now();
+        if now > self.next + self.period {
+            self.next = now + self.period;
+        }
+        if let Some(remaining) = self.next.checked_duration_since(now) {
+            self.clock.sleep(remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Emergency, Heartbeat, HeartbeatProducer, NmtState, SdoClient, SdoError, SdoServer,
+        SyncMessage, SyncProducer,
+    };
+    use crate::{ManualClock, ShutdownToken, Socket};
+    use embedded_can::blocking::Can;
+    use std::time::Duration;
+
+    #[test]
+    fn test_heartbeat() {
+        let heartbeat = Heartbeat {
+            node_id: 0x12,
+            state: NmtState::Operational,
+        };
+        let frame = heartbeat.to_frame();
+        assert_eq!(frame.data(), &[0x05]);
+        assert_eq!(Heartbeat::from_frame(&frame), Some(heartbeat));
+
+        let sync = SyncMessage { counter: None }.to_frame();
+        assert_eq!(Heartbeat::from_frame(&sync), None);
+    }
+
+    #[test]
+    fn test_emergency() {
+        let frame =
+            crate::Frame::new(0x85, &[0x30, 0x81, 0x11, 1, 2, 3, 4, 5], false, false).unwrap();
+        let emcy = Emergency::from_frame(&frame).unwrap();
+        assert_eq!(emcy.node_id, 5);
+        assert_eq!(emcy.error_code, 0x8130);
+        assert_eq!(emcy.error_register, 0x11);
+        assert_eq!(emcy.manufacturer, [1, 2, 3, 4, 5]);
+        assert_eq!(emcy.description(), "life guard error or heartbeat error");
+        assert_eq!(emcy.to_frame().data(), frame.data());
+
+        let emcy = Emergency {
+            error_code: 0x2310,
+            ..emcy
+        };
+        assert_eq!(emcy.description(), "current, device output side");
+
+        // SYNC shares the base COB-ID but is not an emergency message
+        assert_eq!(
+            Emergency::from_frame(&SyncMessage { counter: None }.to_frame()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sync() {
+        assert_eq!(SyncMessage { counter: None }.to_frame().data(), &[]);
+        assert_eq!(SyncMessage { counter: Some(3) }.to_frame().data(), &[3]);
+    }
+
+    #[test]
+    fn test_sync_producer() {
+        let (can, mut bus) = Socket::pair().unwrap();
+        // the producer does not wait with a manual clock, don't block once
+        // the socket buffer is full
+        can.set_write_timeout(Duration::from_millis(10)).unwrap();
+        let clock = ManualClock::new();
+        let producer =
+            SyncProducer::start_with_clock(can, Duration::from_millis(10), Some(3), clock.clone());
+
+        bus.set_read_timeout(Duration::from_secs(1)).unwrap();
+        for counter in [1, 2, 3, 1] {
+            assert_eq!(bus.receive().unwrap().data(), &[counter]);
+        }
+        producer.stop();
+        // waited for whole periods only
+        assert!(clock.elapsed() >= Duration::from_millis(30));
+        assert_eq!(clock.elapsed().as_millis() % 10, 0);
+    }
+
+    #[test]
+    fn test_producer_stop_on() {
+        let (can, mut bus) = Socket::pair().unwrap();
+        let period = Duration::from_millis(5);
+        let producer = HeartbeatProducer::start(can, 0x12, period, NmtState::Operational);
+        let token = ShutdownToken::new();
+        producer.stop_on(&token);
+
+        bus.set_read_timeout(Duration::from_secs(1)).unwrap();
+        bus.receive().unwrap();
+        token.shutdown(Duration::ZERO);
+        std::thread::sleep(4 * period);
+        while bus.try_receive().unwrap().is_some() {}
+        std::thread::sleep(4 * period);
+        assert!(bus.try_receive().unwrap().is_none());
+        producer.stop();
+    }
+
+    #[test]
+    fn test_sdo() {
+        let (client, mut bus) = Socket::pair().unwrap();
+        let mut server = SdoServer::new(5);
+        server.insert(0x1000, 0, &0x0004_0191u32.to_le_bytes());
+        server.insert(0x1008, 0, b"candev test device");
+        server.insert(0x1017, 0, &[0, 0]);
+        server.insert(0x2000, 1, &[]);
+
+        let serving = std::thread::spawn(move || {
+            bus.set_read_timeout(Duration::from_millis(500)).unwrap();
+            while let Ok(frame) = bus.receive() {
+                if let Some(response) = server.handle(&frame) {
+                    bus.transmit(&response).unwrap();
+                }
+            }
+            server
+        });
+
+        let mut sdo = SdoClient::new(client, 5);
+        // expedited and segmented uploads
+        assert_eq!(sdo.upload(0x1000, 0).unwrap(), [0x91, 0x01, 0x04, 0x00]);
+        assert_eq!(sdo.upload(0x1008, 0).unwrap(), b"candev test device");
+        // expedited and segmented downloads
+        sdo.download(0x1017, 0, &[0xE8, 0x03]).unwrap();
+        sdo.download(0x2000, 1, &[7; 15]).unwrap();
+        assert!(matches!(
+            sdo.upload(0x1234, 0),
+            Err(SdoError::Abort(0x0602_0000))
+        ));
+        drop(sdo);
+
+        let server = serving.join().unwrap();
+        assert_eq!(server.get(0x1017, 0), Some(&[0xE8, 0x03][..]));
+        assert_eq!(server.get(0x2000, 1), Some(&[7; 15][..]));
+        assert_eq!(
+            SdoError::Abort(0x0602_0000).to_string(),
+            "SDO abort 0x06020000: object does not exist in the object dictionary"
+        );
+    }
+
+    #[test]
+    fn test_sdo_timeout() {
+        let (client, mut bus) = Socket::pair().unwrap();
+        client.set_read_timeout(Duration::from_secs(3)).unwrap();
+        let mut sdo = SdoClient::new(client, 5)
+            .timeout(Duration::from_micros(500))
+            .with_clock(ManualClock::new());
+
+        // too little time left to wait for the response
+        assert!(matches!(sdo.upload(0x1000, 0), Err(SdoError::Timeout)));
+        assert_eq!(
+            sdo.get_ref().read_timeout().unwrap(),
+            Some(Duration::from_secs(3))
+        );
+
+        bus.set_read_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(bus.receive().unwrap().data()[0], 0x40);
+        let abort = bus.receive().unwrap();
+        assert_eq!(abort.data()[0], 0x80);
+        assert_eq!(abort.data()[4..], 0x0504_0000u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,768 @@
+use crate::{AnyFrame, FdFrame, Frame, ShutdownReport, ShutdownToken, Socket, SocketError};
+use libc::{
+    c_uint, c_void, cmsghdr, if_nametoindex, iovec, mmsghdr, recvmmsg, timespec, CMSG_DATA,
+    CMSG_FIRSTHDR, CMSG_NXTHDR, MSG_WAITFORONE, SCM_TIMESTAMPNS, SOL_SOCKET, SO_TIMESTAMPNS,
+};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, IoSlice, Read, Write},
+    mem::size_of,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How long receivers block before checking whether the capture was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Space for the control messages of a single received frame
+type ControlBuffer = [u64; 8];
+
+/// Options for `capture_to`
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    interfaces: Vec<String>,
+    batch_size: usize,
+    buffer_len: usize,
+    buffers: usize,
+    fd_frames: bool,
+    shutdown: Option<ShutdownToken>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> CaptureOptions {
+        CaptureOptions {
+            interfaces: Vec::new(),
+            batch_size: 64,
+            buffer_len: 1024,
+            buffers: 64,
+            fd_frames: true,
+            shutdown: None,
+        }
+    }
+}
+
+impl CaptureOptions {
+    /// Create options without any interface
+    pub fn new() -> CaptureOptions {
+        CaptureOptions::default()
+    }
+
+    /// Add an interface to capture from
+    pub fn interface(mut self, ifname: &str) -> CaptureOptions {
+        self.interfaces.push(ifname.to_string());
+        self
+    }
+
+    /// Maximum number of frames fetched by a single `recvmmsg` call.
+    /// Default is 64.
+    pub fn batch_size(mut self, batch_size: usize) -> CaptureOptions {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Number of preallocated buffers between receivers and the writer, and
+    /// the number of frames each of them holds. Defaults are 64 buffers of
+    /// 1024 frames.
+    ///
+    /// Frames are dropped (and counted) when the writer falls behind so far
+    /// that all buffers are in use.
+    pub fn buffers(mut self, buffers: usize, buffer_len: usize) -> CaptureOptions {
+        self.buffers = buffers.max(1);
+        self.buffer_len = buffer_len.max(1);
+        self
+    }
+
+    /// Enable or disable capturing CAN FD frames. Enabled by default.
+    pub fn fd_frames(mut self, enabled: bool) -> CaptureOptions {
+        self.fd_frames = enabled;
+        self
+    }
+
+    /// Stop receiving once `token` requests shutdown. Buffered frames are
+    /// written until the grace period ends and dropped afterwards; the
+    /// outcome is reported to the token as subsystem `capture`.
+    pub fn shutdown(mut self, token: &ShutdownToken) -> CaptureOptions {
+        self.shutdown = Some(token.clone());
+        self
+    }
+}
+
+/// Captured frame as stored on disk
+///
+/// Capture files are a plain sequence of these records in host byte order:
+/// the receive timestamp in nanoseconds since the Unix epoch (u64), the
+/// interface index (u32), the number of valid frame bytes (u32, 16 for
+/// classic and 72 for CAN FD frames) and the 72 byte kernel `canfd_frame`.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct CaptureRecord {
+    timestamp: u64,
+    if_index: u32,
+    mtu: u32,
+    frame: FdFrame,
+}
+
+impl CaptureRecord {
+    /// Receive time since the Unix epoch
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_nanos(self.timestamp)
+    }
+
+    /// Index of the interface the frame was received on
+    pub fn if_index(&self) -> u32 {
+        self.if_index
+    }
+
+    /// The captured frame
+    pub fn frame(&self) -> AnyFrame {
+        if self.mtu as usize == size_of::<Frame>() {
+            let classic = unsafe { *(&self.frame as *const FdFrame as *const Frame) };
+            AnyFrame::Classic(classic)
+        } else {
+            AnyFrame::Fd(self.frame)
+        }
+    }
+}
+
+/// Frame counters of a capture
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Frames handed to the writer
+    pub frames: u64,
+    /// Frames dropped because no buffer was available, or because the
+    /// shutdown grace period ended before they were written
+    pub dropped: u64,
+}
+
+/// Storage backend of a capture
+///
+/// `capture_into` hands the captured records to the sink in batches on the
+/// writer thread. Implement this to store captures elsewhere than in a local
+/// file, e.g. to upload them.
+pub trait FrameSink: Send + 'static {
+    /// Store a batch of records
+    fn write_batch(&mut self, records: &[CaptureRecord]) -> io::Result<()>;
+
+    /// Store several batches at once, by default one after the other
+    fn write_batches(&mut self, batches: &[&[CaptureRecord]]) -> io::Result<()> {
+        for batch in batches {
+            self.write_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Make all stored records durable. Called when the capture stops.
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Finish the current segment and continue in a new one. Called on
+    /// `Capture::rotate`.
+    fn rotate(&mut self) -> io::Result<()>;
+}
+
+/// Capture file sink used by `capture_to`
+///
+/// Writes records in the `CaptureRecord` format. Rotating continues in a
+/// new file with a numbered suffix: `can.cap`, `can.cap.1`, `can.cap.2`, ...
+pub struct FileSink {
+    file: File,
+    path: PathBuf,
+    segment: usize,
+}
+
+impl FileSink {
+    /// Create a capture file, replacing an existing one
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<FileSink> {
+        Ok(FileSink {
+            file: File::create(path.as_ref())?,
+            path: path.as_ref().to_path_buf(),
+            segment: 0,
+        })
+    }
+
+    /// Path of the file currently written
+    pub fn path(&self) -> PathBuf {
+        match self.segment {
+            0 => self.path.clone(),
+            n => {
+                let mut path = self.path.clone().into_os_string();
+                path.push(format!(".{}", n));
+                path.into()
+            }
+        }
+    }
+}
+
+impl FrameSink for FileSink {
+    fn write_batch(&mut self, records: &[CaptureRecord]) -> io::Result<()> {
+        self.file.write_all(records_as_bytes(records))
+    }
+
+    fn write_batches(&mut self, batches: &[&[CaptureRecord]]) -> io::Result<()> {
+        let bufs: Vec<&[u8]> = batches.iter().map(|b| records_as_bytes(b)).collect();
+        write_all_vectored(&mut self.file, &bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.segment += 1;
+        self.file = File::create(self.path())?;
+        Ok(())
+    }
+}
+
+/// Running capture started by `capture_to`
+///
+/// Receiving and writing happen on background threads. The capture runs
+/// until `stop` is called; dropping the handle stops it as well, discarding
+/// any error.
+pub struct Capture {
+    stop: Arc<AtomicBool>,
+    rotate: Arc<AtomicBool>,
+    frames: Arc<AtomicU64>,
+    dropped: Arc<AtomicU64>,
+    receivers: Vec<JoinHandle<Result<(), SocketError>>>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Capture {
+    /// Current frame counters
+    pub fn stats(&self) -> CaptureStats {
+        CaptureStats {
+            frames: self.frames.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Ask the sink to continue in a new segment, see `FrameSink::rotate`.
+    ///
+    /// Frames already handed to the writer end up in the current segment.
+    pub fn rotate(&self) {
+        self.rotate.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop capturing, flush all buffered frames to disk and return the
+    /// final counters.
+    pub fn stop(mut self) -> Result<CaptureStats, SocketError> {
+        self.shutdown()?;
+        Ok(self.stats())
+    }
+
+    fn shutdown(&mut self) -> Result<(), SocketError> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        let mut result = Ok(());
+        for receiver in self.receivers.drain(..) {
+            let rv = receiver.join().unwrap();
+            result = result.and(rv);
+        }
+        // all senders are gone now, so the writer drains the queue and exits
+        if let Some(writer) = self.writer.take() {
+            let rv = writer.join().unwrap().map_err(SocketError::from);
+            result = result.and(rv);
+        }
+        result
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.shutdown().ok(); // ignore result
+    }
+}
+
+/// Capture all frames received on the configured interfaces into `path`.
+///
+/// Every interface is served by a receiver thread fetching frames in batches
+/// using `recvmmsg` into preallocated buffers, which are passed to a single
+/// writer thread storing them with vectored writes. See `CaptureRecord` for
+/// the file format.
+pub fn capture_to<P: AsRef<Path>>(
+    path: P,
+    options: CaptureOptions,
+) -> Result<Capture, SocketError> {
+    capture_into(FileSink::create(path)?, options)
+}
+
+/// Capture all frames received on the configured interfaces into `sink`.
+///
+/// Works like `capture_to`, with the writer thread passing the buffers to
+/// the sink instead of a file.
+pub fn capture_into<S: FrameSink>(
+    sink: S,
+    options: CaptureOptions,
+) -> Result<Capture, SocketError> {
+    let mut sockets = Vec::new();
+    for ifname in &options.interfaces {
+        let name = CString::new(ifname.as_str()).map_err(|_| SocketError::NoSuchDevice)?;
+        let if_index = unsafe { if_nametoindex(name.as_ptr()) };
+        if if_index == 0 {
+            return Err(SocketError::NoSuchDevice);
+        }
+        let socket = Socket::open_if(if_index)?;
+        if options.fd_frames {
+            socket.set_fd_frames(true)?;
+        }
+        let enabled: libc::c_int = 1;
+        socket.set_socket_option(socket.as_raw_fd(), SOL_SOCKET, SO_TIMESTAMPNS, &enabled)?;
+        socket.set_read_timeout(POLL_INTERVAL)?;
+        sockets.push((socket, if_index));
+    }
+
+    let pool: Vec<Vec<CaptureRecord>> = (0..options.buffers)
+        .map(|_| Vec::with_capacity(options.buffer_len))
+        .collect();
+    let pool = Arc::new(Mutex::new(pool));
+    let (full_tx, full_rx) = mpsc::channel();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let rotate = Arc::new(AtomicBool::new(false));
+    let frames = Arc::new(AtomicU64::new(0));
+    let dropped = Arc::new(AtomicU64::new(0));
+
+    let receivers = sockets
+        .into_iter()
+        .map(|(socket, if_index)| {
+            let receiver = Receiver {
+                socket,
+                if_index,
+                pool: pool.clone(),
+                full: full_tx.clone(),
+                stop: stop.clone(),
+                frames: frames.clone(),
+                dropped: dropped.clone(),
+                batch_size: options.batch_size,
+                shutdown: options.shutdown.clone(),
+            };
+            thread::spawn(move || receiver.run())
+        })
+        .collect();
+    drop(full_tx);
+
+    let writer = {
+        let rotate = rotate.clone();
+        let dropped = dropped.clone();
+        let shutdown = options.shutdown;
+        thread::spawn(move || write_buffers(sink, full_rx, pool, rotate, shutdown, dropped))
+    };
+
+    Ok(Capture {
+        stop,
+        rotate,
+        frames,
+        dropped,
+        receivers,
+        writer: Some(writer),
+    })
+}
+
+/// Iterator over the records of a capture file
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl CaptureReader<io::BufReader<File>> {
+    /// Open a capture file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CaptureReader<io::BufReader<File>>> {
+        Ok(CaptureReader::new(io::BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Read records from `reader`
+    pub fn new(reader: R) -> CaptureReader<R> {
+        CaptureReader { reader }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<io::Result<CaptureRecord>> {
+        let mut record = CaptureRecord::default();
+        let buf = unsafe { records_as_bytes_mut(slice::from_mut(&mut record)) };
+        match self.reader.read_exact(buf) {
+            Ok(()) => Some(Ok(record)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+struct Receiver {
+    socket: Socket,
+    if_index: u32,
+    pool: Arc<Mutex<Vec<Vec<CaptureRecord>>>>,
+    full: mpsc::Sender<Vec<CaptureRecord>>,
+    stop: Arc<AtomicBool>,
+    frames: Arc<AtomicU64>,
+    dropped: Arc<AtomicU64>,
+    batch_size: usize,
+    shutdown: Option<ShutdownToken>,
+}
+
+impl Receiver {
+    fn run(self) -> Result<(), SocketError> {
+        let n = self.batch_size;
+        let mut frames = vec![FdFrame::default(); n];
+        let mut control = vec![ControlBuffer::default(); n];
+        let mut iovecs: Vec<iovec> = frames
+            .iter_mut()
+            .map(|f| iovec {
+                iov_base: f as *mut FdFrame as *mut c_void,
+                iov_len: size_of::<FdFrame>(),
+            })
+            .collect();
+        let mut headers: Vec<mmsghdr> = (0..n)
+            .map(|i| {
+                let mut hdr: mmsghdr = unsafe { std::mem::zeroed() };
+                hdr.msg_hdr.msg_iov = &mut iovecs[i];
+                hdr.msg_hdr.msg_iovlen = 1;
+                hdr.msg_hdr.msg_control = control[i].as_mut_ptr() as *mut c_void;
+                hdr
+            })
+            .collect();
+
+        let mut buffer = self.take_buffer();
+        while !self.is_stopped() {
+            for hdr in headers.iter_mut() {
+                hdr.msg_hdr.msg_controllen = size_of::<ControlBuffer>() as _;
+                hdr.msg_hdr.msg_flags = 0;
+            }
+
+            let count = unsafe {
+                recvmmsg(
+                    self.socket.as_raw_fd(),
+                    headers.as_mut_ptr(),
+                    n as c_uint,
+                    MSG_WAITFORONE,
+                    ptr::null_mut::<timespec>(),
+                )
+            };
+
+            if count < 0 {
+                let e = io::Error::last_os_error();
+                match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => {
+                        // idle: hand over what we have to bound the latency
+                        if buffer.as_ref().map_or(false, |b| !b.is_empty()) {
+                            buffer = self.submit(buffer);
+                        }
+                        continue;
+                    }
+                    _ => return Err(SocketError::from(e)),
+                }
+            }
+
+            for (i, hdr) in headers.iter().enumerate().take(count as usize) {
+                let record = CaptureRecord {
+                    timestamp: timestamp(&hdr.msg_hdr),
+                    if_index: self.if_index,
+                    mtu: hdr.msg_len,
+                    frame: frames[i],
+                };
+                match buffer.as_mut() {
+                    Some(b) => b.push(record),
+                    None => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        buffer = self.take_buffer();
+                        continue;
+                    }
+                }
+                if buffer.as_ref().map_or(false, |b| b.len() == b.capacity()) {
+                    buffer = self.submit(buffer);
+                }
+            }
+        }
+
+        if buffer.as_ref().map_or(false, |b| !b.is_empty()) {
+            self.submit(buffer);
+        }
+        Ok(())
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+            || self.shutdown.as_ref().map_or(false, |t| t.is_requested())
+    }
+
+    fn take_buffer(&self) -> Option<Vec<CaptureRecord>> {
+        self.pool.lock().unwrap().pop()
+    }
+
+    /// Pass a filled buffer to the writer and return a fresh one, if
+    /// available.
+    fn submit(&self, buffer: Option<Vec<CaptureRecord>>) -> Option<Vec<CaptureRecord>> {
+        if let Some(buffer) = buffer {
+            let len = buffer.len() as u64;
+            match self.full.send(buffer) {
+                Ok(()) => self.frames.fetch_add(len, Ordering::Relaxed),
+                Err(_) => self.dropped.fetch_add(len, Ordering::Relaxed),
+            };
+        }
+        self.take_buffer()
+    }
+}
+
+/// Writer thread: store filled buffers and return them to the pool. Once
+/// the grace period of `shutdown` ended, buffers are dropped unwritten.
+fn write_buffers<S: FrameSink>(
+    mut sink: S,
+    full: mpsc::Receiver<Vec<CaptureRecord>>,
+    pool: Arc<Mutex<Vec<Vec<CaptureRecord>>>>,
+    rotate: Arc<AtomicBool>,
+    shutdown: Option<ShutdownToken>,
+    dropped: Arc<AtomicU64>,
+) -> io::Result<()> {
+    let mut pending = Vec::new();
+    let (mut flushed, mut discarded) = (0, 0);
+    loop {
+        if rotate.swap(false, Ordering::Relaxed) {
+            sink.rotate()?;
+        }
+        match full.recv_timeout(POLL_INTERVAL) {
+            Ok(buffer) => pending.push(buffer),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        while let Ok(buffer) = full.try_recv() {
+            pending.push(buffer);
+        }
+
+        let records: u64 = pending.iter().map(|b| b.len() as u64).sum();
+        match &shutdown {
+            Some(token) if token.is_expired() => {
+                dropped.fetch_add(records, Ordering::Relaxed);
+                discarded += records;
+            }
+            token => {
+                let batches: Vec<&[CaptureRecord]> = pending.iter().map(|b| b.as_slice()).collect();
+                sink.write_batches(&batches)?;
+                if token.as_ref().map_or(false, |t| t.is_requested()) {
+                    flushed += records;
+                }
+            }
+        }
+
+        let mut pool = pool.lock().unwrap();
+        for mut buffer in pending.drain(..) {
+            buffer.clear();
+            pool.push(buffer);
+        }
+    }
+    sink.flush()?;
+    if let Some(token) = shutdown {
+        token.report(ShutdownReport {
+            subsystem: "capture".to_string(),
+            flushed,
+            dropped: discarded,
+        });
+    }
+    Ok(())
+}
+
+fn write_all_vectored(file: &mut File, bufs: &[&[u8]]) -> io::Result<()> {
+    let total: usize = bufs.iter().map(|b| b.len()).sum();
+    let mut written = 0;
+    while written < total {
+        let mut skip = written;
+        let slices: Vec<IoSlice> = bufs
+            .iter()
+            .filter_map(|b| {
+                if skip >= b.len() {
+                    skip -= b.len();
+                    None
+                } else {
+                    let slice = IoSlice::new(&b[skip..]);
+                    skip = 0;
+                    Some(slice)
+                }
+            })
+            .collect();
+
+        match file.write_vectored(&slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Extract the `SCM_TIMESTAMPNS` control message, falling back to the current
+/// time if the kernel did not provide one.
+fn timestamp(hdr: &libc::msghdr) -> u64 {
+    unsafe {
+        let mut cmsg: *mut cmsghdr = CMSG_FIRSTHDR(hdr);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == SOL_SOCKET && (*cmsg).cmsg_type == SCM_TIMESTAMPNS {
+                let ts = ptr::read_unaligned(CMSG_DATA(cmsg) as *const timespec);
+                return ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64;
+            }
+            cmsg = CMSG_NXTHDR(hdr, cmsg);
+        }
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn records_as_bytes(records: &[CaptureRecord]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            records.as_ptr() as *const u8,
+            std::mem::size_of_val(records),
+        )
+    }
+}
+
+/// # Safety
+///
+/// Any byte pattern is a valid `CaptureRecord`.
+unsafe fn records_as_bytes_mut(records: &mut [CaptureRecord]) -> &mut [u8] {
+    slice::from_raw_parts_mut(
+        records.as_mut_ptr() as *mut u8,
+        std::mem::size_of_val(records),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        records_as_bytes, write_buffers, CaptureReader, CaptureRecord, FileSink, FrameSink,
+    };
+    use crate::{AnyFrame, FdFrame, Frame, ManualClock, ShutdownReport, ShutdownToken};
+    use std::{
+        env, fs, io,
+        mem::size_of,
+        process,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    struct CountingSink(Arc<AtomicU64>);
+
+    impl FrameSink for CountingSink {
+        fn write_batch(&mut self, records: &[CaptureRecord]) -> io::Result<()> {
+            self.0.fetch_add(records.len() as u64, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn rotate(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let classic = Frame::new(0x123, &[1, 2, 3], false, false).unwrap();
+        let fd = FdFrame::new(0x456, &[0xAA; 16], true, false).unwrap();
+        let records = [
+            CaptureRecord {
+                timestamp: 1_000_000_001,
+                if_index: 3,
+                mtu: size_of::<Frame>() as u32,
+                frame: FdFrame::from(classic),
+            },
+            CaptureRecord {
+                timestamp: 2_000_000_002,
+                if_index: 4,
+                mtu: size_of::<FdFrame>() as u32,
+                frame: fd,
+            },
+        ];
+
+        let records: Vec<CaptureRecord> = CaptureReader::new(records_as_bytes(&records))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].if_index(), 3);
+        match records[0].frame() {
+            AnyFrame::Classic(frame) => assert_eq!(frame.data(), &[1, 2, 3]),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+        assert_eq!(records[1].timestamp().as_nanos(), 2_000_000_002);
+        match records[1].frame() {
+            AnyFrame::Fd(frame) => assert_eq!(frame.data(), fd.data()),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_file_sink_rotate() {
+        let path = env::temp_dir().join(format!("candev-sink-{}.cap", process::id()));
+        let record = CaptureRecord::default();
+
+        let mut sink = FileSink::create(&path).unwrap();
+        sink.write_batches(&[&[record], &[record, record]]).unwrap();
+        sink.rotate().unwrap();
+        assert!(sink.path().to_str().unwrap().ends_with(".cap.1"));
+        sink.write_batch(&[record]).unwrap();
+        sink.flush().unwrap();
+
+        let count = |path| CaptureReader::open(path).unwrap().count();
+        assert_eq!(count(&path), 3);
+        assert_eq!(count(&sink.path()), 1);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(sink.path()).unwrap();
+    }
+
+    #[test]
+    fn test_writer_shutdown() {
+        let clock = ManualClock::new();
+        let token = ShutdownToken::new().with_clock(clock.clone());
+        token.shutdown(Duration::from_secs(1));
+        let (written, dropped) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let write = |records: usize| {
+            let (full_tx, full_rx) = mpsc::channel();
+            full_tx
+                .send(vec![CaptureRecord::default(); records])
+                .unwrap();
+            drop(full_tx);
+            let pool = Arc::new(Mutex::new(Vec::new()));
+            let sink = CountingSink(written.clone());
+            let rotate = Default::default();
+            write_buffers(
+                sink,
+                full_rx,
+                pool,
+                rotate,
+                Some(token.clone()),
+                dropped.clone(),
+            )
+            .unwrap();
+        };
+
+        // written during the grace period, dropped afterwards
+        write(3);
+        clock.advance(Duration::from_secs(1));
+        write(2);
+        assert_eq!(written.load(Ordering::Relaxed), 3);
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+        let report = |flushed, dropped| ShutdownReport {
+            subsystem: "capture".to_string(),
+            flushed,
+            dropped,
+        };
+        assert_eq!(token.reports(), [report(3, 0), report(0, 2)]);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{CanError, Clock, SystemClock};
+use std::time::{Duration, Instant};
+
+/// Identical errors merged by an `ErrorCoalescer`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ErrorSummary {
+    /// The repeated error
+    pub error: CanError,
+    /// Number of occurrences
+    pub count: u64,
+    /// Time of the first occurrence
+    pub first: Instant,
+    /// Time of the last occurrence
+    pub last: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    error: CanError,
+    /// Start of the current interval
+    since: Instant,
+    /// Errors suppressed within the current interval
+    count: u64,
+    first: Instant,
+    last: Instant,
+}
+
+/// Error frame rate limiter
+///
+/// Coalesces identical repeated errors, e.g. thousands of `NoAck` per second
+/// while a node is absent, into one summary per interval, so error storms do
+/// not flood the application:
+///
+/// ```no_run
+/// use candev::{CanError, ErrorCoalescer, Socket};
+/// use embedded_can::blocking::Can;
+/// use std::time::Duration;
+///
+/// let mut socket = Socket::new("vcan0").unwrap();
+/// socket.set_error_mask(u32::MAX).unwrap();
+/// let mut coalescer = ErrorCoalescer::new(Duration::from_secs(1));
+/// loop {
+///     let frame = socket.receive().unwrap();
+///     if let Ok(error) = CanError::from_frame(&frame) {
+///         if let Some(summary) = coalescer.record(&error) {
+///             println!("{:?} x{}", summary.error, summary.count);
+///         }
+///     }
+/// }
+/// ```
+///
+/// The first occurrence of an error is passed on immediately, repetitions
+/// within the interval are only counted. Call `flush` periodically to
+/// collect the summaries of storms that ended. The times of the errors are
+/// taken from a `Clock`.
+#[derive(Debug, Clone)]
+pub struct ErrorCoalescer<C = SystemClock> {
+    clock: C,
+    interval: Duration,
+    pending: Vec<Pending>,
+}
+
+impl ErrorCoalescer {
+    /// Create a coalescer passing on each distinct error at most once per
+    /// `interval`.
+    pub fn new(interval: Duration) -> ErrorCoalescer {
+        ErrorCoalescer {
+            clock: SystemClock,
+            interval,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<C: Clock> ErrorCoalescer<C> {
+    /// Use `clock` to timestamp the errors
+    pub fn with_clock<D: Clock>(self, clock: D) -> ErrorCoalescer<D> {
+        ErrorCoalescer {
+            clock,
+            interval: self.interval,
+            pending: self.pending,
+        }
+    }
+
+    /// Record an error which occurred just now.
+    ///
+    /// Returns a summary to deliver if the error is new or its interval
+    /// elapsed, covering the suppressed repetitions and this error.
+    pub fn record(&mut self, error: &CanError) -> Option<ErrorSummary> {
+        let time = self.clock.now();
+        let pending = match self.pending.iter_mut().find(|p| p.error == *error) {
+            Some(pending) => pending,
+            None => {
+                self.pending.push(Pending {
+                    error: *error,
+                    since: time,
+                    count: 0,
+                    first: time,
+                    last: time,
+                });
+                return Some(ErrorSummary {
+                    error: *error,
+                    count: 1,
+                    first: time,
+                    last: time,
+                });
+            }
+        };
+
+        if time.saturating_duration_since(pending.since) < self.interval {
+            if pending.count == 0 {
+                pending.first = time;
+            }
+            pending.count += 1;
+            pending.last = time;
+            return None;
+        }
+
+        let summary = ErrorSummary {
+            error: *error,
+            count: pending.count + 1,
+            first: if pending.count == 0 {
+                time
+            } else {
+                pending.first
+            },
+            last: time,
+        };
+        pending.since = time;
+        pending.count = 0;
+        Some(summary)
+    }
+
+    /// Summaries of the errors suppressed up to now whose interval elapsed.
+    ///
+    /// Errors without repetitions in their last interval are forgotten, so
+    /// their next occurrence is passed on immediately again.
+    pub fn flush(&mut self) -> Vec<ErrorSummary> {
+        let time = self.clock.now();
+        let interval = self.interval;
+        let mut summaries = Vec::new();
+        self.pending.retain(|p| {
+            if time.saturating_duration_since(p.since) < interval {
+                return true;
+            }
+            if p.count > 0 {
+                summaries.push(ErrorSummary {
+                    error: p.error,
+                    count: p.count,
+                    first: p.first,
+                    last: p.last,
+                });
+            }
+            false
+        });
+        summaries
+    }
+
+    /// Number of suppressed errors not yet reported in a summary
+    pub fn suppressed(&self) -> u64 {
+        self.pending.iter().map(|p| p.count).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorCoalescer;
+    use crate::{CanError, Clock, ManualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn test_coalesce() {
+        let clock = ManualClock::new();
+        let mut coalescer = ErrorCoalescer::new(Duration::from_secs(1)).with_clock(clock.clone());
+        let ms = Duration::from_millis;
+
+        // the first error is passed on, repetitions are counted
+        let summary = coalescer.record(&CanError::NoAck).unwrap();
+        assert_eq!(summary.count, 1);
+        for _ in 1..=999 {
+            clock.advance(ms(1));
+            assert_eq!(coalescer.record(&CanError::NoAck), None);
+        }
+        assert!(coalescer.record(&CanError::BusOff).is_some());
+        assert_eq!(coalescer.suppressed(), 999);
+
+        clock.advance(ms(1));
+        let summary = coalescer.record(&CanError::NoAck).unwrap();
+        assert_eq!(summary.count, 1000);
+        assert_eq!(summary.last, clock.now());
+        assert_eq!(summary.last - summary.first, ms(999));
+
+        // the storm ends
+        clock.advance(ms(500));
+        coalescer.record(&CanError::NoAck);
+        clock.advance(ms(1000));
+        let summaries = coalescer.flush();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].error, CanError::NoAck);
+        assert_eq!(summaries[0].count, 1);
+        clock.advance(ms(500));
+        assert!(coalescer.flush().is_empty());
+        assert_eq!(coalescer.record(&CanError::NoAck).unwrap().count, 1);
+    }
+}
@@ -0,0 +1,424 @@
+use crate::{CanId, Filter, FilterGroup, Frame, PeriodicMessage, PeriodicScheduler, Socket};
+use libc::{CAN_EFF_FLAG, CAN_EFF_MASK, CAN_SFF_MASK};
+use std::{
+    error, fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// Errors loading a `BusConfig`
+#[derive(Debug)]
+pub enum ConfigError {
+    /// System error while reading the file
+    IOError(io::Error),
+
+    /// A line could not be parsed. Contains the line number (starting at 1)
+    /// and the offending part.
+    Parse { line: usize, what: &'static str },
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::IOError(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::IOError(e) => write!(f, "failed to read configuration: {}", e),
+            ConfigError::Parse { line, what } => write!(f, "invalid {} in line {}", what, line),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::IOError(e) => Some(e),
+            ConfigError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Receive filters and periodic messages of a bus
+///
+/// The text format has one setting per line, using the `candump` notation
+/// for filters and the `cansend` notation for frames:
+///
+/// ```text
+/// # accept 0x100 to 0x1FF and all extended ids but 18FEF100
+/// filter 100:700
+/// filter 18FEF100~1FFFFFFF
+/// # frames have to match all filters
+/// join
+/// # transmit every 100 ms
+/// periodic 123#DEADBEEF 100ms
+/// ```
+///
+/// Ids with more than 3 digits are extended ids. Periods are given in `s`,
+/// `ms` or `us`. Without filters all frames are accepted. Empty lines and
+/// lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BusConfig {
+    /// Receive filters
+    pub filters: FilterGroup,
+    /// Messages to transmit periodically, at most one per id
+    pub periodic: Vec<PeriodicMessage>,
+}
+
+impl BusConfig {
+    /// Load a configuration from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<BusConfig, ConfigError> {
+        BusConfig::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a configuration from a reader
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<BusConfig, ConfigError> {
+        let mut config = BusConfig::default();
+        for (n, line) in reader.lines().enumerate() {
+            config.parse_line(n + 1, &line?)?;
+        }
+        Ok(config)
+    }
+
+    /// Changes turning this configuration into `new`
+    pub fn diff(&self, new: &BusConfig) -> ConfigDiff {
+        let filters = if new.filters != self.filters {
+            Some(new.filters.clone())
+        } else {
+            None
+        };
+        let started = new
+            .periodic
+            .iter()
+            .filter(|m| !self.periodic.contains(m))
+            .copied()
+            .collect();
+        let stopped = self
+            .periodic
+            .iter()
+            .map(PeriodicMessage::id)
+            .filter(|id| new.periodic.iter().all(|m| m.id() != *id))
+            .collect();
+        ConfigDiff {
+            filters,
+            started,
+            stopped,
+        }
+    }
+
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<(), ConfigError> {
+        let text = text.trim();
+        let error = |what| ConfigError::Parse { line, what };
+        let mut fields = text.split_whitespace();
+        match fields.next() {
+            None => {}
+            Some(comment) if comment.starts_with('#') => return Ok(()),
+            Some("filter") => {
+                let filter = fields.next().and_then(parse_filter);
+                self.filters = self.filters.clone().filter(filter.ok_or(error("filter"))?);
+            }
+            Some("join") => self.filters = self.filters.clone().join(true),
+            Some("periodic") => {
+                let frame: Frame = fields
+                    .next()
+                    .and_then(|f| f.parse().ok())
+                    .ok_or(error("frame"))?;
+                let period = fields
+                    .next()
+                    .and_then(parse_period)
+                    .ok_or(error("period"))?;
+                let message = PeriodicMessage { frame, period };
+                if self.periodic.iter().any(|m| m.id() == message.id()) {
+                    return Err(error("duplicate periodic id"));
+                }
+                self.periodic.push(message);
+            }
+            Some(_) => return Err(error("setting")),
+        }
+        if fields.next().is_some() {
+            return Err(error("trailing text"));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BusConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<BusConfig, ConfigError> {
+        BusConfig::from_reader(s.as_bytes())
+    }
+}
+
+/// Changes between two configurations, created by `BusConfig::diff`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    /// New filters, `None` if unchanged
+    pub filters: Option<FilterGroup>,
+    /// Periodic messages added or changed
+    pub started: Vec<PeriodicMessage>,
+    /// Ids of periodic messages removed
+    pub stopped: Vec<CanId>,
+}
+
+impl ConfigDiff {
+    /// Check if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_none() && self.started.is_empty() && self.stopped.is_empty()
+    }
+
+    /// Apply the changes to a running socket and scheduler.
+    ///
+    /// Filters are only replaced if they changed, using
+    /// `Socket::set_filter_group`, which keeps accepting the frames both the
+    /// old and the new filters accept. Messages that did not change keep
+    /// their schedule.
+    pub fn apply(&self, socket: &Socket, scheduler: &mut PeriodicScheduler) -> io::Result<()> {
+        if let Some(filters) = &self.filters {
+            if filters.filters().is_empty() {
+                socket.set_filter_group(&FilterGroup::new().filter(Filter::accept_all()))?;
+            } else {
+                socket.set_filter_group(filters)?;
+            }
+        }
+        for id in &self.stopped {
+            scheduler.stop(*id);
+        }
+        for message in &self.started {
+            scheduler.start(*message);
+        }
+        Ok(())
+    }
+}
+
+/// Watches a configuration file for changes
+///
+/// For long-running gateways, which reconfigure without recreating their
+/// sockets:
+///
+/// ```no_run
+/// use candev::{ConfigWatcher, PeriodicScheduler, Socket};
+/// use std::time::Duration;
+///
+/// let mut socket = Socket::new("can0").unwrap();
+/// let mut scheduler = PeriodicScheduler::new().unwrap();
+/// let mut watcher = ConfigWatcher::new("/etc/can0.conf").unwrap();
+/// watcher.initial().apply(&socket, &mut scheduler).unwrap();
+/// loop {
+///     scheduler.run_once(&mut socket, Duration::from_secs(1)).unwrap();
+///     match watcher.poll() {
+///         Ok(Some(diff)) => diff.apply(&socket, &mut scheduler).unwrap(),
+///         Ok(None) => {}
+///         Err(e) => eprintln!("keeping the current configuration: {}", e),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<(SystemTime, u64)>,
+    config: BusConfig,
+}
+
+impl ConfigWatcher {
+    /// Load the configuration at `path`
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<ConfigWatcher, ConfigError> {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified(&path)?;
+        let config = BusConfig::from_file(&path)?;
+        Ok(ConfigWatcher {
+            path,
+            modified: Some(modified),
+            config,
+        })
+    }
+
+    /// The configuration currently in effect
+    pub fn config(&self) -> &BusConfig {
+        &self.config
+    }
+
+    /// Changes from the default configuration to the current one, to set up
+    /// a new socket and scheduler
+    pub fn initial(&self) -> ConfigDiff {
+        BusConfig::default().diff(&self.config)
+    }
+
+    /// Reload the file if it was modified since the last call and return the
+    /// changes, or `None` if it was not modified.
+    ///
+    /// An invalid file is reported as error and keeps the current
+    /// configuration, it is read again once modified.
+    pub fn poll(&mut self) -> Result<Option<ConfigDiff>, ConfigError> {
+        let modified = modified(&self.path)?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+        self.modified = Some(modified);
+        let config = BusConfig::from_file(&self.path)?;
+        let diff = self.config.diff(&config);
+        self.config = config;
+        Ok(Some(diff))
+    }
+}
+
+/// Modification time and length of a file
+fn modified(path: &Path) -> io::Result<(SystemTime, u64)> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Parse a filter in the `candump` notation, `id:mask` or `id~mask` for an
+/// inverted filter
+fn parse_filter(s: &str) -> Option<Filter> {
+    let (id, mask, inverted) = match s.split_once(':') {
+        Some((id, mask)) => (id, mask, false),
+        None => {
+            let (id, mask) = s.split_once('~')?;
+            (id, mask, true)
+        }
+    };
+    let (id_value, mask) = (
+        u32::from_str_radix(id, 16).ok()?,
+        u32::from_str_radix(mask, 16).ok()?,
+    );
+    let filter = if id.len() > 3 {
+        if id_value > CAN_EFF_MASK {
+            return None;
+        }
+        Filter::extended(id_value)
+    } else {
+        if id_value > CAN_SFF_MASK {
+            return None;
+        }
+        Filter::standard(id_value as u16)
+    };
+    let filter = filter.with_mask(mask & !CAN_EFF_FLAG);
+    Some(if inverted { filter.invert() } else { filter })
+}
+
+/// Parse a period such as `100ms`, `1s` or `500us`
+fn parse_period(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let value: u64 = s[..split].parse().ok()?;
+    let period = match &s[split..] {
+        "s" => Duration::from_secs(value),
+        "ms" => Duration::from_millis(value),
+        "us" => Duration::from_micros(value),
+        _ => return None,
+    };
+    if period.is_zero() {
+        return None;
+    }
+    Some(period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BusConfig, ConfigError, ConfigWatcher};
+    use crate::{CanId, Filter, FilterGroup};
+    #[cfg(feature = "vcan0")]
+    use crate::{PeriodicScheduler, Socket};
+    use std::{env, fs, process, time::Duration};
+
+    const CONFIG: &str = "\
+# comment
+filter 100:700
+filter 18FEF100~1FFFFFFF
+periodic 123#DEADBEEF 100ms
+periodic 18FEF100#01 1s
+";
+
+    #[test]
+    fn test_parse() {
+        let config: BusConfig = CONFIG.parse().unwrap();
+        assert_eq!(
+            config.filters,
+            FilterGroup::new()
+                .filter(Filter::standard(0x100).with_mask(0x700))
+                .filter(Filter::extended(0x18FEF100).invert())
+        );
+        assert_eq!(config.periodic.len(), 2);
+        assert_eq!(config.periodic[0].id(), CanId::Standard(0x123));
+        assert_eq!(config.periodic[0].period, Duration::from_millis(100));
+        assert_eq!(config.periodic[1].id(), CanId::Extended(0x18FEF100));
+
+        for (text, what) in [
+            ("filter 100", "filter"),
+            ("periodic 123#01 100", "period"),
+            (
+                "periodic 123#01 1s\nperiodic 123#02 2s",
+                "duplicate periodic id",
+            ),
+            ("bitrate 500000", "setting"),
+        ] {
+            match text.parse::<BusConfig>() {
+                Err(ConfigError::Parse { what: w, .. }) => assert_eq!(w, what),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old: BusConfig = CONFIG.parse().unwrap();
+        assert!(old.diff(&old).is_empty());
+
+        let new: BusConfig = "\
+filter 100:700
+filter 18FEF100~1FFFFFFF
+periodic 123#DEADBEEF 100ms
+periodic 321#02 10ms
+"
+        .parse()
+        .unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(diff.filters, None);
+        assert_eq!(diff.started, &new.periodic[1..]);
+        assert_eq!(diff.stopped, [CanId::Extended(0x18FEF100)]);
+    }
+
+    #[test]
+    fn test_watcher() {
+        let path = env::temp_dir().join(format!("candev-config-{}.conf", process::id()));
+        fs::write(&path, CONFIG).unwrap();
+        let mut watcher = ConfigWatcher::new(&path).unwrap();
+        assert_eq!(watcher.initial().started.len(), 2);
+        assert_eq!(watcher.poll().unwrap(), None);
+
+        // a broken file keeps the configuration
+        fs::write(&path, "filter 100\n").unwrap();
+        assert!(watcher.poll().is_err());
+        assert_eq!(watcher.config().periodic.len(), 2);
+
+        fs::write(&path, "periodic 123#DEADBEEF 100ms\n").unwrap();
+        let diff = watcher.poll().unwrap().unwrap();
+        assert_eq!(diff.filters, Some(FilterGroup::new()));
+        assert_eq!(diff.stopped, [CanId::Extended(0x18FEF100)]);
+        assert!(diff.started.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "vcan0")]
+    fn test_apply() {
+        let socket = Socket::new("vcan0").unwrap();
+        let mut scheduler = PeriodicScheduler::new().unwrap();
+        let old: BusConfig = CONFIG.parse().unwrap();
+        BusConfig::default()
+            .diff(&old)
+            .apply(&socket, &mut scheduler)
+            .unwrap();
+        assert_eq!(scheduler.messages().count(), 2);
+
+        old.diff(&BusConfig::default())
+            .apply(&socket, &mut scheduler)
+            .unwrap();
+        assert_eq!(scheduler.messages().count(), 0);
+    }
+}
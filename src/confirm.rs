@@ -0,0 +1,285 @@
+use crate::{Clock, Direction, Frame, Interest, Poller, Socket, SocketError, SystemClock};
+use std::{
+    collections::VecDeque,
+    io,
+    time::{Duration, Instant},
+};
+
+/// Tag of a frame transmitted by a `ConfirmedSocket`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TxTag(u64);
+
+/// Event reported by `ConfirmedSocket::poll`
+#[derive(Debug, Clone)]
+pub enum TxEvent {
+    /// Frame received from the bus or another local socket
+    Received(Frame),
+
+    /// The tagged frame was put on the bus
+    Confirmed {
+        /// Tag returned by `ConfirmedSocket::send`
+        tag: TxTag,
+        /// The transmitted frame
+        frame: Frame,
+        /// Time from queueing the frame until its confirmation
+        latency: Duration,
+    },
+
+    /// The tagged frame was not confirmed within the timeout, e.g. because
+    /// no other node acknowledged it
+    TimedOut {
+        /// Tag returned by `ConfirmedSocket::send`
+        tag: TxTag,
+        /// The frame which was not confirmed
+        frame: Frame,
+    },
+}
+
+#[derive(Debug)]
+struct Pending {
+    tag: TxTag,
+    frame: Frame,
+    sent: Instant,
+}
+
+/// Socket reporting when transmitted frames were actually put on the bus
+///
+/// A successful `Socket::transmit` only means that the frame was accepted
+/// into the transmit queue. `ConfirmedSocket` enables `recv_own_msgs` and
+/// matches the echoes of the own frames, which the kernel only delivers
+/// after the controller transmitted them, against the outstanding frames:
+///
+/// ```no_run
+/// use candev::{ConfirmedSocket, Frame, Socket};
+/// use std::time::Duration;
+///
+/// let socket = Socket::new("can0").unwrap();
+/// let mut socket = ConfirmedSocket::new(socket, Duration::from_millis(100)).unwrap();
+/// let frame = Frame::new(0x123, &[1, 2, 3], false, false).unwrap();
+/// let latency = socket.transmit_confirmed(&frame).unwrap();
+/// ```
+///
+/// Frames not confirmed within the timeout are reported as timed out and
+/// forgotten, an echo arriving later is ignored. Use `send` and `poll` to
+/// keep several frames in flight. Latencies and timeouts are measured with a
+/// `Clock`.
+#[derive(Debug)]
+pub struct ConfirmedSocket<C = SystemClock> {
+    clock: C,
+    socket: Socket,
+    poller: Poller,
+    timeout: Duration,
+    next_tag: u64,
+    pending: VecDeque<Pending>,
+    events: VecDeque<TxEvent>,
+}
+
+impl ConfirmedSocket {
+    /// Confirm frames transmitted on `socket`, giving up after `timeout`
+    pub fn new(socket: Socket, timeout: Duration) -> io::Result<ConfirmedSocket> {
+        socket.set_recv_own_msgs(true)?;
+        ConfirmedSocket::wrap(socket, timeout)
+    }
+
+    fn wrap(socket: Socket, timeout: Duration) -> io::Result<ConfirmedSocket> {
+        let mut poller = Poller::new()?;
+        poller.add(&socket, 0, Interest::Readable)?;
+        Ok(ConfirmedSocket {
+            clock: SystemClock,
+            socket,
+            poller,
+            timeout,
+            next_tag: 0,
+            pending: VecDeque::new(),
+            events: VecDeque::new(),
+        })
+    }
+}
+
+impl<C: Clock> ConfirmedSocket<C> {
+    /// Use `clock` to measure latencies and timeouts
+    pub fn with_clock<D: Clock>(self, clock: D) -> ConfirmedSocket<D> {
+        ConfirmedSocket {
+            clock,
+            socket: self.socket,
+            poller: self.poller,
+            timeout: self.timeout,
+            next_tag: self.next_tag,
+            pending: self.pending,
+            events: self.events,
+        }
+    }
+
+    /// Queue a frame for transmission, returning the tag its confirmation
+    /// is reported with
+    pub fn send(&mut self, frame: &Frame) -> Result<TxTag, SocketError> {
+        use embedded_can::blocking::Can;
+
+        self.socket.transmit(frame)?;
+        let tag = TxTag(self.next_tag);
+        self.next_tag += 1;
+        self.pending.push_back(Pending {
+            tag,
+            frame: *frame,
+            sent: self.clock.now(),
+        });
+        Ok(tag)
+    }
+
+    /// Transmit a frame and wait until it was put on the bus, returning the
+    /// latency.
+    ///
+    /// Fails with `SocketError::Timeout` if the frame is not confirmed in
+    /// time. Events of other frames are kept for `poll`.
+    pub fn transmit_confirmed(&mut self, frame: &Frame) -> Result<Duration, SocketError> {
+        let tag = self.send(frame)?;
+        loop {
+            match self.next_event(None)? {
+                Some(TxEvent::Confirmed {
+                    tag: t, latency, ..
+                }) if t == tag => return Ok(latency),
+                Some(TxEvent::TimedOut { tag: t, .. }) if t == tag => {
+                    return Err(SocketError::Timeout)
+                }
+                Some(event) => self.events.push_back(event),
+                None => {}
+            }
+        }
+    }
+
+    /// Wait for the next event for up to `timeout`, or forever without
+    /// timeout.
+    ///
+    /// Returns `None` if nothing happened in time.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<TxEvent>, SocketError> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+        let deadline = timeout.map(|t| self.clock.now() + t);
+        self.next_event(deadline)
+    }
+
+    /// Number of frames waiting for their confirmation
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Access the wrapped socket
+    pub fn get_ref(&self) -> &Socket {
+        &self.socket
+    }
+
+    /// Return the wrapped socket, dropping outstanding confirmations
+    pub fn into_inner(self) -> Socket {
+        self.socket
+    }
+
+    /// Wait for the next event until `deadline`, or forever without
+    /// deadline. `None` if the deadline passed without event.
+    fn next_event(&mut self, deadline: Option<Instant>) -> Result<Option<TxEvent>, SocketError> {
+        loop {
+            let now = self.clock.now();
+            let remaining = deadline.map(|d| d.saturating_duration_since(now));
+            // wait no longer than until the oldest pending frame times out
+            let (timeout, expiring) = match self.pending.front() {
+                Some(oldest) => {
+                    let expiry = oldest.sent + self.timeout;
+                    if expiry <= now {
+                        let Pending { tag, frame, .. } = self.pending.pop_front().unwrap();
+                        return Ok(Some(TxEvent::TimedOut { tag, frame }));
+                    }
+                    match remaining {
+                        Some(remaining) if remaining < expiry - now => (Some(remaining), false),
+                        _ => (Some(expiry - now), true),
+                    }
+                }
+                None => (remaining, false),
+            };
+            if self.poller.wait(timeout)?.is_empty() {
+                if expiring {
+                    continue;
+                }
+                return Ok(None);
+            }
+
+            let (frame, direction) = self.socket.receive_tagged()?;
+            if direction != Direction::TxEcho {
+                return Ok(Some(TxEvent::Received(frame)));
+            }
+            // echoes arrive in transmit order, unless frames were given up
+            let position = self.pending.iter().position(|p| p.frame == frame);
+            if let Some(p) = position.and_then(|i| self.pending.remove(i)) {
+                return Ok(Some(TxEvent::Confirmed {
+                    tag: p.tag,
+                    frame: p.frame,
+                    latency: self.clock.now().saturating_duration_since(p.sent),
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfirmedSocket, TxEvent};
+    use crate::{Frame, ManualClock, Socket, SocketError};
+    use embedded_can::blocking::Can;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timeout() {
+        // socket pairs never echo, so frames time out
+        let (a, mut b) = Socket::pair().unwrap();
+        let clock = ManualClock::new();
+        let mut socket = ConfirmedSocket::wrap(a, Duration::from_millis(20))
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let frame = Frame::new(0x123, &[1], false, false).unwrap();
+        let tag = socket.send(&frame).unwrap();
+        assert_eq!(socket.pending(), 1);
+        b.transmit(&Frame::new(0x456, &[2], false, false).unwrap())
+            .unwrap();
+
+        match socket.poll(None).unwrap() {
+            Some(TxEvent::Received(frame)) => assert_eq!(frame.data(), &[2]),
+            other => panic!("unexpected event {:?}", other),
+        }
+        clock.advance(Duration::from_millis(20));
+        match socket.poll(None).unwrap() {
+            Some(TxEvent::TimedOut { tag: t, .. }) => assert_eq!(t, tag),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(socket.pending(), 0);
+        assert!(socket
+            .poll(Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_none());
+
+        // waits for the timeout on the system clock
+        let (a, _b) = Socket::pair().unwrap();
+        let mut socket = ConfirmedSocket::wrap(a, Duration::from_millis(20)).unwrap();
+        assert!(matches!(
+            socket.transmit_confirmed(&frame),
+            Err(SocketError::Timeout)
+        ));
+    }
+
+    #[cfg(feature = "vcan0")]
+    #[test]
+    fn vcan0_confirmed() {
+        let socket = Socket::new("vcan0").unwrap();
+        let mut socket = ConfirmedSocket::new(socket, Duration::from_millis(100)).unwrap();
+        let frame = Frame::new(0x123, &[1, 2], false, false).unwrap();
+        socket.transmit_confirmed(&frame).unwrap();
+
+        let first = socket.send(&frame).unwrap();
+        let second = socket.send(&frame).unwrap();
+        for tag in [first, second] {
+            match socket.poll(None).unwrap() {
+                Some(TxEvent::Confirmed { tag: t, .. }) => assert_eq!(t, tag),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+    }
+}
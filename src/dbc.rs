@@ -0,0 +1,1025 @@
+use crate::{CanId, Frame};
+use embedded_can::Frame as _;
+use libc::CAN_EFF_MASK;
+use std::{
+    collections::HashMap,
+    error, fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+/// Bit 31 of a DBC message id marks extended ids
+const DBC_EXTENDED: u32 = 0x8000_0000;
+
+/// Id of the `VECTOR__INDEPENDENT_SIG_MSG` pseudo message, which holds
+/// signals not assigned to any message
+const INDEPENDENT_SIGNALS: &str = "3221225472";
+
+/// Errors loading a DBC file
+#[derive(Debug)]
+pub enum DbcError {
+    /// System error while reading the file
+    IOError(io::Error),
+
+    /// A line could not be parsed. Contains the line number (starting at 1)
+    /// and the offending part.
+    Parse { line: usize, what: &'static str },
+}
+
+impl From<io::Error> for DbcError {
+    fn from(e: io::Error) -> DbcError {
+        DbcError::IOError(e)
+    }
+}
+
+impl fmt::Display for DbcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbcError::IOError(e) => write!(f, "failed to read DBC file: {}", e),
+            DbcError::Parse { line, what } => write!(f, "invalid {} in line {}", what, line),
+        }
+    }
+}
+
+impl error::Error for DbcError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DbcError::IOError(e) => Some(e),
+            DbcError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Errors encoding signal values into a frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodeError {
+    /// The database contains no message with the given name
+    UnknownMessage(String),
+
+    /// The message contains no signal with the given name
+    UnknownSignal(String),
+
+    /// The multiplexed signal is not selected by the multiplexor value
+    NotSelected(String),
+
+    /// The value is outside the range of the signal
+    OutOfRange { signal: String, value: f64 },
+
+    /// The signal does not fit into the payload of the message
+    Layout(String),
+
+    /// The message is longer than 8 bytes
+    TooMuchData,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::UnknownMessage(name) => write!(f, "unknown message {}", name),
+            EncodeError::UnknownSignal(name) => write!(f, "unknown signal {}", name),
+            EncodeError::NotSelected(name) => {
+                write!(f, "signal {} is not selected by the multiplexor", name)
+            }
+            EncodeError::OutOfRange { signal, value } => {
+                write!(f, "value {} out of range for signal {}", value, signal)
+            }
+            EncodeError::Layout(name) => {
+                write!(f, "signal {} does not fit into the message", name)
+            }
+            EncodeError::TooMuchData => write!(f, "message longer than 8 bytes"),
+        }
+    }
+}
+
+impl error::Error for EncodeError {}
+
+/// Bit order of a signal
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Intel byte order (`@1`), the start bit is the least significant bit
+    LittleEndian,
+    /// Motorola byte order (`@0`), the start bit is the most significant bit
+    BigEndian,
+}
+
+/// Role of a signal in a multiplexed message
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Multiplexing {
+    /// Always present
+    None,
+    /// Selects which multiplexed signals are present (`M`)
+    Multiplexor,
+    /// Only present if the multiplexor has the contained value (`m<n>`)
+    Multiplexed(u64),
+    /// Only present if the multiplexor has the contained value, and selects
+    /// further signals itself (`m<n>M`, extended multiplexing)
+    MultiplexedMultiplexor(u64),
+}
+
+impl Multiplexing {
+    /// Multiplexor value selecting the signal, if it is multiplexed
+    fn selected_by(self) -> Option<u64> {
+        match self {
+            Multiplexing::Multiplexed(value) | Multiplexing::MultiplexedMultiplexor(value) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Signal definition of a `DbcMessage`
+#[derive(Debug, Clone)]
+pub struct Signal {
+    /// Signal name
+    pub name: String,
+    /// Start bit, as given in the DBC file
+    pub start_bit: u16,
+    /// Length of the signal in bits
+    pub bit_length: u16,
+    /// Bit order
+    pub byte_order: ByteOrder,
+    /// Whether the raw value is a two's complement number
+    pub signed: bool,
+    /// Scaling factor
+    pub factor: f64,
+    /// Offset added after scaling
+    pub offset: f64,
+    /// Smallest physical value
+    pub min: f64,
+    /// Largest physical value
+    pub max: f64,
+    /// Physical unit
+    pub unit: String,
+    /// Nodes receiving the signal
+    pub receivers: Vec<String>,
+    /// Role in a multiplexed message
+    pub multiplexing: Multiplexing,
+    /// Names of raw values, from `VAL_` definitions
+    pub values: HashMap<i64, String>,
+}
+
+impl Signal {
+    /// Extract the raw value of the signal from `data`.
+    ///
+    /// Returns `None` if the payload is too short to contain the signal.
+    pub fn raw(&self, data: &[u8]) -> Option<u64> {
+        let len = self.bit_length as usize;
+        if len == 0 || len > 64 {
+            return None;
+        }
+        let bit = |pos: usize| data.get(pos / 8).map(|b| (b >> (pos % 8)) as u64 & 1);
+
+        let mut raw = 0u64;
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                let start = self.start_bit as usize;
+                for pos in (start..start + len).rev() {
+                    raw = (raw << 1) | bit(pos)?;
+                }
+            }
+            ByteOrder::BigEndian => {
+                // bits are numbered LSB first within each byte, the signal
+                // continues at the MSB of the next byte
+                let mut pos = self.start_bit as usize;
+                for i in 0..len {
+                    raw = (raw << 1) | bit(pos)?;
+                    if i + 1 < len {
+                        pos = if pos % 8 == 0 { pos + 15 } else { pos - 1 };
+                    }
+                }
+            }
+        }
+        Some(raw)
+    }
+
+    /// Raw value interpreted as signed if the signal is signed
+    pub fn signed_raw(&self, raw: u64) -> i64 {
+        let len = self.bit_length as u32;
+        if self.signed && len > 0 && len < 64 && raw & (1 << (len - 1)) != 0 {
+            (raw | (u64::MAX << len)) as i64
+        } else {
+            raw as i64
+        }
+    }
+
+    /// Physical value of a raw value
+    pub fn physical(&self, raw: u64) -> f64 {
+        let raw = if self.signed {
+            self.signed_raw(raw) as f64
+        } else {
+            raw as f64
+        };
+        raw * self.factor + self.offset
+    }
+
+    /// Raw value of a physical value.
+    ///
+    /// Fails if the value is outside of `min` and `max`, unless both are 0,
+    /// or the raw value does not fit into the signal.
+    pub fn to_raw(&self, value: f64) -> Result<u64, EncodeError> {
+        let out_of_range = || EncodeError::OutOfRange {
+            signal: self.name.clone(),
+            value,
+        };
+        let checked = self.min != 0.0 || self.max != 0.0;
+        if !value.is_finite() || checked && (value < self.min || value > self.max) {
+            return Err(out_of_range());
+        }
+
+        let len = self.bit_length as u32;
+        let raw = ((value - self.offset) / self.factor).round();
+        let (low, high) = match (self.signed, len) {
+            (_, 0) => return Err(out_of_range()),
+            (false, 64) => (0.0, u64::MAX as f64),
+            (true, 64) => (i64::MIN as f64, i64::MAX as f64),
+            (false, len) => (0.0, ((1u64 << len) - 1) as f64),
+            (true, len) => (
+                -((1u64 << (len - 1)) as f64),
+                ((1u64 << (len - 1)) - 1) as f64,
+            ),
+        };
+        if raw < low || raw > high {
+            return Err(out_of_range());
+        }
+        let mask = u64::MAX >> (64 - len);
+        Ok(if raw < 0.0 {
+            (raw as i64) as u64 & mask
+        } else {
+            raw as u64
+        })
+    }
+
+    /// Write a raw value into `data`, the inverse of `raw`.
+    ///
+    /// Returns `None` if the payload is too short to contain the signal.
+    pub fn set_raw(&self, data: &mut [u8], raw: u64) -> Option<()> {
+        let len = self.bit_length as usize;
+        if len == 0 || len > 64 {
+            return None;
+        }
+        let mut set = |pos: usize, value: u64| {
+            let byte = data.get_mut(pos / 8)?;
+            *byte = *byte & !(1 << (pos % 8)) | ((value as u8 & 1) << (pos % 8));
+            Some(())
+        };
+
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                for i in 0..len {
+                    set(self.start_bit as usize + i, raw >> i)?;
+                }
+            }
+            ByteOrder::BigEndian => {
+                let mut pos = self.start_bit as usize;
+                for i in 0..len {
+                    set(pos, raw >> (len - 1 - i))?;
+                    if i + 1 < len {
+                        pos = if pos % 8 == 0 { pos + 15 } else { pos - 1 };
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Name of a raw value, if defined in a value table
+    pub fn describe(&self, raw: u64) -> Option<&str> {
+        self.values.get(&self.signed_raw(raw)).map(|s| s.as_str())
+    }
+}
+
+/// Message definition of a `Dbc`
+#[derive(Debug, Clone)]
+pub struct DbcMessage {
+    /// Frame id
+    pub id: CanId,
+    /// Message name
+    pub name: String,
+    /// Payload length
+    pub dlc: u8,
+    /// Node transmitting the message
+    pub sender: String,
+    /// Signals carried in the payload
+    pub signals: Vec<Signal>,
+}
+
+impl DbcMessage {
+    /// Signal with the name `name`
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// Encode physical signal values into a frame.
+    ///
+    /// Signals without a value are transmitted as raw 0. Values of
+    /// multiplexed signals are only accepted if the multiplexor value
+    /// selects them.
+    pub fn encode(&self, values: &HashMap<&str, f64>) -> Result<Frame, EncodeError> {
+        if self.dlc > 8 {
+            return Err(EncodeError::TooMuchData);
+        }
+        for name in values.keys() {
+            if self.signal(name).is_none() {
+                return Err(EncodeError::UnknownSignal(name.to_string()));
+            }
+        }
+
+        let selector = match self
+            .signals
+            .iter()
+            .find(|s| s.multiplexing == Multiplexing::Multiplexor)
+        {
+            Some(s) => Some(s.to_raw(values.get(s.name.as_str()).copied().unwrap_or(0.0))?),
+            None => None,
+        };
+        let mut data = [0u8; 8];
+        for signal in &self.signals {
+            let value = match values.get(signal.name.as_str()) {
+                Some(value) => *value,
+                None => continue,
+            };
+            if let Some(selected) = signal.multiplexing.selected_by() {
+                if selector != Some(selected) {
+                    return Err(EncodeError::NotSelected(signal.name.clone()));
+                }
+            }
+            signal
+                .set_raw(&mut data[..self.dlc as usize], signal.to_raw(value)?)
+                .ok_or_else(|| EncodeError::Layout(signal.name.clone()))?;
+        }
+
+        embedded_can::Frame::new(self.id, &data[..self.dlc as usize])
+            .ok_or(EncodeError::TooMuchData)
+    }
+}
+
+/// Decoded signal
+#[derive(Debug, Clone, Copy)]
+pub struct SignalValue<'a> {
+    /// Definition the value was decoded with
+    pub signal: &'a Signal,
+    /// Raw value as transmitted
+    pub raw: u64,
+    /// Physical value
+    pub value: f64,
+}
+
+/// CAN database in the DBC format
+///
+/// Loads the message (`BO_`), signal (`SG_`) and value table (`VAL_`)
+/// definitions of a `.dbc` file and decodes received frames into named,
+/// scaled signals:
+///
+/// ```
+/// use candev::{Dbc, Frame};
+///
+/// let dbc: Dbc = r#"
+/// BO_ 291 Engine: 8 ECU
+///  SG_ Speed : 0|16@1+ (0.125,0) [0|8191] "rpm" Dashboard
+/// "#
+/// .parse()
+/// .unwrap();
+///
+/// let frame = Frame::new(0x123, &[0x40, 0x1F], false, false).unwrap();
+/// let values = dbc.decode(&frame);
+/// assert_eq!(values[0].signal.name, "Speed");
+/// assert_eq!(values[0].value, 1000.0);
+/// ```
+///
+/// Other sections like nodes, comments and attributes are skipped, as are
+/// the signals of `VECTOR__INDEPENDENT_SIG_MSG` and value tables of
+/// environment variables. Signals of multiplexed messages are only decoded
+/// if the multiplexor selects them. With extended multiplexing, all
+/// multiplexed signals are selected by the message's `M` signal, as
+/// `SG_MUL_VAL_` is not evaluated.
+///
+/// To decode a few signals of many frames, `compile` them into a
+/// `DecodePlan`.
+#[derive(Debug, Clone, Default)]
+pub struct Dbc {
+    messages: HashMap<CanId, DbcMessage>,
+}
+
+impl Dbc {
+    /// Create an empty database
+    pub fn new() -> Dbc {
+        Dbc::default()
+    }
+
+    /// Load a database from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Dbc, DbcError> {
+        Dbc::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a database from a reader
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Dbc, DbcError> {
+        let mut dbc = Dbc::new();
+        let mut current = Section::Other;
+        for (n, line) in reader.lines().enumerate() {
+            dbc.parse_line(n + 1, &line?, &mut current)?;
+        }
+        Ok(dbc)
+    }
+
+    /// Add a message, replacing a message with the same id
+    pub fn insert(&mut self, message: DbcMessage) {
+        self.messages.insert(message.id, message);
+    }
+
+    /// Message with the id `id`
+    pub fn message(&self, id: CanId) -> Option<&DbcMessage> {
+        self.messages.get(&id)
+    }
+
+    /// Message with the name `name`
+    pub fn message_by_name(&self, name: &str) -> Option<&DbcMessage> {
+        self.messages.values().find(|m| m.name == name)
+    }
+
+    /// All messages, in no particular order
+    pub fn messages(&self) -> impl Iterator<Item = &DbcMessage> {
+        self.messages.values()
+    }
+
+    /// Encode physical signal values into a frame of the message `name`,
+    /// see `DbcMessage::encode`.
+    pub fn encode(&self, name: &str, values: &HashMap<&str, f64>) -> Result<Frame, EncodeError> {
+        self.message_by_name(name)
+            .ok_or_else(|| EncodeError::UnknownMessage(name.to_string()))?
+            .encode(values)
+    }
+
+    /// Decode all signals of a frame.
+    ///
+    /// Unknown, remote and error frames yield no values. Signals that do not
+    /// fit into the received payload are skipped.
+    pub fn decode(&self, frame: &Frame) -> Vec<SignalValue<'_>> {
+        if frame.is_remote_frame() || frame.is_error() {
+            return Vec::new();
+        }
+        let message = match self.message(CanId::from(frame.id())) {
+            Some(message) => message,
+            None => return Vec::new(),
+        };
+
+        let data = frame.data();
+        let selector = message
+            .signals
+            .iter()
+            .find(|s| s.multiplexing == Multiplexing::Multiplexor)
+            .and_then(|s| s.raw(data));
+        message
+            .signals
+            .iter()
+            .filter(|s| match s.multiplexing.selected_by() {
+                Some(value) => selector == Some(value),
+                None => true,
+            })
+            .filter_map(|signal| {
+                let raw = signal.raw(data)?;
+                Some(SignalValue {
+                    signal,
+                    raw,
+                    value: signal.physical(raw),
+                })
+            })
+            .collect()
+    }
+
+    /// Compile a subset of signals, given as message and signal name, into a
+    /// `DecodePlan`.
+    ///
+    /// The decoded values of `signals` are written to the slots with the same
+    /// index. Returns `None` if a message or signal is unknown.
+    pub fn compile(&self, signals: &[(&str, &str)]) -> Option<DecodePlan<'_>> {
+        let mut entries = Vec::with_capacity(signals.len());
+        for (slot, (message, signal)) in signals.iter().enumerate() {
+            let message = self.message_by_name(message)?;
+            entries.push(PlannedSignal {
+                id: message.id,
+                slot,
+                signal: message.signal(signal)?,
+                multiplexor: message
+                    .signals
+                    .iter()
+                    .find(|s| s.multiplexing == Multiplexing::Multiplexor),
+            });
+        }
+        entries.sort_by_key(|e| e.id);
+        Some(DecodePlan {
+            slots: signals.len(),
+            entries,
+        })
+    }
+
+    fn parse_line(
+        &mut self,
+        line: usize,
+        text: &str,
+        current: &mut Section,
+    ) -> Result<(), DbcError> {
+        let text = text.trim();
+        let error = |what| DbcError::Parse { line, what };
+        if let Some(rest) = text.strip_prefix("BO_ ") {
+            if rest.split_whitespace().next() == Some(INDEPENDENT_SIGNALS) {
+                *current = Section::Independent;
+                return Ok(());
+            }
+            let message = parse_message(rest).ok_or(error("message"))?;
+            *current = Section::Message(message.id);
+            self.insert(message);
+        } else if let Some(rest) = text.strip_prefix("SG_ ") {
+            let message = match current {
+                Section::Message(id) => self.messages.get_mut(id),
+                Section::Independent => None,
+                Section::Other => return Err(error("signal outside of a message")),
+            };
+            let signal = parse_signal(rest).ok_or(error("signal"))?;
+            if let Some(message) = message {
+                message.signals.push(signal);
+            }
+        } else if let Some(rest) = text.strip_prefix("VAL_ ") {
+            self.parse_values(rest).ok_or(error("value table"))?;
+        } else if !text.is_empty() {
+            *current = Section::Other;
+        }
+        Ok(())
+    }
+
+    /// Parse `<id> <signal> <value> "<name>" ... ;`. Tables of environment
+    /// variables, `VAL_ <variable> <value> "<name>" ... ;`, and of unknown
+    /// signals are skipped.
+    fn parse_values(&mut self, text: &str) -> Option<()> {
+        let mut tokens = tokenize(text).into_iter();
+        let id = tokens.next()?;
+        if !id.bytes().all(|c| c.is_ascii_digit()) {
+            // environment variable
+            return Some(());
+        }
+        let name = tokens.next()?;
+        let signal = parse_id(&id)
+            .and_then(|id| self.messages.get_mut(&id))
+            .and_then(|message| message.signals.iter_mut().find(|s| s.name == name));
+        let mut values = HashMap::new();
+        while let Some(value) = tokens.next() {
+            if value == ";" {
+                break;
+            }
+            let description = tokens.next()?;
+            values.insert(value.parse().ok()?, description);
+        }
+        if let Some(signal) = signal {
+            signal.values.extend(values);
+        }
+        Some(())
+    }
+}
+
+impl FromStr for Dbc {
+    type Err = DbcError;
+
+    fn from_str(s: &str) -> Result<Dbc, DbcError> {
+        Dbc::from_reader(s.as_bytes())
+    }
+}
+
+/// Precompiled decoding of selected signals
+///
+/// Created by `Dbc::compile`. Keeps the selected signals in a flat table
+/// sorted by id, so decoding a frame needs no hash lookup, and consecutive
+/// frames of the same message in a batch share a single lookup. Use
+/// `Dbc::decode` for ad-hoc queries.
+#[derive(Debug, Clone)]
+pub struct DecodePlan<'a> {
+    slots: usize,
+    entries: Vec<PlannedSignal<'a>>,
+}
+
+#[derive(Debug, Clone)]
+struct PlannedSignal<'a> {
+    id: CanId,
+    slot: usize,
+    signal: &'a Signal,
+    /// Multiplexor of the message
+    multiplexor: Option<&'a Signal>,
+}
+
+/// Signal values decoded from a batch of frames by
+/// `DecodePlan::decode_batch`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignalUpdates {
+    /// Latest value of each slot, `None` if no frame of the batch carried
+    /// the signal
+    pub values: Vec<Option<f64>>,
+    /// Number of frames of the batch that updated each slot
+    pub updates: Vec<usize>,
+}
+
+impl<'a> DecodePlan<'a> {
+    /// Number of value slots, i.e. signals passed to `compile`
+    pub fn len(&self) -> usize {
+        self.slots
+    }
+
+    /// Whether the plan contains no signals
+    pub fn is_empty(&self) -> bool {
+        self.slots == 0
+    }
+
+    /// Decode the planned signals carried by `frame` into `values`.
+    ///
+    /// Only the slots of signals contained in the frame are written.
+    /// Signals not selected by the multiplexor or not fitting into the
+    /// received payload are skipped. Returns the number of slots written.
+    ///
+    /// Panics if `values` is shorter than `len()`.
+    pub fn decode(&self, frame: &Frame, values: &mut [Option<f64>]) -> usize {
+        assert!(values.len() >= self.slots, "too few value slots");
+        if frame.is_remote_frame() || frame.is_error() {
+            return 0;
+        }
+        let entries = self.entries(CanId::from(frame.id()));
+        extract(entries, frame.data(), |slot, value| {
+            values[slot] = Some(value)
+        })
+    }
+
+    /// Decode the planned signals of a batch of frames, keeping the latest
+    /// value of each signal.
+    pub fn decode_batch(&self, frames: &[Frame]) -> SignalUpdates {
+        let mut updates = SignalUpdates {
+            values: vec![None; self.slots],
+            updates: vec![0; self.slots],
+        };
+        let mut last: Option<(CanId, &[PlannedSignal])> = None;
+        for frame in frames {
+            if frame.is_remote_frame() || frame.is_error() {
+                continue;
+            }
+            let id = CanId::from(frame.id());
+            let entries = match last {
+                Some((last_id, entries)) if last_id == id => entries,
+                _ => {
+                    let entries = self.entries(id);
+                    last = Some((id, entries));
+                    entries
+                }
+            };
+            extract(entries, frame.data(), |slot, value| {
+                updates.values[slot] = Some(value);
+                updates.updates[slot] += 1;
+            });
+        }
+        updates
+    }
+
+    /// Planned signals of the message `id`
+    fn entries(&self, id: CanId) -> &[PlannedSignal<'a>] {
+        let first = self.entries.partition_point(|e| e.id < id);
+        let len = self.entries[first..]
+            .iter()
+            .take_while(|e| e.id == id)
+            .count();
+        &self.entries[first..first + len]
+    }
+}
+
+/// Pass the values of the selected signals in `data` to `update`, returns
+/// the number of values passed
+fn extract(entries: &[PlannedSignal], data: &[u8], mut update: impl FnMut(usize, f64)) -> usize {
+    let mut written = 0;
+    for e in entries {
+        if let Some(selected) = e.signal.multiplexing.selected_by() {
+            if e.multiplexor.and_then(|m| m.raw(data)) != Some(selected) {
+                continue;
+            }
+        }
+        if let Some(raw) = e.signal.raw(data) {
+            update(e.slot, e.signal.physical(raw));
+            written += 1;
+        }
+    }
+    written
+}
+
+/// Section of the file the following `SG_` lines belong to
+enum Section {
+    /// Outside of a message
+    Other,
+    /// Signals of the message with the contained id
+    Message(CanId),
+    /// Signals of `VECTOR__INDEPENDENT_SIG_MSG`
+    Independent,
+}
+
+/// Parse a DBC message id, where bit 31 marks extended ids
+fn parse_id(s: &str) -> Option<CanId> {
+    let id: u32 = s.parse().ok()?;
+    if id & DBC_EXTENDED != 0 {
+        let id = id & !DBC_EXTENDED;
+        (id <= CAN_EFF_MASK).then_some(CanId::Extended(id))
+    } else {
+        CanId::new(id).ok().filter(|id| id.is_standard())
+    }
+}
+
+/// Parse `<id> <name>: <dlc> <sender>`
+fn parse_message(text: &str) -> Option<DbcMessage> {
+    let (head, tail) = text.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let id = parse_id(head.next()?)?;
+    let name = head.next()?.to_string();
+    let mut tail = tail.split_whitespace();
+    let dlc = tail.next()?.parse().ok()?;
+    let sender = tail.next().unwrap_or("").to_string();
+    Some(DbcMessage {
+        id,
+        name,
+        dlc,
+        sender,
+        signals: Vec::new(),
+    })
+}
+
+/// Parse `<name> [M|m<n>] : <start>|<len>@<order><sign> (<factor>,<offset>)
+/// [<min>|<max>] "<unit>" <receivers>`
+fn parse_signal(text: &str) -> Option<Signal> {
+    let (head, tail) = text.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_string();
+    let multiplexing = match head.next() {
+        None => Multiplexing::None,
+        Some("M") => Multiplexing::Multiplexor,
+        Some(m) => {
+            let m = m.strip_prefix('m')?;
+            match m.strip_suffix('M') {
+                Some(value) => Multiplexing::MultiplexedMultiplexor(value.parse().ok()?),
+                None => Multiplexing::Multiplexed(m.parse().ok()?),
+            }
+        }
+    };
+
+    let (layout, tail) = tail.split_once('(')?;
+    let (start_bit, layout) = layout.trim().split_once('|')?;
+    let (bit_length, layout) = layout.split_once('@')?;
+    let (byte_order, signed) = match layout.trim() {
+        "1+" => (ByteOrder::LittleEndian, false),
+        "1-" => (ByteOrder::LittleEndian, true),
+        "0+" => (ByteOrder::BigEndian, false),
+        "0-" => (ByteOrder::BigEndian, true),
+        _ => return None,
+    };
+
+    let (scaling, tail) = tail.split_once(')')?;
+    let (factor, offset) = scaling.split_once(',')?;
+    let (_, tail) = tail.split_once('[')?;
+    let (range, tail) = tail.split_once(']')?;
+    let (min, max) = range.split_once('|')?;
+    let (_, tail) = tail.split_once('"')?;
+    let (unit, receivers) = tail.split_once('"')?;
+
+    Some(Signal {
+        name,
+        start_bit: start_bit.trim().parse().ok()?,
+        bit_length: bit_length.trim().parse().ok()?,
+        byte_order,
+        signed,
+        factor: factor.trim().parse().ok()?,
+        offset: offset.trim().parse().ok()?,
+        min: min.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        unit: unit.to_string(),
+        receivers: receivers
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|r| !r.is_empty())
+            .map(str::to_string)
+            .collect(),
+        multiplexing,
+        values: HashMap::new(),
+    })
+}
+
+/// Split at whitespace, keeping quoted strings together. A trailing `;` is
+/// returned as separate token.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            tokens.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else if c == ';' {
+            chars.next();
+            tokens.push(";".to_string());
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ';' || c == '"' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteOrder, Dbc, DbcError, EncodeError, Multiplexing};
+    use crate::{CanId, Frame};
+    use embedded_can::Frame as _;
+    use std::collections::HashMap;
+
+    const DBC: &str = r#"VERSION ""
+
+NS_ :
+    CM_
+    BA_
+
+BU_: ECU Dashboard
+
+BO_ 2364540158 EEC1: 8 ECU
+ SG_ EngineSpeed : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Dashboard
+ SG_ StarterMode : 48|4@1+ (1,0) [0|15] "" Dashboard,Logger
+ SG_ Torque : 16|8@1- (1,0) [-128|127] "%" Dashboard
+
+BO_ 1024 Status: 4 ECU
+ SG_ Page M : 0|8@1+ (1,0) [0|255] "" Dashboard
+ SG_ Voltage m1 : 15|16@0+ (0.01,0) [0|655.35] "V" Dashboard
+ SG_ Current m2 : 15|16@0- (0.1,0) [-3276.8|3276.7] "A" Dashboard
+
+CM_ SG_ 2364540158 EngineSpeed "Actual engine speed";
+VAL_ 2364540158 StarterMode 0 "start not requested" 1 "starter active, gear not engaged" ;
+"#;
+
+    #[test]
+    fn test_parse() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let eec1 = dbc.message(CanId::Extended(0x0CF004FE)).unwrap();
+        assert_eq!(eec1.name, "EEC1");
+        assert_eq!(eec1.dlc, 8);
+        assert_eq!(eec1.signals.len(), 3);
+        let mode = eec1.signal("StarterMode").unwrap();
+        assert_eq!(mode.receivers, ["Dashboard", "Logger"]);
+        assert_eq!(mode.describe(1), Some("starter active, gear not engaged"));
+
+        let status = dbc.message_by_name("Status").unwrap();
+        assert_eq!(status.id, CanId::Standard(0x400));
+        let voltage = status.signal("Voltage").unwrap();
+        assert_eq!(voltage.byte_order, ByteOrder::BigEndian);
+        assert_eq!(voltage.multiplexing, Multiplexing::Multiplexed(1));
+        assert_eq!(voltage.unit, "V");
+    }
+
+    #[test]
+    fn test_decode() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let frame = Frame::new(
+            0x0CF004FE,
+            &[0xFF, 0xFF, 0xF6, 0x40, 0x1F, 0xFF, 0x01, 0xFF],
+            false,
+            false,
+        )
+        .unwrap();
+        let values = dbc.decode(&frame);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].value, 1000.0);
+        assert_eq!(values[1].raw, 1);
+        assert_eq!(values[2].value, -10.0);
+
+        // short payloads skip the signals beyond
+        let short = Frame::new(0x0CF004FE, &frame.data()[..4], false, false).unwrap();
+        assert_eq!(dbc.decode(&short).len(), 1);
+        assert!(dbc
+            .decode(&Frame::new(0x0CF004FE, &[0; 8], true, false).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_multiplexed() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let values = dbc.decode(&Frame::new(0x400, &[1, 0x04, 0xD2, 0], false, false).unwrap());
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1].signal.name, "Voltage");
+        assert!((values[1].value - 12.34).abs() < 1e-9);
+
+        let values = dbc.decode(&Frame::new(0x400, &[2, 0xFF, 0x9C, 0], false, false).unwrap());
+        assert_eq!(values[1].signal.name, "Current");
+        assert!((values[1].value + 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_decode_plan() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let plan = dbc
+            .compile(&[
+                ("Status", "Current"),
+                ("EEC1", "EngineSpeed"),
+                ("Status", "Voltage"),
+            ])
+            .unwrap();
+        assert_eq!(plan.len(), 3);
+        assert!(dbc.compile(&[("EEC1", "Speed")]).is_none());
+        assert!(dbc.compile(&[("EEC2", "EngineSpeed")]).is_none());
+
+        let eec1 = [0xFF, 0xFF, 0xF6, 0x40, 0x1F, 0xFF, 0x01, 0xFF];
+        let frames = [
+            Frame::new(0x400, &[1, 0x04, 0xD2, 0], false, false).unwrap(),
+            Frame::new(0x400, &[1, 0x04, 0xD3, 0], false, false).unwrap(),
+            Frame::new(0x0CF004FE, &eec1, false, false).unwrap(),
+            Frame::new(0x400, &[0; 4], true, false).unwrap(),
+        ];
+        let updates = plan.decode_batch(&frames);
+        assert_eq!(updates.updates, [0, 1, 2]);
+        assert_eq!(updates.values[0], None);
+        assert_eq!(updates.values[1], Some(1000.0));
+        assert!((updates.values[2].unwrap() - 12.35).abs() < 1e-9);
+
+        let mut values = [None; 3];
+        let frame = Frame::new(0x400, &[2, 0xFF, 0x9C, 0], false, false).unwrap();
+        assert_eq!(plan.decode(&frame, &mut values), 1);
+        assert!((values[0].unwrap() + 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_encode() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let values = HashMap::from([
+            ("EngineSpeed", 1000.0),
+            ("StarterMode", 1.0),
+            ("Torque", -10.0),
+        ]);
+        let frame = dbc.encode("EEC1", &values).unwrap();
+        assert_eq!(CanId::from(frame.id()), CanId::Extended(0x0CF004FE));
+        assert_eq!(frame.data(), &[0, 0, 0xF6, 0x40, 0x1F, 0, 0x01, 0]);
+        for value in dbc.decode(&frame) {
+            assert_eq!(value.value, values[value.signal.name.as_str()]);
+        }
+
+        let values = HashMap::from([("Page", 2.0), ("Current", -10.0)]);
+        let frame = dbc.encode("Status", &values).unwrap();
+        assert_eq!(frame.data(), &[2, 0xFF, 0x9C, 0]);
+
+        assert_eq!(
+            dbc.encode("Status", &HashMap::from([("Page", 1.0), ("Current", 1.0)])),
+            Err(EncodeError::NotSelected("Current".to_string()))
+        );
+        assert_eq!(
+            dbc.encode("EEC1", &HashMap::from([("Torque", 200.0)])),
+            Err(EncodeError::OutOfRange {
+                signal: "Torque".to_string(),
+                value: 200.0
+            })
+        );
+        assert!(matches!(
+            dbc.encode("EEC1", &HashMap::from([("Speed", 1.0)])),
+            Err(EncodeError::UnknownSignal(_))
+        ));
+        assert!(matches!(
+            dbc.encode("EEC2", &HashMap::new()),
+            Err(EncodeError::UnknownMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_vector_sections() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/vector.dbc");
+        let dbc = Dbc::from_file(path).unwrap();
+        assert_eq!(dbc.messages().count(), 1);
+        let diagnosis = dbc.message(CanId::Standard(0x500)).unwrap();
+        assert_eq!(
+            diagnosis.signal("Subfunction").unwrap().multiplexing,
+            Multiplexing::MultiplexedMultiplexor(1)
+        );
+        let session = diagnosis.signal("Session").unwrap();
+        assert_eq!(session.describe(3), Some("extended"));
+
+        let values = dbc.decode(&Frame::new(0x500, &[1, 2, 3, 0], false, false).unwrap());
+        let names: Vec<_> = values.iter().map(|v| v.signal.name.as_str()).collect();
+        assert_eq!(names, ["Service", "Subfunction", "Session"]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        match " SG_ Speed : 0|16@1+ (1,0) [0|1] \"\" X".parse::<Dbc>() {
+            Err(DbcError::Parse { line, what }) => {
+                assert_eq!(line, 1);
+                assert_eq!(what, "signal outside of a message");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(
+            "BO_ 291 Engine: 8 ECU\n SG_ Speed : 0|16@2+ (1,0) [0|1] \"\" X"
+                .parse::<Dbc>()
+                .is_err()
+        );
+    }
+}
@@ -1,11 +1,37 @@
 use crate::Frame;
 use embedded_can::ErrorKind;
-use std::{convert::TryFrom, io::Error};
+use std::{
+    convert::TryFrom,
+    error, fmt,
+    io::{Error, ErrorKind as ErrorKindIo},
+};
 
-/// Errors opening socket
+/// Errors of socket operations
+///
+/// Common conditions are mapped from their `errno` to dedicated variants,
+/// everything else is reported as `IOError`.
 #[derive(Debug)]
 pub enum SocketError {
-    /// System error while trying to look up device name
+    /// A read or write timeout set on the socket expired
+    Timeout,
+
+    /// The operation would block on a non-blocking socket
+    WouldBlock,
+
+    /// The transmit queue of the device is full (`ENOBUFS`)
+    TxQueueFull,
+
+    /// The interface is down (`ENETDOWN`)
+    InterfaceDown,
+
+    /// The interface does not exist
+    NoSuchDevice,
+
+    /// The socket is monitor-only and refuses to transmit, see
+    /// `Socket::set_monitor_only`
+    MonitorOnly,
+
+    /// Other system error
     IOError(Error),
 }
 
@@ -17,19 +43,117 @@ impl embedded_can::Error for SocketError {
 
 impl From<Error> for SocketError {
     fn from(e: Error) -> SocketError {
-        SocketError::IOError(e)
+        match e.raw_os_error() {
+            Some(libc::EAGAIN) => SocketError::WouldBlock,
+            Some(libc::ETIMEDOUT) => SocketError::Timeout,
+            Some(libc::ENOBUFS) => SocketError::TxQueueFull,
+            Some(libc::ENETDOWN) => SocketError::InterfaceDown,
+            Some(libc::ENODEV) | Some(libc::ENXIO) => SocketError::NoSuchDevice,
+            _ if e.get_ref().map_or(false, |e| e.is::<MonitorOnly>()) => SocketError::MonitorOnly,
+            _ if e.kind() == ErrorKindIo::WouldBlock => SocketError::WouldBlock,
+            _ if e.kind() == ErrorKindIo::TimedOut => SocketError::Timeout,
+            _ => SocketError::IOError(e),
+        }
+    }
+}
+
+impl fmt::Display for SocketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SocketError::Timeout => write!(f, "socket timeout"),
+            SocketError::WouldBlock => write!(f, "socket operation would block"),
+            SocketError::TxQueueFull => write!(f, "transmit queue full"),
+            SocketError::InterfaceDown => write!(f, "interface is down"),
+            SocketError::NoSuchDevice => write!(f, "no such CAN interface"),
+            SocketError::MonitorOnly => write!(f, "socket is monitor-only"),
+            SocketError::IOError(e) => write!(f, "socket error: {}", e),
+        }
+    }
+}
+
+impl error::Error for SocketError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SocketError::IOError(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
+/// Cause of the `io::Error` returned when transmitting on a monitor-only
+/// socket, mapped to `SocketError::MonitorOnly`
+#[derive(Debug)]
+pub(crate) struct MonitorOnly;
+
+impl MonitorOnly {
+    pub(crate) fn error() -> Error {
+        Error::new(ErrorKindIo::PermissionDenied, MonitorOnly)
+    }
+}
+
+impl fmt::Display for MonitorOnly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "socket is monitor-only")
+    }
+}
+
+impl error::Error for MonitorOnly {}
+
 #[derive(Debug, Copy, Clone)]
 /// Error that occurs when creating CAN packets
 pub enum ConstructionError {
     /// CAN ID was outside the range of valid IDs
     IDTooLarge,
-    /// More than 8 Bytes of payload data were passed in
+    /// More payload data was passed in than the frame can hold (8 bytes for
+    /// classic frames, 64 bytes for CAN FD frames, 2048 bytes for CAN XL
+    /// frames)
     TooMuchData,
 }
 
+impl fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstructionError::IDTooLarge => write!(f, "CAN id out of range"),
+            ConstructionError::TooMuchData => write!(f, "too much payload data for the frame"),
+        }
+    }
+}
+
+impl error::Error for ConstructionError {}
+
+/// Error parsing a frame in the `cansend` notation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The `#` between id and data is missing
+    MissingSeparator,
+    /// The id is not 3 (standard) or 8 (extended) hex digits
+    InvalidId,
+    /// The data is not a sequence of hex bytes, optionally separated by `.`
+    InvalidData,
+    /// The length of a remote frame is not a single digit up to 8
+    InvalidLength,
+    /// The flags of a CAN FD frame are not a single hex digit
+    InvalidFlags,
+    /// More payload data than the frame can hold
+    TooMuchData,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ParseError::MissingSeparator => "missing '#' separator",
+            ParseError::InvalidId => "invalid CAN id",
+            ParseError::InvalidData => "invalid payload data",
+            ParseError::InvalidLength => "invalid remote frame length",
+            ParseError::InvalidFlags => "invalid CAN FD flags",
+            ParseError::TooMuchData => "too much payload data for the frame",
+        };
+        write!(f, "invalid frame: {}", msg)
+    }
+}
+
+impl error::Error for ParseError {}
+
 /// Helper function to retrieve a specific byte of frame data or returning an
 /// `Err(..)` otherwise.
 fn get_data(frame: &Frame, idx: u8) -> Result<u8, DecodingError> {
@@ -67,7 +191,25 @@ pub enum DecodingError {
     InvalidTransceiverError,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodingError::NotAnError => write!(f, "not an error frame"),
+            DecodingError::UnknownErrorType(e) => write!(f, "unknown error type 0x{:X}", e),
+            DecodingError::NotEnoughData(idx) => {
+                write!(f, "error frame too short, missing byte {}", idx)
+            }
+            DecodingError::InvalidControllerProblem => write!(f, "invalid controller problem"),
+            DecodingError::InvalidViolationType => write!(f, "invalid protocol violation type"),
+            DecodingError::InvalidLocation => write!(f, "invalid protocol violation location"),
+            DecodingError::InvalidTransceiverError => write!(f, "invalid transceiver error"),
+        }
+    }
+}
+
+impl error::Error for DecodingError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum CanError {
     /// TX timeout (by netdevice driver)
     TransmitTimeout,
@@ -86,8 +228,8 @@ pub enum CanError {
         location: Location,
     },
 
-    /// Transceiver Error.
-    TransceiverError,
+    /// Transceiver Error, see `TransceiverError`
+    TransceiverError(TransceiverError),
 
     /// No ACK received for current CAN frame.
     NoAck,
@@ -105,7 +247,7 @@ pub enum CanError {
     Unknown(u32),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ControllerError {
     // unspecified
     Unspecified,
@@ -150,7 +292,7 @@ impl TryFrom<u8> for ControllerError {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ViolationType {
     /// Unspecified Violation
     Unspecified,
@@ -202,7 +344,7 @@ impl TryFrom<u8> for ViolationType {
 /// Location
 ///
 /// Describes where inside a received frame an error occured.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Location {
     /// Unspecified
     Unspecified,
@@ -295,16 +437,37 @@ impl TryFrom<u8> for Location {
     }
 }
 
+/// Wiring problem reported by the transceiver
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TransceiverError {
+    /// Unspecified
     Unspecified,
+
+    /// CAN high not connected
     CanHighNoWire,
+
+    /// CAN high shorted to battery voltage
     CanHighShortToBat,
+
+    /// CAN high shorted to supply voltage
     CanHighShortToVcc,
+
+    /// CAN high shorted to ground
     CanHighShortToGnd,
+
+    /// CAN low not connected
     CanLowNoWire,
+
+    /// CAN low shorted to battery voltage
     CanLowShortToBat,
+
+    /// CAN low shorted to supply voltage
     CanLowShortToVcc,
+
+    /// CAN low shorted to ground
     CanLowShortToGnd,
+
+    /// CAN low shorted to CAN high
     CanLowShortToCanHigh,
 }
 
@@ -328,13 +491,69 @@ impl TryFrom<u8> for TransceiverError {
     }
 }
 
+impl fmt::Display for CanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanError::TransmitTimeout => write!(f, "transmit timeout"),
+            CanError::LostArbitration(0) => write!(f, "arbitration lost"),
+            CanError::LostArbitration(bit) => write!(f, "arbitration lost at bit {}", bit),
+            CanError::ControllerProblem(e) => write!(f, "controller problem: {:?}", e),
+            CanError::ProtocolViolation { vtype, location } => {
+                write!(f, "protocol violation: {:?} at {:?}", vtype, location)
+            }
+            CanError::TransceiverError(e) => write!(f, "transceiver error: {:?}", e),
+            CanError::NoAck => write!(f, "no ACK received"),
+            CanError::BusOff => write!(f, "bus off"),
+            CanError::BusError => write!(f, "bus error"),
+            CanError::Restarted => write!(f, "controller restarted"),
+            CanError::Unknown(e) => write!(f, "unknown error 0x{:X}", e),
+        }
+    }
+}
+
+impl error::Error for CanError {}
+
+/// `CAN_ERR_CNT`, error counters are available in `data[6..8]`
+const CAN_ERR_CNT: u32 = 0x00000200;
+
+/// Error counters of the CAN controller
+///
+/// Reported along with other errors by drivers which can read the counters
+/// from the controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorCounters {
+    /// Transmit error counter
+    pub tx: u8,
+    /// Receive error counter
+    pub rx: u8,
+}
+
+impl ErrorCounters {
+    /// Decode the error counters of an error frame.
+    ///
+    /// Returns `None` if the frame does not carry error counters.
+    pub fn from_frame(frame: &Frame) -> Option<ErrorCounters> {
+        if !frame.is_error() || frame.err() & CAN_ERR_CNT == 0 {
+            return None;
+        }
+        Some(ErrorCounters {
+            tx: get_data(frame, 6).ok()?,
+            rx: get_data(frame, 7).ok()?,
+        })
+    }
+}
+
 impl CanError {
+    /// Decode the error of an error frame.
+    ///
+    /// Error counters reported along with the error are decoded by
+    /// `ErrorCounters::from_frame`.
     pub fn from_frame(frame: &Frame) -> Result<CanError, DecodingError> {
         if !frame.is_error() {
             return Err(DecodingError::NotAnError);
         }
 
-        match frame.err() {
+        match frame.err() & !CAN_ERR_CNT {
             0x00000001 => Ok(CanError::TransmitTimeout),
             0x00000002 => Ok(CanError::LostArbitration(get_data(frame, 0)?)),
             0x00000004 => Ok(CanError::ControllerProblem(ControllerError::try_from(
@@ -346,7 +565,9 @@ impl CanError {
                 location: Location::try_from(get_data(frame, 3)?)?,
             }),
 
-            0x00000010 => Ok(CanError::TransceiverError),
+            0x00000010 => Ok(CanError::TransceiverError(TransceiverError::try_from(
+                get_data(frame, 4)?,
+            )?)),
             0x00000020 => Ok(CanError::NoAck),
             0x00000040 => Ok(CanError::BusOff),
             0x00000080 => Ok(CanError::BusError),
@@ -371,3 +592,47 @@ impl ControllerSpecificErrorInformation for Frame {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CanError, ErrorCounters, SocketError, TransceiverError};
+    use crate::Frame;
+
+    #[test]
+    fn test_transceiver_error() {
+        let frame = Frame::new(0x10, &[0, 0, 0, 0, 0x07, 0, 0, 0], false, true).unwrap();
+        assert!(matches!(
+            frame.error(),
+            Ok(CanError::TransceiverError(
+                TransceiverError::CanHighShortToGnd
+            ))
+        ));
+        assert_eq!(ErrorCounters::from_frame(&frame), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let error: Box<dyn std::error::Error> = Box::new(SocketError::from(
+            std::io::Error::from_raw_os_error(libc::EIO),
+        ));
+        assert!(error.to_string().starts_with("socket error: "));
+        assert!(error.source().is_some());
+        let error = SocketError::from(std::io::Error::from_raw_os_error(libc::ENOBUFS));
+        assert!(matches!(error, SocketError::TxQueueFull));
+        assert_eq!(
+            CanError::LostArbitration(3).to_string(),
+            "arbitration lost at bit 3"
+        );
+    }
+
+    #[test]
+    fn test_error_counters() {
+        // CAN_ERR_CRTL | CAN_ERR_CNT, error passive
+        let frame = Frame::new(0x204, &[0, 0x20, 0, 0, 0, 0, 128, 96], false, true).unwrap();
+        assert!(matches!(frame.error(), Ok(CanError::ControllerProblem(_))));
+        assert_eq!(
+            frame.error_counters(),
+            Some(ErrorCounters { tx: 128, rx: 96 })
+        );
+    }
+}
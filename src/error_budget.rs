@@ -0,0 +1,200 @@
+use crate::{CanError, Clock, SystemClock};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Class of a `CanError`, ignoring the details
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// `CanError::TransmitTimeout`
+    TransmitTimeout,
+    /// `CanError::LostArbitration`
+    LostArbitration,
+    /// `CanError::ControllerProblem`
+    ControllerProblem,
+    /// `CanError::ProtocolViolation`
+    ProtocolViolation,
+    /// `CanError::TransceiverError`
+    TransceiverError,
+    /// `CanError::NoAck`
+    NoAck,
+    /// `CanError::BusOff`
+    BusOff,
+    /// `CanError::BusError`
+    BusError,
+    /// `CanError::Restarted`
+    Restarted,
+    /// `CanError::Unknown`
+    Unknown,
+}
+
+impl From<&CanError> for ErrorClass {
+    fn from(error: &CanError) -> ErrorClass {
+        match error {
+            CanError::TransmitTimeout => ErrorClass::TransmitTimeout,
+            CanError::LostArbitration(_) => ErrorClass::LostArbitration,
+            CanError::ControllerProblem(_) => ErrorClass::ControllerProblem,
+            CanError::ProtocolViolation { .. } => ErrorClass::ProtocolViolation,
+            CanError::TransceiverError(_) => ErrorClass::TransceiverError,
+            CanError::NoAck => ErrorClass::NoAck,
+            CanError::BusOff => ErrorClass::BusOff,
+            CanError::BusError => ErrorClass::BusError,
+            CanError::Restarted => ErrorClass::Restarted,
+            CanError::Unknown(_) => ErrorClass::Unknown,
+        }
+    }
+}
+
+/// Assessment of a recorded error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorCondition {
+    /// Isolated error, the budget of its class is not exhausted
+    Glitch(ErrorClass),
+
+    /// More errors of this class than allowed occurred within the window
+    Storm(ErrorClass),
+}
+
+/// Error budget tracking
+///
+/// Counts the errors of each `ErrorClass` within a sliding window and
+/// compares them against a threshold, so that error storms can be told apart
+/// from isolated glitches. The time of an error is taken from a `Clock` when
+/// it is recorded.
+#[derive(Debug, Clone)]
+pub struct ErrorBudget<C = SystemClock> {
+    clock: C,
+    window: Duration,
+    default_threshold: usize,
+    thresholds: HashMap<ErrorClass, usize>,
+    errors: VecDeque<(Instant, ErrorClass)>,
+    counts: HashMap<ErrorClass, usize>,
+}
+
+impl ErrorBudget {
+    /// Create a budget allowing `threshold` errors of each class within
+    /// `window`.
+    pub fn new(window: Duration, threshold: usize) -> ErrorBudget {
+        ErrorBudget {
+            clock: SystemClock,
+            window,
+            default_threshold: threshold,
+            thresholds: HashMap::new(),
+            errors: VecDeque::new(),
+            counts: HashMap::new(),
+        }
+    }
+}
+
+impl<C: Clock> ErrorBudget<C> {
+    /// Use `clock` to timestamp the errors
+    pub fn with_clock<D: Clock>(self, clock: D) -> ErrorBudget<D> {
+        ErrorBudget {
+            clock,
+            window: self.window,
+            default_threshold: self.default_threshold,
+            thresholds: self.thresholds,
+            errors: VecDeque::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Set the number of errors of `class` allowed within the window
+    pub fn threshold(mut self, class: ErrorClass, threshold: usize) -> ErrorBudget<C> {
+        self.thresholds.insert(class, threshold);
+        self
+    }
+
+    /// Record an error which occurred just now
+    pub fn record(&mut self, error: &CanError) -> ErrorCondition {
+        let class = ErrorClass::from(error);
+        let now = self.clock.now();
+        self.expire();
+        self.errors.push_back((now, class));
+        *self.counts.entry(class).or_insert(0) += 1;
+
+        if self.count(class) > self.allowed(class) {
+            ErrorCondition::Storm(class)
+        } else {
+            ErrorCondition::Glitch(class)
+        }
+    }
+
+    /// Forget errors which left the window
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        while let Some((t, class)) = self.errors.front() {
+            if now.saturating_duration_since(*t) < self.window {
+                break;
+            }
+            if let Some(count) = self.counts.get_mut(class) {
+                *count -= 1;
+            }
+            self.errors.pop_front();
+        }
+    }
+
+    /// Number of errors of `class` within the window, as of the last
+    /// `record` or `expire`
+    pub fn count(&self, class: ErrorClass) -> usize {
+        self.counts.get(&class).copied().unwrap_or(0)
+    }
+
+    /// Classes which currently exceed their budget, as of the last `record`
+    /// or `expire`
+    pub fn storms(&self) -> Vec<ErrorClass> {
+        self.counts
+            .iter()
+            .filter(|(class, count)| **count > self.allowed(**class))
+            .map(|(class, _)| *class)
+            .collect()
+    }
+
+    fn allowed(&self, class: ErrorClass) -> usize {
+        self.thresholds
+            .get(&class)
+            .copied()
+            .unwrap_or(self.default_threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorBudget, ErrorClass, ErrorCondition};
+    use crate::{CanError, ManualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn test_storm() {
+        let clock = ManualClock::new();
+        let mut budget = ErrorBudget::new(Duration::from_secs(1), 2)
+            .threshold(ErrorClass::BusOff, 0)
+            .with_clock(clock.clone());
+
+        for _ in 0..2 {
+            let condition = budget.record(&CanError::NoAck);
+            assert_eq!(condition, ErrorCondition::Glitch(ErrorClass::NoAck));
+            clock.advance(Duration::from_millis(100));
+        }
+        let condition = budget.record(&CanError::NoAck);
+        assert_eq!(condition, ErrorCondition::Storm(ErrorClass::NoAck));
+        assert_eq!(budget.storms(), vec![ErrorClass::NoAck]);
+
+        // the first two errors leave the window
+        clock.advance(Duration::from_millis(950));
+        let condition = budget.record(&CanError::NoAck);
+        assert_eq!(condition, ErrorCondition::Glitch(ErrorClass::NoAck));
+        assert_eq!(budget.count(ErrorClass::NoAck), 2);
+
+        clock.advance(Duration::from_millis(50));
+        let condition = budget.record(&CanError::BusOff);
+        assert_eq!(condition, ErrorCondition::Storm(ErrorClass::BusOff));
+        assert_eq!(budget.storms(), vec![ErrorClass::BusOff]);
+
+        clock.advance(Duration::from_secs(1));
+        budget.expire();
+        assert_eq!(budget.count(ErrorClass::BusOff), 0);
+        assert!(budget.storms().is_empty());
+    }
+}
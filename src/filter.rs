@@ -0,0 +1,382 @@
+use crate::Frame;
+use libc::{CAN_EFF_FLAG, CAN_EFF_MASK, CAN_RTR_FLAG, CAN_SFF_MASK};
+use std::ops::RangeInclusive;
+
+/// `CAN_INV_FILTER` from `linux/can.h`
+const CAN_INV_FILTER: u32 = 0x2000_0000;
+
+/// CAN ID filter
+///
+/// A received frame matches a filter if `received_id & mask == id & mask`,
+/// where both ids include the EFF/RTR/ERR flags. An inverted filter accepts
+/// exactly the frames the filter would otherwise reject. Uses the same memory
+/// layout as the kernel `can_filter` struct.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct Filter {
+    id: u32,
+    mask: u32,
+}
+
+impl Filter {
+    /// Create a filter from a raw id and mask, including flags.
+    pub fn new(id: u32, mask: u32) -> Filter {
+        Filter { id, mask }
+    }
+
+    /// Filter accepting every frame
+    pub fn accept_all() -> Filter {
+        Filter::new(0, 0)
+    }
+
+    /// Filter accepting data and remote frames with the standard id `id`.
+    pub fn standard(id: u16) -> Filter {
+        Filter::new(id as u32 & CAN_SFF_MASK, CAN_SFF_MASK | CAN_EFF_FLAG)
+    }
+
+    /// Filter accepting data and remote frames with the extended id `id`.
+    pub fn extended(id: u32) -> Filter {
+        Filter::new(
+            (id & CAN_EFF_MASK) | CAN_EFF_FLAG,
+            CAN_EFF_MASK | CAN_EFF_FLAG,
+        )
+    }
+
+    /// Filter accepting exactly the standard ids in `ids`, if they can be
+    /// expressed by a single id and mask, e.g. `0x100..=0x1FF`.
+    ///
+    /// Use `standard_range` for arbitrary ranges.
+    pub fn from_range(ids: RangeInclusive<u16>) -> Option<Filter> {
+        match Filter::standard_range(ids).as_slice() {
+            [filter] => Some(*filter),
+            _ => None,
+        }
+    }
+
+    /// Smallest set of id/mask filters accepting exactly the standard ids
+    /// in `ids`.
+    ///
+    /// The range is split into aligned blocks of a power of two ids, e.g.
+    /// `0x100..=0x17F, 0x180..=0x1BF` for `0x100..=0x1BF`.
+    pub fn standard_range(ids: RangeInclusive<u16>) -> Vec<Filter> {
+        let (start, end) = ids.into_inner();
+        split_range(start as u32, end as u32, CAN_SFF_MASK)
+            .map(|(id, mask)| Filter::standard(id as u16).with_mask(mask))
+            .collect()
+    }
+
+    /// Smallest set of id/mask filters accepting exactly the extended ids
+    /// in `ids`, see `standard_range`.
+    pub fn extended_range(ids: RangeInclusive<u32>) -> Vec<Filter> {
+        let (start, end) = ids.into_inner();
+        split_range(start, end, CAN_EFF_MASK)
+            .map(|(id, mask)| Filter::extended(id).with_mask(mask))
+            .collect()
+    }
+
+    /// Replace the id bits of the mask, keeping the flag bits.
+    ///
+    /// Only id bits set in `mask` are compared, e.g.
+    /// `Filter::standard(0x100).with_mask(0x700)` accepts 0x100 to 0x1FF.
+    pub fn with_mask(self, mask: u32) -> Filter {
+        let flags = self.mask & !CAN_EFF_MASK;
+        Filter::new(self.id, flags | (mask & CAN_EFF_MASK))
+    }
+
+    /// Accept only remote frames, for standard and extended ids alike.
+    pub fn remote_only(self) -> Filter {
+        Filter::new(self.id | CAN_RTR_FLAG, self.mask | CAN_RTR_FLAG)
+    }
+
+    /// Accept only data frames, ignoring remote requests.
+    pub fn data_only(self) -> Filter {
+        Filter::new(self.id & !CAN_RTR_FLAG, self.mask | CAN_RTR_FLAG)
+    }
+
+    /// Accept data and remote frames (default).
+    pub fn allow_remote(self) -> Filter {
+        Filter::new(self.id & !CAN_RTR_FLAG, self.mask & !CAN_RTR_FLAG)
+    }
+
+    /// Invert the filter, accepting everything except the matching frames.
+    ///
+    /// Sets the kernel `CAN_INV_FILTER` flag, e.g.
+    /// `Filter::standard(0x7DF).invert()` accepts all frames but 0x7DF.
+    pub fn invert(self) -> Filter {
+        Filter::new(self.id ^ CAN_INV_FILTER, self.mask)
+    }
+
+    /// Whether the filter is inverted
+    pub fn is_inverted(&self) -> bool {
+        self.id & CAN_INV_FILTER != 0
+    }
+
+    /// Raw id, including flags
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Raw mask, including flags
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Check whether a frame would be accepted by this filter
+    pub fn matches(&self, frame: &Frame) -> bool {
+        let id = self.id & !CAN_INV_FILTER;
+        (frame.can_id() & self.mask == id & self.mask) != self.is_inverted()
+    }
+}
+
+/// Split `start..=end`, clamped to `id_mask`, into aligned blocks of a power
+/// of two ids, yielding the first id and the mask of each block.
+fn split_range(start: u32, end: u32, id_mask: u32) -> impl Iterator<Item = (u32, u32)> {
+    let end = end.min(id_mask) as u64;
+    let mut next = start as u64;
+    std::iter::from_fn(move || {
+        if next > end {
+            return None;
+        }
+        // largest block aligned at `next` which does not exceed `end`
+        let mut size = if next == 0 {
+            id_mask as u64 + 1
+        } else {
+            next & next.wrapping_neg()
+        };
+        while next + size - 1 > end {
+            size >>= 1;
+        }
+        let block = (next as u32, !(size as u32 - 1) & id_mask);
+        next += size;
+        Some(block)
+    })
+}
+
+/// Set of filters installed on a socket together
+///
+/// By default a frame is accepted if it matches any filter of the group. If
+/// `join` is enabled, it has to match all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterGroup {
+    filters: Vec<Filter>,
+    join: bool,
+}
+
+impl FilterGroup {
+    /// Create an empty group, which accepts no frames
+    pub fn new() -> FilterGroup {
+        FilterGroup::default()
+    }
+
+    /// Add a filter to the group
+    pub fn filter(mut self, filter: Filter) -> FilterGroup {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Require frames to match all filters instead of any
+    pub fn join(mut self, join: bool) -> FilterGroup {
+        self.join = join;
+        self
+    }
+
+    /// Filters of the group
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Whether frames have to match all filters
+    pub fn is_join(&self) -> bool {
+        self.join
+    }
+
+    /// Check whether a frame would be accepted by this group
+    pub fn matches(&self, frame: &Frame) -> bool {
+        if self.join {
+            !self.filters.is_empty() && self.filters.iter().all(|f| f.matches(frame))
+        } else {
+            self.filters.iter().any(|f| f.matches(frame))
+        }
+    }
+
+    /// Install the group with the two socket options `set_join` and
+    /// `set_filters`.
+    ///
+    /// A filter list accepts more frames without join than with, so join
+    /// is disabled before and enabled after replacing the filters. In
+    /// between, the socket accepts at least the frames of the old or of the
+    /// new group, possibly more, but never drops a frame both accept.
+    pub(crate) fn install<E>(
+        &self,
+        mut set_join: impl FnMut(bool) -> Result<(), E>,
+        mut set_filters: impl FnMut(&[Filter]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.join {
+            set_filters(&self.filters)?;
+            set_join(true)
+        } else {
+            set_join(false)?;
+            set_filters(&self.filters)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, FilterGroup};
+    use crate::Frame;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_standard_filter() {
+        let filter = Filter::standard(0x123);
+        assert!(filter.matches(&Frame::new(0x123, &[], false, false).unwrap()));
+        assert!(filter.matches(&Frame::new(0x123, &[], true, false).unwrap()));
+        assert!(!filter.matches(&Frame::new(0x124, &[], false, false).unwrap()));
+        assert!(!filter.matches(&Frame::new(0x1000, &[], false, false).unwrap()));
+    }
+
+    #[test]
+    fn test_masked_filter() {
+        let filter = Filter::standard(0x100).with_mask(0x700);
+        assert!(filter.matches(&Frame::new(0x100, &[], false, false).unwrap()));
+        assert!(filter.matches(&Frame::new(0x1FF, &[], false, false).unwrap()));
+        assert!(!filter.matches(&Frame::new(0x200, &[], false, false).unwrap()));
+    }
+
+    #[test]
+    fn test_remote_filter() {
+        let remote = Frame::builder()
+            .id(0x18FEF100)
+            .rtr()
+            .extended()
+            .build()
+            .unwrap();
+        let data = Frame::builder().id(0x18FEF100).extended().build().unwrap();
+
+        let filter = Filter::extended(0x18FEF100);
+        assert!(filter.matches(&remote) && filter.matches(&data));
+        assert!(filter.remote_only().matches(&remote));
+        assert!(!filter.remote_only().matches(&data));
+        assert!(filter.data_only().matches(&data));
+        assert!(!filter.data_only().matches(&remote));
+        assert_eq!(filter.remote_only().allow_remote(), filter);
+
+        let standard = Frame::new(0x123, &[0; 2], true, false).unwrap();
+        assert!(Filter::standard(0x123).remote_only().matches(&standard));
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            Filter::from_range(0x100..=0x1FF),
+            Some(Filter::standard(0x100).with_mask(0x700))
+        );
+        assert_eq!(Filter::from_range(0x100..=0x1BF), None);
+        assert_eq!(
+            Filter::standard_range(0x100..=0x1BF),
+            vec![
+                Filter::standard(0x100).with_mask(0x780),
+                Filter::standard(0x180).with_mask(0x7C0)
+            ]
+        );
+        assert_eq!(
+            Filter::standard_range(0..=0x7FF),
+            vec![Filter::standard(0).with_mask(0)]
+        );
+        let (start, end) = (0x200, 0x100);
+        assert!(Filter::standard_range(start..=end).is_empty());
+
+        let filters = Filter::extended_range(0x18FEF0FF..=0x18FEF2FF);
+        assert_eq!(filters.len(), 3);
+        let accepts = |id| {
+            let frame = Frame::builder().id(id).extended().build().unwrap();
+            filters.iter().any(|f| f.matches(&frame))
+        };
+        assert!(accepts(0x18FEF0FF) && accepts(0x18FEF100) && accepts(0x18FEF2FF));
+        assert!(!accepts(0x18FEF0FE) && !accepts(0x18FEF300));
+    }
+
+    #[test]
+    fn test_inverted_filter() {
+        let filter = Filter::standard(0x100).with_mask(0x700).invert();
+        assert!(filter.is_inverted());
+        assert!(!filter.matches(&Frame::new(0x1FF, &[], false, false).unwrap()));
+        assert!(filter.matches(&Frame::new(0x200, &[], false, false).unwrap()));
+        let extended = Frame::builder().id(0x100).extended().build().unwrap();
+        assert!(filter.matches(&extended));
+        assert_eq!(filter.invert(), Filter::standard(0x100).with_mask(0x700));
+    }
+
+    #[test]
+    fn test_join() {
+        let group = FilterGroup::new()
+            .filter(Filter::standard(0x100).with_mask(0x700))
+            .filter(Filter::standard(0x0F0).with_mask(0x0F0));
+        let frame = Frame::new(0x1F0, &[], false, false).unwrap();
+        let other = Frame::new(0x101, &[], false, false).unwrap();
+        assert!(group.matches(&frame));
+        assert!(group.matches(&other));
+
+        let group = group.join(true);
+        assert!(group.matches(&frame));
+        assert!(!group.matches(&other));
+    }
+
+    #[test]
+    fn test_install_order() {
+        let frames: Vec<Frame> = [0x100, 0x123, 0x1FF, 0x200]
+            .iter()
+            .map(|&id| Frame::new(id, &[], false, false).unwrap())
+            .collect();
+        let low = Filter::standard(0x100).with_mask(0x700);
+        let filters = [
+            vec![low],
+            vec![low, Filter::standard(0x123)],
+            vec![low, Filter::standard(0x200)],
+        ];
+        let groups: Vec<FilterGroup> = [false, true]
+            .iter()
+            .flat_map(|&join| {
+                filters.iter().map(move |f| {
+                    f.iter()
+                        .copied()
+                        .fold(FilterGroup::new(), FilterGroup::filter)
+                        .join(join)
+                })
+            })
+            .collect();
+
+        for old in &groups {
+            for new in &groups {
+                // frames accepted by both groups are never dropped meanwhile
+                let installed = RefCell::new(old.clone());
+                let update = |group: FilterGroup| {
+                    for frame in frames.iter().filter(|f| old.matches(f) && new.matches(f)) {
+                        assert!(group.matches(frame), "{:?} -> {:?}", old, new);
+                    }
+                    *installed.borrow_mut() = group;
+                    Ok::<_, ()>(())
+                };
+                new.install(
+                    |join| {
+                        let group = installed.borrow().clone();
+                        update(group.join(join))
+                    },
+                    |filters| {
+                        let join = installed.borrow().is_join();
+                        update(
+                            filters
+                                .iter()
+                                .copied()
+                                .fold(FilterGroup::new(), FilterGroup::filter)
+                                .join(join),
+                        )
+                    },
+                )
+                .unwrap();
+                assert_eq!(&*installed.borrow(), new);
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{error, fmt};
+
+/// Bootloader protocol used by `Flasher`
+///
+/// Implemented by protocol adapters (UDS `RequestDownload`/`TransferData`,
+/// proprietary bootloaders, ...) which know how to move a block of the image
+/// to the target.
+pub trait FlashProtocol {
+    /// Error type reported by the adapter
+    type Error;
+
+    /// Largest block the target accepts in a single transfer
+    fn max_block_size(&self) -> usize;
+
+    /// Prepare the target for receiving an image of `len` bytes, starting at
+    /// `offset`. `offset` is non-zero when resuming an interrupted transfer.
+    fn start(&mut self, len: usize, offset: usize) -> Result<(), Self::Error>;
+
+    /// Transfer block number `block`, which starts at `offset` in the image.
+    ///
+    /// Called again with the same arguments when the transfer is retried.
+    fn transfer(&mut self, block: usize, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Finish the transfer.
+    ///
+    /// Returns the CRC-32 of the image as computed by the target, if the
+    /// target supports it. It is compared against the CRC of the image sent.
+    fn finish(&mut self) -> Result<Option<u32>, Self::Error>;
+}
+
+/// Error flashing an image
+#[derive(Debug)]
+pub enum FlashError<E> {
+    /// The protocol adapter failed. Contains the offset up to which the image
+    /// was transferred successfully, which can be passed to
+    /// `Flasher::resume`.
+    Protocol { error: E, offset: usize },
+
+    /// The CRC reported by the target does not match the image
+    CrcMismatch { expected: u32, actual: u32 },
+}
+
+impl<E: fmt::Display> fmt::Display for FlashError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlashError::Protocol { error, offset } => {
+                write!(f, "transfer failed at offset {}: {}", offset, error)
+            }
+            FlashError::CrcMismatch { expected, actual } => write!(
+                f,
+                "CRC mismatch, expected 0x{:08X}, target reported 0x{:08X}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for FlashError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FlashError::Protocol { error, .. } => Some(error),
+            FlashError::CrcMismatch { .. } => None,
+        }
+    }
+}
+
+/// Transfer progress
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of bytes transferred
+    pub transferred: usize,
+    /// Size of the image
+    pub total: usize,
+}
+
+/// Block transfer flashing
+///
+/// Splits an image into blocks, transfers them using a `FlashProtocol`
+/// adapter, retries failed blocks and verifies the result.
+pub struct Flasher<P> {
+    protocol: P,
+    block_size: usize,
+    retries: usize,
+    progress: Option<Box<dyn FnMut(Progress)>>,
+}
+
+impl<P: FlashProtocol> Flasher<P> {
+    /// Create a flasher using the largest block size supported by `protocol`
+    /// and 3 retries per block.
+    pub fn new(protocol: P) -> Flasher<P> {
+        Flasher {
+            block_size: protocol.max_block_size(),
+            protocol,
+            retries: 3,
+            progress: None,
+        }
+    }
+
+    /// Limit the block size. Values above the protocol maximum are ignored.
+    pub fn block_size(mut self, block_size: usize) -> Flasher<P> {
+        self.block_size = block_size.clamp(1, self.protocol.max_block_size());
+        self
+    }
+
+    /// Set how often a failing block is retried before giving up
+    pub fn retries(mut self, retries: usize) -> Flasher<P> {
+        self.retries = retries;
+        self
+    }
+
+    /// Register a callback invoked after every transferred block
+    pub fn on_progress<F: FnMut(Progress) + 'static>(mut self, f: F) -> Flasher<P> {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Access the protocol adapter
+    pub fn protocol(&mut self) -> &mut P {
+        &mut self.protocol
+    }
+
+    /// Transfer `image` to the target
+    pub fn flash(&mut self, image: &[u8]) -> Result<(), FlashError<P::Error>> {
+        self.resume(image, 0)
+    }
+
+    /// Transfer `image` to the target, skipping the first `offset` bytes
+    /// which have already been transferred.
+    pub fn resume(&mut self, image: &[u8], offset: usize) -> Result<(), FlashError<P::Error>> {
+        let offset = offset.min(image.len());
+        self.protocol
+            .start(image.len(), offset)
+            .map_err(|error| FlashError::Protocol { error, offset })?;
+
+        let mut offset = offset;
+        while offset < image.len() {
+            let block = offset / self.block_size;
+            let end = (offset + self.block_size).min(image.len());
+            self.transfer_block(block, offset, &image[offset..end])?;
+
+            offset = end;
+            if let Some(progress) = self.progress.as_mut() {
+                progress(Progress {
+                    transferred: offset,
+                    total: image.len(),
+                });
+            }
+        }
+
+        let actual = self
+            .protocol
+            .finish()
+            .map_err(|error| FlashError::Protocol { error, offset })?;
+        let expected = crc32(image);
+        match actual {
+            Some(actual) if actual != expected => Err(FlashError::CrcMismatch { expected, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    fn transfer_block(
+        &mut self,
+        block: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), FlashError<P::Error>> {
+        let mut attempt = 0;
+        loop {
+            match self.protocol.transfer(block, offset, data) {
+                Ok(()) => return Ok(()),
+                Err(_) if attempt < self.retries => attempt += 1,
+                Err(error) => return Err(FlashError::Protocol { error, offset }),
+            }
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, FlashError, FlashProtocol, Flasher};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Target that fails every `fail_every`th transfer attempt
+    #[derive(Default)]
+    struct Target {
+        memory: Vec<u8>,
+        attempts: usize,
+        fail_every: usize,
+    }
+
+    impl FlashProtocol for Target {
+        type Error = ();
+
+        fn max_block_size(&self) -> usize {
+            16
+        }
+
+        fn start(&mut self, len: usize, offset: usize) -> Result<(), ()> {
+            self.memory.resize(len, 0);
+            assert!(offset <= len);
+            Ok(())
+        }
+
+        fn transfer(&mut self, _block: usize, offset: usize, data: &[u8]) -> Result<(), ()> {
+            self.attempts += 1;
+            if self.attempts == self.fail_every {
+                self.attempts = 0;
+                return Err(());
+            }
+            self.memory[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<Option<u32>, ()> {
+            Ok(Some(crc32(&self.memory)))
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_flash_with_retries() {
+        let image: Vec<u8> = (0..100).collect();
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let log = progress.clone();
+
+        let mut flasher = Flasher::new(Target {
+            fail_every: 2,
+            ..Target::default()
+        })
+        .on_progress(move |p| log.borrow_mut().push(p.transferred));
+
+        flasher.flash(&image).unwrap();
+        assert_eq!(flasher.protocol().memory, image);
+        assert_eq!(*progress.borrow(), vec![16, 32, 48, 64, 80, 96, 100]);
+    }
+
+    #[test]
+    fn test_resume() {
+        let image: Vec<u8> = (0..100).collect();
+        let mut flasher = Flasher::new(Target {
+            fail_every: 3,
+            ..Target::default()
+        })
+        .retries(0);
+
+        let offset = match flasher.flash(&image) {
+            Err(FlashError::Protocol { offset, .. }) => offset,
+            _ => panic!("flashing should fail"),
+        };
+        assert_eq!(offset, 32);
+
+        flasher.protocol().fail_every = 0;
+        flasher.resume(&image, offset).unwrap();
+        assert_eq!(flasher.protocol().memory, image);
+    }
+}
@@ -1,5 +1,16 @@
-use crate::{CanError, ConstructionError, DecodingError};
-use libc::{CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK, CAN_RTR_FLAG, CAN_SFF_MASK};
+use crate::{CanError, CanId, ConstructionError, DecodingError, ErrorCounters, ParseError};
+use libc::{
+    CANFD_BRS, CANFD_ESI, CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK,
+    CAN_RTR_FLAG, CAN_SFF_MASK,
+};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+/// Valid payload lengths of CAN FD frames
+const FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
 
 /// Frame
 ///
@@ -23,6 +34,10 @@ pub struct Frame {
 }
 
 impl Frame {
+    /// Create a frame. Ids above 0x7FF are extended ids.
+    ///
+    /// For remote frames only the length of `data` is used, as data length
+    /// code.
     pub fn new(id: u32, data: &[u8], rtr: bool, err: bool) -> Result<Frame, ConstructionError> {
         let mut id = id;
 
@@ -39,33 +54,192 @@ impl Frame {
             id |= CAN_EFF_FLAG;
         }
 
-        if rtr {
-            id |= CAN_RTR_FLAG;
-        }
-
         if err {
             id |= CAN_ERR_FLAG;
         }
 
+        if rtr {
+            return Frame::remote(id, data.len());
+        }
+
         let mut full_data = [0; 8];
+        full_data[..data.len()].copy_from_slice(data);
 
-        // not cool =/
-        for (n, c) in data.iter().enumerate() {
-            full_data[n] = *c;
+        Ok(Frame {
+            id,
+            dlc: data.len() as u8,
+            pad: 0,
+            res0: 0,
+            res1: 0,
+            data: full_data,
+        })
+    }
+
+    /// Remote frame requesting `dlc` bytes, `id` including the EFF/ERR flags
+    fn remote(id: u32, dlc: usize) -> Result<Frame, ConstructionError> {
+        if dlc > 8 {
+            return Err(ConstructionError::TooMuchData);
         }
 
         Ok(Frame {
+            id: id | CAN_RTR_FLAG,
+            dlc: dlc as u8,
+            ..Frame::default()
+        })
+    }
+
+    /// Start building a frame, as an alternative to the positional
+    /// arguments of `new`
+    ///
+    /// ```
+    /// use candev::Frame;
+    ///
+    /// let frame = Frame::builder().id(0x123).data(&[1, 2]).extended().build().unwrap();
+    /// ```
+    pub fn builder() -> FrameBuilder {
+        FrameBuilder::default()
+    }
+
+    /// Data frame with a standard id, usable in constants.
+    ///
+    /// Panics if the id is above 0x7FF or there are more than 8 bytes of
+    /// data, which is a compile error when evaluated in a `const`.
+    pub const fn new_standard(id: u16, data: &[u8]) -> Frame {
+        if id as u32 > CAN_SFF_MASK {
+            panic!("standard id above 0x7FF");
+        }
+        Frame::from_parts(id as u32, data)
+    }
+
+    /// Data frame with an extended id, usable in constants.
+    ///
+    /// Panics if the id is above 0x1FFFFFFF or there are more than 8 bytes
+    /// of data, which is a compile error when evaluated in a `const`.
+    pub const fn new_extended(id: u32, data: &[u8]) -> Frame {
+        if id > CAN_EFF_MASK {
+            panic!("extended id above 0x1FFFFFFF");
+        }
+        Frame::from_parts(id | CAN_EFF_FLAG, data)
+    }
+
+    const fn from_parts(id: u32, data: &[u8]) -> Frame {
+        if data.len() > 8 {
+            panic!("more than 8 bytes of data");
+        }
+
+        let mut full_data = [0; 8];
+        let mut n = 0;
+        while n < data.len() {
+            full_data[n] = data[n];
+            n += 1;
+        }
+
+        Frame {
             id,
             dlc: data.len() as u8,
             pad: 0,
             res0: 0,
             res1: 0,
             data: full_data,
-        })
+        }
     }
 
+    /// Payload of the frame, empty for remote frames
     pub fn data(&self) -> &[u8] {
-        &self.data[..(self.dlc as usize)]
+        &self.data[..self.data_len()]
+    }
+
+    /// Mutable access to the payload, e.g. to patch single bytes.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let len = self.data_len();
+        &mut self.data[..len]
+    }
+
+    /// Length of the payload, remote frames only carry a data length code
+    fn data_len(&self) -> usize {
+        if self.id & CAN_RTR_FLAG != 0 {
+            0
+        } else {
+            (self.dlc as usize).min(8)
+        }
+    }
+
+    /// Replace the payload, adjusting the data length.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        if data.len() > 8 {
+            return Err(ConstructionError::TooMuchData);
+        }
+
+        self.data = [0; 8];
+        self.data[..data.len()].copy_from_slice(data);
+        self.dlc = data.len() as u8;
+        Ok(())
+    }
+
+    /// Change the data length. Bytes added at the end are zero, unless
+    /// they were part of the payload before.
+    pub fn set_dlc(&mut self, dlc: usize) -> Result<(), ConstructionError> {
+        if dlc > 8 {
+            return Err(ConstructionError::TooMuchData);
+        }
+
+        self.dlc = dlc as u8;
+        Ok(())
+    }
+
+    /// Change the id, keeping the RTR and error flags. Like in `new`, ids
+    /// above 0x7FF are extended ids.
+    pub fn set_id(&mut self, id: u32) -> Result<(), ConstructionError> {
+        if id > CAN_EFF_MASK {
+            return Err(ConstructionError::IDTooLarge);
+        }
+
+        let flags = self.id & (CAN_RTR_FLAG | CAN_ERR_FLAG);
+        let eff = if id > CAN_SFF_MASK { CAN_EFF_FLAG } else { 0 };
+        self.id = id | eff | flags;
+        Ok(())
+    }
+
+    /// Turn the frame into a remote or data frame, keeping the data length
+    /// code. The payload is cleared when it becomes a remote frame.
+    pub fn set_rtr(&mut self, rtr: bool) {
+        if rtr {
+            self.id |= CAN_RTR_FLAG;
+            self.data = [0; 8];
+        } else {
+            self.id &= !CAN_RTR_FLAG;
+        }
+    }
+
+    /// Id value without the EFF/RTR/ERR flags
+    pub fn raw_id(&self) -> u32 {
+        if self.id & CAN_EFF_FLAG != 0 {
+            self.id & CAN_EFF_MASK
+        } else {
+            self.id & CAN_SFF_MASK
+        }
+    }
+
+    /// Check if the frame has an 11 bit standard id
+    pub fn is_standard(&self) -> bool {
+        self.id & CAN_EFF_FLAG == 0
+    }
+
+    /// Whether the length is valid for a classic frame, i.e. the frame was
+    /// not read from a CAN FD frame
+    pub(crate) fn is_classic(&self) -> bool {
+        self.dlc <= 8
+    }
+
+    /// 32 bit CAN_ID including EFF/RTR/ERR flags
+    pub(crate) fn can_id(&self) -> u32 {
+        self.id
+    }
+
+    /// Replace the 32 bit CAN_ID including EFF/RTR/ERR flags
+    pub(crate) fn with_can_id(mut self, can_id: u32) -> Frame {
+        self.id = can_id;
+        self
     }
 
     /// Return the error message
@@ -89,54 +263,380 @@ impl Frame {
     pub fn error(&self) -> Result<CanError, DecodingError> {
         CanError::from_frame(self)
     }
+
+    /// Read the controller's error counters from an error message, if the
+    /// driver reported them.
+    pub fn error_counters(&self) -> Option<ErrorCounters> {
+        ErrorCounters::from_frame(self)
+    }
+
+    /// Display the frame prefixed by the interface name, like the
+    /// `candump -L` log format, e.g. `can0 123#DEADBEEF`.
+    pub fn display_on<'a>(&'a self, ifname: &'a str) -> impl fmt::Display + 'a {
+        OnInterface {
+            ifname,
+            frame: self,
+        }
+    }
+}
+
+/// Frames are equal if id, flags, data length and the valid data are equal.
+/// Padding and bytes beyond the data length are ignored.
+impl PartialEq for Frame {
+    fn eq(&self, other: &Frame) -> bool {
+        self.id == other.id && self.dlc == other.dlc && self.data() == other.data()
+    }
+}
+
+impl Eq for Frame {}
+
+impl Hash for Frame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.dlc.hash(state);
+        self.data().hash(state);
+    }
+}
+
+/// Frame in the `candump` notation, e.g. `123#DEADBEEF`, `1F334455#R` for
+/// remote frames or `1F334455#R4` with a data length.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_id(f, self.id)?;
+        f.write_str("#")?;
+        if self.id & CAN_RTR_FLAG != 0 {
+            f.write_str("R")?;
+            if self.dlc > 0 {
+                write!(f, "{}", self.dlc)?;
+            }
+            return Ok(());
+        }
+        write_data(f, self.data())
+    }
+}
+
+/// Parse a frame in the `cansend` notation, as written by `Display`.
+///
+/// Ids with 3 digits are standard ids, ids with 8 digits extended ones.
+/// Data bytes may be separated by `.`, e.g. `123#DE.AD.BE.EF`.
+impl FromStr for Frame {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Frame, ParseError> {
+        let (id, data) = text.split_once('#').ok_or(ParseError::MissingSeparator)?;
+        let id = parse_id(id)?;
+
+        if let Some(len) = data.strip_prefix('R') {
+            let dlc = match len.as_bytes() {
+                [] => 0,
+                [digit @ b'0'..=b'8'] => digit - b'0',
+                _ => return Err(ParseError::InvalidLength),
+            };
+            return Ok(Frame {
+                id: id | CAN_RTR_FLAG,
+                dlc,
+                ..Frame::default()
+            });
+        }
+
+        let mut frame = Frame {
+            id,
+            ..Frame::default()
+        };
+        frame.dlc = parse_data(data, &mut frame.data)? as u8;
+        Ok(frame)
+    }
+}
+
+/// Frame displayed with the interface it was received on
+struct OnInterface<'a> {
+    ifname: &'a str,
+    frame: &'a Frame,
+}
+
+impl fmt::Display for OnInterface<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.ifname, self.frame)
+    }
+}
+
+/// Builder for `Frame`, see `Frame::builder`
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameBuilder {
+    id: u32,
+    data: [u8; 8],
+    len: usize,
+    rtr: bool,
+    extended: bool,
+    err: bool,
+}
+
+impl FrameBuilder {
+    /// Set the id. Ids above 0x7FF are extended ids.
+    pub fn id(mut self, id: u32) -> FrameBuilder {
+        self.id = id;
+        self
+    }
+
+    /// Set the payload. More than 8 bytes fail in `build`.
+    pub fn data(mut self, data: &[u8]) -> FrameBuilder {
+        self.len = data.len();
+        let n = data.len().min(8);
+        self.data[..n].copy_from_slice(&data[..n]);
+        self
+    }
+
+    /// Make it a remote frame
+    pub fn rtr(mut self) -> FrameBuilder {
+        self.rtr = true;
+        self
+    }
+
+    /// Use an extended id, even if it fits into 11 bits
+    pub fn extended(mut self) -> FrameBuilder {
+        self.extended = true;
+        self
+    }
+
+    /// Make it an error frame
+    pub fn error(mut self) -> FrameBuilder {
+        self.err = true;
+        self
+    }
+
+    /// Create the frame
+    pub fn build(self) -> Result<Frame, ConstructionError> {
+        if self.len > 8 {
+            return Err(ConstructionError::TooMuchData);
+        }
+        let mut frame = Frame::new(self.id, &self.data[..self.len], self.rtr, self.err)?;
+        if self.extended {
+            frame.id |= CAN_EFF_FLAG;
+        }
+        Ok(frame)
+    }
 }
 
 impl embedded_can::Frame for Frame {
     /// Creates a new frame with an extended identifier.
     fn new(id: impl Into<embedded_can::Id>, data: &[u8]) -> Option<Self> {
-        match id.into() {
-            embedded_can::Id::Extended(value) => {
-                match Self::new(value.as_raw(), data, false, false) {
-                    Ok(frame) => Some(frame),
-                    _ => None,
-                }
-            }
-            embedded_can::Id::Standard(value) => {
-                match Self::new(value.as_raw() as u32, data, false, false) {
-                    Ok(frame) => Some(frame),
-                    _ => None,
-                }
-            }
-        }
+        let mut frame = Self::new(0, data, false, false).ok()?;
+        frame.id = can_id(id.into());
+        Some(frame)
     }
 
     fn new_remote(id: impl Into<embedded_can::Id>, dlc: usize) -> Option<Self> {
-        let data = vec![0; dlc];
+        Self::remote(can_id(id.into()), dlc).ok()
+    }
+
+    fn id(&self) -> embedded_can::Id {
+        if self.is_extended() {
+            CanId::Extended(self.raw_id()).into()
+        } else {
+            CanId::Standard(self.raw_id() as u16).into()
+        }
+    }
+
+    fn is_extended(&self) -> bool {
+        self.id & CAN_EFF_FLAG != 0
+    }
+
+    fn dlc(&self) -> usize {
+        self.dlc as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        Frame::data(self)
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.id & CAN_RTR_FLAG != 0
+    }
+}
+
+/// CAN FD frame
+///
+/// Uses the same memory layout as the underlying kernel struct for performance
+/// reasons.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct FdFrame {
+    /// 32 bit CAN_ID + EFF/RTR/ERR flags
+    id: u32,
+    /// data length. Bytes beyond are not valid
+    len: u8,
+    /// additional flags for CAN FD
+    flags: u8,
+    /// reserved
+    res0: u8,
+    /// reserved
+    res1: u8,
+    /// buffer for data
+    data: [u8; CANFD_MAX_DLEN],
+}
+
+impl Default for FdFrame {
+    fn default() -> FdFrame {
+        FdFrame {
+            id: 0,
+            len: 0,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data: [0; CANFD_MAX_DLEN],
+        }
+    }
+}
+
+impl FdFrame {
+    /// Create a new CAN FD frame.
+    ///
+    /// `brs` enables the bit rate switch for the data phase, `esi` sets the
+    /// error state indicator. Payloads whose length is not a valid CAN FD
+    /// length are padded with zeros up to the next valid length.
+    pub fn new(id: u32, data: &[u8], brs: bool, esi: bool) -> Result<FdFrame, ConstructionError> {
+        let mut id = id;
+
+        if data.len() > CANFD_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData);
+        }
+
+        if id > CAN_EFF_MASK {
+            return Err(ConstructionError::IDTooLarge);
+        }
+
+        // set EFF_FLAG on large message
+        if id > CAN_SFF_MASK {
+            id |= CAN_EFF_FLAG;
+        }
+
+        let mut flags = 0;
+        if brs {
+            flags |= CANFD_BRS as u8;
+        }
+        if esi {
+            flags |= CANFD_ESI as u8;
+        }
+
+        let mut full_data = [0; CANFD_MAX_DLEN];
+        full_data[..data.len()].copy_from_slice(data);
+
+        Ok(FdFrame {
+            id,
+            len: fd_len(data.len()) as u8,
+            flags,
+            res0: 0,
+            res1: 0,
+            data: full_data,
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(CANFD_MAX_DLEN)]
+    }
+
+    /// Id value without the EFF/RTR/ERR flags
+    pub fn raw_id(&self) -> u32 {
+        if self.id & CAN_EFF_FLAG != 0 {
+            self.id & CAN_EFF_MASK
+        } else {
+            self.id & CAN_SFF_MASK
+        }
+    }
+
+    /// Check if the frame has an 11 bit standard id
+    pub fn is_standard(&self) -> bool {
+        self.id & CAN_EFF_FLAG == 0
+    }
+
+    /// Replace the 32 bit CAN_ID including EFF/RTR/ERR flags
+    #[cfg(any(feature = "blf", feature = "protobuf"))]
+    pub(crate) fn with_can_id(mut self, can_id: u32) -> FdFrame {
+        self.id = can_id;
+        self
+    }
+
+    /// Check if the bit rate switch is enabled
+    pub fn is_brs(&self) -> bool {
+        self.flags & CANFD_BRS as u8 != 0
+    }
+
+    /// Check if the error state indicator is set
+    pub fn is_esi(&self) -> bool {
+        self.flags & CANFD_ESI as u8 != 0
+    }
+}
+
+/// Frame in the `candump` notation, e.g. `123##1DEADBEEF` where the digit
+/// after `##` holds the CAN FD flags.
+impl fmt::Display for FdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_id(f, self.id)?;
+        write!(f, "##{:X}", self.flags & 0xF)?;
+        write_data(f, self.data())
+    }
+}
+
+/// Parse a frame in the `cansend` notation, e.g. `123##1DEADBEEF`.
+///
+/// Payloads whose length is not a valid CAN FD length are padded with zeros.
+impl FromStr for FdFrame {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<FdFrame, ParseError> {
+        let (id, data) = text.split_once("##").ok_or(ParseError::MissingSeparator)?;
+        let id = parse_id(id)?;
+
+        let flags = data
+            .get(..1)
+            .and_then(|flags| u8::from_str_radix(flags, 16).ok())
+            .ok_or(ParseError::InvalidFlags)?;
+        let mut frame = FdFrame {
+            id,
+            flags,
+            ..FdFrame::default()
+        };
+        let len = parse_data(&data[1..], &mut frame.data)?;
+        frame.len = fd_len(len) as u8;
+        Ok(frame)
+    }
+}
+
+impl From<Frame> for FdFrame {
+    fn from(frame: Frame) -> FdFrame {
+        let mut data = [0; CANFD_MAX_DLEN];
+        data[..8].copy_from_slice(&frame.data);
+        FdFrame {
+            id: frame.id,
+            len: frame.dlc,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data,
+        }
+    }
+}
+
+impl embedded_can::Frame for FdFrame {
+    fn new(id: impl Into<embedded_can::Id>, data: &[u8]) -> Option<Self> {
         match id.into() {
-            embedded_can::Id::Extended(value) => {
-                match Self::new(value.as_raw(), &data, true, false) {
-                    Ok(frame) => Some(frame),
-                    _ => None,
-                }
-            }
+            embedded_can::Id::Extended(value) => Self::new(value.as_raw(), data, false, false).ok(),
             embedded_can::Id::Standard(value) => {
-                match Self::new(value.as_raw() as u32, &data, true, false) {
-                    Ok(frame) => Some(frame),
-                    _ => None,
-                }
+                Self::new(value.as_raw() as u32, data, false, false).ok()
             }
         }
     }
 
+    /// CAN FD does not support remote frames
+    fn new_remote(_id: impl Into<embedded_can::Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
     fn id(&self) -> embedded_can::Id {
         if self.is_extended() {
-            embedded_can::Id::Extended(
-                embedded_can::ExtendedId::new(self.id & CAN_EFF_MASK).unwrap(),
-            )
+            CanId::Extended(self.raw_id()).into()
         } else {
-            embedded_can::Id::Standard(
-                embedded_can::StandardId::new((self.id & CAN_SFF_MASK) as u16).unwrap(),
-            )
+            CanId::Standard(self.raw_id() as u16).into()
         }
     }
 
@@ -145,14 +645,321 @@ impl embedded_can::Frame for Frame {
     }
 
     fn dlc(&self) -> usize {
-        self.dlc as usize
+        self.len as usize
     }
 
     fn data(&self) -> &[u8] {
-        &self.data[..(self.dlc as usize)]
+        &self.data[..(self.len as usize).min(CANFD_MAX_DLEN)]
     }
 
     fn is_remote_frame(&self) -> bool {
-        self.id & CAN_RTR_FLAG != 0
+        false
+    }
+}
+
+/// Frame received on a socket with CAN FD frames enabled
+#[derive(Debug, Copy, Clone)]
+pub enum AnyFrame {
+    /// Classic CAN frame
+    Classic(Frame),
+    /// CAN FD frame
+    Fd(FdFrame),
+}
+
+impl fmt::Display for AnyFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnyFrame::Classic(frame) => frame.fmt(f),
+            AnyFrame::Fd(frame) => frame.fmt(f),
+        }
+    }
+}
+
+/// Parse either a classic or a CAN FD frame, depending on the separator
+impl FromStr for AnyFrame {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<AnyFrame, ParseError> {
+        if text.contains("##") {
+            text.parse().map(AnyFrame::Fd)
+        } else {
+            text.parse().map(AnyFrame::Classic)
+        }
+    }
+}
+
+/// Write a CAN id as 3 hex digits for standard ids, 8 otherwise. Error
+/// frames keep the error flag like `candump` does.
+fn write_id(f: &mut fmt::Formatter, id: u32) -> fmt::Result {
+    if id & CAN_ERR_FLAG != 0 {
+        write!(f, "{:08X}", id & (CAN_ERR_MASK | CAN_ERR_FLAG))
+    } else if id & CAN_EFF_FLAG != 0 {
+        write!(f, "{:08X}", id & CAN_EFF_MASK)
+    } else {
+        write!(f, "{:03X}", id & CAN_SFF_MASK)
+    }
+}
+
+fn write_data(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    for byte in data {
+        write!(f, "{:02X}", byte)?;
+    }
+    Ok(())
+}
+
+/// Parse a CAN id as written by `write_id`, returning it with flags
+fn parse_id(text: &str) -> Result<u32, ParseError> {
+    if !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidId);
+    }
+    let id = u32::from_str_radix(text, 16).map_err(|_| ParseError::InvalidId)?;
+    match text.len() {
+        3 if id <= CAN_SFF_MASK => Ok(id),
+        8 if id & CAN_ERR_FLAG != 0 => Ok(id & (CAN_ERR_MASK | CAN_ERR_FLAG)),
+        8 if id <= CAN_EFF_MASK => Ok(id | CAN_EFF_FLAG),
+        _ => Err(ParseError::InvalidId),
+    }
+}
+
+/// Parse hex bytes into `buf`, returning the number of bytes
+fn parse_data(text: &str, buf: &mut [u8]) -> Result<usize, ParseError> {
+    let mut len = 0;
+    let mut digits = text.bytes().filter(|c| *c != b'.');
+    while let Some(high) = digits.next() {
+        let low = digits.next().ok_or(ParseError::InvalidData)?;
+        let byte = match (hex_digit(high), hex_digit(low)) {
+            (Some(high), Some(low)) => high << 4 | low,
+            _ => return Err(ParseError::InvalidData),
+        };
+        *buf.get_mut(len).ok_or(ParseError::TooMuchData)? = byte;
+        len += 1;
+    }
+    Ok(len)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// CAN_ID with the EFF flag set for extended ids, regardless of their value
+fn can_id(id: embedded_can::Id) -> u32 {
+    match id {
+        embedded_can::Id::Standard(id) => id.as_raw() as u32,
+        embedded_can::Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG,
+    }
+}
+
+/// Round a payload length up to the next valid CAN FD length
+fn fd_len(len: usize) -> usize {
+    *FD_LENGTHS.iter().find(|&&l| l >= len).unwrap()
+}
+
+/// Data length code of a CAN FD payload length
+#[cfg(feature = "blf")]
+pub(crate) fn fd_dlc(len: usize) -> u8 {
+    FD_LENGTHS.iter().position(|&l| l >= len).unwrap() as u8
+}
+
+/// Raw bytes of a `repr(C)` value, e.g. a frame in the kernel layout
+#[cfg(any(feature = "netlink", feature = "pcap"))]
+pub(crate) fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FdFrame, Frame};
+    use crate::{AnyFrame, CanId, ConstructionError, ParseError};
+    use embedded_can::Frame as _;
+
+    #[test]
+    fn test_frame_mutation() {
+        let mut frame = Frame::new(0x123, &[1, 2, 3], false, true).unwrap();
+        frame.data_mut()[0] = 0xFF;
+        assert_eq!(frame.data(), &[0xFF, 2, 3]);
+
+        frame.set_dlc(5).unwrap();
+        assert_eq!(frame.data(), &[0xFF, 2, 3, 0, 0]);
+        frame.set_data(&[4, 5]).unwrap();
+        frame.set_dlc(3).unwrap();
+        assert_eq!(frame.data(), &[4, 5, 0]);
+        assert!(matches!(
+            frame.set_data(&[0; 9]),
+            Err(ConstructionError::TooMuchData)
+        ));
+
+        frame.set_id(0x18FEF100).unwrap();
+        assert!(frame.is_extended());
+        assert!(frame.is_error());
+        frame.set_id(0x7FF).unwrap();
+        assert!(!frame.is_extended());
+        assert!(matches!(
+            frame.set_id(0x2000_0000),
+            Err(ConstructionError::IDTooLarge)
+        ));
+
+        frame.set_rtr(true);
+        assert!(frame.is_remote_frame());
+        assert!(frame.data().is_empty());
+        assert_eq!(frame.dlc(), 3);
+        frame.set_rtr(false);
+        assert_eq!(frame.data(), &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_frame_eq() {
+        use std::collections::HashSet;
+
+        let frame = Frame::new(0x123, &[1, 2], false, false).unwrap();
+        let mut stale = Frame::new(0x123, &[1, 2, 3, 4], false, false).unwrap();
+        stale.set_dlc(2).unwrap();
+        assert_eq!(frame, stale);
+        assert_ne!(frame, Frame::new(0x123, &[1, 2], false, true).unwrap());
+        assert_ne!(frame, Frame::new(0x123, &[1, 2], true, false).unwrap());
+        assert_ne!(frame, Frame::new(0x123, &[1], false, false).unwrap());
+
+        let set: HashSet<Frame> = [frame, stale].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_const_frames() {
+        const REQUEST: Frame = Frame::new_standard(0x7DF, &[0x02, 0x01, 0x0C]);
+        const TABLE: [Frame; 2] = [
+            Frame::new_extended(0x18FEF100, &[0xFF; 8]),
+            Frame::new_extended(0x100, &[]),
+        ];
+
+        assert_eq!(REQUEST.data(), &[0x02, 0x01, 0x0C]);
+        assert!(!REQUEST.is_extended());
+        assert_eq!(
+            REQUEST.can_id(),
+            Frame::new(0x7DF, &[], false, false).unwrap().can_id()
+        );
+        assert_eq!(TABLE[0].dlc(), 8);
+        // small ids stay extended
+        assert!(TABLE[1].is_extended());
+    }
+
+    #[test]
+    fn test_id_helpers() {
+        let frame = Frame::new(0x18FEF100, &[], true, false).unwrap();
+        assert_eq!(frame.raw_id(), 0x18FEF100);
+        assert!(!frame.is_standard());
+        assert_eq!(CanId::from(frame.id()), CanId::Extended(0x18FEF100));
+
+        let frame = FdFrame::new(0x123, &[], false, false).unwrap();
+        assert_eq!(frame.raw_id(), 0x123);
+        assert!(frame.is_standard());
+    }
+
+    #[test]
+    fn test_remote_frames() {
+        let id = embedded_can::ExtendedId::new(0x100).unwrap();
+        let frame = Frame::new_remote(id, 4).unwrap();
+        assert!(frame.is_remote_frame());
+        assert!(frame.is_extended());
+        assert_eq!(frame.dlc(), 4);
+        assert!(frame.data().is_empty());
+        assert!(Frame::new_remote(id, 9).is_none());
+
+        // only the length of the data is used
+        let frame = Frame::new(0x123, &[1, 2], true, false).unwrap();
+        assert_eq!(frame.dlc(), 2);
+        assert!(frame.data().is_empty());
+    }
+
+    #[test]
+    fn test_builder() {
+        let frame = Frame::builder()
+            .id(0x123)
+            .data(&[1, 2])
+            .rtr()
+            .extended()
+            .build()
+            .unwrap();
+        assert_eq!(frame.to_string(), "00000123#R2");
+
+        let frame = Frame::builder().id(0x7FF).data(&[3]).build().unwrap();
+        assert_eq!(frame.to_string(), "7FF#03");
+        assert!(matches!(
+            Frame::builder().data(&[0; 9]).build(),
+            Err(ConstructionError::TooMuchData)
+        ));
+        assert!(matches!(
+            Frame::builder().id(0x2000_0000).build(),
+            Err(ConstructionError::IDTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let frame = Frame::new(0x123, &[0xDE, 0xAD, 0xBE, 0xEF], false, false).unwrap();
+        assert_eq!(frame.to_string(), "123#DEADBEEF");
+        assert_eq!(frame.display_on("can0").to_string(), "can0 123#DEADBEEF");
+
+        let remote = Frame::new(0x1F334455, &[], true, false).unwrap();
+        assert_eq!(remote.to_string(), "1F334455#R");
+        let remote = Frame::new(0x1F334455, &[0; 4], true, false).unwrap();
+        assert_eq!(remote.to_string(), "1F334455#R4");
+
+        let error = Frame::new(0x004, &[0; 8], false, true).unwrap();
+        assert_eq!(error.to_string(), "20000004#0000000000000000");
+
+        let fd = FdFrame::new(0x7E8, &[1, 2], true, false).unwrap();
+        assert_eq!(fd.to_string(), "7E8##10102");
+    }
+
+    #[test]
+    fn test_parse() {
+        let frame: Frame = "123#DE.AD.BE.EF".parse().unwrap();
+        assert_eq!(frame.raw_id(), 0x123);
+        assert_eq!(frame.data(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+        for text in [
+            "1F334455#R",
+            "1F334455#R4",
+            "00000123#",
+            "20000004#0000000000000000",
+        ] {
+            assert_eq!(text.parse::<Frame>().unwrap().to_string(), text);
+        }
+        assert!(matches!(
+            "7E8##10102".parse::<AnyFrame>(),
+            Ok(AnyFrame::Fd(frame)) if frame.is_brs() && frame.data() == [1, 2]
+        ));
+        assert_eq!("123##0".parse::<FdFrame>().unwrap().dlc(), 0);
+
+        let errors = [
+            ("123", ParseError::MissingSeparator),
+            ("800#", ParseError::InvalidId),
+            ("12#", ParseError::InvalidId),
+            ("+12#", ParseError::InvalidId),
+            ("123#ABC", ParseError::InvalidData),
+            ("123#XY", ParseError::InvalidData),
+            ("123#R9", ParseError::InvalidLength),
+            ("123#000000000000000000", ParseError::TooMuchData),
+        ];
+        for (text, error) in errors {
+            assert_eq!(text.parse::<Frame>().unwrap_err(), error, "{}", text);
+        }
+        assert_eq!(
+            "123##".parse::<FdFrame>().unwrap_err(),
+            ParseError::InvalidFlags
+        );
+    }
+
+    #[test]
+    fn test_fd_frame_padding() {
+        let frame = FdFrame::new(0x123, &[0xAA; 9], true, false).unwrap();
+        assert_eq!(frame.data().len(), 12);
+        assert_eq!(&frame.data()[9..], &[0, 0, 0]);
+        assert!(frame.is_brs());
+        assert!(!frame.is_esi());
+    }
+
+    #[test]
+    fn test_fd_frame_too_long() {
+        assert!(FdFrame::new(0x123, &[0; 65], false, false).is_err());
     }
 }
@@ -0,0 +1,440 @@
+use crate::{
+    shutdown::POLL_INTERVAL, Filter, FilterGroup, Frame, Interest, Poller, ShutdownReport,
+    ShutdownToken, Socket, SocketError,
+};
+use std::{fmt, io, time::Duration};
+
+/// Forwarding rule of a `Gateway`
+///
+/// Forwards the frames received on port `from` which pass the filter to
+/// port `to`, after passing them through the hooks in the order they were
+/// added. A hook returning `None` drops the frame.
+pub struct Route {
+    from: usize,
+    to: usize,
+    filter: FilterGroup,
+    hooks: Vec<Box<dyn FnMut(Frame) -> Option<Frame> + Send>>,
+    forwarded: u64,
+}
+
+impl Route {
+    /// Forward all frames from port `from` to port `to`
+    pub fn new(from: usize, to: usize) -> Route {
+        Route {
+            from,
+            to,
+            filter: FilterGroup::new().filter(Filter::accept_all()),
+            hooks: Vec::new(),
+            forwarded: 0,
+        }
+    }
+
+    /// Only forward frames accepted by `filter`
+    pub fn filter(mut self, filter: FilterGroup) -> Route {
+        self.filter = filter;
+        self
+    }
+
+    /// Modify forwarded frames using `hook`
+    pub fn hook<F>(mut self, hook: F) -> Route
+    where
+        F: FnMut(Frame) -> Option<Frame> + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Store the XOR of the data bytes `from` to `to` in byte `result` of
+    /// the forwarded frames, starting with `init`, like
+    /// `GwRule::xor_checksum`.
+    ///
+    /// Negative indices count from the end of the data, -1 is the last
+    /// byte. Frames too short for the indices are forwarded unchanged. The
+    /// checksum is added as hook, so it covers the changes of the hooks
+    /// added before.
+    pub fn xor_checksum(self, from: i8, to: i8, result: i8, init: u8) -> Route {
+        self.hook(move |mut frame| {
+            let data = frame.data_mut();
+            if let Some((bytes, result)) = checksum_indices(data.len(), from, to, result) {
+                data[result] = bytes.fold(init, |xor, i| xor ^ data[i]);
+            }
+            Some(frame)
+        })
+    }
+
+    /// Store the CRC8 with the generator `polynomial` of the data bytes
+    /// `from` to `to` in byte `result` of the forwarded frames, like
+    /// `GwRule::crc8_checksum`.
+    ///
+    /// The CRC starts with `init` and is XORed with `final_xor`, e.g.
+    /// polynomial 0x1D, 0xFF and 0xFF for SAE J1850. The bytes are processed
+    /// in reverse order if `from` is after `to`. Indices are resolved as by
+    /// `xor_checksum`.
+    pub fn crc8_checksum(
+        self,
+        from: i8,
+        to: i8,
+        result: i8,
+        polynomial: u8,
+        init: u8,
+        final_xor: u8,
+    ) -> Route {
+        let table: Vec<u8> = crc8_table(polynomial).collect();
+        self.hook(move |mut frame| {
+            let data = frame.data_mut();
+            if let Some((bytes, result)) = checksum_indices(data.len(), from, to, result) {
+                let crc = bytes.fold(init, |crc, i| table[(crc ^ data[i]) as usize]);
+                data[result] = crc ^ final_xor;
+            }
+            Some(frame)
+        })
+    }
+
+    /// Count the forwarded frames in the bits `mask` of data byte `index`,
+    /// e.g. `rolling_counter(1, 0x0F)` for a 4 bit counter in the low nibble
+    /// of the second byte. The counter starts at 0 and wraps around within
+    /// the mask, which has to be a contiguous run of bits.
+    ///
+    /// Add it before the checksum covering it. Indices are resolved as by
+    /// `xor_checksum`.
+    pub fn rolling_counter(self, index: i8, mask: u8) -> Route {
+        let shift = mask.trailing_zeros() % 8;
+        let mut counter = 0u8;
+        self.hook(move |mut frame| {
+            let data = frame.data_mut();
+            if let Some(index) = data_index(data.len(), index) {
+                data[index] = (data[index] & !mask) | ((counter << shift) & mask);
+                counter = counter.wrapping_add(1) & (mask >> shift);
+            }
+            Some(frame)
+        })
+    }
+
+    /// Number of frames forwarded along this route
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded
+    }
+
+    fn apply(&mut self, frame: &Frame) -> Option<Frame> {
+        if !self.filter.matches(frame) {
+            return None;
+        }
+        let mut frame = *frame;
+        for hook in self.hooks.iter_mut() {
+            frame = hook(frame)?;
+        }
+        Some(frame)
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("filter", &self.filter)
+            .field("hooks", &self.hooks.len())
+            .field("forwarded", &self.forwarded)
+            .finish()
+    }
+}
+
+/// Userspace gateway between sockets
+///
+/// Forwards frames between any number of sockets, the ports, according to
+/// per-direction `Route`s, like the kernel `can-gw` module:
+///
+/// ```no_run
+/// use candev::{Filter, FilterGroup, Gateway, IdMap, CanId, Route, Socket};
+///
+/// let mut gateway = Gateway::new().unwrap();
+/// let powertrain = gateway.add(Socket::new("can0").unwrap()).unwrap();
+/// let diagnostics = gateway.add(Socket::new("can1").unwrap()).unwrap();
+///
+/// let map = IdMap::new().map(CanId::Standard(0x123), CanId::Standard(0x523));
+///
+/// gateway.route(
+///     Route::new(powertrain, diagnostics)
+///         .filter(FilterGroup::new().filter(Filter::standard(0x100).with_mask(0x700)))
+///         .hook(move |frame| Some(map.translate(&frame))),
+/// );
+/// gateway.route(Route::new(diagnostics, powertrain));
+/// gateway.run().unwrap();
+/// ```
+///
+/// Frames which can not be forwarded because the transmit queue of the
+/// target port is full are dropped and counted, so a congested bus does not
+/// stall the other directions.
+#[derive(Debug)]
+pub struct Gateway {
+    ports: Vec<Socket>,
+    routes: Vec<Route>,
+    poller: Poller,
+    dropped: u64,
+}
+
+impl Gateway {
+    /// Create a gateway without ports
+    pub fn new() -> io::Result<Gateway> {
+        Ok(Gateway {
+            ports: Vec::new(),
+            routes: Vec::new(),
+            poller: Poller::new()?,
+            dropped: 0,
+        })
+    }
+
+    /// Add a port, returning its index for use in routes
+    pub fn add(&mut self, socket: Socket) -> io::Result<usize> {
+        let port = self.ports.len();
+        self.poller.add(&socket, port, Interest::Readable)?;
+        self.ports.push(socket);
+        Ok(port)
+    }
+
+    /// Add a forwarding rule.
+    ///
+    /// # Panics
+    ///
+    /// If a port of the route does not exist.
+    pub fn route(&mut self, route: Route) {
+        assert!(route.from < self.ports.len() && route.to < self.ports.len());
+        self.routes.push(route);
+    }
+
+    /// The forwarding rules, in the order they were added
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Access a port
+    pub fn port(&self, port: usize) -> Option<&Socket> {
+        self.ports.get(port)
+    }
+
+    /// Number of frames dropped because of a full transmit queue
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Wait up to `timeout` for frames and forward them, returning the
+    /// number of forwarded frames. Waits forever without timeout.
+    pub fn forward(&mut self, timeout: Option<Duration>) -> Result<usize, SocketError> {
+        Ok(self.forward_received(timeout)?.1)
+    }
+
+    /// Forward frames until an error occurs
+    pub fn run(&mut self) -> Result<(), SocketError> {
+        loop {
+            self.forward(None)?;
+        }
+    }
+
+    /// Forward frames until `token` requests shutdown, then forward the
+    /// frames still queued on the ports until none is left or the grace
+    /// period ends. The frames forwarded and dropped meanwhile are reported
+    /// to the token as subsystem `gateway`.
+    pub fn run_until(&mut self, token: &ShutdownToken) -> Result<(), SocketError> {
+        while !token.is_requested() {
+            self.forward(Some(POLL_INTERVAL))?;
+        }
+
+        let dropped = self.dropped;
+        let mut flushed = 0;
+        while !token.is_expired() {
+            match self.forward_received(Some(Duration::ZERO))? {
+                (0, _) => break,
+                (_, forwarded) => flushed += forwarded as u64,
+            }
+        }
+        token.report(ShutdownReport {
+            subsystem: "gateway".to_string(),
+            flushed,
+            dropped: self.dropped - dropped,
+        });
+        Ok(())
+    }
+
+    /// Return the ports
+    pub fn into_inner(self) -> Vec<Socket> {
+        self.ports
+    }
+
+    /// Forward the frames of the ready ports, returning the number of
+    /// received and forwarded frames
+    fn forward_received(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(usize, usize), SocketError> {
+        let mut received = 0;
+        let mut forwarded = 0;
+        for ready in self.poller.wait(timeout)? {
+            let frame = match self.ports[ready.key].try_receive()? {
+                Some(frame) => frame,
+                None => continue,
+            };
+            received += 1;
+            for route in self.routes.iter_mut().filter(|r| r.from == ready.key) {
+                let frame = match route.apply(&frame) {
+                    Some(frame) => frame,
+                    None => continue,
+                };
+                match self.ports[route.to].transmit_nonblocking(&frame) {
+                    Ok(()) => {
+                        route.forwarded += 1;
+                        forwarded += 1;
+                    }
+                    Err(SocketError::TxQueueFull) => self.dropped += 1,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok((received, forwarded))
+    }
+}
+
+/// Resolve a data index, negative indices count from the end
+fn data_index(len: usize, index: i8) -> Option<usize> {
+    let index = if index < 0 {
+        len.checked_sub(index.unsigned_abs() as usize)?
+    } else {
+        index as usize
+    };
+    if index < len {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Indices of the checksum bytes `from` to `to`, in that order, and of
+/// the `result` byte
+fn checksum_indices(
+    len: usize,
+    from: i8,
+    to: i8,
+    result: i8,
+) -> Option<(impl Iterator<Item = usize>, usize)> {
+    let (from, to) = (data_index(len, from)?, data_index(len, to)?);
+    let result = data_index(len, result)?;
+    let (first, last) = (from.min(to), from.max(to));
+    let reverse = from > to;
+    let bytes = (first..=last).map(move |i| if reverse { first + last - i } else { i });
+    Some((bytes, result))
+}
+
+/// Lookup table of the MSB first CRC8 with `polynomial`
+pub(crate) fn crc8_table(polynomial: u8) -> impl Iterator<Item = u8> {
+    (0..=255u8).map(move |byte| {
+        (0..8).fold(byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ polynomial
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Gateway, Route};
+    use crate::{CanId, Filter, FilterGroup, Frame, IdMap, ShutdownReport, ShutdownToken, Socket};
+    use embedded_can::{blocking::Can, Frame as _};
+    use std::time::Duration;
+
+    #[test]
+    fn test_gateway() {
+        let (a, mut bus_a) = Socket::pair().unwrap();
+        let (b, mut bus_b) = Socket::pair().unwrap();
+        let map = IdMap::new().map(CanId::Standard(0x123), CanId::Standard(0x523));
+        let mut gateway = Gateway::new().unwrap();
+        let port_a = gateway.add(a).unwrap();
+        let port_b = gateway.add(b).unwrap();
+        gateway.route(
+            Route::new(port_a, port_b)
+                .filter(FilterGroup::new().filter(Filter::standard(0x100).with_mask(0x700)))
+                .hook(move |frame| Some(map.translate(&frame)))
+                .hook(|mut frame| {
+                    frame.data_mut()[0] += 1;
+                    Some(frame)
+                }),
+        );
+        gateway.route(Route::new(port_b, port_a));
+
+        let timeout = Some(Duration::from_millis(10));
+        for id in [0x123, 0x200] {
+            bus_a
+                .transmit(&Frame::new(id, &[1], false, false).unwrap())
+                .unwrap();
+        }
+        while gateway.forward(timeout).unwrap() > 0 {}
+        let frame = bus_b.receive().unwrap();
+        assert_eq!(CanId::from(frame.id()), CanId::Standard(0x523));
+        assert_eq!(frame.data(), &[2]);
+        assert!(bus_b.try_receive().unwrap().is_none());
+
+        bus_b
+            .transmit(&Frame::new(0x7FF, &[3], false, false).unwrap())
+            .unwrap();
+        assert_eq!(gateway.forward(timeout).unwrap(), 1);
+        assert_eq!(bus_a.receive().unwrap().data(), &[3]);
+        assert_eq!(gateway.routes()[0].forwarded(), 1);
+        assert_eq!(gateway.dropped(), 0);
+    }
+
+    #[test]
+    fn test_checksums() {
+        let frame = Frame::new(0x123, &[1, 0, 1, 2, 3, 4, 5, 0], false, false).unwrap();
+        let mut route = Route::new(0, 1)
+            .rolling_counter(0, 0xF0)
+            .crc8_checksum(0, -2, -1, 0x1D, 0xFF, 0xFF);
+        assert_eq!(
+            route.apply(&frame).unwrap().data(),
+            &[0x01, 0, 1, 2, 3, 4, 5, 0xD2]
+        );
+        assert_eq!(
+            route.apply(&frame).unwrap().data(),
+            &[0x11, 0, 1, 2, 3, 4, 5, 0x6B]
+        );
+
+        let mut route = Route::new(0, 1).xor_checksum(1, 3, 0, 0xA5);
+        let frame = Frame::new(0x123, &[0, 1, 2, 4], false, false).unwrap();
+        assert_eq!(route.apply(&frame).unwrap().data(), &[0xA5 ^ 7, 1, 2, 4]);
+
+        // too short for the indices
+        let frame = Frame::new(0x123, &[0, 1], false, false).unwrap();
+        assert_eq!(route.apply(&frame).unwrap().data(), &[0, 1]);
+    }
+
+    #[test]
+    fn test_run_until_shutdown() {
+        let (a, mut bus_a) = Socket::pair().unwrap();
+        let (b, mut bus_b) = Socket::pair().unwrap();
+        let mut gateway = Gateway::new().unwrap();
+        let port_a = gateway.add(a).unwrap();
+        let port_b = gateway.add(b).unwrap();
+        gateway.route(Route::new(port_a, port_b));
+
+        // frames queued before the request are still forwarded
+        for n in 0..3 {
+            bus_a
+                .transmit(&Frame::new(0x123, &[n], false, false).unwrap())
+                .unwrap();
+        }
+        let token = ShutdownToken::new();
+        token.shutdown(Duration::from_secs(1));
+        gateway.run_until(&token).unwrap();
+        for n in 0..3 {
+            assert_eq!(bus_b.receive().unwrap().data(), &[n]);
+        }
+        assert_eq!(
+            token.reports(),
+            [ShutdownReport {
+                subsystem: "gateway".to_string(),
+                flushed: 3,
+                dropped: 0,
+            }]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{CanId, Clock, Frame, QueueStats, SystemClock};
+use embedded_can::Frame as _;
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+/// In-memory history of recent frames
+///
+/// Keeps the last frames in a ring buffer of fixed capacity, indexed by id
+/// and time, so views like "all frames of 0x1F0 in the last 2 s" can be
+/// answered without scanning unrelated traffic. Frames are timestamped with
+/// a `Clock` when they are recorded.
+#[derive(Debug, Clone)]
+pub struct History<C = SystemClock> {
+    clock: C,
+    capacity: usize,
+    /// Sequence number of the oldest frame
+    first: u64,
+    frames: VecDeque<(Instant, Frame)>,
+    ids: HashMap<CanId, VecDeque<u64>>,
+    dropped: u64,
+}
+
+impl History {
+    /// Create a history keeping up to `capacity` frames
+    pub fn new(capacity: usize) -> History {
+        History {
+            clock: SystemClock,
+            capacity: capacity.max(1),
+            first: 0,
+            frames: VecDeque::with_capacity(capacity),
+            ids: HashMap::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl<C: Clock> History<C> {
+    /// Use `clock` to timestamp the frames
+    pub fn with_clock<D: Clock>(self, clock: D) -> History<D> {
+        History {
+            clock,
+            capacity: self.capacity,
+            first: self.first,
+            frames: self.frames,
+            ids: self.ids,
+            dropped: self.dropped,
+        }
+    }
+
+    /// Record a frame received just now, dropping the oldest frame if the
+    /// history is full
+    pub fn record(&mut self, frame: &Frame) {
+        let time = self.clock.now();
+        if self.frames.len() == self.capacity {
+            self.evict();
+            self.dropped += 1;
+        }
+        let seq = self.first + self.frames.len() as u64;
+        self.frames.push_back((time, *frame));
+        self.ids
+            .entry(CanId::from(frame.id()))
+            .or_default()
+            .push_back(seq);
+    }
+
+    /// Frames of `id` received within `window` before now, oldest first
+    pub fn recent(&self, id: CanId, window: Duration) -> Vec<(Instant, Frame)> {
+        let now = self.clock.now();
+        match now.checked_sub(window) {
+            Some(from) => self.query(id, from, now),
+            None => self.query_until(id, now),
+        }
+    }
+
+    /// Frames of `id` received from `from` up to and including `to`, oldest
+    /// first
+    pub fn query(&self, id: CanId, from: Instant, to: Instant) -> Vec<(Instant, Frame)> {
+        let seqs = match self.ids.get(&id) {
+            Some(seqs) => seqs,
+            None => return Vec::new(),
+        };
+        let start = seqs.partition_point(|seq| self.time_of(*seq) < from);
+        seqs.range(start..)
+            .map(|seq| self.frames[(seq - self.first) as usize])
+            .take_while(|(time, _)| *time <= to)
+            .collect()
+    }
+
+    /// Frames of all ids received from `from` up to and including `to`,
+    /// oldest first
+    pub fn between(&self, from: Instant, to: Instant) -> Vec<(Instant, Frame)> {
+        let start = self.frames.partition_point(|(time, _)| *time < from);
+        self.frames
+            .range(start..)
+            .take_while(|(time, _)| *time <= to)
+            .copied()
+            .collect()
+    }
+
+    /// Most recent frame of `id`
+    pub fn last(&self, id: CanId) -> Option<(Instant, Frame)> {
+        let seq = self.ids.get(&id)?.back()?;
+        Some(self.frames[(seq - self.first) as usize])
+    }
+
+    /// Ids with recorded frames
+    pub fn ids(&self) -> Vec<CanId> {
+        let mut ids: Vec<CanId> = self.ids.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Number of recorded frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether no frames are recorded
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Occupancy of the ring buffer and frames dropped to make room for
+    /// newer ones. The memory includes the id index.
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            pending: self.frames.len(),
+            capacity: self.capacity,
+            memory: self.frames.len() * (size_of::<(Instant, Frame)>() + size_of::<u64>()),
+            dropped: self.dropped,
+        }
+    }
+
+    /// Forget all frames
+    pub fn clear(&mut self) {
+        self.first += self.frames.len() as u64;
+        self.frames.clear();
+        self.ids.clear();
+    }
+
+    fn query_until(&self, id: CanId, to: Instant) -> Vec<(Instant, Frame)> {
+        match self.frames.front() {
+            Some((first, _)) => self.query(id, *first, to),
+            None => Vec::new(),
+        }
+    }
+
+    fn time_of(&self, seq: u64) -> Instant {
+        self.frames[(seq - self.first) as usize].0
+    }
+
+    fn evict(&mut self) {
+        let (_, frame) = match self.frames.pop_front() {
+            Some(oldest) => oldest,
+            None => return,
+        };
+        let id = CanId::from(frame.id());
+        if let Some(seqs) = self.ids.get_mut(&id) {
+            seqs.pop_front();
+            if seqs.is_empty() {
+                self.ids.remove(&id);
+            }
+        }
+        self.first += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::{CanId, Clock, Frame, ManualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn test_query() {
+        let clock = ManualClock::new();
+        let mut history = History::new(5).with_clock(clock.clone());
+        let ms = Duration::from_millis;
+        // one frame every 100 ms
+        for (data, id) in [(0, 0x1F0), (100, 0x100), (200, 0x1F0), (300, 0x1F0)] {
+            let frame = Frame::new(id, &[data as u8], false, false).unwrap();
+            history.record(&frame);
+            clock.advance(ms(100));
+        }
+
+        let id = CanId::Standard(0x1F0);
+        let now = clock.now();
+        let frames = history.query(id, now - ms(300), now - ms(100));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, now - ms(200));
+        assert_eq!(history.between(now - ms(350), now - ms(150)).len(), 2);
+        assert_eq!(history.recent(id, ms(250)).len(), 2);
+        assert_eq!(history.last(id).unwrap().1.data(), &[44]);
+
+        // the oldest frames are dropped once the history is full
+        for _ in 0..2 {
+            history.record(&Frame::new(0x100, &[], false, false).unwrap());
+            clock.advance(ms(100));
+        }
+        assert_eq!(history.len(), 5);
+        assert_eq!(history.stats().dropped, 1);
+        assert_eq!(history.recent(id, ms(1000)).len(), 2);
+        assert_eq!(
+            history.ids(),
+            [CanId::Standard(0x100), CanId::Standard(0x1F0)]
+        );
+
+        history.clear();
+        assert!(history.recent(id, ms(1000)).is_empty());
+        history.record(&Frame::new(0x1F0, &[], false, false).unwrap());
+        assert_eq!(history.recent(id, Duration::from_secs(2)).len(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::ConstructionError;
+use embedded_can::{ExtendedId, Id, StandardId};
+use libc::{CAN_EFF_MASK, CAN_SFF_MASK};
+use std::{cmp::Ordering, fmt};
+
+/// CAN identifier without flags
+///
+/// Ordered by bus priority, so the id winning arbitration compares lowest.
+/// Converts from and to `embedded_can::Id`, e.g. `CanId::from(frame.id())`.
+/// Displays like `candump`, as 3 hex digits for standard and 8 for extended
+/// ids.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CanId {
+    /// 11 bit standard id
+    Standard(u16),
+    /// 29 bit extended id
+    Extended(u32),
+}
+
+impl CanId {
+    /// Standard id for values up to 0x7FF, extended id for larger ones,
+    /// like `Frame::new` does.
+    pub fn new(id: u32) -> Result<CanId, ConstructionError> {
+        if id > CAN_EFF_MASK {
+            Err(ConstructionError::IDTooLarge)
+        } else if id > CAN_SFF_MASK {
+            Ok(CanId::Extended(id))
+        } else {
+            Ok(CanId::Standard(id as u16))
+        }
+    }
+
+    /// Id value without flags
+    pub fn raw(&self) -> u32 {
+        match *self {
+            CanId::Standard(id) => id as u32 & CAN_SFF_MASK,
+            CanId::Extended(id) => id & CAN_EFF_MASK,
+        }
+    }
+
+    /// Check if this is an 11 bit standard id
+    pub fn is_standard(&self) -> bool {
+        matches!(self, CanId::Standard(_))
+    }
+
+    /// Check if this is a 29 bit extended id
+    pub fn is_extended(&self) -> bool {
+        matches!(self, CanId::Extended(_))
+    }
+
+    /// Fields in arbitration order: base id, IDE bit and id extension
+    fn arbitration(&self) -> (u32, bool, u32) {
+        match *self {
+            CanId::Standard(_) => (self.raw(), false, 0),
+            CanId::Extended(_) => (self.raw() >> 18, true, self.raw() & 0x3FFFF),
+        }
+    }
+}
+
+impl Ord for CanId {
+    fn cmp(&self, other: &CanId) -> Ordering {
+        self.arbitration().cmp(&other.arbitration())
+    }
+}
+
+impl PartialOrd for CanId {
+    fn partial_cmp(&self, other: &CanId) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for CanId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanId::Standard(_) => write!(f, "{:03X}", self.raw()),
+            CanId::Extended(_) => write!(f, "{:08X}", self.raw()),
+        }
+    }
+}
+
+impl From<Id> for CanId {
+    fn from(id: Id) -> CanId {
+        match id {
+            Id::Standard(id) => CanId::Standard(id.as_raw()),
+            Id::Extended(id) => CanId::Extended(id.as_raw()),
+        }
+    }
+}
+
+impl From<CanId> for Id {
+    fn from(id: CanId) -> Id {
+        match id {
+            // the raw value is masked, so the fallbacks are never used
+            CanId::Standard(_) => {
+                Id::Standard(StandardId::new(id.raw() as u16).unwrap_or(StandardId::MAX))
+            }
+            CanId::Extended(_) => {
+                Id::Extended(ExtendedId::new(id.raw()).unwrap_or(ExtendedId::MAX))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CanId;
+
+    #[test]
+    fn test_ordering() {
+        let mut ids = vec![
+            CanId::Extended(0x0490_0000),
+            CanId::Standard(0x123),
+            CanId::Standard(0x012),
+            CanId::Extended(0x0020_0000),
+        ];
+        ids.sort();
+        assert_eq!(
+            ids,
+            [
+                CanId::Extended(0x0020_0000),
+                CanId::Standard(0x012),
+                CanId::Standard(0x123),
+                CanId::Extended(0x0490_0000),
+            ]
+        );
+
+        assert_eq!(CanId::new(0x7FF).unwrap(), CanId::Standard(0x7FF));
+        assert_eq!(CanId::new(0x800).unwrap().to_string(), "00000800");
+        assert!(CanId::new(0x2000_0000).is_err());
+    }
+}
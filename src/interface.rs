@@ -0,0 +1,670 @@
+use crate::frame::bytes_of;
+use libc::{
+    c_char, c_int, c_void, close, if_nametoindex, ifinfomsg, ifreq, ioctl, nlmsgerr, nlmsghdr,
+    recv, send, socket, AF_NETLINK, AF_UNIX, AF_UNSPEC, EACCES, EBUSY, EINVAL, ENODEV, EPERM,
+    IFF_UP, IFLA_IFNAME, IFLA_INFO_DATA, IFLA_INFO_KIND, IFLA_INFO_XSTATS, IFLA_LINKINFO,
+    NETLINK_ROUTE, NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_EXCL, NLM_F_REQUEST, RTM_DELLINK,
+    RTM_GETLINK, RTM_NEWLINK, SIOCGIFFLAGS, SOCK_CLOEXEC, SOCK_DGRAM, SOCK_RAW,
+};
+use std::{error, ffi::CString, fmt, io, mem::size_of, ptr, time::Duration};
+
+/// `IFLA_CAN_BITTIMING` from `linux/can/netlink.h`
+const IFLA_CAN_BITTIMING: u16 = 1;
+
+/// `IFLA_CAN_STATE` from `linux/can/netlink.h`
+const IFLA_CAN_STATE: u16 = 4;
+
+/// `IFLA_CAN_CTRLMODE` from `linux/can/netlink.h`
+const IFLA_CAN_CTRLMODE: u16 = 5;
+
+/// `IFLA_CAN_RESTART_MS` from `linux/can/netlink.h`
+const IFLA_CAN_RESTART_MS: u16 = 6;
+
+/// `IFLA_CAN_RESTART` from `linux/can/netlink.h`
+const IFLA_CAN_RESTART: u16 = 7;
+
+/// `IFLA_CAN_BERR_COUNTER` from `linux/can/netlink.h`
+const IFLA_CAN_BERR_COUNTER: u16 = 8;
+
+/// `IFLA_CAN_DATA_BITTIMING` from `linux/can/netlink.h`
+const IFLA_CAN_DATA_BITTIMING: u16 = 9;
+
+/// `CAN_CTRLMODE_FD` from `linux/can/netlink.h`
+const CAN_CTRLMODE_FD: u32 = 0x20;
+
+/// Controller mode as used by the kernel `can_ctrlmode` struct
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct CtrlMode {
+    mask: u32,
+    flags: u32,
+}
+
+/// Error counters as used by the kernel `can_berr_counter` struct
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct BerrCounter {
+    txerr: u16,
+    rxerr: u16,
+}
+
+/// Bus statistics as used by the kernel `can_device_stats` struct
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct BusStatistics {
+    /// Bus errors
+    pub bus_error: u32,
+    /// Changes to the error warning state
+    pub error_warning: u32,
+    /// Changes to the error passive state
+    pub error_passive: u32,
+    /// Changes to the bus-off state
+    pub bus_off: u32,
+    /// Lost arbitrations
+    pub arbitration_lost: u32,
+    /// Restarts of the controller
+    pub restarts: u32,
+}
+
+/// Controller state, as shown by `ip -details link`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CanState {
+    /// RX/TX error count < 96
+    ErrorActive,
+    /// RX/TX error count < 128
+    ErrorWarning,
+    /// RX/TX error count < 256
+    ErrorPassive,
+    /// RX/TX error count >= 256
+    BusOff,
+    /// Device is stopped
+    Stopped,
+    /// Device is sleeping
+    Sleeping,
+}
+
+impl CanState {
+    fn from_raw(raw: u32) -> Option<CanState> {
+        Some(match raw {
+            0 => CanState::ErrorActive,
+            1 => CanState::ErrorWarning,
+            2 => CanState::ErrorPassive,
+            3 => CanState::BusOff,
+            4 => CanState::Stopped,
+            5 => CanState::Sleeping,
+            _ => return None,
+        })
+    }
+}
+
+/// Health of a CAN interface, see `Interface::state`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InterfaceState {
+    /// Controller state
+    pub state: CanState,
+    /// Transmit error counter (TEC), if supported by the driver
+    pub tx_errors: Option<u16>,
+    /// Receive error counter (REC), if supported by the driver
+    pub rx_errors: Option<u16>,
+    /// Error and restart statistics, if provided by the driver
+    pub statistics: Option<BusStatistics>,
+}
+
+/// Bit timing as used by the kernel `can_bittiming` struct
+///
+/// Fields left at zero are calculated by the kernel from the bitrate.
+#[derive(Debug, Default)]
+#[repr(C)]
+struct BitTiming {
+    bitrate: u32,
+    sample_point: u32,
+    tq: u32,
+    prop_seg: u32,
+    phase_seg1: u32,
+    phase_seg2: u32,
+    sjw: u32,
+    brp: u32,
+}
+
+/// Errors configuring an interface
+#[derive(Debug)]
+pub enum InterfaceError {
+    /// The interface does not exist
+    NotFound,
+
+    /// Missing permissions, configuring interfaces requires `CAP_NET_ADMIN`
+    PermissionDenied,
+
+    /// The setting can not be changed in the current state, e.g. while the
+    /// interface is up
+    Busy,
+
+    /// Other system error
+    IOError(io::Error),
+}
+
+impl From<io::Error> for InterfaceError {
+    fn from(e: io::Error) -> InterfaceError {
+        match e.raw_os_error() {
+            Some(EPERM) | Some(EACCES) => InterfaceError::PermissionDenied,
+            Some(ENODEV) => InterfaceError::NotFound,
+            Some(EBUSY) => InterfaceError::Busy,
+            _ => InterfaceError::IOError(e),
+        }
+    }
+}
+
+impl fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterfaceError::NotFound => write!(f, "interface not found"),
+            InterfaceError::PermissionDenied => {
+                write!(f, "permission denied, CAP_NET_ADMIN is required")
+            }
+            InterfaceError::Busy => write!(f, "interface is busy"),
+            InterfaceError::IOError(e) => write!(f, "interface error: {}", e),
+        }
+    }
+}
+
+impl error::Error for InterfaceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            InterfaceError::IOError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// CAN network interface
+///
+/// Configures an interface using rtnetlink, like `ip link` does. Changing the
+/// configuration requires `CAP_NET_ADMIN`.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    name: String,
+    index: u32,
+}
+
+impl Interface {
+    /// Look up the named interface
+    pub fn new(name: &str) -> Result<Interface, InterfaceError> {
+        let cname = CString::new(name).map_err(|_| InterfaceError::NotFound)?;
+        let index = unsafe { if_nametoindex(cname.as_ptr()) };
+        if index == 0 {
+            return Err(InterfaceError::NotFound);
+        }
+        Ok(Interface {
+            name: name.to_string(),
+            index,
+        })
+    }
+
+    /// Create a virtual CAN interface, like
+    /// `ip link add dev <name> type vcan`.
+    ///
+    /// The interface is created down, use `bring_up` before opening sockets
+    /// on it.
+    pub fn create_vcan(name: &str) -> Result<Interface, InterfaceError> {
+        let mut msg = Message::link(
+            RTM_NEWLINK,
+            NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL,
+            0,
+        );
+        let mut ifname = name.as_bytes().to_vec();
+        ifname.push(0);
+        msg.attr(IFLA_IFNAME, &ifname);
+        let info = msg.begin_nested(IFLA_LINKINFO);
+        msg.attr(IFLA_INFO_KIND, b"vcan");
+        msg.end_nested(info);
+        msg.request()?;
+
+        Interface::new(name)
+    }
+
+    /// Delete the interface, like `ip link delete dev <name>`.
+    pub fn delete(self) -> Result<(), InterfaceError> {
+        Ok(Message::link(RTM_DELLINK, NLM_F_REQUEST | NLM_F_ACK, self.index).request()?)
+    }
+
+    /// Interface name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Kernel interface index, as used by `Socket::open_if`
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Set the bitrate in bit/s.
+    ///
+    /// The interface has to be down. Sample point and segments are
+    /// calculated by the kernel.
+    pub fn set_bitrate(&self, bitrate: u32) -> Result<(), InterfaceError> {
+        let timing = BitTiming {
+            bitrate,
+            ..BitTiming::default()
+        };
+        self.configure(&[(IFLA_CAN_BITTIMING, bytes_of(&timing))])
+    }
+
+    /// Switch the controller between classic CAN and CAN FD operation.
+    ///
+    /// The interface has to be down.
+    pub fn set_fd(&self, enabled: bool) -> Result<(), InterfaceError> {
+        let mode = CtrlMode {
+            mask: CAN_CTRLMODE_FD,
+            flags: if enabled { CAN_CTRLMODE_FD } else { 0 },
+        };
+        self.configure(&[(IFLA_CAN_CTRLMODE, bytes_of(&mode))])
+    }
+
+    /// Enable CAN FD and set the bitrate of the data phase in bit/s.
+    ///
+    /// The sample point is given as a fraction of the bit time, e.g. `0.75`,
+    /// and calculated by the kernel if `None`. The interface has to be down.
+    pub fn set_data_bitrate(
+        &self,
+        bitrate: u32,
+        sample_point: Option<f32>,
+    ) -> Result<(), InterfaceError> {
+        let mode = CtrlMode {
+            mask: CAN_CTRLMODE_FD,
+            flags: CAN_CTRLMODE_FD,
+        };
+        let timing = BitTiming {
+            bitrate,
+            // the kernel uses tenths of a percent
+            sample_point: sample_point.map_or(0, |sp| (sp * 1000.0).round() as u32),
+            ..BitTiming::default()
+        };
+        self.configure(&[
+            (IFLA_CAN_CTRLMODE, bytes_of(&mode)),
+            (IFLA_CAN_DATA_BITTIMING, bytes_of(&timing)),
+        ])
+    }
+
+    /// Restart the controller automatically after `delay` once it went
+    /// bus-off, like `ip link set <name> type can restart-ms <ms>`. `None`
+    /// disables automatic restarts.
+    ///
+    /// The interface has to be down.
+    pub fn set_restart_delay(&self, delay: Option<Duration>) -> Result<(), InterfaceError> {
+        // 0 disables automatic restarts, so round short delays up
+        let ms = delay.map_or(0, |d| d.as_millis().clamp(1, u32::MAX as u128) as u32);
+        self.configure(&[(IFLA_CAN_RESTART_MS, &ms.to_ne_bytes())])
+    }
+
+    /// Restart a controller which is bus-off, like
+    /// `ip link set <name> type can restart`.
+    ///
+    /// Fails with `InterfaceError::Busy` if the controller is not bus-off or
+    /// automatic restarts are enabled.
+    pub fn restart(&self) -> Result<(), InterfaceError> {
+        let msg = Message::can_link(self.index, &[(IFLA_CAN_RESTART, &1u32.to_ne_bytes())]);
+        msg.request().map_err(|e| match e.raw_os_error() {
+            // the kernel refuses manual restarts with restart-ms set this way
+            Some(EINVAL) => InterfaceError::Busy,
+            _ => e.into(),
+        })
+    }
+
+    /// Query the controller state, error counters and statistics.
+    ///
+    /// Fails with `InterfaceError::IOError` if the interface is not a CAN
+    /// device, e.g. a vcan interface.
+    pub fn state(&self) -> Result<InterfaceState, InterfaceError> {
+        let attrs = Message::get_link(self.index).query()?;
+        let info = find_attr(&attrs, IFLA_LINKINFO).unwrap_or(&[]);
+        let data = find_attr(info, IFLA_INFO_DATA).unwrap_or(&[]);
+
+        let state = find_attr(data, IFLA_CAN_STATE)
+            .and_then(read_attr::<u32>)
+            .and_then(CanState::from_raw)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a CAN interface"))?;
+        let counter = find_attr(data, IFLA_CAN_BERR_COUNTER).and_then(read_attr::<BerrCounter>);
+        let statistics = find_attr(info, IFLA_INFO_XSTATS).and_then(read_attr::<BusStatistics>);
+
+        Ok(InterfaceState {
+            state,
+            tx_errors: counter.map(|c| c.txerr),
+            rx_errors: counter.map(|c| c.rxerr),
+            statistics,
+        })
+    }
+
+    /// Bring the interface up, like `ip link set <name> up`.
+    pub fn bring_up(&self) -> Result<(), InterfaceError> {
+        self.set_up(true)
+    }
+
+    /// Bring the interface down, like `ip link set <name> down`.
+    pub fn bring_down(&self) -> Result<(), InterfaceError> {
+        self.set_up(false)
+    }
+
+    /// Check whether the interface is up
+    pub fn is_up(&self) -> Result<bool, InterfaceError> {
+        let fd = unsafe { socket(AF_UNIX, SOCK_DGRAM | SOCK_CLOEXEC, 0) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut req: ifreq = unsafe { std::mem::zeroed() };
+        for (dst, src) in req.ifr_name.iter_mut().zip(self.name.bytes()) {
+            *dst = src as c_char;
+        }
+        let rv = unsafe { ioctl(fd, SIOCGIFFLAGS as _, &mut req) };
+        let result = if rv == -1 {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(unsafe { req.ifr_ifru.ifru_flags } as c_int & IFF_UP != 0)
+        };
+        unsafe {
+            close(fd);
+        }
+        result
+    }
+
+    /// Set CAN specific attributes
+    fn configure(&self, attrs: &[(u16, &[u8])]) -> Result<(), InterfaceError> {
+        Ok(Message::can_link(self.index, attrs).request()?)
+    }
+
+    fn set_up(&self, up: bool) -> Result<(), InterfaceError> {
+        let mut msg = Message::new_link(self.index);
+        msg.set_flags(if up { IFF_UP as u32 } else { 0 }, IFF_UP as u32);
+        Ok(msg.request()?)
+    }
+}
+
+/// rtnetlink request under construction
+pub(crate) struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    /// `RTM_NEWLINK` request modifying the interface `index`
+    fn new_link(index: u32) -> Message {
+        Message::link(RTM_NEWLINK, NLM_F_REQUEST | NLM_F_ACK, index)
+    }
+
+    /// `RTM_GETLINK` request querying the interface `index`
+    fn get_link(index: u32) -> Message {
+        Message::link(RTM_GETLINK, NLM_F_REQUEST, index)
+    }
+
+    /// `RTM_NEWLINK` request setting CAN specific attributes of the
+    /// interface `index`
+    fn can_link(index: u32, attrs: &[(u16, &[u8])]) -> Message {
+        let mut msg = Message::new_link(index);
+        let info = msg.begin_nested(IFLA_LINKINFO);
+        msg.attr(IFLA_INFO_KIND, b"can");
+        let data = msg.begin_nested(IFLA_INFO_DATA);
+        for (ty, value) in attrs {
+            msg.attr(*ty, value);
+        }
+        msg.end_nested(data);
+        msg.end_nested(info);
+        msg
+    }
+
+    fn link(ty: u16, flags: c_int, index: u32) -> Message {
+        let mut info: ifinfomsg = unsafe { std::mem::zeroed() };
+        info.ifi_family = AF_UNSPEC as u8;
+        info.ifi_index = index as c_int;
+        Message::new(ty, flags, bytes_of(&info))
+    }
+
+    /// Request of type `ty` with the family specific header `family`
+    pub(crate) fn new(ty: u16, flags: c_int, family: &[u8]) -> Message {
+        let header = nlmsghdr {
+            nlmsg_len: 0,
+            nlmsg_type: ty,
+            nlmsg_flags: flags as u16,
+            nlmsg_seq: 1,
+            nlmsg_pid: 0,
+        };
+
+        let mut msg = Message { buf: Vec::new() };
+        msg.buf.extend_from_slice(bytes_of(&header));
+        msg.buf.extend_from_slice(family);
+        msg.pad();
+        msg
+    }
+
+    /// Change the interface flags selected by `change` to `flags`
+    fn set_flags(&mut self, flags: u32, change: u32) {
+        let offset = size_of::<nlmsghdr>();
+        // ifi_flags and ifi_change follow family, type and index
+        self.buf[offset + 8..offset + 12].copy_from_slice(&flags.to_ne_bytes());
+        self.buf[offset + 12..offset + 16].copy_from_slice(&change.to_ne_bytes());
+    }
+
+    /// Append an attribute, padded to 4 bytes
+    pub(crate) fn attr(&mut self, ty: u16, data: &[u8]) {
+        let len = (4 + data.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&ty.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.pad();
+    }
+
+    /// Start a nested attribute, returning its offset for `end_nested`
+    fn begin_nested(&mut self, ty: u16) -> usize {
+        let start = self.buf.len();
+        self.attr(ty, &[]);
+        start
+    }
+
+    /// Fix up the length of the nested attribute started at `start`
+    fn end_nested(&mut self, start: usize) {
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    fn pad(&mut self) {
+        while self.buf.len() % 4 != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    /// Final message with the length set
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+
+    /// Send the request and wait for the acknowledgement
+    pub(crate) fn request(self) -> io::Result<()> {
+        let response = self.exchange()?;
+        match error_code(&response)? {
+            Some(0) => Ok(()),
+            Some(e) => Err(io::Error::from_raw_os_error(-e)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected netlink response",
+            )),
+        }
+    }
+
+    /// Send the request and return the attributes of the `RTM_NEWLINK`
+    /// response
+    fn query(self) -> io::Result<Vec<u8>> {
+        let response = self.exchange()?;
+        match error_code(&response)? {
+            Some(e) => Err(io::Error::from_raw_os_error(-e)),
+            None => Ok(response[size_of::<nlmsghdr>() + size_of::<ifinfomsg>()..].to_vec()),
+        }
+    }
+
+    /// Send the request and return the first response message
+    fn exchange(self) -> io::Result<Vec<u8>> {
+        let msg = self.finish();
+        let fd = unsafe { socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_ROUTE) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let result = transfer(fd, &msg);
+        unsafe {
+            close(fd);
+        }
+        result
+    }
+}
+
+/// Send `msg` to the kernel and receive the response
+fn transfer(fd: c_int, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let rv = unsafe { send(fd, msg.as_ptr() as *const c_void, msg.len(), 0) };
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buf = vec![0u8; 16384];
+    let len = unsafe { recv(fd, buf.as_mut_ptr() as *mut c_void, buf.len(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let short = || io::Error::new(io::ErrorKind::InvalidData, "Short netlink response");
+    let msg_len = buf
+        .get(..4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(short)?;
+    if msg_len > len as usize || msg_len < size_of::<nlmsghdr>() + size_of::<ifinfomsg>() {
+        return Err(short());
+    }
+    buf.truncate(msg_len);
+    Ok(buf)
+}
+
+/// Error code of an `NLMSG_ERROR` response, 0 for acknowledgements. `None`
+/// for other messages.
+fn error_code(response: &[u8]) -> io::Result<Option<i32>> {
+    let header = unsafe { ptr::read_unaligned(response.as_ptr() as *const nlmsghdr) };
+    if header.nlmsg_type != NLMSG_ERROR as u16 {
+        return Ok(None);
+    }
+    if response.len() < size_of::<nlmsghdr>() + size_of::<nlmsgerr>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Short netlink response",
+        ));
+    }
+    let error = unsafe {
+        ptr::read_unaligned(response[size_of::<nlmsghdr>()..].as_ptr() as *const nlmsgerr)
+    };
+    Ok(Some(error.error))
+}
+
+/// Find the attribute `ty` in a list of attributes
+pub(crate) fn find_attr(mut attrs: &[u8], ty: u16) -> Option<&[u8]> {
+    while attrs.len() >= 4 {
+        let len = u16::from_ne_bytes([attrs[0], attrs[1]]) as usize;
+        // the upper bits of the type are flags, like NLA_F_NESTED
+        let attr_ty = u16::from_ne_bytes([attrs[2], attrs[3]]) & 0x3FFF;
+        if len < 4 || len > attrs.len() {
+            return None;
+        }
+        if attr_ty == ty {
+            return Some(&attrs[4..len]);
+        }
+        attrs = &attrs[((len + 3) & !3).min(attrs.len())..];
+    }
+    None
+}
+
+/// Read a `repr(C)` value from the start of an attribute
+fn read_attr<T: Copy>(data: &[u8]) -> Option<T> {
+    if data.len() < size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { ptr::read_unaligned(data.as_ptr() as *const T) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_attr, read_attr, CtrlMode, Interface, Message, CAN_CTRLMODE_FD, IFLA_CAN_BITTIMING,
+        IFLA_CAN_CTRLMODE,
+    };
+    use crate::frame::bytes_of;
+    use libc::{IFLA_INFO_DATA, IFLA_INFO_KIND, IFLA_LINKINFO};
+
+    #[test]
+    fn test_nonexistant_interface() {
+        assert!(Interface::new("invalid").is_err());
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut msg = Message::new_link(3);
+        msg.set_flags(1, 1);
+        let buf = msg.finish();
+        assert_eq!(u32::from_ne_bytes([buf[20], buf[21], buf[22], buf[23]]), 3);
+        assert_eq!(u32::from_ne_bytes([buf[24], buf[25], buf[26], buf[27]]), 1);
+        assert_eq!(u32::from_ne_bytes([buf[28], buf[29], buf[30], buf[31]]), 1);
+    }
+
+    #[test]
+    fn test_nested_attributes() {
+        let mut msg = Message::new_link(3);
+        let info = msg.begin_nested(IFLA_LINKINFO);
+        msg.attr(IFLA_INFO_KIND, b"can");
+        let data = msg.begin_nested(IFLA_INFO_DATA);
+        msg.attr(IFLA_CAN_BITTIMING, &[0; 32]);
+        msg.end_nested(data);
+        msg.end_nested(info);
+        let buf = msg.finish();
+
+        // header (16) + ifinfomsg (16) + linkinfo (4 + 8 + 4 + 36)
+        assert_eq!(buf.len(), 84);
+        assert_eq!(u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]), 84);
+        assert_eq!(u16::from_ne_bytes([buf[32], buf[33]]), 52);
+        assert_eq!(&buf[40..43], b"can");
+        assert_eq!(u16::from_ne_bytes([buf[44], buf[45]]), 40);
+
+        let attrs = &buf[32..];
+        let info = find_attr(attrs, IFLA_LINKINFO).unwrap();
+        assert_eq!(find_attr(info, IFLA_INFO_KIND), Some(&b"can"[..]));
+        let data = find_attr(info, IFLA_INFO_DATA).unwrap();
+        assert_eq!(find_attr(data, IFLA_CAN_BITTIMING).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_can_link() {
+        let mode = CtrlMode {
+            mask: CAN_CTRLMODE_FD,
+            flags: 0,
+        };
+        let buf = Message::can_link(3, &[(IFLA_CAN_CTRLMODE, bytes_of(&mode))]).finish();
+
+        let info = find_attr(&buf[32..], IFLA_LINKINFO).unwrap();
+        let data = find_attr(info, IFLA_INFO_DATA).unwrap();
+        let mode = find_attr(data, IFLA_CAN_CTRLMODE)
+            .and_then(read_attr::<CtrlMode>)
+            .unwrap();
+        assert_eq!(mode.mask, CAN_CTRLMODE_FD);
+        assert_eq!(mode.flags, 0);
+    }
+
+    #[cfg(feature = "vcan0")]
+    #[test]
+    fn test_create_vcan() {
+        let vcan = Interface::create_vcan("candevtest0").unwrap();
+        vcan.bring_up().unwrap();
+        assert!(vcan.is_up().unwrap());
+        crate::Socket::open_if(vcan.index()).unwrap();
+        vcan.delete().unwrap();
+        assert!(Interface::new("candevtest0").is_err());
+    }
+
+    #[test]
+    fn test_state_not_can() {
+        let lo = Interface::new("lo").unwrap();
+        assert!(lo.state().is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{socket::c_timeval_new, CanId, SocketError};
+use libc::{
+    bind, c_int, c_short, c_void, close, fcntl, if_nametoindex, read, setsockopt, sockaddr, socket,
+    socklen_t, write, AF_CAN, CAN_EFF_FLAG, F_GETFL, F_SETFL, O_NONBLOCK, PF_CAN, SOCK_DGRAM,
+    SOL_SOCKET, SO_RCVTIMEO,
+};
+use std::{
+    ffi::CString,
+    io,
+    mem::size_of,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    time::Duration,
+};
+
+/// `CAN_ISOTP` from `linux/can.h`
+const CAN_ISOTP: c_int = 6;
+
+/// `SOL_CAN_ISOTP` from `linux/can/isotp.h`
+const SOL_CAN_ISOTP: c_int = 100 + CAN_ISOTP;
+
+/// `CAN_ISOTP_OPTS` from `linux/can/isotp.h`
+const CAN_ISOTP_OPTS: c_int = 1;
+
+/// `CAN_ISOTP_TX_PADDING` from `linux/can/isotp.h`
+const CAN_ISOTP_TX_PADDING: u32 = 0x004;
+
+/// ISO-TP address, as used by the kernel `sockaddr_can`
+#[repr(C)]
+struct IsoTpAddr {
+    can_family: c_short,
+    if_index: c_int,
+    rx_id: u32,
+    tx_id: u32,
+    _pad: [u8; 8],
+}
+
+/// `struct can_isotp_options` from `linux/can/isotp.h`
+#[derive(Default)]
+#[repr(C)]
+struct IsoTpOptions {
+    flags: u32,
+    frame_txtime: u32,
+    ext_address: u8,
+    txpad_content: u8,
+    rxpad_content: u8,
+    rx_ext_address: u8,
+}
+
+/// ISO 15765-2 (ISO-TP) socket
+///
+/// Uses the kernel ISO-TP stack (`CAN_ISOTP`), which handles segmentation
+/// and flow control, so messages of up to 4095 bytes are sent and received
+/// as a whole.
+#[derive(Debug)]
+pub struct IsoTpSocket {
+    fd: c_int,
+}
+
+impl IsoTpSocket {
+    /// Open an ISO-TP socket on the named CAN device, receiving messages
+    /// sent to `rx_id` and transmitting with `tx_id`.
+    pub fn new(ifname: &str, rx_id: CanId, tx_id: CanId) -> Result<IsoTpSocket, SocketError> {
+        IsoTpSocket::with_options(ifname, rx_id, tx_id, IsoTpOptions::default())
+    }
+
+    /// Like `new`, padding transmitted frames to 8 bytes with `padding`, as
+    /// required by many ECUs
+    pub fn with_padding(
+        ifname: &str,
+        rx_id: CanId,
+        tx_id: CanId,
+        padding: u8,
+    ) -> Result<IsoTpSocket, SocketError> {
+        let options = IsoTpOptions {
+            flags: CAN_ISOTP_TX_PADDING,
+            txpad_content: padding,
+            ..IsoTpOptions::default()
+        };
+        IsoTpSocket::with_options(ifname, rx_id, tx_id, options)
+    }
+
+    fn with_options(
+        ifname: &str,
+        rx_id: CanId,
+        tx_id: CanId,
+        options: IsoTpOptions,
+    ) -> Result<IsoTpSocket, SocketError> {
+        let ifname = CString::new(ifname).map_err(|_| SocketError::NoSuchDevice)?;
+        let if_index = unsafe { if_nametoindex(ifname.as_ptr()) };
+        if if_index == 0 {
+            return Err(SocketError::NoSuchDevice);
+        }
+
+        let fd = unsafe { socket(PF_CAN, SOCK_DGRAM, CAN_ISOTP) };
+        if fd == -1 {
+            return Err(SocketError::from(io::Error::last_os_error()));
+        }
+        // closes the socket on error
+        let socket = IsoTpSocket { fd };
+        // options have to be set before binding
+        socket.set_option(SOL_CAN_ISOTP, CAN_ISOTP_OPTS, &options)?;
+
+        let addr = IsoTpAddr {
+            can_family: AF_CAN as c_short,
+            if_index: if_index as c_int,
+            rx_id: raw_id(rx_id),
+            tx_id: raw_id(tx_id),
+            _pad: [0; 8],
+        };
+        let rv = unsafe {
+            bind(
+                fd,
+                &addr as *const IsoTpAddr as *const sockaddr,
+                size_of::<IsoTpAddr>() as socklen_t,
+            )
+        };
+        if rv == -1 {
+            return Err(SocketError::from(io::Error::last_os_error()));
+        }
+        Ok(socket)
+    }
+
+    /// Send a message, blocking until it was transmitted completely
+    pub fn send(&self, data: &[u8]) -> io::Result<usize> {
+        let rv = unsafe { write(self.fd, data.as_ptr() as *const c_void, data.len()) };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(rv as usize)
+    }
+
+    /// Receive a message into `buf`, returning its length.
+    ///
+    /// Messages longer than `buf` are truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let rv = unsafe { read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(rv as usize)
+    }
+
+    /// Change socket to non-blocking mode
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let oldfl = unsafe { fcntl(self.fd, F_GETFL) };
+        if oldfl == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let newfl = if nonblocking {
+            oldfl | O_NONBLOCK
+        } else {
+            oldfl & !O_NONBLOCK
+        };
+        if unsafe { fcntl(self.fd, F_SETFL, newfl) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Sets the read timeout on the socket
+    pub fn set_read_timeout(&self, duration: Duration) -> io::Result<()> {
+        self.set_option(SOL_SOCKET, SO_RCVTIMEO, &c_timeval_new(duration))
+    }
+
+    fn set_option<T>(&self, level: c_int, name: c_int, val: &T) -> io::Result<()> {
+        let rv = unsafe {
+            setsockopt(
+                self.fd,
+                level,
+                name,
+                val as *const T as *const c_void,
+                size_of::<T>() as socklen_t,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for IsoTpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl AsFd for IsoTpSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the descriptor stays open as long as the socket is alive
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+impl Drop for IsoTpSocket {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+/// Id with `CAN_EFF_FLAG` set for extended ids
+fn raw_id(id: CanId) -> u32 {
+    match id {
+        CanId::Standard(_) => id.raw(),
+        CanId::Extended(_) => id.raw() | CAN_EFF_FLAG,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IsoTpAddr, IsoTpOptions, IsoTpSocket};
+    use crate::CanId;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_nonexistant_device() {
+        let id = CanId::Standard(0x7E8);
+        assert!(IsoTpSocket::new("invalid", id, id).is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        // must match the kernel structs
+        assert_eq!(size_of::<IsoTpAddr>(), 24);
+        assert_eq!(size_of::<IsoTpOptions>(), 12);
+    }
+
+    #[cfg(feature = "vcan0")]
+    #[test]
+    fn vcan0_transfer() {
+        let tester =
+            IsoTpSocket::new("vcan0", CanId::Standard(0x7E8), CanId::Standard(0x7E0)).unwrap();
+        let ecu =
+            IsoTpSocket::new("vcan0", CanId::Standard(0x7E0), CanId::Standard(0x7E8)).unwrap();
+
+        // segmented into a first frame and consecutive frames
+        let message: Vec<u8> = (0..100).collect();
+        let sender = std::thread::spawn(move || ecu.send(&message).unwrap());
+        let mut buf = [0u8; 4095];
+        let len = tester.recv(&mut buf).unwrap();
+        assert_eq!(sender.join().unwrap(), 100);
+        assert_eq!(&buf[..len], (0..100).collect::<Vec<u8>>().as_slice());
+    }
+}
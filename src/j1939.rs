@@ -0,0 +1,291 @@
+use crate::SocketError;
+use libc::{
+    bind, c_int, c_short, c_void, close, connect, if_nametoindex, recvfrom, send, sendto,
+    setsockopt, sockaddr, socket, socklen_t, AF_CAN, CAN_J1939, J1939_NO_ADDR, J1939_NO_PGN,
+    PF_CAN, SOCK_DGRAM, SOL_CAN_J1939, SOL_SOCKET, SO_BROADCAST, SO_J1939_PROMISC,
+    SO_J1939_SEND_PRIO,
+};
+use std::{
+    ffi::CString,
+    io,
+    mem::size_of,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+/// `J1939_NO_NAME`, which is a `c_ulong` in libc
+const NO_NAME: u64 = 0;
+
+/// J1939 address, as used by the kernel `sockaddr_can`
+#[derive(Debug)]
+#[repr(C)]
+struct J1939Addr {
+    can_family: c_short,
+    if_index: c_int,
+    name: u64,
+    pgn: u32,
+    addr: u8,
+}
+
+/// Endpoint of a J1939 connection
+///
+/// Any combination of NAME, PGN and address can be given, unset parts act as
+/// wildcards when binding and are left to the kernel when sending.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct J1939Address {
+    /// 64 bit NAME, or `None` to use the address only
+    pub name: Option<u64>,
+    /// Parameter group number, or `None` for any PGN
+    pub pgn: Option<u32>,
+    /// Source or destination address, or `None` if not used
+    pub addr: Option<u8>,
+}
+
+impl J1939Address {
+    /// Address without NAME, PGN or address set
+    pub fn any() -> J1939Address {
+        J1939Address {
+            name: None,
+            pgn: None,
+            addr: None,
+        }
+    }
+
+    /// Address `addr`, e.g. `0xFF` for broadcasts
+    pub fn addr(addr: u8) -> J1939Address {
+        J1939Address {
+            addr: Some(addr),
+            ..J1939Address::any()
+        }
+    }
+
+    /// Restrict the address to `pgn`
+    pub fn with_pgn(mut self, pgn: u32) -> J1939Address {
+        self.pgn = Some(pgn);
+        self
+    }
+
+    /// Address an ECU by its NAME, using the address claimed by it
+    pub fn with_name(mut self, name: u64) -> J1939Address {
+        self.name = Some(name);
+        self
+    }
+
+    fn to_raw(self, if_index: c_int) -> J1939Addr {
+        J1939Addr {
+            can_family: AF_CAN as c_short,
+            if_index,
+            name: self.name.unwrap_or(NO_NAME),
+            pgn: self.pgn.unwrap_or(J1939_NO_PGN),
+            addr: self.addr.unwrap_or(J1939_NO_ADDR),
+        }
+    }
+
+    fn from_raw(raw: &J1939Addr) -> J1939Address {
+        J1939Address {
+            name: Some(raw.name).filter(|n| *n != NO_NAME),
+            pgn: Some(raw.pgn).filter(|p| *p != J1939_NO_PGN),
+            addr: Some(raw.addr).filter(|a| *a != J1939_NO_ADDR),
+        }
+    }
+}
+
+/// SAE J1939 socket
+///
+/// Uses the kernel J1939 stack (`CAN_J1939`), which handles address claiming
+/// state and the transport protocols, so messages of up to 1785 bytes (TP)
+/// or more (ETP) are sent and received as a whole.
+#[derive(Debug)]
+pub struct J1939Socket {
+    fd: c_int,
+}
+
+impl J1939Socket {
+    /// Open a J1939 socket on the named CAN device, bound to `local`.
+    ///
+    /// The local address is used as source of transmitted messages and
+    /// filters received ones, e.g. by PGN.
+    pub fn new(ifname: &str, local: J1939Address) -> Result<J1939Socket, SocketError> {
+        let ifname = CString::new(ifname).map_err(|_| SocketError::NoSuchDevice)?;
+        let if_index = unsafe { if_nametoindex(ifname.as_ptr()) };
+        if if_index == 0 {
+            return Err(SocketError::NoSuchDevice);
+        }
+
+        let fd = unsafe { socket(PF_CAN, SOCK_DGRAM, CAN_J1939) };
+        if fd == -1 {
+            return Err(SocketError::from(io::Error::last_os_error()));
+        }
+        // closes the socket on error
+        let socket = J1939Socket { fd };
+
+        let addr = local.to_raw(if_index as c_int);
+        let rv = unsafe {
+            bind(
+                fd,
+                &addr as *const J1939Addr as *const sockaddr,
+                size_of::<J1939Addr>() as socklen_t,
+            )
+        };
+        if rv == -1 {
+            return Err(SocketError::from(io::Error::last_os_error()));
+        }
+        Ok(socket)
+    }
+
+    /// Set the default destination of `send` and only receive messages
+    /// from `peer`.
+    pub fn connect(&self, peer: J1939Address) -> io::Result<()> {
+        let addr = peer.to_raw(0);
+        let rv = unsafe {
+            connect(
+                self.fd,
+                &addr as *const J1939Addr as *const sockaddr,
+                size_of::<J1939Addr>() as socklen_t,
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Send a message to the connected peer
+    pub fn send(&self, data: &[u8]) -> io::Result<usize> {
+        let rv = unsafe { send(self.fd, data.as_ptr() as *const c_void, data.len(), 0) };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(rv as usize)
+    }
+
+    /// Send a message to `dest`.
+    ///
+    /// Broadcasts require `set_broadcast` to be enabled.
+    pub fn send_to(&self, data: &[u8], dest: J1939Address) -> io::Result<usize> {
+        let addr = dest.to_raw(0);
+        let rv = unsafe {
+            sendto(
+                self.fd,
+                data.as_ptr() as *const c_void,
+                data.len(),
+                0,
+                &addr as *const J1939Addr as *const sockaddr,
+                size_of::<J1939Addr>() as socklen_t,
+            )
+        };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(rv as usize)
+    }
+
+    /// Receive a message into `buf`, returning its length and sender.
+    ///
+    /// Messages longer than `buf` are truncated.
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, J1939Address)> {
+        let mut addr = J1939Address::any().to_raw(0);
+        let mut len = size_of::<J1939Addr>() as socklen_t;
+        let rv = unsafe {
+            recvfrom(
+                self.fd,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                0,
+                &mut addr as *mut J1939Addr as *mut sockaddr,
+                &mut len,
+            )
+        };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((rv as usize, J1939Address::from_raw(&addr)))
+    }
+
+    /// Allow sending to and receiving from the broadcast address
+    pub fn set_broadcast(&self, enabled: bool) -> io::Result<()> {
+        let value: c_int = if enabled { 1 } else { 0 };
+        self.set_option(SOL_SOCKET, SO_BROADCAST, &value)
+    }
+
+    /// Receive all messages, regardless of the bound address
+    pub fn set_promiscuous(&self, enabled: bool) -> io::Result<()> {
+        let value: c_int = if enabled { 1 } else { 0 };
+        self.set_option(SOL_CAN_J1939, SO_J1939_PROMISC, &value)
+    }
+
+    /// Priority (0 = highest to 7) of transmitted messages, default 6
+    pub fn set_send_priority(&self, priority: u8) -> io::Result<()> {
+        let value = priority as c_int;
+        self.set_option(SOL_CAN_J1939, SO_J1939_SEND_PRIO, &value)
+    }
+
+    fn set_option<T>(&self, level: c_int, name: c_int, val: &T) -> io::Result<()> {
+        let rv = unsafe {
+            setsockopt(
+                self.fd,
+                level,
+                name,
+                val as *const T as *const c_void,
+                size_of::<T>() as socklen_t,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for J1939Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for J1939Socket {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{J1939Addr, J1939Address, J1939Socket};
+
+    #[test]
+    fn test_nonexistant_device() {
+        assert!(J1939Socket::new("invalid", J1939Address::addr(0x20)).is_err());
+    }
+
+    #[test]
+    fn test_address_layout() {
+        // must match the kernel sockaddr_can
+        assert_eq!(std::mem::size_of::<J1939Addr>(), 24);
+
+        let address = J1939Address::addr(0x20).with_pgn(0xFEF1);
+        assert_eq!(J1939Address::from_raw(&address.to_raw(1)), address);
+    }
+
+    #[cfg(feature = "vcan0")]
+    #[test]
+    fn vcan0_broadcast() {
+        let receiver = J1939Socket::new("vcan0", J1939Address::any()).unwrap();
+        receiver.set_broadcast(true).unwrap();
+        let sender = J1939Socket::new("vcan0", J1939Address::addr(0x20)).unwrap();
+        sender.set_broadcast(true).unwrap();
+
+        // longer than a single frame, sent using BAM
+        let data: Vec<u8> = (0..20).collect();
+        sender
+            .send_to(&data, J1939Address::addr(0xFF).with_pgn(0xFEF1))
+            .unwrap();
+
+        let mut buf = [0; 64];
+        let (len, from) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &data[..]);
+        assert_eq!(from.addr, Some(0x20));
+        assert_eq!(from.pgn, Some(0xFEF1));
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{pdu::pgn, Frame};
+use embedded_can::{Frame as _, Id};
+use std::{
+    collections::HashMap,
+    error, fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+/// Errors loading a parameter database
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// System error while reading the database file
+    IOError(io::Error),
+
+    /// A line of the database could not be parsed. Contains the line number
+    /// (starting at 1) and the name of the offending column.
+    Parse { line: usize, column: &'static str },
+}
+
+impl From<io::Error> for DatabaseError {
+    fn from(e: io::Error) -> DatabaseError {
+        DatabaseError::IOError(e)
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::IOError(e) => write!(f, "failed to read database: {}", e),
+            DatabaseError::Parse { line, column } => {
+                write!(f, "invalid {} in line {}", column, line)
+            }
+        }
+    }
+}
+
+impl error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DatabaseError::IOError(e) => Some(e),
+            DatabaseError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Suspect parameter (SPN) definition
+///
+/// Describes where a parameter is located inside the payload of its PGN and
+/// how the raw value is converted into a physical value.
+#[derive(Debug, Clone)]
+pub struct Spn {
+    /// Suspect parameter number
+    pub spn: u32,
+    /// Human readable name
+    pub name: String,
+    /// Position of the least significant bit inside the payload
+    pub start_bit: u16,
+    /// Length of the parameter in bits
+    pub bit_length: u16,
+    /// Scaling factor per bit
+    pub resolution: f64,
+    /// Offset added after scaling
+    pub offset: f64,
+    /// Physical unit
+    pub unit: String,
+}
+
+impl Spn {
+    /// Extract the raw value of the parameter from `data`.
+    ///
+    /// Returns `None` if the payload is too short to contain the parameter.
+    pub fn raw(&self, data: &[u8]) -> Option<u64> {
+        let end = self.start_bit as usize + self.bit_length as usize;
+        if self.bit_length == 0 || self.bit_length > 64 || end > data.len() * 8 {
+            return None;
+        }
+
+        // J1939 parameters are transmitted little endian
+        let mut raw = 0u64;
+        for bit in (self.start_bit as usize..end).rev() {
+            raw = (raw << 1) | ((data[bit / 8] >> (bit % 8)) & 1) as u64;
+        }
+        Some(raw)
+    }
+
+    /// Check whether a raw value lies inside the valid range.
+    ///
+    /// J1939 reserves the upper part of each parameter range to signal
+    /// errors or "not available".
+    pub fn is_valid(&self, raw: u64) -> bool {
+        raw <= self.max_valid()
+    }
+
+    /// Largest raw value that is not reserved, the two largest values of
+    /// short parameters are the error indicator and "not available"
+    fn max_valid(&self) -> u64 {
+        if self.bit_length >= 8 {
+            (0xFB << (self.bit_length - 8)) - 1
+        } else {
+            (1u64 << self.bit_length).saturating_sub(3)
+        }
+    }
+}
+
+/// Decoded parameter
+#[derive(Debug, Clone, Copy)]
+pub struct SpnValue<'a> {
+    /// Definition the value was decoded with
+    pub spn: &'a Spn,
+    /// Raw value as transmitted
+    pub raw: u64,
+    /// Physical value, or `None` if the sender signaled an error or that the
+    /// parameter is not available
+    pub value: Option<f64>,
+}
+
+/// J1939 parameter database
+///
+/// Maps parameter group numbers (PGNs) to the suspect parameters they carry.
+/// The text format follows the column layout of the J1939 digital annex, one
+/// parameter per line:
+///
+/// ```text
+/// # PGN,SPN,Name,Position,Length,Resolution,Offset,Units
+/// 61444,190,Engine Speed,4-5,2 bytes,0.125 rpm/bit,0,rpm
+/// 65262,110,Engine Coolant Temperature,1,1 byte,1 °C/bit,-40,°C
+/// ```
+///
+/// Positions are 1-based and given either as byte (`4`, `4-5`) or as
+/// byte and bit (`1.5`). Resolutions may be written as fractions (`1/128`).
+/// Empty lines, lines starting with `#` and a leading `PGN` header are
+/// ignored.
+#[derive(Debug, Clone, Default)]
+pub struct PgnDatabase {
+    pgns: HashMap<u32, Vec<Spn>>,
+}
+
+impl PgnDatabase {
+    /// Create an empty database
+    pub fn new() -> PgnDatabase {
+        PgnDatabase::default()
+    }
+
+    /// Load a database from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<PgnDatabase, DatabaseError> {
+        PgnDatabase::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a database from a reader
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<PgnDatabase, DatabaseError> {
+        let mut db = PgnDatabase::new();
+        for (n, line) in reader.lines().enumerate() {
+            db.parse_line(n + 1, &line?)?;
+        }
+        Ok(db)
+    }
+
+    /// Add a parameter to a PGN
+    pub fn insert(&mut self, pgn: u32, spn: Spn) {
+        self.pgns.entry(pgn).or_default().push(spn);
+    }
+
+    /// All parameters carried by `pgn`
+    pub fn spns(&self, pgn: u32) -> &[Spn] {
+        self.pgns.get(&pgn).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    /// Decode all known parameters of a frame.
+    ///
+    /// Frames with a standard identifier or an unknown PGN yield no values.
+    /// Parameters that do not fit into the received payload are skipped.
+    pub fn decode(&self, frame: &Frame) -> Vec<SpnValue<'_>> {
+        let id = match frame.id() {
+            Id::Extended(id) => id.as_raw(),
+            Id::Standard(_) => return Vec::new(),
+        };
+
+        let data = frame.data();
+        self.spns(pgn(id))
+            .iter()
+            .filter_map(|spn| {
+                let raw = spn.raw(data)?;
+                let value = if spn.is_valid(raw) {
+                    Some(raw as f64 * spn.resolution + spn.offset)
+                } else {
+                    None
+                };
+                Some(SpnValue { spn, raw, value })
+            })
+            .collect()
+    }
+
+    /// Compile a subset of parameters into an `ExtractionPlan`.
+    ///
+    /// The decoded values of `spns` are written to the slots with the same
+    /// index. Returns `None` if a parameter is unknown.
+    pub fn compile(&self, spns: &[u32]) -> Option<ExtractionPlan> {
+        let mut entries = Vec::new();
+        for (slot, number) in spns.iter().enumerate() {
+            let before = entries.len();
+            for (pgn, defs) in &self.pgns {
+                for spn in defs.iter().filter(|spn| spn.spn == *number) {
+                    if spn.bit_length == 0 || spn.bit_length > 64 {
+                        return None;
+                    }
+                    let shift = spn.start_bit % 8;
+                    let mask = u64::MAX >> (64 - spn.bit_length);
+                    entries.push(Extraction {
+                        pgn: *pgn,
+                        slot,
+                        byte: spn.start_bit as usize / 8,
+                        len: (shift + spn.bit_length + 7) as usize / 8,
+                        shift: shift as u32,
+                        mask,
+                        limit: spn.max_valid(),
+                        resolution: spn.resolution,
+                        offset: spn.offset,
+                    });
+                }
+            }
+            if entries.len() == before {
+                return None;
+            }
+        }
+        entries.sort_by_key(|e| e.pgn);
+        Some(ExtractionPlan {
+            slots: spns.len(),
+            entries,
+        })
+    }
+
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<(), DatabaseError> {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            return Ok(());
+        }
+
+        let columns: Vec<&str> = text.split(',').map(str::trim).collect();
+        if columns[0].eq_ignore_ascii_case("pgn") {
+            return Ok(());
+        }
+
+        let column = |idx: usize, name: &'static str| {
+            columns
+                .get(idx)
+                .copied()
+                .ok_or(DatabaseError::Parse { line, column: name })
+        };
+        let error = |column| DatabaseError::Parse { line, column };
+
+        let pgn = column(0, "PGN")?.parse().map_err(|_| error("PGN"))?;
+        let spn = column(1, "SPN")?.parse().map_err(|_| error("SPN"))?;
+        let name = column(2, "Name")?.to_string();
+        let start_bit = parse_position(column(3, "Position")?).ok_or(error("Position"))?;
+        let bit_length = parse_length(column(4, "Length")?).ok_or(error("Length"))?;
+        let resolution = parse_number(column(5, "Resolution")?).ok_or(error("Resolution"))?;
+        let offset = parse_number(column(6, "Offset")?).ok_or(error("Offset"))?;
+        let unit = columns.get(7).copied().unwrap_or("").to_string();
+
+        self.insert(
+            pgn,
+            Spn {
+                spn,
+                name,
+                start_bit,
+                bit_length,
+                resolution,
+                offset,
+                unit,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl FromStr for PgnDatabase {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<PgnDatabase, DatabaseError> {
+        PgnDatabase::from_reader(s.as_bytes())
+    }
+}
+
+/// Precompiled extraction of selected parameters
+///
+/// Created by `PgnDatabase::compile`. Stores the byte offset, shift, mask and
+/// scaling of every parameter in a flat table sorted by PGN, so decoding a
+/// frame needs neither a hash lookup nor bit-by-bit extraction. Use
+/// `PgnDatabase::decode` for ad-hoc queries.
+#[derive(Debug, Clone)]
+pub struct ExtractionPlan {
+    slots: usize,
+    entries: Vec<Extraction>,
+}
+
+#[derive(Debug, Clone)]
+struct Extraction {
+    pgn: u32,
+    slot: usize,
+    byte: usize,
+    len: usize,
+    shift: u32,
+    mask: u64,
+    limit: u64,
+    resolution: f64,
+    offset: f64,
+}
+
+impl ExtractionPlan {
+    /// Number of value slots, i.e. parameters passed to `compile`
+    pub fn len(&self) -> usize {
+        self.slots
+    }
+
+    /// Whether the plan contains no parameters
+    pub fn is_empty(&self) -> bool {
+        self.slots == 0
+    }
+
+    /// Decode the planned parameters carried by `frame` into `values`.
+    ///
+    /// Only the slots of parameters contained in the frame are written;
+    /// a slot is set to `None` if the sender signaled an error or that the
+    /// parameter is not available. Parameters that do not fit into the
+    /// received payload are skipped. Returns the number of slots written.
+    ///
+    /// Panics if `values` is shorter than `len()`.
+    pub fn decode(&self, frame: &Frame, values: &mut [Option<f64>]) -> usize {
+        assert!(values.len() >= self.slots, "too few value slots");
+        let pgn = match frame.id() {
+            Id::Extended(id) => pgn(id.as_raw()),
+            Id::Standard(_) => return 0,
+        };
+
+        let data = frame.data();
+        let first = self.entries.partition_point(|e| e.pgn < pgn);
+        let mut written = 0;
+        for e in self.entries[first..].iter().take_while(|e| e.pgn == pgn) {
+            let bytes = match data.get(e.byte..e.byte + e.len) {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            let word = bytes
+                .iter()
+                .rev()
+                .fold(0u128, |word, b| (word << 8) | *b as u128);
+            let raw = (word >> e.shift) as u64 & e.mask;
+            values[e.slot] = if raw <= e.limit {
+                Some(raw as f64 * e.resolution + e.offset)
+            } else {
+                None
+            };
+            written += 1;
+        }
+        written
+    }
+}
+
+/// Parse a 1-based `byte`, `byte-byte` or `byte.bit` position into a bit
+/// offset.
+fn parse_position(s: &str) -> Option<u16> {
+    let first = s.split('-').next()?.trim();
+    let (byte, bit) = match first.split_once('.') {
+        Some((byte, bit)) => (byte.parse::<u16>().ok()?, bit.parse::<u16>().ok()?),
+        None => (first.parse::<u16>().ok()?, 1),
+    };
+    if byte == 0 || bit == 0 || bit > 8 {
+        return None;
+    }
+    (byte - 1).checked_mul(8)?.checked_add(bit - 1)
+}
+
+/// Parse a length such as `2 bytes` or `4 bits` into a number of bits.
+fn parse_length(s: &str) -> Option<u16> {
+    let mut parts = s.split_whitespace();
+    let count: u16 = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some(unit) if unit.starts_with("bit") => Some(count),
+        Some(unit) if unit.starts_with("byte") => count.checked_mul(8),
+        _ => None,
+    }
+}
+
+/// Parse the leading number of a column, accepting fractions like `1/128`.
+fn parse_number(s: &str) -> Option<f64> {
+    let number = s.split_whitespace().next()?;
+    match number.split_once('/') {
+        Some((num, den)) => Some(num.parse::<f64>().ok()? / den.parse::<f64>().ok()?),
+        None => number.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PgnDatabase;
+    use crate::Frame;
+
+    const DB: &str = "\
+PGN,SPN,Name,Position,Length,Resolution,Offset,Units
+# EEC1
+61444,513,Actual Engine - Percent Torque,3,1 byte,1 %/bit,-125,%
+61444,190,Engine Speed,4-5,2 bytes,0.125 rpm/bit,0,rpm
+61444,1675,Engine Starter Mode,4.1,4 bits,1 states/bit,0,
+";
+
+    #[test]
+    fn test_decode_eec1() {
+        let db: PgnDatabase = DB.parse().unwrap();
+        let frame = Frame::new(
+            0x0CF00400,
+            &[0xFF, 0xFF, 0x7D, 0x40, 0x1F, 0xFF, 0xFF, 0xFF],
+            false,
+            false,
+        )
+        .unwrap();
+
+        let values = db.decode(&frame);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].spn.spn, 513);
+        assert_eq!(values[0].value, Some(0.0));
+        assert_eq!(values[1].spn.spn, 190);
+        assert_eq!(values[1].value, Some(1000.0));
+        assert_eq!(values[2].raw, 0x0);
+    }
+
+    #[test]
+    fn test_not_available() {
+        let db: PgnDatabase = DB.parse().unwrap();
+        let frame = Frame::new(0x0CF00400, &[0xFF; 8], false, false).unwrap();
+
+        let values = db.decode(&frame);
+        assert!(values.iter().all(|v| v.value.is_none()));
+    }
+
+    #[test]
+    fn test_error_indicator() {
+        let db: PgnDatabase = "65265,70,Parking Brake Switch,1.3,2 bits,1 states/bit,0,"
+            .parse()
+            .unwrap();
+        let frame = |byte| Frame::new(0x18FEF100, &[byte; 8], false, false).unwrap();
+
+        let values = db.decode(&frame(0b0000_0100));
+        assert_eq!(values[0].value, Some(1.0));
+        let values = db.decode(&frame(0b0000_1000));
+        assert_eq!(values[0].raw, 0b10);
+        assert_eq!(values[0].value, None);
+    }
+
+    #[test]
+    fn test_extraction_plan() {
+        let db: PgnDatabase = DB.parse().unwrap();
+        let plan = db.compile(&[190, 1675, 513]).unwrap();
+        assert!(db.compile(&[190, 91]).is_none());
+
+        let frame = Frame::new(
+            0x0CF00400,
+            &[0xFF, 0xFF, 0x7D, 0x45, 0x1F, 0xFF, 0xFF, 0xFF],
+            false,
+            false,
+        )
+        .unwrap();
+        let mut values = [None; 3];
+        assert_eq!(plan.decode(&frame, &mut values), 3);
+        for value in db.decode(&frame) {
+            let slot = [190, 1675, 513]
+                .iter()
+                .position(|spn| *spn == value.spn.spn)
+                .unwrap();
+            assert_eq!(values[slot], value.value);
+        }
+
+        let short = Frame::new(0x0CF00400, &frame.data()[..4], false, false).unwrap();
+        let mut values = [None; 3];
+        assert_eq!(plan.decode(&short, &mut values), 2);
+        assert_eq!(values, [None, Some(5.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = "61444,190,Engine Speed,4-5,two bytes,0.125,0,rpm"
+            .parse::<PgnDatabase>()
+            .unwrap_err();
+        match err {
+            super::DatabaseError::Parse { line, column } => {
+                assert_eq!(line, 1);
+                assert_eq!(column, "Length");
+            }
+            _ => panic!("unexpected error"),
+        }
+
+        // sizes overflowing the bit offsets
+        for (line, column) in [
+            (
+                "61444,190,Engine Speed,9000,2 bytes,0.125,0,rpm",
+                "Position",
+            ),
+            (
+                "61444,190,Engine Speed,4-5,9000 bytes,0.125,0,rpm",
+                "Length",
+            ),
+        ] {
+            match line.parse::<PgnDatabase>().unwrap_err() {
+                super::DatabaseError::Parse { column: c, .. } => assert_eq!(c, column),
+                _ => panic!("unexpected error"),
+            }
+        }
+    }
+}
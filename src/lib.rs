@@ -1,11 +1,201 @@
+#[cfg(feature = "j1939-db")]
+mod alarm;
+#[cfg(feature = "j1939-db")]
+pub use alarm::{AlarmEvent, AlarmKind, AlarmMonitor, Threshold};
+
+#[cfg(feature = "async-io")]
+mod async_socket;
+#[cfg(feature = "async-io")]
+pub use async_socket::AsyncSocket;
+
+#[cfg(feature = "cannelloni")]
+mod cannelloni;
+#[cfg(feature = "cannelloni")]
+pub use cannelloni::{Cannelloni, TunnelStats};
+
+#[cfg(feature = "canopen")]
+mod canopen;
+#[cfg(feature = "canopen")]
+pub use canopen::{
+    Emergency, Heartbeat, HeartbeatProducer, NmtState, SdoClient, SdoError, SdoServer, SyncMessage,
+    SyncProducer,
+};
+
+#[cfg(feature = "capture")]
+mod capture;
+#[cfg(feature = "capture")]
+pub use capture::{
+    capture_into, capture_to, Capture, CaptureOptions, CaptureReader, CaptureRecord, CaptureStats,
+    FileSink, FrameSink,
+};
+
+#[cfg(feature = "netlink")]
+mod cangw;
+#[cfg(feature = "netlink")]
+pub use cangw::{GwModOp, GwRule};
+
+mod clock;
+pub use clock::{Clock, ManualClock, SystemClock};
+
+mod coalesce;
+pub use coalesce::{ErrorCoalescer, ErrorSummary};
+
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "config")]
+pub use config::{BusConfig, ConfigDiff, ConfigError, ConfigWatcher};
+
+mod confirm;
+pub use confirm::{ConfirmedSocket, TxEvent, TxTag};
+
+#[cfg(feature = "dbc")]
+mod dbc;
+#[cfg(feature = "dbc")]
+pub use dbc::{
+    ByteOrder, Dbc, DbcError, DbcMessage, DecodePlan, EncodeError, Multiplexing, Signal,
+    SignalUpdates, SignalValue,
+};
+
 mod error;
-pub use error::{CanError, ConstructionError, DecodingError, SocketError};
+pub use error::{
+    CanError, ConstructionError, ControllerError, ControllerSpecificErrorInformation,
+    DecodingError, ErrorCounters, Location, ParseError, SocketError, TransceiverError,
+    ViolationType,
+};
 
-// mod filter;
-// pub use filter::{Filter, FilterGroup, FilterGroups};
+mod error_budget;
+pub use error_budget::{ErrorBudget, ErrorClass, ErrorCondition};
+
+mod filter;
+pub use filter::{Filter, FilterGroup};
+
+#[cfg(feature = "flash")]
+mod flash;
+#[cfg(feature = "flash")]
+pub use flash::{crc32, FlashError, FlashProtocol, Flasher, Progress};
 
 mod frame;
-pub use frame::Frame;
+pub use frame::{AnyFrame, FdFrame, Frame, FrameBuilder};
+
+mod gateway;
+pub use gateway::{Gateway, Route};
+
+mod history;
+pub use history::History;
+
+mod id;
+pub use id::CanId;
+
+#[cfg(feature = "netlink")]
+mod interface;
+#[cfg(feature = "netlink")]
+pub use interface::{BusStatistics, CanState, Interface, InterfaceError, InterfaceState};
+
+#[cfg(feature = "isotp")]
+mod isotp;
+#[cfg(feature = "isotp")]
+pub use isotp::IsoTpSocket;
+
+#[cfg(feature = "j1939")]
+mod j1939;
+#[cfg(feature = "j1939")]
+pub use j1939::{J1939Address, J1939Socket};
+
+#[cfg(feature = "j1939-db")]
+mod j1939db;
+#[cfg(feature = "j1939-db")]
+pub use j1939db::{DatabaseError, ExtractionPlan, PgnDatabase, Spn, SpnValue};
+
+#[cfg(feature = "blf")]
+mod blf;
+#[cfg(feature = "blf")]
+pub use blf::{BlfMessage, BlfReader, BlfWriter};
+
+#[cfg(feature = "candump")]
+mod log;
+#[cfg(feature = "candump")]
+pub use log::{CandumpReader, CandumpWriter, LogEntry};
+
+mod middleware;
+pub use middleware::{Middleware, Stack};
+
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mock")]
+pub use mock::{RecordedFrame, Recorder, Recording, Replay};
+
+mod mux;
+pub use mux::{Channel, Mux};
+
+#[cfg(feature = "nmea2000")]
+mod nmea2000;
+#[cfg(feature = "nmea2000")]
+pub use nmea2000::{AddressClaim, FastPacket, N2kMessage, Name, ProductInformation};
+
+#[cfg(feature = "obd")]
+mod obd;
+#[cfg(all(feature = "obd", feature = "async-io"))]
+pub use obd::AsyncObdClient;
+#[cfg(feature = "obd")]
+pub use obd::{Dtc, ObdClient, ObdError, Pid};
+
+mod pacing;
+pub use pacing::Paced;
+
+#[cfg(feature = "pcap")]
+mod pcap;
+#[cfg(feature = "pcap")]
+pub use pcap::{PcapNgWriter, PcapPacket, PcapReader, PcapWriter};
+
+#[cfg(any(feature = "j1939-db", feature = "nmea2000"))]
+mod pdu;
+
+mod poll;
+pub use poll::{Interest, Poller, Readiness};
+
+#[cfg(feature = "protobuf")]
+mod proto;
+#[cfg(feature = "protobuf")]
+pub use proto::{ProtoRecord, PROTO_SCHEMA};
+
+mod registry;
+pub use registry::{Allocation, IdRegistry};
+
+mod report;
+pub use report::{BusReport, QueueStats, SessionReport, SessionStats};
+
+mod schedule;
+pub use schedule::{next_boundary, PeriodicMessage, PeriodicScheduler, WallClockTimer};
+
+mod shutdown;
+pub use shutdown::{ShutdownReport, ShutdownToken};
 
 mod socket;
-pub use socket::Socket;
+pub use socket::{
+    Direction, ReceiveStrategy, RxSocket, Socket, Timestamp, TimestampMode, TransmitOutcome,
+    TxSocket,
+};
+
+#[cfg(feature = "socketcand")]
+mod socketcand;
+#[cfg(feature = "socketcand")]
+pub use socketcand::SocketCand;
+
+mod tap;
+pub use tap::{Tap, TapFrame, Tapped};
+
+#[cfg(feature = "netlink")]
+mod testbus;
+#[cfg(feature = "netlink")]
+pub use testbus::TestBus;
+
+mod translate;
+pub use translate::{IdMap, Translated};
+
+#[cfg(feature = "uds")]
+mod uds;
+#[cfg(feature = "uds")]
+pub use uds::{Nrc, ResetType, RoutineControl, Session, UdsClient, UdsError};
+
+mod xl;
+pub use xl::{XlAnyFrame, XlFrame};
@@ -0,0 +1,224 @@
+use crate::{AnyFrame, ShutdownToken};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Line of a `candump -l` log file
+///
+/// ```text
+/// (1436509052.249713) vcan0 044#2A366C2BBA
+/// (1436509052.449847) vcan0 123##1DEADBEEF
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Receive time since the Unix epoch
+    pub timestamp: Duration,
+    /// Name of the interface the frame was received on
+    pub interface: String,
+    /// The logged frame
+    pub frame: AnyFrame,
+}
+
+/// Iterator over the entries of a `candump -l` log file
+///
+/// Reads one line at a time, so large files are streamed. Empty lines are
+/// skipped; a trailing direction marker (` R` or ` T`), as written by newer
+/// versions of `candump`, is ignored.
+pub struct CandumpReader<R> {
+    reader: R,
+    line: String,
+    line_no: usize,
+}
+
+impl CandumpReader<BufReader<File>> {
+    /// Open a log file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CandumpReader<BufReader<File>>> {
+        Ok(CandumpReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    /// Read entries from `reader`
+    pub fn new(reader: R) -> CandumpReader<R> {
+        CandumpReader {
+            reader,
+            line: String::new(),
+            line_no: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = io::Result<LogEntry>;
+
+    fn next(&mut self) -> Option<io::Result<LogEntry>> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_no += 1,
+                Err(e) => return Some(Err(e)),
+            }
+
+            let line = self.line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            return Some(parse_entry(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid log entry in line {}", self.line_no),
+                )
+            }));
+        }
+    }
+}
+
+/// Writer of `candump -l` log files
+pub struct CandumpWriter<W> {
+    writer: W,
+    /// Entries written since the last flush
+    pending: u64,
+}
+
+impl CandumpWriter<BufWriter<File>> {
+    /// Create a log file, replacing an existing one
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<CandumpWriter<BufWriter<File>>> {
+        Ok(CandumpWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> CandumpWriter<W> {
+    /// Write entries to `writer`
+    pub fn new(writer: W) -> CandumpWriter<W> {
+        CandumpWriter { writer, pending: 0 }
+    }
+
+    /// Append an entry
+    pub fn write(&mut self, entry: &LogEntry) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "({}.{:06}) {} {}",
+            entry.timestamp.as_secs(),
+            entry.timestamp.subsec_micros(),
+            entry.interface,
+            entry.frame
+        )?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Flush buffered entries
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Flush the buffered entries once shutting down and return the underlying
+    /// writer. The flushed entries are reported to `token` as subsystem
+    /// `candump`, or as dropped if flushing failed.
+    pub fn finish_on(mut self, token: &ShutdownToken) -> io::Result<W> {
+        let pending = self.pending;
+        let rv = self.flush();
+        token.report_flush("candump", pending, &rv);
+        rv.map(|_| self.writer)
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn parse_entry(line: &str) -> Option<LogEntry> {
+    let mut fields = line.split_whitespace();
+    let timestamp = parse_timestamp(fields.next()?)?;
+    let interface = fields.next()?.to_string();
+    let frame = fields.next()?.parse().ok()?;
+    match fields.next() {
+        None | Some("R") | Some("T") => {}
+        _ => return None,
+    }
+    Some(LogEntry {
+        timestamp,
+        interface,
+        frame,
+    })
+}
+
+/// Parse a `(seconds.fraction)` timestamp
+fn parse_timestamp(text: &str) -> Option<Duration> {
+    let text = text.strip_prefix('(')?.strip_suffix(')')?;
+    let (secs, frac) = text.split_once('.')?;
+    if frac.is_empty() || frac.len() > 9 || !frac.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = frac.parse::<u32>().ok()? * 10u32.pow(9 - frac.len() as u32);
+    Some(Duration::new(secs.parse().ok()?, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CandumpReader, CandumpWriter};
+    use crate::{AnyFrame, ShutdownToken};
+    use std::time::Duration;
+
+    const LOG: &str = "\
+(1436509052.249713) vcan0 044#2A366C2BBA
+(1436509052.449847) vcan1 123##1DEADBEEF
+(1436509052.650000) vcan0 1F334455#R4
+";
+
+    #[test]
+    fn test_log_roundtrip() {
+        let entries = CandumpReader::new(LOG.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].timestamp, Duration::new(1436509052, 249_713_000));
+        assert_eq!(entries[1].interface, "vcan1");
+        assert!(matches!(entries[1].frame, AnyFrame::Fd(frame) if frame.is_brs()));
+
+        let mut writer = CandumpWriter::new(Vec::new());
+        for entry in &entries {
+            writer.write(entry).unwrap();
+        }
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), LOG);
+    }
+
+    #[test]
+    fn test_finish_on() {
+        let entries = CandumpReader::new(LOG.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut writer = CandumpWriter::new(Vec::new());
+        writer.write(&entries[0]).unwrap();
+        writer.flush().unwrap();
+        for entry in &entries[1..] {
+            writer.write(entry).unwrap();
+        }
+
+        let token = ShutdownToken::new();
+        let log = writer.finish_on(&token).unwrap();
+        assert_eq!(String::from_utf8(log).unwrap(), LOG);
+        assert_eq!(
+            token.reports()[0].to_string(),
+            "candump: 2 frames flushed, 0 dropped"
+        );
+    }
+
+    #[test]
+    fn test_log_errors() {
+        let log = "(1.5) can0 123#01 T\n\n(1.5) can0 123#0\n";
+        let mut reader = CandumpReader::new(log.as_bytes());
+        let entry = reader.next().unwrap().unwrap();
+        assert_eq!(entry.timestamp, Duration::from_millis(1500));
+        let error = reader.next().unwrap().unwrap_err();
+        assert!(error.to_string().contains("line 3"));
+        assert!(reader.next().is_none());
+    }
+}
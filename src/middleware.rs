@@ -0,0 +1,164 @@
+use crate::{FilterGroup, Frame, IdMap};
+use embedded_can::blocking::Can;
+use std::fmt;
+
+/// Frame transformation applied by a `Stack`
+///
+/// Both hooks pass frames through unchanged by default. Returning `None`
+/// drops the frame.
+pub trait Middleware {
+    /// Transform a frame before it is transmitted
+    fn transmit(&mut self, frame: Frame) -> Option<Frame> {
+        Some(frame)
+    }
+
+    /// Transform a received frame before it is returned
+    fn receive(&mut self, frame: Frame) -> Option<Frame> {
+        Some(frame)
+    }
+}
+
+/// Translates the ids of received frames
+impl Middleware for IdMap {
+    fn receive(&mut self, frame: Frame) -> Option<Frame> {
+        Some(self.translate(&frame))
+    }
+}
+
+/// Drops received frames not accepted by the group
+impl Middleware for FilterGroup {
+    fn receive(&mut self, frame: Frame) -> Option<Frame> {
+        if self.matches(&frame) {
+            Some(frame)
+        } else {
+            None
+        }
+    }
+}
+
+/// Ordered layers of middleware around a `Can` implementation
+///
+/// Transmitted frames pass the layers in the order they were added before
+/// reaching the wrapped device, received frames pass them in reverse order:
+///
+/// ```no_run
+/// use candev::{Filter, FilterGroup, IdMap, Socket, Stack};
+/// use embedded_can::blocking::Can;
+///
+/// // receives PGN 0xFEF1 from source address 0x00 at any priority
+/// let mut stack = Stack::new(Socket::new("vcan0").unwrap())
+///     .layer(FilterGroup::new().filter(Filter::extended(0x00FE_F100)))
+///     .layer(IdMap::new().clear_extended_bits(0x1C00_0000));
+/// let frame = stack.receive().unwrap();
+/// ```
+///
+/// A frame dropped by a layer is silently discarded: `transmit` succeeds
+/// without sending it and `receive` waits for the next frame.
+pub struct Stack<T> {
+    inner: T,
+    layers: Vec<Box<dyn Middleware + Send>>,
+}
+
+impl<T: Can<Frame = Frame>> Stack<T> {
+    /// Wrap `inner` without any layers
+    pub fn new(inner: T) -> Stack<T> {
+        Stack {
+            inner,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Add a layer on top of the current ones
+    pub fn layer(mut self, layer: impl Middleware + Send + 'static) -> Stack<T> {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Access the wrapped device
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Return the wrapped device
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Can<Frame = Frame>> Can for Stack<T> {
+    type Frame = Frame;
+    type Error = T::Error;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+        let mut frame = *frame;
+        for layer in self.layers.iter_mut() {
+            frame = match layer.transmit(frame) {
+                Some(frame) => frame,
+                None => return Ok(()),
+            };
+        }
+        self.inner.transmit(&frame)
+    }
+
+    fn receive(&mut self) -> Result<Frame, Self::Error> {
+        'receive: loop {
+            let mut frame = self.inner.receive()?;
+            for layer in self.layers.iter_mut().rev() {
+                frame = match layer.receive(frame) {
+                    Some(frame) => frame,
+                    None => continue 'receive,
+                };
+            }
+            return Ok(frame);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stack")
+            .field("inner", &self.inner)
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Middleware, Stack};
+    use crate::{CanId, Filter, FilterGroup, Frame, IdMap, Socket};
+    use embedded_can::{blocking::Can, Frame as _};
+
+    /// Stamps a rolling counter into the first data byte
+    struct Counter(u8);
+
+    impl Middleware for Counter {
+        fn transmit(&mut self, mut frame: Frame) -> Option<Frame> {
+            self.0 += 1;
+            frame.data_mut()[0] = self.0;
+            Some(frame)
+        }
+    }
+
+    #[test]
+    fn test_stack() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let mut stack = Stack::new(a)
+            .layer(Counter(0))
+            .layer(FilterGroup::new().filter(Filter::standard(0x101)))
+            .layer(IdMap::new().map(CanId::Standard(0x100), CanId::Standard(0x101)));
+
+        stack
+            .transmit(&Frame::new(0x200, &[0], false, false).unwrap())
+            .unwrap();
+        assert_eq!(b.receive().unwrap().data(), &[1]);
+
+        // ids are translated before the filter sees them
+        for id in [0x102, 0x100] {
+            b.transmit(&Frame::new(id, &[], false, false).unwrap())
+                .unwrap();
+        }
+        let frame = stack.receive().unwrap();
+        assert_eq!(CanId::from(frame.id()), CanId::Standard(0x101));
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{Frame, ShutdownToken, SocketError};
+use embedded_can::blocking::Can;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, Write},
+};
+
+/// Frame recorded by a `Recorder`
+#[derive(Debug, Copy, Clone)]
+pub enum RecordedFrame {
+    /// Frame transmitted by the application
+    Transmit(Frame),
+    /// Frame received from the bus
+    Receive(Frame),
+}
+
+/// Recorded session
+///
+/// Can be stored as text, one frame per line in the `candump` notation
+/// prefixed by the direction:
+///
+/// ```text
+/// tx 7DF#0201050000000000
+/// rx 7E8#03410587AAAAAAAA
+/// rx 18FEF100#R8
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    /// Create an empty recording
+    pub fn new() -> Recording {
+        Recording::default()
+    }
+
+    /// Append a frame
+    pub fn push(&mut self, frame: RecordedFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Recorded frames in order
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    /// Write the recording as text
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for frame in &self.frames {
+            let (dir, frame) = match frame {
+                RecordedFrame::Transmit(frame) => ("tx", frame),
+                RecordedFrame::Receive(frame) => ("rx", frame),
+            };
+            writeln!(writer, "{} {}", dir, frame)?;
+        }
+        Ok(())
+    }
+
+    /// Read a recording written by `write_to`.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Recording> {
+        let mut recording = Recording::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid frame in line {}", n + 1),
+                )
+            };
+            let (dir, frame) = line.split_once(' ').ok_or_else(invalid)?;
+            let frame = frame.trim().parse().map_err(|_| invalid())?;
+            recording.push(match dir {
+                "tx" => RecordedFrame::Transmit(frame),
+                "rx" => RecordedFrame::Receive(frame),
+                _ => return Err(invalid()),
+            });
+        }
+        Ok(recording)
+    }
+}
+
+/// Records the traffic of a session
+///
+/// Wraps a `Can` implementation, e.g. a `Socket`, and records every frame
+/// transmitted and received through it.
+#[derive(Debug)]
+pub struct Recorder<T> {
+    inner: T,
+    recording: Recording,
+}
+
+impl<T: Can<Frame = Frame>> Recorder<T> {
+    /// Start recording the traffic of `inner`
+    pub fn new(inner: T) -> Recorder<T> {
+        Recorder {
+            inner,
+            recording: Recording::new(),
+        }
+    }
+
+    /// Traffic recorded so far
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Write the recording to `writer` once shutting down, see
+    /// `Recording::write_to`. The recorded frames are reported to `token` as
+    /// flushed by subsystem `recorder`, or as dropped if writing failed.
+    pub fn save_on<W: Write>(&self, writer: W, token: &ShutdownToken) -> io::Result<()> {
+        let rv = self.recording.write_to(writer);
+        let pending = self.recording.frames().len() as u64;
+        token.report_flush("recorder", pending, &rv);
+        rv
+    }
+
+    /// Stop recording, returning the wrapped device and the recording
+    pub fn finish(self) -> (T, Recording) {
+        (self.inner, self.recording)
+    }
+}
+
+impl<T: Can<Frame = Frame>> Can for Recorder<T> {
+    type Frame = Frame;
+    type Error = T::Error;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+        self.inner.transmit(frame)?;
+        self.recording.push(RecordedFrame::Transmit(*frame));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Frame, Self::Error> {
+        let frame = self.inner.receive()?;
+        self.recording.push(RecordedFrame::Receive(frame));
+        Ok(frame)
+    }
+}
+
+/// Stands in for the bus by replaying a `Recording`
+///
+/// Every transmitted frame is looked up in the recording, and the frames
+/// received after it was recorded are queued for `receive`. If the same
+/// frame was transmitted several times, the recorded responses are replayed
+/// in order, repeating the last one. Frames received before the first
+/// transmission are available right away.
+///
+/// `receive` fails with `WouldBlock` if no response is queued. To test an
+/// application using a real `Socket` without any CAN interface, `serve` one
+/// end of a `Socket::pair` and hand the other end to the application.
+#[derive(Debug)]
+pub struct Replay {
+    responses: HashMap<(u32, Vec<u8>), VecDeque<Vec<Frame>>>,
+    queue: VecDeque<Frame>,
+}
+
+impl Replay {
+    /// Create a replay from a recording
+    pub fn new(recording: &Recording) -> Replay {
+        let mut replay = Replay {
+            responses: HashMap::new(),
+            queue: VecDeque::new(),
+        };
+
+        let mut current: Option<(u32, Vec<u8>)> = None;
+        let mut frames = Vec::new();
+        for frame in recording.frames() {
+            match frame {
+                RecordedFrame::Receive(frame) => frames.push(*frame),
+                RecordedFrame::Transmit(frame) => {
+                    replay.store(current.take(), std::mem::take(&mut frames));
+                    current = Some(key(frame));
+                }
+            }
+        }
+        replay.store(current, frames);
+        replay
+    }
+
+    /// Number of frames waiting to be received
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Stand in for the bus behind `bus`, usually one end of a
+    /// `Socket::pair`.
+    ///
+    /// Sends the queued frames to `bus`, then answers every frame received
+    /// from it with the recorded responses. Runs until `bus` fails, e.g.
+    /// because the other end of the pair was closed, and returns that error.
+    pub fn serve<T: Can<Frame = Frame>>(&mut self, bus: &mut T) -> T::Error {
+        loop {
+            while let Some(frame) = self.queue.pop_front() {
+                if let Err(e) = bus.transmit(&frame) {
+                    return e;
+                }
+            }
+            match bus.receive() {
+                Ok(frame) => {
+                    let _ = Can::transmit(self, &frame);
+                }
+                Err(e) => return e,
+            }
+        }
+    }
+
+    fn store(&mut self, request: Option<(u32, Vec<u8>)>, frames: Vec<Frame>) {
+        match request {
+            Some(request) => self.responses.entry(request).or_default().push_back(frames),
+            None => self.queue.extend(frames),
+        }
+    }
+}
+
+impl Can for Replay {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        if let Some(responses) = self.responses.get_mut(&key(frame)) {
+            let frames = if responses.len() > 1 {
+                responses.pop_front()
+            } else {
+                responses.front().cloned()
+            };
+            self.queue.extend(frames.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Frame, SocketError> {
+        self.queue.pop_front().ok_or(SocketError::WouldBlock)
+    }
+}
+
+fn key(frame: &Frame) -> (u32, Vec<u8>) {
+    (frame.can_id(), frame.data().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordedFrame, Recorder, Recording, Replay};
+    use crate::{Frame, ShutdownToken, Socket};
+    use embedded_can::{blocking::Can, Frame as _};
+
+    const SESSION: &str = "\
+# OBD-II engine speed
+rx 18FEF100#R8
+tx 7DF#02010C
+rx 7E8#04410C1AF8
+tx 7DF#02010C
+rx 7E8#04410C1B00
+";
+
+    #[test]
+    fn test_text_roundtrip() {
+        let recording = Recording::read_from(SESSION.as_bytes()).unwrap();
+        assert_eq!(recording.frames().len(), 5);
+
+        let mut text = Vec::new();
+        recording.write_to(&mut text).unwrap();
+        let expected: Vec<&str> = SESSION.lines().skip(1).collect();
+        assert_eq!(
+            String::from_utf8(text).unwrap().lines().collect::<Vec<_>>(),
+            expected
+        );
+
+        assert!(Recording::read_from("tx 7DF#0".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let recording = Recording::read_from(SESSION.as_bytes()).unwrap();
+        let mut replay = Replay::new(&recording);
+        assert_eq!(replay.pending(), 1);
+        replay.receive().unwrap();
+        assert!(replay.receive().is_err());
+
+        let request = Frame::new(0x7DF, &[0x02, 0x01, 0x0C], false, false).unwrap();
+        for last in [0xF8, 0x00, 0x00] {
+            replay.transmit(&request).unwrap();
+            assert_eq!(replay.receive().unwrap().data()[4], last);
+        }
+
+        // unknown requests stay unanswered
+        let other = Frame::new(0x7DF, &[0x02, 0x01, 0x0D], false, false).unwrap();
+        replay.transmit(&other).unwrap();
+        assert_eq!(replay.pending(), 0);
+    }
+
+    #[test]
+    fn test_serve() {
+        let recording = Recording::read_from(SESSION.as_bytes()).unwrap();
+        let mut replay = Replay::new(&recording);
+        let (mut app, mut bus) = Socket::pair().unwrap();
+        let server = std::thread::spawn(move || replay.serve(&mut bus));
+
+        assert!(app.receive().unwrap().is_remote_frame());
+        let request = Frame::new(0x7DF, &[0x02, 0x01, 0x0C], false, false).unwrap();
+        app.transmit(&request).unwrap();
+        assert_eq!(app.receive().unwrap().data()[4], 0xF8);
+
+        drop(app);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_recorder() {
+        let recording = Recording::read_from(SESSION.as_bytes()).unwrap();
+        let mut recorder = Recorder::new(Replay::new(&recording));
+
+        let request = Frame::new(0x7DF, &[0x02, 0x01, 0x0C], false, false).unwrap();
+        recorder.transmit(&request).unwrap();
+        recorder.receive().unwrap();
+        assert!(recorder.receive().is_ok());
+        assert!(recorder.receive().is_err());
+
+        let token = ShutdownToken::new();
+        let mut text = Vec::new();
+        recorder.save_on(&mut text, &token).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap().lines().count(), 3);
+        let full: &mut [u8] = &mut [];
+        assert!(recorder.save_on(full, &token).is_err());
+        let reports: Vec<String> = token.reports().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            reports,
+            [
+                "recorder: 3 frames flushed, 0 dropped",
+                "recorder: 0 frames flushed, 3 dropped"
+            ]
+        );
+
+        let (_, recorded) = recorder.finish();
+        assert_eq!(recorded.frames().len(), 3);
+        match recorded.frames()[0] {
+            RecordedFrame::Transmit(frame) => assert_eq!(frame.data(), request.data()),
+            _ => panic!("expected transmission"),
+        }
+    }
+}
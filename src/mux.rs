@@ -0,0 +1,391 @@
+use crate::{
+    shutdown::POLL_INTERVAL,
+    tap::{TapFrame, Taps},
+    Filter, FilterGroup, Frame, Interest, Poller, QueueStats, ShutdownReport, ShutdownToken,
+    Socket, SocketError, Tap,
+};
+use libc::{CAN_RAW_FILTER_MAX, MSG_DONTWAIT};
+use std::{
+    collections::VecDeque,
+    io,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+/// Virtual sub-buses sharing one socket
+///
+/// Partitions the traffic of a single socket among in-process channels,
+/// each with its own filters and receive queue. Frames are distributed by
+/// calling `dispatch`, usually in a loop on a dedicated thread:
+///
+/// ```no_run
+/// use candev::{Filter, FilterGroup, Mux, Socket};
+/// use embedded_can::blocking::Can;
+///
+/// let mux = Mux::new(Socket::new("vcan0").unwrap());
+/// let mut engine = mux
+///     .channel(FilterGroup::new().filter(Filter::standard(0x100).with_mask(0x700)), 64)
+///     .unwrap();
+///
+/// let dispatcher = mux.clone();
+/// std::thread::spawn(move || loop {
+///     dispatcher.dispatch().unwrap();
+/// });
+///
+/// let frame = engine.receive().unwrap();
+/// ```
+///
+/// By default the union of all channel filters is installed on the socket,
+/// so frames no channel is interested in are already dropped by the kernel.
+#[derive(Debug, Clone)]
+pub struct Mux {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    socket: Socket,
+    kernel_filters: bool,
+    channels: Mutex<Channels>,
+    taps: Taps,
+}
+
+#[derive(Debug, Default)]
+struct Channels {
+    next_id: usize,
+    entries: Vec<(usize, FilterGroup, Arc<Queue>)>,
+}
+
+#[derive(Debug)]
+struct Queue {
+    frames: Mutex<VecDeque<Frame>>,
+    ready: Condvar,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl Mux {
+    /// Multiplex `socket`
+    pub fn new(socket: Socket) -> Mux {
+        Mux::with_kernel_filters(socket, true)
+    }
+
+    /// Multiplex `socket`, choosing whether the channel filters are also
+    /// installed on the socket. Without, every frame is received and
+    /// filtered in process.
+    pub fn with_kernel_filters(socket: Socket, enabled: bool) -> Mux {
+        Mux {
+            shared: Arc::new(Shared {
+                socket,
+                kernel_filters: enabled,
+                channels: Mutex::new(Channels::default()),
+                taps: Taps::default(),
+            }),
+        }
+    }
+
+    /// Open a channel receiving the frames accepted by `group`.
+    ///
+    /// Up to `capacity` frames are queued, the oldest frames are dropped if
+    /// the channel is not read fast enough.
+    pub fn channel(&self, group: FilterGroup, capacity: usize) -> io::Result<Channel> {
+        let queue = Arc::new(Queue {
+            frames: Mutex::new(VecDeque::with_capacity(capacity)),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+            dropped: AtomicU64::new(0),
+        });
+
+        let mut channels = self.shared.channels.lock().unwrap();
+        let id = channels.next_id;
+        channels.next_id += 1;
+        channels.entries.push((id, group, queue.clone()));
+        if let Err(e) = self.shared.update_filters(&channels) {
+            channels.entries.pop();
+            return Err(e);
+        }
+
+        Ok(Channel {
+            id,
+            queue,
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Attach a tap receiving copies of all dispatched frames and of the
+    /// frames transmitted by the channels.
+    ///
+    /// With kernel filters enabled, frames no channel is interested in do
+    /// not reach the tap either.
+    pub fn tap(&self, capacity: usize) -> Tap {
+        self.shared.taps.attach(capacity)
+    }
+
+    /// Receive a frame from the socket and queue it in every channel
+    /// accepting it.
+    ///
+    /// Returns the number of channels the frame was delivered to.
+    pub fn dispatch(&self) -> Result<usize, SocketError> {
+        let frame = self.shared.socket.receive_frame()?;
+        Ok(self.deliver(frame))
+    }
+
+    /// Dispatch frames until `token` requests shutdown, then dispatch the
+    /// frames still queued on the socket until none is left or the grace
+    /// period ends. The frames dispatched meanwhile and the frames dropped
+    /// from full channels are reported to the token as subsystem `mux`.
+    pub fn run_until(&self, token: &ShutdownToken) -> Result<(), SocketError> {
+        let mut poller = Poller::new()?;
+        poller.add(&self.shared.socket, 0, Interest::Readable)?;
+        while !token.is_requested() {
+            if self.try_dispatch()?.is_none() {
+                poller.wait(Some(POLL_INTERVAL))?;
+            }
+        }
+
+        let dropped = self.dropped();
+        let mut flushed = 0;
+        while !token.is_expired() && self.try_dispatch()?.is_some() {
+            flushed += 1;
+        }
+        token.report(ShutdownReport {
+            subsystem: "mux".to_string(),
+            flushed,
+            dropped: self.dropped() - dropped,
+        });
+        Ok(())
+    }
+
+    /// Queue statistics of all open channels, in the order they were opened
+    pub fn stats(&self) -> Vec<QueueStats> {
+        let channels = self.shared.channels.lock().unwrap();
+        channels.entries.iter().map(|(_, _, q)| q.stats()).collect()
+    }
+
+    /// Access the underlying socket, e.g. to change socket options.
+    pub fn get_ref(&self) -> &Socket {
+        &self.shared.socket
+    }
+
+    /// Dispatch a frame if one is queued on the socket
+    fn try_dispatch(&self) -> Result<Option<usize>, SocketError> {
+        match self.shared.socket.recv_one(MSG_DONTWAIT) {
+            Ok(frame) => Ok(Some(self.deliver(frame))),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Queue `frame` in every channel accepting it
+    fn deliver(&self, frame: Frame) -> usize {
+        self.shared.taps.copy(TapFrame::Received(frame));
+
+        let channels = self.shared.channels.lock().unwrap();
+        let mut delivered = 0;
+        for (_, group, queue) in channels.entries.iter() {
+            if group.matches(&frame) {
+                queue.push(frame);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Frames dropped by all open channels
+    fn dropped(&self) -> u64 {
+        let channels = self.shared.channels.lock().unwrap();
+        channels
+            .entries
+            .iter()
+            .map(|(_, _, q)| q.dropped.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl Shared {
+    /// Install the union of all channel filters on the socket
+    fn update_filters(&self, channels: &Channels) -> io::Result<()> {
+        if !self.kernel_filters {
+            return Ok(());
+        }
+
+        // joined groups can not be combined with others in the kernel
+        let filters: Vec<Filter> = if channels.entries.iter().any(|(_, g, _)| g.is_join()) {
+            vec![Filter::accept_all()]
+        } else {
+            channels
+                .entries
+                .iter()
+                .flat_map(|(_, g, _)| g.filters().iter().copied())
+                .collect()
+        };
+
+        if filters.len() > CAN_RAW_FILTER_MAX as usize {
+            self.socket.clear_filters()
+        } else {
+            self.socket.set_filters(&filters)
+        }
+    }
+}
+
+impl Queue {
+    fn push(&self, frame: Frame) {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() == self.capacity {
+            frames.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        frames.push_back(frame);
+        self.ready.notify_one();
+    }
+
+    fn stats(&self) -> QueueStats {
+        let pending = self.frames.lock().unwrap().len();
+        QueueStats {
+            pending,
+            capacity: self.capacity,
+            memory: pending * size_of::<Frame>(),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Channel of a `Mux`
+///
+/// Receives the frames accepted by its filters and transmits on the shared
+/// socket. The channel is closed and its filters removed when dropped.
+#[derive(Debug)]
+pub struct Channel {
+    id: usize,
+    queue: Arc<Queue>,
+    shared: Arc<Shared>,
+}
+
+impl Channel {
+    /// Take the next queued frame, if any
+    pub fn try_receive(&self) -> Option<Frame> {
+        self.queue.frames.lock().unwrap().pop_front()
+    }
+
+    /// Number of queued frames
+    pub fn pending(&self) -> usize {
+        self.queue.frames.lock().unwrap().len()
+    }
+
+    /// Queue occupancy and frames dropped because the channel was not read
+    /// fast enough
+    pub fn stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+}
+
+impl embedded_can::blocking::Can for Channel {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        self.shared.socket.send_frame(frame, 0)?;
+        self.shared.taps.copy(TapFrame::Transmitted(*frame));
+        Ok(())
+    }
+
+    /// Wait until a frame is dispatched to this channel.
+    fn receive(&mut self) -> Result<Frame, SocketError> {
+        let mut frames = self.queue.frames.lock().unwrap();
+        loop {
+            if let Some(frame) = frames.pop_front() {
+                return Ok(frame);
+            }
+            frames = self.queue.ready.wait(frames).unwrap();
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let mut channels = self.shared.channels.lock().unwrap();
+        channels.entries.retain(|(id, _, _)| *id != self.id);
+        // best effort, the remaining channels filter in process anyway
+        let _ = self.shared.update_filters(&channels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mux;
+    use crate::{Filter, FilterGroup, Frame, ShutdownReport, ShutdownToken, Socket};
+    use embedded_can::blocking::Can;
+    use std::time::Duration;
+
+    #[test]
+    fn test_dispatch() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let mux = Mux::with_kernel_filters(a, false);
+        let mut low = mux
+            .channel(
+                FilterGroup::new().filter(Filter::standard(0x100).with_mask(0x700)),
+                2,
+            )
+            .unwrap();
+        let all = mux
+            .channel(FilterGroup::new().filter(Filter::accept_all()), 8)
+            .unwrap();
+        let tap = mux.tap(8);
+
+        for id in [0x100, 0x200, 0x101, 0x102] {
+            b.transmit(&Frame::new(id, &[], false, false).unwrap())
+                .unwrap();
+            mux.dispatch().unwrap();
+        }
+        assert_eq!(all.pending(), 4);
+
+        // the oldest frame was dropped
+        assert_eq!(low.pending(), 2);
+        assert_eq!(low.stats().dropped, 1);
+        assert_eq!(mux.stats().len(), 2);
+        let ids: Vec<u32> = std::iter::from_fn(|| low.try_receive())
+            .map(|f| f.can_id())
+            .collect();
+        assert_eq!(ids, vec![0x101, 0x102]);
+
+        low.transmit(&Frame::new(0x7FF, &[1], false, false).unwrap())
+            .unwrap();
+        assert_eq!(b.receive().unwrap().data(), &[1]);
+        assert_eq!(tap.pending(), 5);
+
+        drop(all);
+        b.transmit(&Frame::new(0x200, &[], false, false).unwrap())
+            .unwrap();
+        assert_eq!(mux.dispatch().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_run_until_shutdown() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let mux = Mux::with_kernel_filters(a, false);
+        let channel = mux
+            .channel(FilterGroup::new().filter(Filter::accept_all()), 2)
+            .unwrap();
+
+        // frames queued before the request are still dispatched
+        for n in 0..3 {
+            b.transmit(&Frame::new(0x123, &[n], false, false).unwrap())
+                .unwrap();
+        }
+        let token = ShutdownToken::new();
+        token.shutdown(Duration::from_secs(1));
+        mux.run_until(&token).unwrap();
+        assert_eq!(channel.pending(), 2);
+        assert_eq!(
+            token.reports(),
+            [ShutdownReport {
+                subsystem: "mux".to_string(),
+                flushed: 3,
+                dropped: 1,
+            }]
+        );
+    }
+}
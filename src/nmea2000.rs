@@ -0,0 +1,682 @@
+use crate::{pdu, Frame};
+use embedded_can::{Frame as _, Id};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+/// PGN of the ISO request message
+const PGN_ISO_REQUEST: u32 = 59904;
+/// PGN of the ISO address claim message
+const PGN_ISO_ADDRESS_CLAIM: u32 = 60928;
+/// PGN of the NMEA 2000 product information message
+const PGN_PRODUCT_INFORMATION: u32 = 126996;
+
+/// Source address used by devices which failed to claim an address
+const NULL_ADDRESS: u8 = 254;
+/// Destination address addressing all devices
+const GLOBAL_ADDRESS: u8 = 255;
+
+/// Highest address a device may claim
+const MAX_ADDRESS: u8 = 251;
+
+/// Industry group of marine equipment
+const INDUSTRY_GROUP_MARINE: u8 = 4;
+
+/// Longest payload of a fast packet: 6 bytes in the first frame and 7 in
+/// each of the following 31 frames
+const FAST_PACKET_MAX_LEN: usize = 223;
+
+/// Common PGNs transmitted as fast packets
+const FAST_PACKET_PGNS: [u32; 24] = [
+    126208, 126464, 126720, 126996, 126998, 127233, 127237, 127489, 127496, 127497, 127503, 128275,
+    129029, 129038, 129039, 129040, 129284, 129285, 129540, 129794, 129809, 129810, 130074, 130577,
+];
+
+/// ISO 11783 / NMEA 2000 device NAME
+///
+/// The 64 bit NAME identifies a device on the bus and decides address
+/// conflicts: the device with the numerically lower NAME keeps the address.
+/// NAMEs are ordered by their 64 bit representation accordingly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Name {
+    /// Unique number (serial number), 21 bits
+    pub unique_number: u32,
+    /// Manufacturer code, 11 bits
+    pub manufacturer_code: u16,
+    /// Device instance, 8 bits (lower 3 and upper 5 bits combined)
+    pub device_instance: u8,
+    /// Device function
+    pub device_function: u8,
+    /// Device class, 7 bits
+    pub device_class: u8,
+    /// System instance, 4 bits
+    pub system_instance: u8,
+    /// Industry group, 3 bits
+    pub industry_group: u8,
+    /// Whether the device may pick another address on conflicts
+    pub arbitrary_address_capable: bool,
+}
+
+impl Name {
+    /// Create a NAME for a marine device.
+    ///
+    /// Uses the marine industry group and marks the device as arbitrary
+    /// address capable, as required by NMEA 2000.
+    pub fn nmea2000(
+        unique_number: u32,
+        manufacturer_code: u16,
+        device_function: u8,
+        device_class: u8,
+    ) -> Name {
+        Name {
+            unique_number,
+            manufacturer_code,
+            device_function,
+            device_class,
+            industry_group: INDUSTRY_GROUP_MARINE,
+            arbitrary_address_capable: true,
+            ..Name::default()
+        }
+    }
+
+    /// Decode a NAME from its 64 bit representation
+    pub fn from_raw(raw: u64) -> Name {
+        Name {
+            unique_number: (raw & 0x1F_FFFF) as u32,
+            manufacturer_code: ((raw >> 21) & 0x7FF) as u16,
+            device_instance: (raw >> 32) as u8,
+            device_function: (raw >> 40) as u8,
+            device_class: ((raw >> 49) & 0x7F) as u8,
+            system_instance: ((raw >> 56) & 0xF) as u8,
+            industry_group: ((raw >> 60) & 0x7) as u8,
+            arbitrary_address_capable: raw >> 63 != 0,
+        }
+    }
+
+    /// Encode the NAME into its 64 bit representation
+    pub fn to_raw(&self) -> u64 {
+        (self.unique_number as u64 & 0x1F_FFFF)
+            | (self.manufacturer_code as u64 & 0x7FF) << 21
+            | (self.device_instance as u64) << 32
+            | (self.device_function as u64) << 40
+            | (self.device_class as u64 & 0x7F) << 49
+            | (self.system_instance as u64 & 0xF) << 56
+            | (self.industry_group as u64 & 0x7) << 60
+            | (self.arbitrary_address_capable as u64) << 63
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        self.to_raw().cmp(&other.to_raw())
+    }
+}
+
+/// Address claim procedure
+///
+/// Keeps track of the address claimed by a device and of addresses claimed by
+/// other devices. Every received frame should be passed to `handle`, which
+/// returns the frame to transmit in response, if any.
+#[derive(Debug, Clone)]
+pub struct AddressClaim {
+    name: Name,
+    address: Option<u8>,
+    claimed: [Option<Name>; MAX_ADDRESS as usize + 1],
+}
+
+impl AddressClaim {
+    /// Start claiming `preferred` as address for the device named `name`.
+    ///
+    /// Transmit `claim_frame()` afterwards to announce the claim.
+    pub fn new(name: Name, preferred: u8) -> AddressClaim {
+        AddressClaim {
+            name,
+            address: Some(preferred.min(MAX_ADDRESS)),
+            claimed: [None; MAX_ADDRESS as usize + 1],
+        }
+    }
+
+    /// NAME of the device
+    pub fn name(&self) -> Name {
+        self.name
+    }
+
+    /// Currently claimed address, `None` if no address could be claimed
+    pub fn address(&self) -> Option<u8> {
+        self.address
+    }
+
+    /// Address claim frame for the current state.
+    ///
+    /// If no address could be claimed, this is the "cannot claim address"
+    /// message sent from the null address.
+    pub fn claim_frame(&self) -> Frame {
+        let source = self.address.unwrap_or(NULL_ADDRESS);
+        let id = pdu::id(6, PGN_ISO_ADDRESS_CLAIM, GLOBAL_ADDRESS, source);
+        Frame::new(id, &self.name.to_raw().to_le_bytes(), false, false).unwrap()
+    }
+
+    /// Frame requesting all devices to announce their address claims
+    pub fn request_frame(&self) -> Frame {
+        request_frame(
+            PGN_ISO_ADDRESS_CLAIM,
+            GLOBAL_ADDRESS,
+            self.address.unwrap_or(NULL_ADDRESS),
+        )
+    }
+
+    /// Process a received frame.
+    ///
+    /// Answers requests for address claims and resolves conflicts with other
+    /// devices claiming the same address. Returns the frame that has to be
+    /// transmitted in response.
+    pub fn handle(&mut self, frame: &Frame) -> Option<Frame> {
+        let id = match frame.id() {
+            Id::Extended(id) => id.as_raw(),
+            Id::Standard(_) => return None,
+        };
+
+        match pdu::pgn(id) {
+            PGN_ISO_REQUEST if requested_pgn(frame) == Some(PGN_ISO_ADDRESS_CLAIM) => {
+                match pdu::destination(id) {
+                    Some(dst) if dst == GLOBAL_ADDRESS || Some(dst) == self.address => {
+                        Some(self.claim_frame())
+                    }
+                    _ => None,
+                }
+            }
+            PGN_ISO_ADDRESS_CLAIM if frame.data().len() == 8 => {
+                let mut raw = [0; 8];
+                raw.copy_from_slice(frame.data());
+                let other = Name::from_raw(u64::from_le_bytes(raw));
+                let source = pdu::source(id);
+
+                if other == self.name || source > MAX_ADDRESS {
+                    return None;
+                }
+                self.claimed[source as usize] = Some(other);
+
+                if Some(source) != self.address {
+                    return None;
+                }
+                if self.name < other {
+                    // we win, defend our address
+                    return Some(self.claim_frame());
+                }
+
+                self.address = if self.name.arbitrary_address_capable {
+                    self.next_free_address(source)
+                } else {
+                    None
+                };
+                Some(self.claim_frame())
+            }
+            _ => None,
+        }
+    }
+
+    fn next_free_address(&self, lost: u8) -> Option<u8> {
+        (1..=MAX_ADDRESS)
+            .map(|n| ((lost as u16 + n as u16) % (MAX_ADDRESS as u16 + 1)) as u8)
+            .find(|&addr| self.claimed[addr as usize].is_none())
+    }
+}
+
+/// Build an ISO request for `pgn`
+fn request_frame(pgn: u32, destination: u8, source: u8) -> Frame {
+    let id = pdu::id(6, PGN_ISO_REQUEST, destination, source);
+    Frame::new(id, &pgn.to_le_bytes()[..3], false, false).unwrap()
+}
+
+/// PGN requested by an ISO request frame
+fn requested_pgn(frame: &Frame) -> Option<u32> {
+    match frame.id() {
+        Id::Extended(id) if pdu::pgn(id.as_raw()) == PGN_ISO_REQUEST => {}
+        _ => return None,
+    }
+    match frame.data() {
+        [a, b, c, ..] => Some(u32::from_le_bytes([*a, *b, *c, 0])),
+        _ => None,
+    }
+}
+
+/// NMEA 2000 product information (PGN 126996)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProductInformation {
+    /// Supported NMEA 2000 database version, in units of 0.001
+    pub nmea2000_version: u16,
+    /// Product code assigned by NMEA
+    pub product_code: u16,
+    /// Model ID, at most 32 bytes
+    pub model_id: String,
+    /// Software version, at most 32 bytes
+    pub software_version: String,
+    /// Model version, at most 32 bytes
+    pub model_version: String,
+    /// Model serial code, at most 32 bytes
+    pub model_serial_code: String,
+    /// Certification level
+    pub certification_level: u8,
+    /// Load equivalency number, in units of 50 mA
+    pub load_equivalency: u8,
+}
+
+impl ProductInformation {
+    /// Length of the encoded message
+    pub const LEN: usize = 134;
+
+    /// Encode into the message payload.
+    ///
+    /// Strings are truncated to 32 bytes and padded with `0xFF`.
+    pub fn to_bytes(&self) -> [u8; ProductInformation::LEN] {
+        let mut buf = [0xFF; ProductInformation::LEN];
+        buf[0..2].copy_from_slice(&self.nmea2000_version.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.product_code.to_le_bytes());
+        let strings = [
+            &self.model_id,
+            &self.software_version,
+            &self.model_version,
+            &self.model_serial_code,
+        ];
+        for (n, s) in strings.iter().enumerate() {
+            let len = s.len().min(32);
+            buf[4 + n * 32..4 + n * 32 + len].copy_from_slice(&s.as_bytes()[..len]);
+        }
+        buf[132] = self.certification_level;
+        buf[133] = self.load_equivalency;
+        buf
+    }
+
+    /// Decode from a message payload
+    pub fn from_bytes(data: &[u8]) -> Option<ProductInformation> {
+        if data.len() < ProductInformation::LEN {
+            return None;
+        }
+        let string = |n: usize| {
+            let raw = &data[4 + n * 32..4 + (n + 1) * 32];
+            let len = raw
+                .iter()
+                .rposition(|&c| !matches!(c, 0xFF | 0x00 | b' ' | b'@'))
+                .map_or(0, |p| p + 1);
+            String::from_utf8_lossy(&raw[..len]).into_owned()
+        };
+        Some(ProductInformation {
+            nmea2000_version: u16::from_le_bytes([data[0], data[1]]),
+            product_code: u16::from_le_bytes([data[2], data[3]]),
+            model_id: string(0),
+            software_version: string(1),
+            model_version: string(2),
+            model_serial_code: string(3),
+            certification_level: data[132],
+            load_equivalency: data[133],
+        })
+    }
+
+    /// Check whether `frame` requests the product information.
+    ///
+    /// Devices have to answer such requests by transmitting `frames()`.
+    pub fn is_request(frame: &Frame) -> bool {
+        requested_pgn(frame) == Some(PGN_PRODUCT_INFORMATION)
+    }
+
+    /// Encode into the fast packet frames to transmit from `source`.
+    ///
+    /// `sequence` (0..=7) distinguishes consecutive transmissions and should
+    /// be incremented for every message sent.
+    pub fn frames(&self, source: u8, sequence: u8) -> Vec<Frame> {
+        let id = pdu::id(6, PGN_PRODUCT_INFORMATION, GLOBAL_ADDRESS, source);
+        fast_packet_frames(id, sequence, &self.to_bytes())
+    }
+}
+
+/// Complete NMEA 2000 message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct N2kMessage {
+    /// Priority, 0 (highest) to 7
+    pub priority: u8,
+    /// Parameter group number
+    pub pgn: u32,
+    /// Source address
+    pub source: u8,
+    /// Destination address, `255` for broadcast PGNs
+    pub destination: u8,
+    /// Payload
+    pub data: Vec<u8>,
+}
+
+impl N2kMessage {
+    /// Decode a message sent in a single frame
+    pub fn from_frame(frame: &Frame) -> Option<N2kMessage> {
+        let id = match frame.id() {
+            Id::Extended(id) => id.as_raw(),
+            Id::Standard(_) => return None,
+        };
+        Some(N2kMessage {
+            priority: (id >> 26) as u8 & 0x7,
+            pgn: pdu::pgn(id),
+            source: pdu::source(id),
+            destination: pdu::destination(id).unwrap_or(GLOBAL_ADDRESS),
+            data: frame.data().to_vec(),
+        })
+    }
+
+    /// Encode into a single frame, `None` if the payload is longer than 8
+    /// bytes
+    pub fn frame(&self) -> Option<Frame> {
+        Frame::new(self.id(), &self.data, false, false).ok()
+    }
+
+    /// Encode into fast packet frames.
+    ///
+    /// `sequence` (0..=7) distinguishes consecutive transmissions of the
+    /// same PGN and should be incremented for every message sent.
+    ///
+    /// # Panics
+    ///
+    /// If the payload is longer than 223 bytes, the maximum of a fast packet.
+    pub fn fast_packet_frames(&self, sequence: u8) -> Vec<Frame> {
+        assert!(
+            self.data.len() <= FAST_PACKET_MAX_LEN,
+            "payload too long for a fast packet"
+        );
+        fast_packet_frames(self.id(), sequence, &self.data)
+    }
+
+    fn id(&self) -> u32 {
+        pdu::id(self.priority, self.pgn, self.destination, self.source)
+    }
+}
+
+/// Fast packet reassembly
+///
+/// Pass every received frame to `handle`, which returns complete messages:
+/// frames of single frame PGNs right away, fast packet PGNs once all their
+/// frames were received. Transfers are tracked per source and PGN, so
+/// interleaved fast packets of different devices are reassembled
+/// independently. A transfer missing a frame is dropped.
+#[derive(Debug, Clone)]
+pub struct FastPacket {
+    pgns: HashSet<u32>,
+    pending: HashMap<(u8, u32), Transfer>,
+}
+
+/// Fast packet transfer in progress
+#[derive(Debug, Clone)]
+struct Transfer {
+    sequence: u8,
+    next_frame: u8,
+    len: usize,
+    message: N2kMessage,
+}
+
+impl FastPacket {
+    /// Reassemble the common fast packet PGNs, e.g. product information,
+    /// GNSS position data and AIS reports
+    pub fn new() -> FastPacket {
+        FastPacket {
+            pgns: FAST_PACKET_PGNS.into_iter().collect(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Also treat `pgn` as fast packet PGN, e.g. for proprietary messages
+    pub fn pgn(mut self, pgn: u32) -> Self {
+        self.pgns.insert(pgn);
+        self
+    }
+
+    /// Check whether `pgn` is reassembled from fast packets
+    pub fn is_fast_packet(&self, pgn: u32) -> bool {
+        self.pgns.contains(&pgn)
+    }
+
+    /// Number of transfers in progress
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Process a received frame, returning the message it completes
+    pub fn handle(&mut self, frame: &Frame) -> Option<N2kMessage> {
+        let mut message = N2kMessage::from_frame(frame)?;
+        if !self.pgns.contains(&message.pgn) {
+            return Some(message);
+        }
+
+        let key = (message.source, message.pgn);
+        let (counter, data) = message.data.split_first()?;
+        let sequence = counter >> 5;
+        let frame_number = counter & 0x1F;
+
+        if frame_number == 0 {
+            let (&len, first) = data.split_first()?;
+            let len = len as usize;
+            let first = &first[..first.len().min(len)];
+            if first.len() == len {
+                message.data = first.to_vec();
+                self.pending.remove(&key);
+                return Some(message);
+            }
+            message.data = first.to_vec();
+            self.pending.insert(
+                key,
+                Transfer {
+                    sequence,
+                    next_frame: 1,
+                    len,
+                    message,
+                },
+            );
+            return None;
+        }
+
+        let transfer = self.pending.get_mut(&key)?;
+        if transfer.sequence != sequence || transfer.next_frame != frame_number {
+            // lost a frame
+            self.pending.remove(&key);
+            return None;
+        }
+        let missing = transfer.len - transfer.message.data.len();
+        transfer
+            .message
+            .data
+            .extend_from_slice(&data[..data.len().min(missing)]);
+        transfer.next_frame += 1;
+
+        if transfer.message.data.len() < transfer.len {
+            return None;
+        }
+        self.pending.remove(&key).map(|transfer| transfer.message)
+    }
+}
+
+impl Default for FastPacket {
+    fn default() -> Self {
+        FastPacket::new()
+    }
+}
+
+/// Split a payload into NMEA 2000 fast packet frames.
+///
+/// The first frame carries the sequence/frame counter byte, the total length
+/// and 6 bytes of data; every following frame carries the counter and 7 bytes.
+fn fast_packet_frames(id: u32, sequence: u8, payload: &[u8]) -> Vec<Frame> {
+    let sequence = (sequence & 0x7) << 5;
+    let mut frames = Vec::new();
+    let mut buf = [0xFF; 8];
+
+    buf[0] = sequence;
+    buf[1] = payload.len() as u8;
+    let (first, rest) = payload.split_at(payload.len().min(6));
+    buf[2..2 + first.len()].copy_from_slice(first);
+    frames.push(Frame::new(id, &buf, false, false).unwrap());
+
+    for (n, chunk) in rest.chunks(7).enumerate() {
+        buf = [0xFF; 8];
+        buf[0] = sequence | ((n + 1) as u8 & 0x1F);
+        buf[1..1 + chunk.len()].copy_from_slice(chunk);
+        frames.push(Frame::new(id, &buf, false, false).unwrap());
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressClaim, FastPacket, N2kMessage, Name, ProductInformation, NULL_ADDRESS};
+    use crate::Frame;
+
+    fn claim(name: Name, source: u8) -> Frame {
+        let id = 0x18EEFF00 | source as u32;
+        Frame::new(id, &name.to_raw().to_le_bytes(), false, false).unwrap()
+    }
+
+    #[test]
+    fn test_name_roundtrip() {
+        let name = Name {
+            device_instance: 0x23,
+            system_instance: 3,
+            ..Name::nmea2000(123456, 1851, 130, 25)
+        };
+        assert_eq!(Name::from_raw(name.to_raw()), name);
+
+        // the industry group outweighs the unique number
+        let other = Name {
+            industry_group: 2,
+            ..Name::nmea2000(999_999, 1851, 130, 25)
+        };
+        assert!(other < name);
+    }
+
+    #[test]
+    fn test_address_conflict() {
+        let ours = Name::nmea2000(2, 1851, 130, 25);
+        let mut claim_state = AddressClaim::new(ours, 10);
+
+        // a device with a higher NAME claims our address: we defend it
+        let response = claim_state.handle(&claim(Name::nmea2000(3, 1851, 130, 25), 10));
+        assert!(response.is_some());
+        assert_eq!(claim_state.address(), Some(10));
+
+        // a device with a lower NAME claims our address: we move on
+        claim_state.handle(&claim(Name::nmea2000(1, 1851, 130, 25), 10));
+        assert_eq!(claim_state.address(), Some(11));
+    }
+
+    #[test]
+    fn test_cannot_claim() {
+        let ours = Name {
+            arbitrary_address_capable: false,
+            ..Name::nmea2000(2, 1851, 130, 25)
+        };
+        let mut claim_state = AddressClaim::new(ours, 10);
+        let other = Name {
+            unique_number: 1,
+            ..ours
+        };
+        let response = claim_state.handle(&claim(other, 10)).unwrap();
+        assert_eq!(claim_state.address(), None);
+        assert_eq!(claim_state.claim_frame().data(), response.data());
+        assert_eq!(
+            embedded_can::Frame::id(&response),
+            embedded_can::Id::Extended(
+                embedded_can::ExtendedId::new(0x18EEFF00 | NULL_ADDRESS as u32).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_product_information() {
+        let info = ProductInformation {
+            nmea2000_version: 2100,
+            product_code: 1234,
+            model_id: "candev gateway".into(),
+            software_version: "0.1.0".into(),
+            model_version: "A".into(),
+            model_serial_code: "0001".into(),
+            certification_level: 1,
+            load_equivalency: 2,
+        };
+        assert_eq!(
+            ProductInformation::from_bytes(&info.to_bytes()),
+            Some(info.clone())
+        );
+
+        let frames = info.frames(10, 1);
+        // 6 bytes in the first frame, 7 in each following one
+        assert_eq!(frames.len(), 20);
+        assert_eq!(frames[0].data()[..2], [0x20, ProductInformation::LEN as u8]);
+        assert_eq!(frames[1].data()[0], 0x21);
+    }
+
+    #[test]
+    fn test_fast_packet_roundtrip() {
+        let message = N2kMessage {
+            priority: 3,
+            pgn: 129029,
+            source: 12,
+            destination: 255,
+            data: (0..43).collect(),
+        };
+        let frames = message.fast_packet_frames(5);
+        assert_eq!(frames.len(), 7);
+
+        let mut reassembly = FastPacket::new();
+        for frame in &frames[..6] {
+            assert_eq!(reassembly.handle(frame), None);
+        }
+        assert_eq!(reassembly.pending(), 1);
+        assert_eq!(reassembly.handle(&frames[6]), Some(message));
+        assert_eq!(reassembly.pending(), 0);
+    }
+
+    #[test]
+    fn test_fast_packet_interleaved() {
+        let message = |source: u8| N2kMessage {
+            priority: 6,
+            pgn: 126996,
+            source,
+            destination: 255,
+            data: vec![source; 20],
+        };
+        let a = message(1).fast_packet_frames(0);
+        let b = message(2).fast_packet_frames(0);
+
+        let mut reassembly = FastPacket::new();
+        let mut complete = Vec::new();
+        for (a, b) in a.iter().zip(&b) {
+            complete.extend(reassembly.handle(a));
+            complete.extend(reassembly.handle(b));
+        }
+        assert_eq!(complete, [message(1), message(2)]);
+    }
+
+    #[test]
+    fn test_fast_packet_lost_frame() {
+        let message = N2kMessage {
+            priority: 6,
+            pgn: 126996,
+            source: 1,
+            destination: 255,
+            data: vec![0; 30],
+        };
+        let frames = message.fast_packet_frames(0);
+        let mut reassembly = FastPacket::new();
+        reassembly.handle(&frames[0]);
+        assert_eq!(reassembly.handle(&frames[2]), None);
+        assert_eq!(reassembly.pending(), 0);
+
+        // single frame PGNs are passed through
+        let rudder = Frame::new(0x09F10D0A, &[0; 8], false, false).unwrap();
+        let rudder = reassembly.handle(&rudder).unwrap();
+        assert_eq!(
+            (rudder.pgn, rudder.source, rudder.priority),
+            (127245, 10, 2)
+        );
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{CanId, IsoTpSocket, SocketError};
+#[cfg(feature = "async-io")]
+use async_io::{Async, Timer};
+use std::{error, fmt, io, time::Duration};
+#[cfg(feature = "async-io")]
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::Poll,
+};
+
+/// Request id of the engine control module, physical addressing
+const OBD_REQUEST_ID: u16 = 0x7E0;
+
+/// Response id of the engine control module
+const OBD_RESPONSE_ID: u16 = 0x7E8;
+
+/// Padding of the request frames, as required by ISO 15765-4
+const OBD_PADDING: u8 = 0xCC;
+
+/// Time an ECU has to answer a request, P2 of ISO 15765-4
+const OBD_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Service id of negative responses
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+
+/// Errors of OBD-II requests
+#[derive(Debug)]
+pub enum ObdError {
+    /// The ECU did not answer in time or the socket failed
+    IOError(io::Error),
+
+    /// Opening or configuring the socket failed
+    Socket(SocketError),
+
+    /// The ECU rejected the request with the contained response code
+    NegativeResponse(u8),
+
+    /// The response does not match the request
+    InvalidResponse,
+}
+
+impl From<io::Error> for ObdError {
+    fn from(e: io::Error) -> ObdError {
+        ObdError::IOError(e)
+    }
+}
+
+impl From<SocketError> for ObdError {
+    fn from(e: SocketError) -> ObdError {
+        ObdError::Socket(e)
+    }
+}
+
+impl fmt::Display for ObdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObdError::IOError(e) => write!(f, "OBD request failed: {}", e),
+            ObdError::Socket(e) => write!(f, "OBD request failed: {}", e),
+            ObdError::NegativeResponse(code) => write!(f, "negative response 0x{:02X}", code),
+            ObdError::InvalidResponse => write!(f, "invalid OBD response"),
+        }
+    }
+}
+
+impl error::Error for ObdError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ObdError::IOError(e) => Some(e),
+            ObdError::Socket(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Common mode 01 parameter ids
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pid {
+    /// Calculated engine load in %
+    EngineLoad,
+    /// Engine coolant temperature in °C
+    CoolantTemperature,
+    /// Intake manifold absolute pressure in kPa
+    IntakePressure,
+    /// Engine speed in rpm
+    EngineSpeed,
+    /// Vehicle speed in km/h
+    VehicleSpeed,
+    /// Intake air temperature in °C
+    IntakeAirTemperature,
+    /// Mass air flow rate in g/s
+    MassAirFlow,
+    /// Throttle position in %
+    ThrottlePosition,
+    /// Run time since engine start in s
+    RunTime,
+    /// Fuel tank level in %
+    FuelLevel,
+    /// Ambient air temperature in °C
+    AmbientAirTemperature,
+    /// Engine oil temperature in °C
+    OilTemperature,
+}
+
+impl Pid {
+    /// Parameter id sent in the request
+    pub fn id(self) -> u8 {
+        match self {
+            Pid::EngineLoad => 0x04,
+            Pid::CoolantTemperature => 0x05,
+            Pid::IntakePressure => 0x0B,
+            Pid::EngineSpeed => 0x0C,
+            Pid::VehicleSpeed => 0x0D,
+            Pid::IntakeAirTemperature => 0x0F,
+            Pid::MassAirFlow => 0x10,
+            Pid::ThrottlePosition => 0x11,
+            Pid::RunTime => 0x1F,
+            Pid::FuelLevel => 0x2F,
+            Pid::AmbientAirTemperature => 0x46,
+            Pid::OilTemperature => 0x5C,
+        }
+    }
+
+    /// Physical unit of the decoded value
+    pub fn unit(self) -> &'static str {
+        match self {
+            Pid::EngineLoad | Pid::ThrottlePosition | Pid::FuelLevel => "%",
+            Pid::CoolantTemperature
+            | Pid::IntakeAirTemperature
+            | Pid::AmbientAirTemperature
+            | Pid::OilTemperature => "°C",
+            Pid::IntakePressure => "kPa",
+            Pid::EngineSpeed => "rpm",
+            Pid::VehicleSpeed => "km/h",
+            Pid::MassAirFlow => "g/s",
+            Pid::RunTime => "s",
+        }
+    }
+
+    /// Decode the data bytes following the PID in a response.
+    ///
+    /// Returns `None` if the data is too short.
+    pub fn decode(self, data: &[u8]) -> Option<f64> {
+        let a = *data.first()? as f64;
+        let ab = || Some(u16::from_be_bytes([*data.first()?, *data.get(1)?]) as f64);
+        Some(match self {
+            Pid::EngineLoad | Pid::ThrottlePosition | Pid::FuelLevel => a * 100.0 / 255.0,
+            Pid::CoolantTemperature
+            | Pid::IntakeAirTemperature
+            | Pid::AmbientAirTemperature
+            | Pid::OilTemperature => a - 40.0,
+            Pid::IntakePressure | Pid::VehicleSpeed => a,
+            Pid::EngineSpeed => ab()? / 4.0,
+            Pid::MassAirFlow => ab()? / 100.0,
+            Pid::RunTime => ab()?,
+        })
+    }
+}
+
+/// Diagnostic trouble code, e.g. P0301
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Dtc(pub u16);
+
+/// Displays the code in the standard notation, e.g. `P0301`
+impl fmt::Display for Dtc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let system = ['P', 'C', 'B', 'U'][(self.0 >> 14) as usize];
+        write!(f, "{}{:04X}", system, self.0 & 0x3FFF)
+    }
+}
+
+/// OBD-II client
+///
+/// Sends OBD-II (SAE J1979) requests over an `IsoTpSocket` and decodes the
+/// responses:
+///
+/// ```no_run
+/// use candev::{ObdClient, Pid};
+///
+/// let mut obd = ObdClient::open("can0").unwrap();
+/// println!("VIN {}", obd.vin().unwrap());
+/// println!("{} rpm", obd.current_data(Pid::EngineSpeed).unwrap());
+/// for dtc in obd.dtcs().unwrap() {
+///     println!("{}", dtc);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ObdClient {
+    socket: IsoTpSocket,
+}
+
+impl ObdClient {
+    /// Talk to the engine control module on the named CAN device, using
+    /// the physical request id 0x7E0 and response id 0x7E8
+    pub fn open(ifname: &str) -> Result<ObdClient, ObdError> {
+        let socket = IsoTpSocket::with_padding(
+            ifname,
+            CanId::Standard(OBD_RESPONSE_ID),
+            CanId::Standard(OBD_REQUEST_ID),
+            OBD_PADDING,
+        )?;
+        socket.set_read_timeout(OBD_TIMEOUT)?;
+        Ok(ObdClient::new(socket))
+    }
+
+    /// Send requests on `socket`, which is connected to a single ECU
+    pub fn new(socket: IsoTpSocket) -> ObdClient {
+        ObdClient { socket }
+    }
+
+    /// Send a request of `mode` and return the response data following
+    /// the echoed mode.
+    pub fn request(&mut self, mode: u8, data: &[u8]) -> Result<Vec<u8>, ObdError> {
+        let mut request = vec![mode];
+        request.extend_from_slice(data);
+        self.socket.send(&request)?;
+
+        let mut buf = [0u8; 4095];
+        let len = self.socket.recv(&mut buf)?;
+        response_data(mode, &buf[..len]).map(|data| data.to_vec())
+    }
+
+    /// Current value of a mode 01 parameter
+    pub fn current_data(&mut self, pid: Pid) -> Result<f64, ObdError> {
+        let response = self.request(0x01, &[pid.id()])?;
+        pid_data(pid.id(), &response)
+            .and_then(|data| pid.decode(data))
+            .ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Raw data of a mode 01 parameter
+    pub fn current_data_raw(&mut self, pid: u8) -> Result<Vec<u8>, ObdError> {
+        let response = self.request(0x01, &[pid])?;
+        pid_data(pid, &response)
+            .map(|data| data.to_vec())
+            .ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Mode 01 parameter ids supported by the ECU
+    pub fn supported_pids(&mut self) -> Result<Vec<u8>, ObdError> {
+        let mut supported = Vec::new();
+        let mut base = 0x00;
+        loop {
+            let bitmap = self.current_data_raw(base)?;
+            if !supported_range(base, &bitmap, &mut supported)? {
+                return Ok(supported);
+            }
+            base += 0x20;
+        }
+    }
+
+    /// Stored diagnostic trouble codes (mode 03)
+    pub fn dtcs(&mut self) -> Result<Vec<Dtc>, ObdError> {
+        let response = self.request(0x03, &[])?;
+        parse_dtcs(&response).ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Vehicle identification number (mode 09, PID 02)
+    pub fn vin(&mut self) -> Result<String, ObdError> {
+        let response = self.request(0x09, &[0x02])?;
+        parse_vin(&response)
+    }
+
+    /// Return the socket
+    pub fn into_inner(self) -> IsoTpSocket {
+        self.socket
+    }
+}
+
+/// Asynchronous OBD-II client
+///
+/// Same requests as `ObdClient`, built on `async-io` like `AsyncSocket`.
+#[cfg(feature = "async-io")]
+#[derive(Debug)]
+pub struct AsyncObdClient {
+    inner: Async<IsoTpSocket>,
+    timeout: Duration,
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncObdClient {
+    /// Talk to the engine control module on the named CAN device, using
+    /// the physical request id 0x7E0 and response id 0x7E8
+    pub fn open(ifname: &str) -> Result<AsyncObdClient, ObdError> {
+        let socket = IsoTpSocket::with_padding(
+            ifname,
+            CanId::Standard(OBD_RESPONSE_ID),
+            CanId::Standard(OBD_REQUEST_ID),
+            OBD_PADDING,
+        )?;
+        Ok(AsyncObdClient::new(socket)?)
+    }
+
+    /// Send requests on `socket`, which is connected to a single ECU. The
+    /// socket is switched to non-blocking mode.
+    pub fn new(socket: IsoTpSocket) -> io::Result<AsyncObdClient> {
+        Ok(AsyncObdClient {
+            inner: Async::new(socket)?,
+            timeout: OBD_TIMEOUT,
+        })
+    }
+
+    /// Send a request of `mode` and return the response data following
+    /// the echoed mode.
+    pub async fn request(&self, mode: u8, data: &[u8]) -> Result<Vec<u8>, ObdError> {
+        let mut request = vec![mode];
+        request.extend_from_slice(data);
+        self.inner.write_with(|s| s.send(&request)).await?;
+
+        let mut buf = vec![0u8; 4095];
+        let len = {
+            let mut read = Box::pin(self.inner.read_with(|s| s.recv(&mut buf)));
+            let mut timer = Timer::after(self.timeout);
+            poll_fn(|cx| {
+                if let Poll::Ready(rv) = read.as_mut().poll(cx) {
+                    return Poll::Ready(rv);
+                }
+                Pin::new(&mut timer)
+                    .poll(cx)
+                    .map(|_| Err(io::Error::from(io::ErrorKind::TimedOut)))
+            })
+            .await?
+        };
+        response_data(mode, &buf[..len]).map(|data| data.to_vec())
+    }
+
+    /// Current value of a mode 01 parameter
+    pub async fn current_data(&self, pid: Pid) -> Result<f64, ObdError> {
+        let response = self.request(0x01, &[pid.id()]).await?;
+        pid_data(pid.id(), &response)
+            .and_then(|data| pid.decode(data))
+            .ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Raw data of a mode 01 parameter
+    pub async fn current_data_raw(&self, pid: u8) -> Result<Vec<u8>, ObdError> {
+        let response = self.request(0x01, &[pid]).await?;
+        pid_data(pid, &response)
+            .map(|data| data.to_vec())
+            .ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Mode 01 parameter ids supported by the ECU
+    pub async fn supported_pids(&self) -> Result<Vec<u8>, ObdError> {
+        let mut supported = Vec::new();
+        let mut base = 0x00;
+        loop {
+            let bitmap = self.current_data_raw(base).await?;
+            if !supported_range(base, &bitmap, &mut supported)? {
+                return Ok(supported);
+            }
+            base += 0x20;
+        }
+    }
+
+    /// Stored diagnostic trouble codes (mode 03)
+    pub async fn dtcs(&self) -> Result<Vec<Dtc>, ObdError> {
+        let response = self.request(0x03, &[]).await?;
+        parse_dtcs(&response).ok_or(ObdError::InvalidResponse)
+    }
+
+    /// Vehicle identification number (mode 09, PID 02)
+    pub async fn vin(&self) -> Result<String, ObdError> {
+        let response = self.request(0x09, &[0x02]).await?;
+        parse_vin(&response)
+    }
+
+    /// Return the socket, switched back to blocking mode.
+    pub fn into_inner(self) -> io::Result<IsoTpSocket> {
+        let socket = self.inner.into_inner()?;
+        socket.set_nonblocking(false)?;
+        Ok(socket)
+    }
+}
+
+/// Data of a positive response to `mode`, after the echoed mode
+fn response_data(mode: u8, response: &[u8]) -> Result<&[u8], ObdError> {
+    match response {
+        [NEGATIVE_RESPONSE, m, code, ..] if *m == mode => Err(ObdError::NegativeResponse(*code)),
+        [m, data @ ..] if *m == mode | 0x40 => Ok(data),
+        _ => Err(ObdError::InvalidResponse),
+    }
+}
+
+/// Add the PIDs of a supported PIDs bitmap starting after `base`, returning
+/// whether the next range is supported as well
+fn supported_range(base: u8, bitmap: &[u8], supported: &mut Vec<u8>) -> Result<bool, ObdError> {
+    let bitmap = bitmap.get(..4).ok_or(ObdError::InvalidResponse)?;
+    let bits = u32::from_be_bytes([bitmap[0], bitmap[1], bitmap[2], bitmap[3]]);
+    supported.extend(
+        (0..32)
+            .filter(|i| bits & (1 << (31 - i)) != 0)
+            // the range at 0xE0 ends with PID 0x100, which does not exist
+            .filter_map(|i| u8::try_from(base as u16 + 1 + i).ok()),
+    );
+    // the last bit announces the next range
+    Ok(bits & 1 != 0 && base < 0xE0)
+}
+
+/// Parse the VIN of a mode 09 PID 02 response
+fn parse_vin(response: &[u8]) -> Result<String, ObdError> {
+    let data = pid_data(0x02, response).ok_or(ObdError::InvalidResponse)?;
+    // preceded by the number of data items
+    let vin = data.get(data.len().saturating_sub(17)..).unwrap_or(&[]);
+    if vin.len() != 17 || !vin.iter().all(u8::is_ascii_alphanumeric) {
+        return Err(ObdError::InvalidResponse);
+    }
+    Ok(String::from_utf8_lossy(vin).into_owned())
+}
+
+/// Data following the echoed `pid`
+fn pid_data(pid: u8, response: &[u8]) -> Option<&[u8]> {
+    match response {
+        [p, data @ ..] if *p == pid => Some(data),
+        _ => None,
+    }
+}
+
+/// Parse the trouble codes of a mode 03 response, which starts with the
+/// number of codes on CAN
+fn parse_dtcs(response: &[u8]) -> Option<Vec<Dtc>> {
+    let (count, codes) = response.split_first()?;
+    if codes.len() < *count as usize * 2 {
+        return None;
+    }
+    Some(
+        codes
+            .chunks_exact(2)
+            .take(*count as usize)
+            .map(|c| Dtc(u16::from_be_bytes([c[0], c[1]])))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_dtcs, parse_vin, pid_data, response_data, supported_range, Dtc, ObdError, Pid,
+    };
+
+    #[test]
+    fn test_pid_decoding() {
+        assert_eq!(Pid::EngineSpeed.decode(&[0x1F, 0x40]), Some(2000.0));
+        assert_eq!(Pid::CoolantTemperature.decode(&[0x5A]), Some(50.0));
+        assert_eq!(Pid::EngineLoad.decode(&[0xFF]), Some(100.0));
+        assert_eq!(Pid::EngineSpeed.decode(&[0x1F]), None);
+        assert_eq!(Pid::VehicleSpeed.unit(), "km/h");
+    }
+
+    #[test]
+    fn test_responses() {
+        let data = response_data(0x01, &[0x41, 0x0C, 0x1F, 0x40]).unwrap();
+        assert_eq!(pid_data(0x0C, data), Some(&[0x1F, 0x40][..]));
+        assert!(pid_data(0x0D, data).is_none());
+        assert!(matches!(
+            response_data(0x01, &[0x7F, 0x01, 0x12]),
+            Err(ObdError::NegativeResponse(0x12))
+        ));
+        assert!(matches!(
+            response_data(0x01, &[0x43, 0x00]),
+            Err(ObdError::InvalidResponse)
+        ));
+    }
+
+    #[test]
+    fn test_dtcs() {
+        let dtcs = parse_dtcs(&[0x02, 0x03, 0x01, 0xC1, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(dtcs, [Dtc(0x0301), Dtc(0xC100)]);
+        assert_eq!(dtcs[0].to_string(), "P0301");
+        assert_eq!(dtcs[1].to_string(), "U0100");
+        assert!(parse_dtcs(&[0x02, 0x03, 0x01]).is_none());
+    }
+
+    #[test]
+    fn test_supported_pids() {
+        let mut supported = Vec::new();
+        assert!(supported_range(0x00, &[0x98, 0x3B, 0x00, 0x11], &mut supported).unwrap());
+        assert_eq!(supported[..4], [0x01, 0x04, 0x05, 0x0B]);
+        assert_eq!(supported.last(), Some(&0x20));
+        assert!(!supported_range(0x20, &[0x80, 0, 0, 0], &mut supported).unwrap());
+        assert_eq!(supported.last(), Some(&0x21));
+        assert!(!supported_range(0xE0, &[0, 0, 0, 0x03], &mut supported).unwrap());
+        assert_eq!(supported.last(), Some(&0xFF));
+    }
+
+    #[test]
+    fn test_vin() {
+        let mut response = vec![0x02, 0x01];
+        response.extend_from_slice(b"1G1JC5444R7252367");
+        assert_eq!(parse_vin(&response).unwrap(), "1G1JC5444R7252367");
+        assert!(parse_vin(&response[..10]).is_err());
+    }
+
+    #[cfg(all(feature = "async-io", feature = "vcan0"))]
+    #[test]
+    fn vcan0_async_request() {
+        use super::AsyncObdClient;
+        use crate::{CanId, IsoTpSocket};
+
+        let ecu =
+            IsoTpSocket::new("vcan0", CanId::Standard(0x7E0), CanId::Standard(0x7E8)).unwrap();
+        let client = AsyncObdClient::open("vcan0").unwrap();
+        let responder = std::thread::spawn(move || {
+            let mut buf = [0u8; 8];
+            let len = ecu.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], &[0x01, 0x0D]);
+            ecu.send(&[0x41, 0x0D, 0x32]).unwrap();
+        });
+        let speed = async_io::block_on(client.current_data(Pid::VehicleSpeed)).unwrap();
+        responder.join().unwrap();
+        assert_eq!(speed, 50.0);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{Clock, SystemClock};
+use embedded_can::{blocking::Can, Frame as _, Id};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Enforces a minimum gap between transmitted frames
+///
+/// Wraps a `Can` implementation and delays transmissions so that slow
+/// listeners like bootloaders, which drop back-to-back frames, can keep up.
+/// The gap applies to all frames and can be raised for individual ids.
+#[derive(Debug)]
+pub struct Paced<T, C = SystemClock> {
+    inner: T,
+    clock: C,
+    gap: Duration,
+    id_gaps: HashMap<Id, Duration>,
+    last: Option<Instant>,
+    last_by_id: HashMap<Id, Instant>,
+}
+
+impl<T: Can> Paced<T> {
+    /// Wait at least `gap` between any two frames transmitted on `inner`
+    pub fn new(inner: T, gap: Duration) -> Paced<T> {
+        Paced {
+            inner,
+            clock: SystemClock,
+            gap,
+            id_gaps: HashMap::new(),
+            last: None,
+            last_by_id: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Can, C: Clock> Paced<T, C> {
+    /// Use `clock` to measure and wait for the gaps
+    pub fn with_clock<D: Clock>(self, clock: D) -> Paced<T, D> {
+        Paced {
+            inner: self.inner,
+            clock,
+            gap: self.gap,
+            id_gaps: self.id_gaps,
+            last: None,
+            last_by_id: HashMap::new(),
+        }
+    }
+
+    /// Wait at least `gap` between two frames with the id `id`
+    pub fn gap_for(mut self, id: impl Into<Id>, gap: Duration) -> Paced<T, C> {
+        self.id_gaps.insert(id.into(), gap);
+        self
+    }
+
+    /// Access the wrapped device
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Return the wrapped device
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Earliest time a frame with `id` may be transmitted
+    fn earliest(&self, id: Id) -> Option<Instant> {
+        let any = self.last.map(|t| t + self.gap);
+        let same = match (self.last_by_id.get(&id), self.id_gaps.get(&id)) {
+            (Some(t), Some(gap)) => Some(*t + *gap),
+            _ => None,
+        };
+        any.max(same)
+    }
+}
+
+impl<T: Can, C: Clock> Can for Paced<T, C> {
+    type Frame = T::Frame;
+    type Error = T::Error;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        let id = frame.id();
+        if let Some(earliest) = self.earliest(id) {
+            let now = self.clock.now();
+            if earliest > now {
+                self.clock.sleep(earliest - now);
+            }
+        }
+
+        self.inner.transmit(frame)?;
+        let now = self.clock.now();
+        self.last = Some(now);
+        if self.id_gaps.contains_key(&id) {
+            self.last_by_id.insert(id, now);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        self.inner.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Paced;
+    use crate::{Frame, ManualClock, Socket};
+    use embedded_can::{blocking::Can, StandardId};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_gaps() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let slow = StandardId::new(0x7E0).unwrap();
+        let mut paced =
+            Paced::new(a, Duration::from_millis(5)).gap_for(slow, Duration::from_millis(30));
+
+        let start = Instant::now();
+        for id in [0x100, 0x101, 0x102] {
+            paced
+                .transmit(&Frame::new(id, &[], false, false).unwrap())
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        let start = Instant::now();
+        let frame = <Frame as embedded_can::Frame>::new(slow, &[1]).unwrap();
+        paced.transmit(&frame).unwrap();
+        paced.transmit(&frame).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        for _ in 0..5 {
+            b.receive().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let clock = ManualClock::new();
+        let mut paced = Paced::new(a, Duration::from_secs(60)).with_clock(clock.clone());
+
+        for id in [0x100, 0x101, 0x102] {
+            paced
+                .transmit(&Frame::new(id, &[], false, false).unwrap())
+                .unwrap();
+            b.receive().unwrap();
+        }
+        assert_eq!(clock.elapsed(), Duration::from_secs(120));
+
+        // no need to wait once the gap passed
+        clock.advance(Duration::from_secs(90));
+        paced
+            .transmit(&Frame::new(0x103, &[], false, false).unwrap())
+            .unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(210));
+    }
+}
@@ -0,0 +1,652 @@
+use crate::{frame::bytes_of, AnyFrame, FdFrame, Frame, ShutdownToken};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    path::Path,
+    ptr,
+    time::Duration,
+};
+
+/// `LINKTYPE_CAN_SOCKETCAN`
+const LINKTYPE_CAN_SOCKETCAN: u32 = 227;
+
+/// Magic number of files with microsecond timestamps
+const MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+
+/// Magic number of files with nanosecond timestamps
+const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+
+/// `CANFD_FDF`, marks CAN FD frames in newer captures
+const CANFD_FDF: u8 = 0x04;
+
+/// Largest packet, a CAN FD frame
+const MAX_PACKET_LEN: usize = size_of::<FdFrame>();
+
+/// pcapng block types
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+
+/// Byte order magic of pcapng section headers
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// pcapng interface option holding the timestamp resolution
+const OPT_IF_TSRESOL: u16 = 9;
+
+/// Frame stored in a pcap file
+#[derive(Debug, Copy, Clone)]
+pub struct PcapPacket {
+    /// Capture time since the Unix epoch
+    pub timestamp: Duration,
+    /// The captured frame
+    pub frame: AnyFrame,
+}
+
+/// Writer of pcap files with the SocketCAN link type
+///
+/// The files can be opened with Wireshark and tcpdump. Timestamps are
+/// stored with nanosecond resolution.
+pub struct PcapWriter<W> {
+    writer: W,
+    /// Packets written since the last flush
+    pending: u64,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Create a pcap file, replacing an existing one
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<PcapWriter<BufWriter<File>>> {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header to `writer`
+    pub fn new(mut writer: W) -> io::Result<PcapWriter<W>> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC_NANOS.to_ne_bytes());
+        header.extend_from_slice(&2u16.to_ne_bytes());
+        header.extend_from_slice(&4u16.to_ne_bytes());
+        header.extend_from_slice(&[0; 8]); // time zone and accuracy
+        header.extend_from_slice(&(MAX_PACKET_LEN as u32).to_ne_bytes());
+        header.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_ne_bytes());
+        writer.write_all(&header)?;
+        Ok(PcapWriter { writer, pending: 0 })
+    }
+
+    /// Append a packet
+    pub fn write(&mut self, packet: &PcapPacket) -> io::Result<()> {
+        let data = packet_data(&packet.frame);
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&(packet.timestamp.as_secs() as u32).to_ne_bytes());
+        header.extend_from_slice(&packet.timestamp.subsec_nanos().to_ne_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&data)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Flush buffered packets
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Flush the buffered packets once shutting down and return the underlying
+    /// writer. The flushed packets are reported to `token` as subsystem
+    /// `pcap`, or as dropped if flushing failed.
+    pub fn finish_on(mut self, token: &ShutdownToken) -> io::Result<W> {
+        let pending = self.pending;
+        let rv = self.flush();
+        token.report_flush("pcap", pending, &rv);
+        rv.map(|_| self.writer)
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writer of pcapng files with a single SocketCAN interface
+///
+/// The successor of the pcap format and the default of Wireshark.
+/// Timestamps are stored with nanosecond resolution.
+pub struct PcapNgWriter<W> {
+    writer: W,
+    /// Packets written since the last flush
+    pending: u64,
+}
+
+impl PcapNgWriter<BufWriter<File>> {
+    /// Create a pcapng file, replacing an existing one
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<PcapNgWriter<BufWriter<File>>> {
+        PcapNgWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Write the section header and the interface description to `writer`
+    pub fn new(mut writer: W) -> io::Result<PcapNgWriter<W>> {
+        let mut section = Vec::with_capacity(16);
+        section.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        section.extend_from_slice(&1u16.to_ne_bytes());
+        section.extend_from_slice(&0u16.to_ne_bytes());
+        // section length not specified
+        section.extend_from_slice(&u64::MAX.to_ne_bytes());
+        writer.write_all(&block(BLOCK_SHB, &section))?;
+
+        let mut interface = Vec::with_capacity(20);
+        interface.extend_from_slice(&(LINKTYPE_CAN_SOCKETCAN as u16).to_ne_bytes());
+        interface.extend_from_slice(&0u16.to_ne_bytes());
+        interface.extend_from_slice(&(MAX_PACKET_LEN as u32).to_ne_bytes());
+        // nanosecond timestamps, followed by the end of options
+        interface.extend_from_slice(&OPT_IF_TSRESOL.to_ne_bytes());
+        interface.extend_from_slice(&1u16.to_ne_bytes());
+        interface.extend_from_slice(&[9, 0, 0, 0]);
+        interface.extend_from_slice(&[0; 4]);
+        writer.write_all(&block(BLOCK_IDB, &interface))?;
+        Ok(PcapNgWriter { writer, pending: 0 })
+    }
+
+    /// Append a packet
+    pub fn write(&mut self, packet: &PcapPacket) -> io::Result<()> {
+        let data = packet_data(&packet.frame);
+        let nanos = packet.timestamp.as_nanos() as u64;
+        let mut body = Vec::with_capacity(20 + data.len());
+        body.extend_from_slice(&0u32.to_ne_bytes()); // interface
+        body.extend_from_slice(&((nanos >> 32) as u32).to_ne_bytes());
+        body.extend_from_slice(&(nanos as u32).to_ne_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        body.extend_from_slice(&data);
+        self.writer.write_all(&block(BLOCK_EPB, &body))?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Flush buffered packets
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Flush the buffered packets once shutting down and return the underlying
+    /// writer. The flushed packets are reported to `token` as subsystem
+    /// `pcapng`, or as dropped if flushing failed.
+    pub fn finish_on(mut self, token: &ShutdownToken) -> io::Result<W> {
+        let pending = self.pending;
+        let rv = self.flush();
+        token.report_flush("pcapng", pending, &rv);
+        rv.map(|_| self.writer)
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterator over the packets of a pcap or pcapng file with the SocketCAN
+/// link type
+///
+/// Reads files of either byte order, with microsecond or nanosecond
+/// timestamps. Packets of pcapng interfaces with other link types are
+/// skipped.
+pub struct PcapReader<R> {
+    reader: R,
+    swapped: bool,
+    format: Format,
+}
+
+/// Format specific state of a `PcapReader`
+enum Format {
+    /// pcap file with nanosecond or microsecond timestamps
+    Pcap { nanos: bool },
+    /// pcapng file, with the timestamp units per second of each interface
+    /// of the current section, `None` for other link types
+    PcapNg { interfaces: Vec<Option<u64>> },
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Open a pcap or pcapng file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PcapReader<BufReader<File>>> {
+        PcapReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Read the file header from `reader`.
+    ///
+    /// Fails with `InvalidData` for other formats and for pcap files of
+    /// other link types.
+    pub fn new(mut reader: R) -> io::Result<PcapReader<R>> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header[..4])?;
+
+        let magic = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]);
+        if magic == BLOCK_SHB {
+            let mut pcap = PcapReader {
+                reader,
+                swapped: false,
+                format: Format::PcapNg {
+                    interfaces: Vec::new(),
+                },
+            };
+            pcap.read_section_header()?;
+            return Ok(pcap);
+        }
+
+        let (swapped, nanos) = match magic {
+            MAGIC_MICROS => (false, false),
+            MAGIC_NANOS => (false, true),
+            _ if magic.swap_bytes() == MAGIC_MICROS => (true, false),
+            _ if magic.swap_bytes() == MAGIC_NANOS => (true, true),
+            _ => return Err(invalid("not a pcap file")),
+        };
+        reader.read_exact(&mut header[4..])?;
+
+        let pcap = PcapReader {
+            reader,
+            swapped,
+            format: Format::Pcap { nanos },
+        };
+        if pcap.u32_at(&header, 20) != LINKTYPE_CAN_SOCKETCAN {
+            return Err(invalid("not a SocketCAN capture"));
+        }
+        Ok(pcap)
+    }
+
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        let value = u16::from_ne_bytes([buf[offset], buf[offset + 1]]);
+        if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let value = u32::from_ne_bytes([
+            buf[offset],
+            buf[offset + 1],
+            buf[offset + 2],
+            buf[offset + 3],
+        ]);
+        if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        }
+    }
+
+    fn read_packet(&mut self, header: &[u8; 16], nanos: bool) -> io::Result<PcapPacket> {
+        let secs = self.u32_at(header, 0) as u64;
+        let fraction = self.u32_at(header, 4);
+        let len = self.u32_at(header, 8) as usize;
+
+        if len > MAX_PACKET_LEN {
+            // skip the packet, so the next one can be read
+            io::copy(&mut (&mut self.reader).take(len as u64), &mut io::sink())?;
+            return Err(invalid("packet too long"));
+        }
+        let mut data = [0; MAX_PACKET_LEN];
+        self.reader.read_exact(&mut data[..len])?;
+
+        let nanos = if nanos {
+            fraction
+        } else {
+            fraction.saturating_mul(1000)
+        };
+        if nanos >= 1_000_000_000 {
+            return Err(invalid("invalid packet header"));
+        }
+        Ok(PcapPacket {
+            timestamp: Duration::new(secs, nanos),
+            frame: parse_frame(&data[..len])?,
+        })
+    }
+
+    /// Read the rest of a section header after the block type and start a
+    /// new section
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut header = [0; 8];
+        self.reader.read_exact(&mut header)?;
+        let magic = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+        self.swapped = match magic {
+            BYTE_ORDER_MAGIC => false,
+            _ if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid("not a pcapng file")),
+        };
+        let len = self.u32_at(&header, 0) as usize;
+        if len < 28 || len % 4 != 0 {
+            return Err(invalid("invalid section header"));
+        }
+        // version, section length and options
+        io::copy(
+            &mut (&mut self.reader).take((len - 12) as u64),
+            &mut io::sink(),
+        )?;
+        self.format = Format::PcapNg {
+            interfaces: Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// Read pcapng blocks up to the next packet of a SocketCAN interface.
+    /// `None` at the end of the file.
+    fn read_block(&mut self) -> Option<io::Result<PcapPacket>> {
+        loop {
+            let mut header = [0; 8];
+            match self.reader.read_exact(&mut header[..4]) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+                Err(e) => return Some(Err(e)),
+            }
+            let block = self.u32_at(&header, 0);
+            if block == BLOCK_SHB {
+                match self.read_section_header() {
+                    Ok(()) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            if let Err(e) = self.reader.read_exact(&mut header[4..]) {
+                return Some(Err(e));
+            }
+            let len = self.u32_at(&header, 4) as usize;
+            if len < 12 || len % 4 != 0 {
+                return Some(Err(invalid("invalid block length")));
+            }
+            // body and the repeated block length
+            let mut body = vec![0; len - 8];
+            if let Err(e) = self.reader.read_exact(&mut body) {
+                return Some(Err(e));
+            }
+            body.truncate(len - 12);
+
+            match block {
+                BLOCK_IDB => {
+                    if let Err(e) = self.add_interface(&body) {
+                        return Some(Err(e));
+                    }
+                }
+                BLOCK_EPB => match self.parse_packet(&body) {
+                    Ok(Some(packet)) => return Some(Ok(packet)),
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                },
+                // e.g. name resolution or statistics
+                _ => {}
+            }
+        }
+    }
+
+    /// Register the interface of an interface description block
+    fn add_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(invalid("invalid interface description"));
+        }
+        let link_type = self.u16_at(body, 0) as u32;
+
+        // microseconds unless given otherwise
+        let mut resolution = 6;
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = self.u16_at(options, 0);
+            let len = self.u16_at(options, 2) as usize;
+            let value = options.get(4..4 + len).unwrap_or(&[]);
+            if code == OPT_IF_TSRESOL && len == 1 {
+                resolution = value[0];
+            }
+            options = options.get(4 + (len + 3) / 4 * 4..).unwrap_or(&[]);
+        }
+        // a power of 10, or of 2 if the top bit is set
+        let units = if resolution & 0x80 == 0 {
+            10u64.checked_pow(resolution as u32)
+        } else {
+            1u64.checked_shl((resolution & 0x7F) as u32)
+        }
+        .ok_or_else(|| invalid("invalid timestamp resolution"))?;
+
+        if let Format::PcapNg { interfaces } = &mut self.format {
+            interfaces.push((link_type == LINKTYPE_CAN_SOCKETCAN).then_some(units));
+        }
+        Ok(())
+    }
+
+    /// Parse an enhanced packet block. `None` for packets of interfaces with
+    /// other link types.
+    fn parse_packet(&self, body: &[u8]) -> io::Result<Option<PcapPacket>> {
+        if body.len() < 20 {
+            return Err(invalid("invalid packet block"));
+        }
+        let interfaces = match &self.format {
+            Format::PcapNg { interfaces } => interfaces,
+            Format::Pcap { .. } => unreachable!(),
+        };
+        let units = match interfaces.get(self.u32_at(body, 0) as usize) {
+            Some(Some(units)) => *units,
+            Some(None) => return Ok(None),
+            None => return Err(invalid("packet of an unknown interface")),
+        };
+        let ts = (self.u32_at(body, 4) as u64) << 32 | self.u32_at(body, 8) as u64;
+        let len = self.u32_at(body, 12) as usize;
+        let data = body
+            .get(20..20 + len)
+            .ok_or_else(|| invalid("invalid packet block"))?;
+        if len > MAX_PACKET_LEN {
+            return Err(invalid("packet too long"));
+        }
+
+        let nanos = (ts % units) as u128 * 1_000_000_000 / units as u128;
+        Ok(Some(PcapPacket {
+            timestamp: Duration::new(ts / units, nanos as u32),
+            frame: parse_frame(data)?,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<PcapPacket>;
+
+    fn next(&mut self) -> Option<io::Result<PcapPacket>> {
+        let nanos = match self.format {
+            Format::Pcap { nanos } => nanos,
+            Format::PcapNg { .. } => return self.read_block(),
+        };
+        let mut header = [0; 16];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => Some(self.read_packet(&header, nanos)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Frame in the kernel layout, with the id in network byte order as
+/// required by the link type
+fn packet_data(frame: &AnyFrame) -> Vec<u8> {
+    let mut data = match frame {
+        AnyFrame::Classic(frame) => bytes_of(frame).to_vec(),
+        AnyFrame::Fd(frame) => bytes_of(frame).to_vec(),
+    };
+    let id = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+    data[..4].copy_from_slice(&id.to_be_bytes());
+    data
+}
+
+/// Parse the packet data of at most `MAX_PACKET_LEN` bytes
+fn parse_frame(packet: &[u8]) -> io::Result<AnyFrame> {
+    let mut data = [0; MAX_PACKET_LEN];
+    data[..packet.len()].copy_from_slice(packet);
+    let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    data[..4].copy_from_slice(&id.to_ne_bytes());
+
+    if packet.len() == MAX_PACKET_LEN || data[5] & CANFD_FDF != 0 {
+        if data[4] as usize > 64 {
+            return Err(invalid("invalid CAN FD frame length"));
+        }
+        Ok(AnyFrame::Fd(unsafe {
+            ptr::read_unaligned(data.as_ptr() as *const FdFrame)
+        }))
+    } else {
+        if data[4] > 8 {
+            return Err(invalid("invalid CAN frame length"));
+        }
+        Ok(AnyFrame::Classic(unsafe {
+            ptr::read_unaligned(data.as_ptr() as *const Frame)
+        }))
+    }
+}
+
+/// pcapng block of type `ty`, with the body padded to 32 bit
+fn block(ty: u32, body: &[u8]) -> Vec<u8> {
+    let padded = (body.len() + 3) / 4 * 4;
+    let len = (12 + padded) as u32;
+    let mut block = Vec::with_capacity(len as usize);
+    block.extend_from_slice(&ty.to_ne_bytes());
+    block.extend_from_slice(&len.to_ne_bytes());
+    block.extend_from_slice(body);
+    block.resize(8 + padded, 0);
+    block.extend_from_slice(&len.to_ne_bytes());
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{block, PcapNgWriter, PcapPacket, PcapReader, PcapWriter, BLOCK_EPB, BLOCK_IDB};
+    use crate::{AnyFrame, FdFrame, Frame, ShutdownToken};
+    use std::time::Duration;
+
+    fn packets() -> [PcapPacket; 2] {
+        let classic = Frame::new(0x18FEF100, &[1, 2, 3], false, false).unwrap();
+        let fd = FdFrame::new(0x123, &[0xAA; 12], true, false).unwrap();
+        [
+            PcapPacket {
+                timestamp: Duration::new(1436509052, 249_713_001),
+                frame: AnyFrame::Classic(classic),
+            },
+            PcapPacket {
+                timestamp: Duration::new(1436509053, 0),
+                frame: AnyFrame::Fd(fd),
+            },
+        ]
+    }
+
+    fn check_packets(file: &[u8]) {
+        let packets = packets();
+        let read = PcapReader::new(file)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].timestamp, packets[0].timestamp);
+        match read[0].frame {
+            AnyFrame::Classic(frame) => assert_eq!(frame.to_string(), "18FEF100#010203"),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+        match (read[1].frame, packets[1].frame) {
+            (AnyFrame::Fd(frame), AnyFrame::Fd(fd)) => {
+                assert_eq!(frame.to_string(), fd.to_string())
+            }
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pcap_roundtrip() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for packet in &packets() {
+            writer.write(packet).unwrap();
+        }
+        let token = ShutdownToken::new();
+        let file = writer.finish_on(&token).unwrap();
+        assert_eq!(token.reports()[0].flushed, 2);
+        // id in network byte order after the file and packet headers
+        assert_eq!(&file[40..44], &[0x98, 0xFE, 0xF1, 0x00]);
+        check_packets(&file);
+    }
+
+    #[test]
+    fn test_pcapng_roundtrip() {
+        let mut writer = PcapNgWriter::new(Vec::new()).unwrap();
+        for packet in &packets() {
+            writer.write(packet).unwrap();
+        }
+        let file = writer.into_inner();
+        assert_eq!(&file[..4], &[0x0A, 0x0D, 0x0D, 0x0A]);
+        check_packets(&file);
+
+        // a second section with an interface of another link type, followed
+        // by a CAN interface with microsecond timestamps
+        let mut file = file;
+        let section = file[..28].to_vec();
+        file.extend_from_slice(&section);
+        file.extend_from_slice(&block(
+            BLOCK_IDB,
+            &[&1u16.to_ne_bytes()[..], &[0; 6]].concat(),
+        ));
+        file.extend_from_slice(&block(
+            BLOCK_IDB,
+            &[&227u16.to_ne_bytes()[..], &[0; 6]].concat(),
+        ));
+        let packet = |interface: u32, data: &[u8]| {
+            let mut body = interface.to_ne_bytes().to_vec();
+            body.extend_from_slice(&0u32.to_ne_bytes());
+            body.extend_from_slice(&1_000_000u32.to_ne_bytes());
+            body.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+            body.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+            body.extend_from_slice(data);
+            block(BLOCK_EPB, &body)
+        };
+        file.extend_from_slice(&packet(0, &[0xFF; 4]));
+        file.extend_from_slice(&packet(
+            1,
+            &[0, 0, 0x01, 0x23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        ));
+
+        let read = PcapReader::new(&file[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[2].timestamp, Duration::from_secs(1));
+        match read[2].frame {
+            AnyFrame::Classic(frame) => assert_eq!(frame.to_string(), "123#"),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_packet_too_long() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer.write(&packets()[0]).unwrap();
+        let mut file = writer.into_inner();
+        let valid = file.split_off(24);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&100u32.to_ne_bytes());
+        file.extend_from_slice(&100u32.to_ne_bytes());
+        file.extend_from_slice(&[0; 100]);
+        file.extend_from_slice(&valid);
+
+        let mut reader = PcapReader::new(&file[..]).unwrap();
+        let e = reader.next().unwrap().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_not_pcap() {
+        assert!(PcapReader::new(&[0u8; 24][..]).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Helpers for the J1939 / ISO 11783 layout of 29 bit identifiers.
+
+/// Extract the PGN from an extended identifier.
+///
+/// For PDU1 messages (PF < 240) the PDU specific byte holds the destination
+/// address and is not part of the PGN.
+pub(crate) fn pgn(id: u32) -> u32 {
+    let pgn = (id >> 8) & 0x3FFFF;
+    if is_pdu1(pgn) {
+        pgn & 0x3FF00
+    } else {
+        pgn
+    }
+}
+
+/// Destination address of a PDU1 message, `None` for broadcast PDU2 messages.
+#[cfg(feature = "nmea2000")]
+pub(crate) fn destination(id: u32) -> Option<u8> {
+    if is_pdu1((id >> 8) & 0x3FFFF) {
+        Some((id >> 8) as u8)
+    } else {
+        None
+    }
+}
+
+/// Source address of a message
+#[cfg(feature = "nmea2000")]
+pub(crate) fn source(id: u32) -> u8 {
+    id as u8
+}
+
+/// Assemble an extended identifier. `destination` is ignored for PDU2 PGNs.
+#[cfg(feature = "nmea2000")]
+pub(crate) fn id(priority: u8, pgn: u32, destination: u8, source: u8) -> u32 {
+    let mut id = ((priority as u32 & 0x7) << 26) | ((pgn & 0x3FFFF) << 8) | source as u32;
+    if is_pdu1(pgn) {
+        id = (id & !0xFF00) | ((destination as u32) << 8);
+    }
+    id
+}
+
+fn is_pdu1(pgn: u32) -> bool {
+    (pgn >> 8) & 0xFF < 240
+}
@@ -0,0 +1,209 @@
+use libc::{
+    c_int, close, epoll_create1, epoll_ctl, epoll_event, epoll_wait, EINTR, EPOLLIN, EPOLLOUT,
+    EPOLL_CLOEXEC, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD,
+};
+use std::{
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr, time,
+};
+
+/// Readiness a `Poller` waits for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interest {
+    /// A frame can be received
+    Readable,
+    /// A frame can be transmitted
+    Writable,
+    /// Either of both
+    ReadWrite,
+}
+
+impl Interest {
+    fn events(self) -> u32 {
+        match self {
+            Interest::Readable => EPOLLIN as u32,
+            Interest::Writable => EPOLLOUT as u32,
+            Interest::ReadWrite => (EPOLLIN | EPOLLOUT) as u32,
+        }
+    }
+}
+
+/// Readiness of a registered socket, returned by `Poller::wait`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Readiness {
+    /// Key the socket was registered with
+    pub key: usize,
+    /// A frame can be received without blocking
+    pub readable: bool,
+    /// A frame can be transmitted without blocking
+    pub writable: bool,
+}
+
+/// Waits for several sockets at once
+///
+/// Registers any number of sockets, e.g. `Socket`s of several buses and
+/// `J1939Socket`s, with an epoll instance, so a gateway can serve all of
+/// them from a single thread:
+///
+/// ```no_run
+/// use candev::{Interest, Poller, Socket};
+/// use embedded_can::blocking::Can;
+///
+/// let mut sockets = [Socket::new("can0").unwrap(), Socket::new("can1").unwrap()];
+/// let mut poller = Poller::new().unwrap();
+/// for (key, socket) in sockets.iter().enumerate() {
+///     poller.add(socket, key, Interest::Readable).unwrap();
+/// }
+/// loop {
+///     for ready in poller.wait(None).unwrap() {
+///         let frame = sockets[ready.key].receive().unwrap();
+///         sockets[1 - ready.key].transmit(&frame).unwrap();
+///     }
+/// }
+/// ```
+///
+/// Readiness is level triggered, a socket is reported again as long as a
+/// frame is pending.
+#[derive(Debug)]
+pub struct Poller {
+    fd: RawFd,
+    events: Vec<epoll_event>,
+}
+
+impl Poller {
+    /// Create a poller without registered sockets
+    pub fn new() -> io::Result<Poller> {
+        let fd = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Poller {
+            fd,
+            events: Vec::new(),
+        })
+    }
+
+    /// Register `source`, reporting its readiness with `key`
+    pub fn add(&mut self, source: &impl AsRawFd, key: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(EPOLL_CTL_ADD, source.as_raw_fd(), key, interest)?;
+        self.events.push(epoll_event { events: 0, u64: 0 });
+        Ok(())
+    }
+
+    /// Change the key or interest of a registered `source`
+    pub fn modify(&self, source: &impl AsRawFd, key: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(EPOLL_CTL_MOD, source.as_raw_fd(), key, interest)
+    }
+
+    /// Stop watching `source`
+    pub fn delete(&mut self, source: &impl AsRawFd) -> io::Result<()> {
+        let rv = unsafe { epoll_ctl(self.fd, EPOLL_CTL_DEL, source.as_raw_fd(), ptr::null_mut()) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.events.pop();
+        Ok(())
+    }
+
+    /// Wait until at least one registered socket is ready or `timeout`
+    /// expires, returning the ready sockets. Waits forever without timeout.
+    pub fn wait(&mut self, timeout: Option<time::Duration>) -> io::Result<Vec<Readiness>> {
+        let timeout = match timeout {
+            Some(t) => t.as_millis().min(c_int::MAX as u128) as c_int,
+            None => -1,
+        };
+        let max = self.events.len().max(1);
+        self.events.resize(max, epoll_event { events: 0, u64: 0 });
+
+        let n = loop {
+            let n = unsafe { epoll_wait(self.fd, self.events.as_mut_ptr(), max as c_int, timeout) };
+            if n != -1 {
+                break n as usize;
+            }
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(EINTR) {
+                return Err(e);
+            }
+        };
+
+        Ok(self.events[..n]
+            .iter()
+            .map(|event| {
+                let events = event.events;
+                Readiness {
+                    key: event.u64 as usize,
+                    readable: events & EPOLLIN as u32 != 0,
+                    writable: events & EPOLLOUT as u32 != 0,
+                }
+            })
+            .collect())
+    }
+
+    fn ctl(&self, op: c_int, fd: RawFd, key: usize, interest: Interest) -> io::Result<()> {
+        let mut event = epoll_event {
+            events: interest.events(),
+            u64: key as u64,
+        };
+        let rv = unsafe { epoll_ctl(self.fd, op, fd, &mut event) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Poller {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interest, Poller, Readiness};
+    use crate::{Frame, Socket};
+    use embedded_can::blocking::Can;
+    use std::time::Duration;
+
+    #[test]
+    fn test_poll() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let (c, _d) = Socket::pair().unwrap();
+        let mut poller = Poller::new().unwrap();
+        poller.add(&a, 0, Interest::Readable).unwrap();
+        poller.add(&c, 1, Interest::Readable).unwrap();
+
+        let timeout = Some(Duration::from_millis(10));
+        assert!(poller.wait(timeout).unwrap().is_empty());
+
+        b.transmit(&Frame::new(0x123, &[], false, false).unwrap())
+            .unwrap();
+        let ready = poller.wait(timeout).unwrap();
+        assert_eq!(
+            ready,
+            [Readiness {
+                key: 0,
+                readable: true,
+                writable: false
+            }]
+        );
+
+        poller.modify(&c, 7, Interest::Writable).unwrap();
+        let ready = poller.wait(timeout).unwrap();
+        assert_eq!(ready.len(), 2);
+        assert!(ready.iter().any(|r| r.key == 7 && r.writable));
+
+        poller.delete(&a).unwrap();
+        poller.delete(&c).unwrap();
+        assert!(poller.wait(timeout).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{AnyFrame, FdFrame, Frame};
+use embedded_can::Frame as _;
+use libc::CAN_EFF_FLAG;
+use std::{io, time::Duration};
+
+/// Protobuf schema of the messages encoded by `ProtoRecord` and
+/// `AnyFrame::to_protobuf`, to generate code for other languages.
+pub const PROTO_SCHEMA: &str = include_str!("../proto/candev.proto");
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+/// Frame with its receive metadata, the `FrameRecord` message of
+/// `PROTO_SCHEMA`
+#[derive(Debug, Clone)]
+pub struct ProtoRecord {
+    /// Receive time since the Unix epoch
+    pub timestamp: Duration,
+    /// Name of the interface the frame was received on
+    pub interface: String,
+    /// The received frame
+    pub frame: AnyFrame,
+}
+
+impl ProtoRecord {
+    /// Encode as a `FrameRecord` message
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_varint_field(&mut buf, 1, self.timestamp.as_nanos() as u64);
+        put_bytes_field(&mut buf, 2, self.interface.as_bytes());
+        put_bytes_field(&mut buf, 3, &self.frame.to_protobuf());
+        buf
+    }
+
+    /// Decode a `FrameRecord` message.
+    ///
+    /// Fails with `InvalidData` if the message is malformed or lacks the
+    /// frame.
+    pub fn decode(buf: &[u8]) -> io::Result<ProtoRecord> {
+        let mut timestamp = 0;
+        let mut interface = String::new();
+        let mut frame = None;
+
+        let mut reader = Reader { buf };
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (1, Value::Varint(v)) => timestamp = v,
+                (2, Value::Bytes(b)) => {
+                    interface = String::from_utf8(b.to_vec())
+                        .map_err(|_| invalid("interface name is not UTF-8"))?
+                }
+                (3, Value::Bytes(b)) => frame = Some(AnyFrame::from_protobuf(b)?),
+                _ => {}
+            }
+        }
+
+        Ok(ProtoRecord {
+            timestamp: Duration::from_nanos(timestamp),
+            interface,
+            frame: frame.ok_or_else(|| invalid("missing frame"))?,
+        })
+    }
+}
+
+impl AnyFrame {
+    /// Encode as a `Frame` message of `PROTO_SCHEMA`
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            AnyFrame::Classic(frame) => {
+                let id = if frame.is_error() {
+                    frame.err()
+                } else {
+                    frame.raw_id()
+                };
+                put_varint_field(&mut buf, 1, id as u64);
+                put_bool_field(&mut buf, 2, frame.is_extended());
+                put_bool_field(&mut buf, 3, frame.is_remote_frame());
+                put_bool_field(&mut buf, 4, frame.is_error());
+                put_bytes_field(&mut buf, 5, frame.data());
+                if frame.is_remote_frame() {
+                    put_varint_field(&mut buf, 6, frame.dlc() as u64);
+                }
+            }
+            AnyFrame::Fd(frame) => {
+                put_varint_field(&mut buf, 1, frame.raw_id() as u64);
+                put_bool_field(&mut buf, 2, !frame.is_standard());
+                put_bytes_field(&mut buf, 5, frame.data());
+                put_bool_field(&mut buf, 7, true);
+                put_bool_field(&mut buf, 8, frame.is_brs());
+                put_bool_field(&mut buf, 9, frame.is_esi());
+            }
+        }
+        buf
+    }
+
+    /// Decode a `Frame` message.
+    ///
+    /// Fails with `InvalidData` if the message is malformed or does not
+    /// describe a valid frame.
+    pub fn from_protobuf(buf: &[u8]) -> io::Result<AnyFrame> {
+        let mut id = 0;
+        let mut data: &[u8] = &[];
+        let mut dlc = 0;
+        let mut flags = [false; 10];
+
+        let mut reader = Reader { buf };
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (1, Value::Varint(v)) => {
+                    id = u32::try_from(v).map_err(|_| invalid("invalid CAN id"))?
+                }
+                (5, Value::Bytes(b)) => data = b,
+                (6, Value::Varint(v)) => dlc = v.min(u8::MAX as u64) as usize,
+                (2..=4, Value::Varint(v)) | (7..=9, Value::Varint(v)) => {
+                    flags[field as usize] = v != 0
+                }
+                _ => {}
+            }
+        }
+        let [_, _, extended, remote, error, _, _, fd, brs, esi] = flags;
+
+        let frame = if fd {
+            FdFrame::new(id, data, brs, esi).map(|frame| {
+                if extended {
+                    AnyFrame::Fd(frame.with_can_id(id | CAN_EFF_FLAG))
+                } else {
+                    AnyFrame::Fd(frame)
+                }
+            })
+        } else {
+            let mut builder = Frame::builder().id(id);
+            if remote {
+                builder = builder.data(&[0; 9][..dlc.min(9)]).rtr();
+            } else {
+                builder = builder.data(data);
+            }
+            if extended {
+                builder = builder.extended();
+            }
+            if error {
+                builder = builder.error();
+            }
+            builder.build().map(AnyFrame::Classic)
+        };
+        frame.map_err(|_| invalid("invalid frame"))
+    }
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterates over the fields of an encoded message
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn field(&mut self) -> io::Result<Option<(u64, Value<'a>)>> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match (key & 7) as u8 {
+            VARINT => Value::Varint(self.varint()?),
+            LENGTH_DELIMITED => {
+                let len = self.varint()?;
+                Value::Bytes(self.take(usize::try_from(len).unwrap_or(usize::MAX))?)
+            }
+            FIXED64 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            FIXED32 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(invalid("unsupported wire type")),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for (n, b) in self.buf.iter().enumerate().take(10) {
+            value |= ((b & 0x7F) as u64) << (7 * n);
+            if b & 0x80 == 0 {
+                self.buf = &self.buf[n + 1..];
+                return Ok(value);
+            }
+        }
+        Err(invalid("truncated varint"))
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.buf.len() {
+            return Err(invalid("truncated message"));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Append a varint field, omitting the default value as proto3 does
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        put_varint(buf, field << 3 | VARINT as u64);
+        put_varint(buf, value);
+    }
+}
+
+fn put_bool_field(buf: &mut Vec<u8>, field: u64, value: bool) {
+    put_varint_field(buf, field, value as u64);
+}
+
+fn put_bytes_field(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    if !value.is_empty() {
+        put_varint(buf, field << 3 | LENGTH_DELIMITED as u64);
+        put_varint(buf, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtoRecord;
+    use crate::{AnyFrame, FdFrame, Frame};
+    use std::time::Duration;
+
+    #[test]
+    fn test_proto_roundtrip() {
+        let frames = [
+            AnyFrame::Classic(Frame::builder().id(0x12).extended().build().unwrap()),
+            AnyFrame::Classic(Frame::new(0x123, &[0; 3], true, false).unwrap()),
+            AnyFrame::Classic(Frame::new(0x20, &[0; 8], false, true).unwrap()),
+            AnyFrame::Fd(FdFrame::new(0x18FEF100, &[0xAA; 12], true, false).unwrap()),
+        ];
+        for frame in frames {
+            let record = ProtoRecord {
+                timestamp: Duration::new(1436509052, 249_713_000),
+                interface: "can0".to_string(),
+                frame,
+            };
+            let decoded = ProtoRecord::decode(&record.encode()).unwrap();
+            assert_eq!(decoded.timestamp, record.timestamp);
+            assert_eq!(decoded.interface, "can0");
+            assert_eq!(decoded.frame.to_string(), frame.to_string());
+        }
+    }
+
+    #[test]
+    fn test_proto_wire_format() {
+        // id 0x123, data [1, 2]
+        let frame = Frame::new(0x123, &[1, 2], false, false).unwrap();
+        let encoded = AnyFrame::Classic(frame).to_protobuf();
+        assert_eq!(encoded, [0x08, 0xA3, 0x02, 0x2A, 0x02, 0x01, 0x02]);
+
+        // unknown fields are skipped
+        let mut extended = encoded.clone();
+        extended.extend_from_slice(&[0x65, 0, 0, 0, 0]);
+        assert!(AnyFrame::from_protobuf(&extended).is_ok());
+        assert!(AnyFrame::from_protobuf(&encoded[..4]).is_err());
+    }
+}
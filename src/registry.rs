@@ -0,0 +1,150 @@
+use crate::{CanId, Frame};
+use embedded_can::Frame as _;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Id claimed in an `IdRegistry`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// The claimed id
+    pub id: CanId,
+    /// Team or component transmitting the id
+    pub owner: String,
+    /// Purpose of the messages
+    pub description: String,
+    /// Frames with this id observed from other nodes
+    pub collisions: u64,
+}
+
+/// Registry of the ids an application transmits
+///
+/// Tracks which ids are claimed by whom, e.g. when several teams share a
+/// development bus. Received traffic is checked against the claims to
+/// detect other nodes transmitting a claimed id, and the allocation can be
+/// exported as documentation.
+#[derive(Debug, Clone, Default)]
+pub struct IdRegistry {
+    claims: BTreeMap<CanId, Allocation>,
+}
+
+impl IdRegistry {
+    /// Create an empty registry
+    pub fn new() -> IdRegistry {
+        IdRegistry::default()
+    }
+
+    /// Claim `id` for `owner`.
+    ///
+    /// Fails with the existing allocation if the id is already claimed by
+    /// another owner. Claiming an id again for the same owner updates the
+    /// description.
+    pub fn claim(&mut self, id: CanId, owner: &str, description: &str) -> Result<(), Allocation> {
+        match self.claims.get_mut(&id) {
+            Some(allocation) if allocation.owner != owner => Err(allocation.clone()),
+            Some(allocation) => {
+                allocation.description = description.to_string();
+                Ok(())
+            }
+            None => {
+                self.claims.insert(
+                    id,
+                    Allocation {
+                        id,
+                        owner: owner.to_string(),
+                        description: description.to_string(),
+                        collisions: 0,
+                    },
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Give up the claim of `id`
+    pub fn release(&mut self, id: CanId) -> Option<Allocation> {
+        self.claims.remove(&id)
+    }
+
+    /// Allocation of `id`, if claimed
+    pub fn get(&self, id: CanId) -> Option<&Allocation> {
+        self.claims.get(&id)
+    }
+
+    /// All allocations, in bus priority order
+    pub fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.claims.values()
+    }
+
+    /// Check a frame received from another node against the claims.
+    ///
+    /// Returns the allocation if the frame collides with a claimed id.
+    /// Only pass frames received from the bus, not echoes of the own
+    /// transmissions, see `Socket::receive_tagged`. Error frames are
+    /// ignored.
+    pub fn observe(&mut self, frame: &Frame) -> Option<&Allocation> {
+        if frame.is_error() {
+            return None;
+        }
+        let allocation = self.claims.get_mut(&CanId::from(frame.id()))?;
+        allocation.collisions += 1;
+        Some(allocation)
+    }
+
+    /// Allocations which collided with observed traffic
+    pub fn collisions(&self) -> Vec<&Allocation> {
+        self.allocations().filter(|a| a.collisions > 0).collect()
+    }
+
+    /// Export the allocations as a Markdown table
+    pub fn to_markdown(&self) -> String {
+        let mut table = String::from("| Id | Owner | Description | Collisions |\n");
+        table.push_str("|----|-------|-------------|------------|\n");
+        for a in self.allocations() {
+            let _ = writeln!(
+                table,
+                "| {} | {} | {} | {} |",
+                a.id, a.owner, a.description, a.collisions
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdRegistry;
+    use crate::{CanId, Frame};
+
+    #[test]
+    fn test_registry() {
+        let mut registry = IdRegistry::new();
+        let id = CanId::Standard(0x123);
+        registry.claim(id, "powertrain", "torque request").unwrap();
+        registry
+            .claim(CanId::Extended(0x100), "body", "door status")
+            .unwrap();
+        assert_eq!(
+            registry.claim(id, "body", "lights").unwrap_err().owner,
+            "powertrain"
+        );
+
+        assert!(registry
+            .observe(&Frame::new(0x124, &[], false, false).unwrap())
+            .is_none());
+        let collision = registry
+            .observe(&Frame::new(0x123, &[1], false, false).unwrap())
+            .unwrap();
+        assert_eq!(collision.collisions, 1);
+        assert_eq!(registry.collisions().len(), 1);
+
+        assert_eq!(
+            registry.to_markdown(),
+            "| Id | Owner | Description | Collisions |\n\
+             |----|-------|-------------|------------|\n\
+             | 00000100 | body | door status | 0 |\n\
+             | 123 | powertrain | torque request | 1 |\n"
+        );
+
+        registry.release(id);
+        assert!(registry.get(id).is_none());
+    }
+}
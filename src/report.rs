@@ -0,0 +1,272 @@
+use crate::{CanId, Clock, ErrorClass, Frame, SystemClock};
+use embedded_can::Frame as _;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Traffic counters of a session, for a summary at the end of a run
+///
+/// Counts frames per bus and id, error frames per `ErrorClass` and frames
+/// dropped by the application, e.g. as reported by `CaptureStats`. The
+/// session duration is measured with a `Clock`.
+#[derive(Debug, Clone)]
+pub struct SessionStats<C = SystemClock> {
+    clock: C,
+    start: Instant,
+    buses: BTreeMap<String, BusCounters>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct BusCounters {
+    frames: u64,
+    dropped: u64,
+    ids: HashMap<CanId, u64>,
+    errors: HashMap<ErrorClass, u64>,
+}
+
+impl SessionStats {
+    /// Start a session now
+    pub fn new() -> SessionStats {
+        SessionStats {
+            clock: SystemClock,
+            start: Instant::now(),
+            buses: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C: Clock> SessionStats<C> {
+    /// Measure the session with `clock`, starting it anew
+    pub fn with_clock<D: Clock>(self, clock: D) -> SessionStats<D> {
+        SessionStats {
+            start: clock.now(),
+            clock,
+            buses: self.buses,
+        }
+    }
+
+    /// Count a frame seen on `bus`. Error frames are counted by class.
+    pub fn record(&mut self, bus: &str, frame: &Frame) {
+        let counters = self.bus(bus);
+        if frame.is_error() {
+            let class = frame
+                .error()
+                .map_or(ErrorClass::Unknown, |e| ErrorClass::from(&e));
+            *counters.errors.entry(class).or_default() += 1;
+        } else {
+            counters.frames += 1;
+            *counters.ids.entry(CanId::from(frame.id())).or_default() += 1;
+        }
+    }
+
+    /// Count `count` frames dropped on `bus`
+    pub fn record_dropped(&mut self, bus: &str, count: u64) {
+        self.bus(bus).dropped += count;
+    }
+
+    /// Summary of the session so far, listing the `top` busiest ids of each
+    /// bus.
+    pub fn report(&self, top: usize) -> SessionReport {
+        let buses = self
+            .buses
+            .iter()
+            .map(|(name, counters)| {
+                let mut top_talkers: Vec<(CanId, u64)> =
+                    counters.ids.iter().map(|(id, n)| (*id, *n)).collect();
+                top_talkers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                top_talkers.truncate(top);
+
+                let mut errors: Vec<(ErrorClass, u64)> =
+                    counters.errors.iter().map(|(c, n)| (*c, *n)).collect();
+                errors.sort_by(|a, b| {
+                    b.1.cmp(&a.1)
+                        .then_with(|| format!("{:?}", a.0).cmp(&format!("{:?}", b.0)))
+                });
+
+                BusReport {
+                    name: name.clone(),
+                    frames: counters.frames,
+                    dropped: counters.dropped,
+                    top_talkers,
+                    errors,
+                }
+            })
+            .collect();
+
+        SessionReport {
+            duration: self.clock.now().saturating_duration_since(self.start),
+            buses,
+        }
+    }
+
+    fn bus(&mut self, bus: &str) -> &mut BusCounters {
+        if !self.buses.contains_key(bus) {
+            self.buses.insert(bus.to_string(), BusCounters::default());
+        }
+        self.buses.get_mut(bus).unwrap()
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> SessionStats {
+        SessionStats::new()
+    }
+}
+
+/// Occupancy of an in-process frame queue, e.g. of a `Channel`, `Tap` or
+/// `History`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Frames currently queued
+    pub pending: usize,
+    /// Maximum number of queued frames
+    pub capacity: usize,
+    /// Bytes held by the queued frames
+    pub memory: usize,
+    /// Frames dropped because the queue was full
+    pub dropped: u64,
+}
+
+/// Summary created by `SessionStats::report`
+///
+/// Displays as human readable text, `to_json` gives a machine readable
+/// version.
+#[derive(Debug, Clone)]
+pub struct SessionReport {
+    /// Time since the session started
+    pub duration: Duration,
+    /// Statistics of each bus, sorted by name
+    pub buses: Vec<BusReport>,
+}
+
+/// Statistics of a single bus
+#[derive(Debug, Clone)]
+pub struct BusReport {
+    /// Bus name, e.g. the interface name
+    pub name: String,
+    /// Frames seen, without error frames
+    pub frames: u64,
+    /// Frames dropped by the application
+    pub dropped: u64,
+    /// Busiest ids with their frame count, busiest first
+    pub top_talkers: Vec<(CanId, u64)>,
+    /// Error frames per class, most frequent first
+    pub errors: Vec<(ErrorClass, u64)>,
+}
+
+impl SessionReport {
+    /// Report as a JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"duration\":{:.3},\"buses\":[",
+            self.duration.as_secs_f64()
+        );
+        for (n, bus) in self.buses.iter().enumerate() {
+            if n > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"name\":");
+            push_json_string(&mut json, &bus.name);
+            write!(
+                json,
+                ",\"frames\":{},\"dropped\":{},\"top_talkers\":[",
+                bus.frames, bus.dropped
+            )
+            .unwrap();
+            for (n, (id, frames)) in bus.top_talkers.iter().enumerate() {
+                if n > 0 {
+                    json.push(',');
+                }
+                write!(json, "{{\"id\":\"{}\",\"frames\":{}}}", id, frames).unwrap();
+            }
+            json.push_str("],\"errors\":{");
+            for (n, (class, count)) in bus.errors.iter().enumerate() {
+                if n > 0 {
+                    json.push(',');
+                }
+                write!(json, "\"{:?}\":{}", class, count).unwrap();
+            }
+            json.push_str("}}");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl fmt::Display for SessionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "session: {:.3} s", self.duration.as_secs_f64())?;
+        for bus in &self.buses {
+            writeln!(
+                f,
+                "{}: {} frames, {} dropped",
+                bus.name, bus.frames, bus.dropped
+            )?;
+            for (id, frames) in &bus.top_talkers {
+                writeln!(f, "  {}: {} frames", id, frames)?;
+            }
+            for (class, count) in &bus.errors {
+                writeln!(f, "  {:?}: {} errors", class, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_json_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionStats;
+    use crate::{CanId, ErrorClass, Frame, ManualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn test_report() {
+        let clock = ManualClock::new();
+        let mut stats = SessionStats::new().with_clock(clock.clone());
+        for id in [0x100, 0x200, 0x100, 0x300, 0x100, 0x200] {
+            stats.record("can0", &Frame::new(id, &[], false, false).unwrap());
+        }
+        // CAN_ERR_ACK
+        stats.record("can1", &Frame::new(0x20, &[0; 8], false, true).unwrap());
+        stats.record_dropped("can1", 3);
+
+        clock.advance(Duration::from_secs(3));
+
+        let report = stats.report(2);
+        assert_eq!(report.duration, Duration::from_secs(3));
+        assert_eq!(report.buses.len(), 2);
+        let can0 = &report.buses[0];
+        assert_eq!(can0.frames, 6);
+        assert_eq!(
+            can0.top_talkers,
+            [(CanId::Standard(0x100), 3), (CanId::Standard(0x200), 2)]
+        );
+        let can1 = &report.buses[1];
+        assert_eq!(can1.frames, 0);
+        assert_eq!(can1.dropped, 3);
+        assert_eq!(can1.errors, [(ErrorClass::NoAck, 1)]);
+
+        let json = report.to_json();
+        assert!(json.contains(
+            "{\"name\":\"can0\",\"frames\":6,\"dropped\":0,\"top_talkers\":\
+             [{\"id\":\"100\",\"frames\":3},{\"id\":\"200\",\"frames\":2}],\"errors\":{}}"
+        ));
+        assert!(json.contains("\"errors\":{\"NoAck\":1}"));
+        assert!(report.to_string().contains("can1: 0 frames, 3 dropped"));
+    }
+}
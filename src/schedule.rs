@@ -0,0 +1,286 @@
+use crate::{shutdown::POLL_INTERVAL, CanId, Frame, ShutdownToken, Socket, SocketError};
+use embedded_can::{blocking::Can, Frame as _};
+use libc::{
+    c_void, close, itimerspec, read, timerfd_create, timerfd_settime, timespec, CLOCK_REALTIME,
+    TFD_CLOEXEC, TFD_TIMER_ABSTIME,
+};
+use std::{
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Timer firing at absolute wall-clock times
+///
+/// Based on a `CLOCK_REALTIME` timerfd, so nodes with synchronized clocks
+/// (NTP, PTP) fire at the same instant. The file descriptor becomes readable
+/// on expiry and can be polled together with sockets.
+#[derive(Debug)]
+pub struct WallClockTimer {
+    fd: RawFd,
+}
+
+impl WallClockTimer {
+    /// Create a disarmed timer
+    pub fn new() -> io::Result<WallClockTimer> {
+        let fd = unsafe { timerfd_create(CLOCK_REALTIME, TFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(WallClockTimer { fd })
+    }
+
+    /// Arm the timer to fire at `at`. Times in the past fire immediately.
+    pub fn set(&self, at: SystemTime) -> io::Result<()> {
+        // an all zero expiry would disarm the timer
+        let since_epoch = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .max(Duration::from_nanos(1));
+        let spec = itimerspec {
+            it_interval: timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: timespec {
+                tv_sec: since_epoch.as_secs() as _,
+                tv_nsec: since_epoch.subsec_nanos() as _,
+            },
+        };
+
+        let rv =
+            unsafe { timerfd_settime(self.fd, TFD_TIMER_ABSTIME, &spec, std::ptr::null_mut()) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Wait until the armed timer fires
+    pub fn wait(&self) -> io::Result<()> {
+        let mut expirations = 0u64;
+        loop {
+            let rv = unsafe {
+                read(
+                    self.fd,
+                    &mut expirations as *mut u64 as *mut c_void,
+                    std::mem::size_of::<u64>(),
+                )
+            };
+            if rv >= 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Arm the timer to fire at `at` and wait for it
+    pub fn wait_until(&self, at: SystemTime) -> io::Result<()> {
+        self.set(at)?;
+        self.wait()
+    }
+}
+
+impl AsRawFd for WallClockTimer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for WallClockTimer {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+/// Message transmitted by a `PeriodicScheduler`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PeriodicMessage {
+    /// Frame to transmit
+    pub frame: Frame,
+    /// Time between transmissions
+    pub period: Duration,
+}
+
+impl PeriodicMessage {
+    /// Id the message is scheduled by
+    pub fn id(&self) -> CanId {
+        CanId::from(self.frame.id())
+    }
+}
+
+/// Transmits periodic messages at wall-clock times
+///
+/// Every message is sent at the multiples of its period since the Unix
+/// epoch, see `next_boundary`, so nodes with synchronized clocks keep the
+/// same phase. Messages are scheduled by id and can be started, replaced and
+/// stopped while running, without touching the socket or the phase of the
+/// other messages.
+#[derive(Debug)]
+pub struct PeriodicScheduler {
+    timer: WallClockTimer,
+    /// Messages with their next transmission time
+    messages: Vec<(PeriodicMessage, SystemTime)>,
+}
+
+impl PeriodicScheduler {
+    /// Create a scheduler without messages
+    pub fn new() -> io::Result<PeriodicScheduler> {
+        Ok(PeriodicScheduler {
+            timer: WallClockTimer::new()?,
+            messages: Vec::new(),
+        })
+    }
+
+    /// Start transmitting `message`, replacing the message with the same id.
+    ///
+    /// A replaced message with the same period keeps its phase.
+    pub fn start(&mut self, message: PeriodicMessage) {
+        let id = message.id();
+        match self.messages.iter_mut().find(|(m, _)| m.id() == id) {
+            Some((scheduled, _)) if scheduled.period == message.period => *scheduled = message,
+            Some(scheduled) => *scheduled = (message, next_boundary(message.period)),
+            None => {
+                let next = next_boundary(message.period);
+                self.messages.push((message, next));
+            }
+        }
+    }
+
+    /// Stop transmitting the message with the id `id`. Returns whether it
+    /// was scheduled.
+    pub fn stop(&mut self, id: CanId) -> bool {
+        let len = self.messages.len();
+        self.messages.retain(|(m, _)| m.id() != id);
+        self.messages.len() != len
+    }
+
+    /// The scheduled messages, in the order they were started
+    pub fn messages(&self) -> impl Iterator<Item = &PeriodicMessage> {
+        self.messages.iter().map(|(m, _)| m)
+    }
+
+    /// Wait up to `timeout` for the next messages to become due and
+    /// transmit them on `socket`. Returns the number of transmitted
+    /// messages.
+    ///
+    /// Missed transmissions are skipped instead of being sent in a burst.
+    pub fn run_once(
+        &mut self,
+        socket: &mut Socket,
+        timeout: Duration,
+    ) -> Result<usize, SocketError> {
+        let now = SystemTime::now();
+        let next = self.messages.iter().map(|(_, next)| *next).min();
+        let wakeup = match next {
+            Some(next) if next <= now + timeout => next,
+            _ => now + timeout,
+        };
+        self.timer.wait_until(wakeup)?;
+
+        let now = SystemTime::now();
+        let mut transmitted = 0;
+        for (message, next) in self.messages.iter_mut().filter(|(_, next)| *next <= now) {
+            socket.transmit(&message.frame)?;
+            *next = boundary_after(now, message.period);
+            transmitted += 1;
+        }
+        Ok(transmitted)
+    }
+
+    /// Transmit the messages until `token` requests shutdown. Periodic
+    /// messages have nothing to flush, so nothing is reported.
+    pub fn run_until(
+        &mut self,
+        socket: &mut Socket,
+        token: &ShutdownToken,
+    ) -> Result<(), SocketError> {
+        while !token.is_requested() {
+            self.run_once(socket, POLL_INTERVAL)?;
+        }
+        Ok(())
+    }
+}
+
+/// Next wall-clock time which is a multiple of `period`, e.g. the start of
+/// the next second for a period of one second.
+pub fn next_boundary(period: Duration) -> SystemTime {
+    boundary_after(SystemTime::now(), period)
+}
+
+fn boundary_after(now: SystemTime, period: Duration) -> SystemTime {
+    let period = period.as_nanos().max(1);
+    let since_epoch = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let next = (since_epoch / period + 1) * period;
+    UNIX_EPOCH + Duration::new((next / 1_000_000_000) as u64, (next % 1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boundary_after, PeriodicMessage, PeriodicScheduler, WallClockTimer};
+    use crate::{CanId, Frame, ShutdownToken, Socket};
+    use embedded_can::blocking::Can;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_boundary() {
+        let now = UNIX_EPOCH + Duration::from_millis(12_345);
+        assert_eq!(
+            boundary_after(now, Duration::from_secs(1)),
+            UNIX_EPOCH + Duration::from_secs(13)
+        );
+        assert_eq!(
+            boundary_after(now, Duration::from_millis(100)),
+            UNIX_EPOCH + Duration::from_millis(12_400)
+        );
+    }
+
+    #[test]
+    fn test_wait_until() {
+        let timer = WallClockTimer::new().unwrap();
+        let at = SystemTime::now() + Duration::from_millis(20);
+        timer.wait_until(at).unwrap();
+        assert!(SystemTime::now() >= at);
+
+        // deadlines in the past fire right away
+        timer.wait_until(UNIX_EPOCH).unwrap();
+    }
+
+    #[test]
+    fn test_periodic_scheduler() {
+        let (mut socket, mut bus) = Socket::pair().unwrap();
+        let mut scheduler = PeriodicScheduler::new().unwrap();
+        let message = |id, data| PeriodicMessage {
+            frame: Frame::new(id, &[data], false, false).unwrap(),
+            period: Duration::from_millis(10),
+        };
+        scheduler.start(message(0x100, 1));
+        scheduler.start(message(0x200, 2));
+        let phase = scheduler.messages[0].1;
+
+        // replacing the data keeps the phase
+        scheduler.start(message(0x100, 3));
+        assert_eq!(scheduler.messages[0].1, phase);
+        assert!(scheduler.stop(CanId::Standard(0x200)));
+        assert!(!scheduler.stop(CanId::Standard(0x200)));
+
+        let timeout = Duration::from_millis(100);
+        while scheduler.run_once(&mut socket, timeout).unwrap() == 0 {}
+        assert_eq!(bus.receive().unwrap().data(), &[3]);
+        assert!(bus.try_receive().unwrap().is_none());
+        assert_eq!(scheduler.messages().count(), 1);
+
+        let token = ShutdownToken::new();
+        token.shutdown(Duration::ZERO);
+        scheduler.run_until(&mut socket, &token).unwrap();
+        assert!(bus.try_receive().unwrap().is_none());
+    }
+}
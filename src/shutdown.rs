@@ -0,0 +1,230 @@
+use crate::{Clock, SystemClock};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long subsystems watching a `ShutdownToken` block before checking it
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cooperative shutdown of background subsystems
+///
+/// Clones share the same state. Once `shutdown` is called, the subsystems
+/// watching the token stop taking new work, flush what is pending until the
+/// grace period ends and report what they delivered and dropped:
+///
+/// ```no_run
+/// use candev::{Gateway, Route, ShutdownToken, Socket};
+/// use std::time::Duration;
+///
+/// let token = ShutdownToken::new();
+/// let mut gateway = Gateway::new().unwrap();
+/// let a = gateway.add(Socket::new("can0").unwrap()).unwrap();
+/// let b = gateway.add(Socket::new("can1").unwrap()).unwrap();
+/// gateway.route(Route::new(a, b));
+///
+/// let watched = token.clone();
+/// let forwarding = std::thread::spawn(move || gateway.run_until(&watched));
+///
+/// // e.g. on SIGTERM
+/// token.shutdown(Duration::from_secs(1));
+/// forwarding.join().unwrap().unwrap();
+/// for report in token.reports() {
+///     println!("{}", report);
+/// }
+/// ```
+///
+/// Watched by `Gateway::run_until`, `Mux::run_until`,
+/// `Cannelloni::bridge_until` and `CaptureOptions::shutdown`. The file
+/// writers and the `Recorder` flush what they buffered with `finish_on`
+/// (`PcapWriter`, `PcapNgWriter`, `BlfWriter`, `CandumpWriter`) and
+/// `save_on` (`Recorder`). `PeriodicScheduler::run_until` and the CANopen
+/// producers (`stop_on`) have nothing to flush, they just stop and report
+/// nothing.
+#[derive(Clone)]
+pub struct ShutdownToken {
+    clock: Arc<dyn Clock + Send + Sync>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    callbacks: Vec<Box<dyn FnOnce() + Send>>,
+    reports: Vec<ShutdownReport>,
+}
+
+impl ShutdownToken {
+    /// Create a token, shutdown is not requested yet
+    pub fn new() -> ShutdownToken {
+        ShutdownToken {
+            clock: Arc::new(SystemClock),
+            state: Arc::default(),
+        }
+    }
+
+    /// Measure the grace period with `clock`. Clones taken before share the
+    /// state, but keep their clock.
+    pub fn with_clock<C: Clock + Send + Sync + 'static>(self, clock: C) -> ShutdownToken {
+        ShutdownToken {
+            clock: Arc::new(clock),
+            state: self.state,
+        }
+    }
+
+    /// Request shutdown, allowing the subsystems to flush pending data for
+    /// `grace`. Later requests keep the first deadline.
+    pub fn shutdown(&self, grace: Duration) {
+        let callbacks = {
+            let mut state = self.state.lock().unwrap();
+            if state.deadline.is_some() {
+                return;
+            }
+            state.deadline = Some(self.clock.now() + grace);
+            std::mem::take(&mut state.callbacks)
+        };
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Check if shutdown was requested
+    pub fn is_requested(&self) -> bool {
+        self.state.lock().unwrap().deadline.is_some()
+    }
+
+    /// Check if the grace period after a shutdown request ended
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Time left of the grace period, `None` before shutdown is requested
+    pub fn remaining(&self) -> Option<Duration> {
+        let deadline = self.state.lock().unwrap().deadline?;
+        Some(deadline.saturating_duration_since(self.clock.now()))
+    }
+
+    /// Run `callback` once shutdown is requested, or right away if it
+    /// already was
+    pub fn on_shutdown<F: FnOnce() + Send + 'static>(&self, callback: F) {
+        let mut state = self.state.lock().unwrap();
+        if state.deadline.is_some() {
+            drop(state);
+            callback();
+        } else {
+            state.callbacks.push(Box::new(callback));
+        }
+    }
+
+    /// Record the outcome of a subsystem's shutdown
+    pub fn report(&self, report: ShutdownReport) {
+        self.state.lock().unwrap().reports.push(report);
+    }
+
+    /// Outcomes reported so far, in the order the subsystems finished
+    pub fn reports(&self) -> Vec<ShutdownReport> {
+        self.state.lock().unwrap().reports.clone()
+    }
+
+    /// Report `pending` frames as flushed, or as dropped if `rv` failed
+    #[cfg(any(
+        feature = "blf",
+        feature = "candump",
+        feature = "mock",
+        feature = "pcap"
+    ))]
+    pub(crate) fn report_flush<T>(&self, subsystem: &str, pending: u64, rv: &std::io::Result<T>) {
+        let (flushed, dropped) = match rv {
+            Ok(_) => (pending, 0),
+            Err(_) => (0, pending),
+        };
+        self.report(ShutdownReport {
+            subsystem: subsystem.to_string(),
+            flushed,
+            dropped,
+        });
+    }
+}
+
+impl Default for ShutdownToken {
+    fn default() -> ShutdownToken {
+        ShutdownToken::new()
+    }
+}
+
+impl fmt::Debug for ShutdownToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("ShutdownToken")
+            .field("deadline", &state.deadline)
+            .field("callbacks", &state.callbacks.len())
+            .field("reports", &state.reports)
+            .finish()
+    }
+}
+
+/// Outcome of a subsystem's shutdown, see `ShutdownToken`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Name of the subsystem, e.g. `gateway`
+    pub subsystem: String,
+    /// Frames delivered after shutdown was requested
+    pub flushed: u64,
+    /// Frames given up, because the grace period ended or they could not be
+    /// delivered
+    pub dropped: u64,
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} frames flushed, {} dropped",
+            self.subsystem, self.flushed, self.dropped
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShutdownReport, ShutdownToken};
+    use crate::ManualClock;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[test]
+    fn test_shutdown() {
+        let clock = ManualClock::new();
+        let token = ShutdownToken::new().with_clock(clock.clone());
+        let watched = token.clone();
+        let called = Arc::new(AtomicBool::new(false));
+        let flag = called.clone();
+        watched.on_shutdown(move || flag.store(true, Ordering::Relaxed));
+        assert!(!watched.is_requested());
+        assert_eq!(watched.remaining(), None);
+
+        token.shutdown(Duration::from_secs(2));
+        token.shutdown(Duration::from_secs(10));
+        assert!(called.load(Ordering::Relaxed));
+        assert!(watched.is_requested());
+        assert_eq!(watched.remaining(), Some(Duration::from_secs(2)));
+        clock.advance(Duration::from_secs(2));
+        assert!(watched.is_expired());
+
+        watched.report(ShutdownReport {
+            subsystem: "gateway".to_string(),
+            flushed: 3,
+            dropped: 1,
+        });
+        assert_eq!(
+            token.reports()[0].to_string(),
+            "gateway: 3 frames flushed, 1 dropped"
+        );
+    }
+}
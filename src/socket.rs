@@ -1,19 +1,41 @@
-use crate::{Frame, SocketError};
+use crate::{
+    error::MonitorOnly, xl::CANXL_XLF, AnyFrame, FdFrame, Filter, FilterGroup, Frame, SocketError,
+    WallClockTimer, XlAnyFrame, XlFrame,
+};
 use libc::{
-    bind, c_int, c_short, c_uint, c_void, close, fcntl, if_nametoindex, read, setsockopt, sockaddr,
-    socket, socklen_t, suseconds_t, time_t, timeval, write, AF_CAN, CAN_RAW, CAN_RAW_ERR_FILTER,
-    CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, F_GETFL, F_SETFL, O_NONBLOCK,
-    PF_CAN, SOCK_RAW, SOL_CAN_RAW, /*CAN_RAW_FILTER_MAX*/
-    SOL_SOCKET, SO_RCVTIMEO, SO_SNDTIMEO,
+    bind, c_char, c_int, c_short, c_uint, c_void, close, cmsghdr, fcntl, getsockname, getsockopt,
+    if_indextoname, if_nametoindex, iovec, mmsghdr, msghdr, read, recv, recvmmsg, recvmsg,
+    sa_family_t, send, setsockopt, sockaddr, sockaddr_storage, socket, socketpair, socklen_t,
+    suseconds_t, time_t, timespec, timeval, write, AF_CAN, AF_UNIX, CAN_ERR_MASK, CAN_RAW,
+    CAN_RAW_ERR_FILTER, CAN_RAW_FD_FRAMES, CAN_RAW_FILTER, CAN_RAW_FILTER_MAX,
+    CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, CMSG_DATA, CMSG_FIRSTHDR,
+    CMSG_NXTHDR, ENOBUFS, F_GETFL, F_SETFL, IF_NAMESIZE, MSG_CONFIRM, MSG_DONTROUTE, MSG_DONTWAIT,
+    O_NONBLOCK, PF_CAN, SCM_TIMESTAMPING, SOCK_CLOEXEC, SOCK_RAW, SOCK_SEQPACKET,
+    SOF_TIMESTAMPING_RAW_HARDWARE, SOF_TIMESTAMPING_RX_HARDWARE, SOF_TIMESTAMPING_RX_SOFTWARE,
+    SOF_TIMESTAMPING_SOFTWARE, SOL_CAN_RAW, SOL_SOCKET, SO_RCVBUF, SO_RCVTIMEO, SO_SNDTIMEO,
+    SO_TIMESTAMPING,
 };
 use std::{
-    ffi::CString,
+    collections::VecDeque,
+    ffi::{CStr, CString},
     io,
     // iter::{once, Once},
     mem::size_of,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
     time,
 };
 
+/// `SO_RXQ_OVFL` from `asm-generic/socket.h`
+const SO_RXQ_OVFL: c_int = 40;
+
+/// `CAN_RAW_XL_FRAMES` from `linux/can/raw.h`
+const CAN_RAW_XL_FRAMES: c_int = 7;
+
 #[derive(Debug)]
 #[repr(C)]
 struct CanAddr {
@@ -23,6 +45,138 @@ struct CanAddr {
     tx_id: u32,
 }
 
+/// Frame and metadata read by `Socket::recv_msg` or `Socket::recv_batch`
+#[derive(Debug, Copy, Clone)]
+struct ReceivedMsg {
+    frame: Frame,
+    timestamp: Option<Timestamp>,
+    if_index: c_uint,
+    flags: c_int,
+}
+
+/// Strategy used by `receive` to wait for frames
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReceiveStrategy {
+    /// Block in the kernel until a frame arrives (default)
+    Blocking,
+
+    /// Spin on non-blocking reads for up to the given duration before
+    /// falling back to a blocking read.
+    ///
+    /// Avoids the wake-up latency of blocking reads at the cost of keeping
+    /// a CPU core busy. Intended for latency critical loops running on a
+    /// dedicated core.
+    BusyPoll(time::Duration),
+
+    /// Choose between the strategies based on the observed arrival rate.
+    ///
+    /// Idle buses are read blocking. Above 1000 frames/s, non-blocking reads
+    /// spin for up to two mean frame intervals before blocking, and above
+    /// 10000 frames/s frames are fetched in batches using `recvmmsg`.
+    /// Batches are not used while drop monitoring is enabled. Frames of a
+    /// batch are returned first by every receive method, together with
+    /// their metadata.
+    Adaptive,
+}
+
+/// Mean frame interval below which `ReceiveStrategy::Adaptive` busy polls
+const ADAPTIVE_SPIN_INTERVAL: f64 = 1e-3;
+
+/// Mean frame interval below which `ReceiveStrategy::Adaptive` fetches
+/// frames in batches
+const ADAPTIVE_BATCH_INTERVAL: f64 = 1e-4;
+
+/// Frames fetched by a single batched read of `ReceiveStrategy::Adaptive`
+const ADAPTIVE_BATCH_SIZE: usize = 32;
+
+/// Arrival rate estimate and batched frames of `ReceiveStrategy::Adaptive`
+#[derive(Debug)]
+struct Adaptive {
+    /// Moving average of the frame interval in seconds
+    interval: f64,
+    last: Option<time::Instant>,
+    batch: VecDeque<ReceivedMsg>,
+}
+
+impl Adaptive {
+    fn new() -> Adaptive {
+        Adaptive {
+            interval: f64::INFINITY,
+            last: None,
+            batch: VecDeque::new(),
+        }
+    }
+
+    /// Account for `frames` received just now
+    fn record(&mut self, frames: usize) {
+        let now = time::Instant::now();
+        if let Some(last) = self.last {
+            let interval = now.duration_since(last).as_secs_f64() / frames as f64;
+            self.interval = if self.interval.is_finite() {
+                self.interval + (interval - self.interval) / 8.0
+            } else {
+                interval
+            };
+        }
+        self.last = Some(now);
+    }
+}
+
+/// Source of receive timestamps, see `Socket::set_timestamping`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimestampMode {
+    /// No timestamps (default)
+    Disabled,
+
+    /// Kernel timestamps taken when the frame is received by the network
+    /// stack
+    Software,
+
+    /// Timestamps taken by the CAN controller. Falls back to kernel
+    /// timestamps for frames the driver does not stamp.
+    Hardware,
+}
+
+/// Receive timestamp
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Kernel timestamp, relative to the Unix epoch
+    Software(time::Duration),
+
+    /// Raw timestamp of the CAN controller clock
+    Hardware(time::Duration),
+}
+
+/// Outcome of a frame transmitted by `Socket::transmit_all`
+#[derive(Debug)]
+pub enum TransmitOutcome {
+    /// The frame was queued for transmission
+    Sent,
+
+    /// The frame was rejected with the contained error
+    Dropped(io::Error),
+
+    /// The transmit queue stayed full until the timeout expired
+    Expired,
+}
+
+/// Interval in which transmissions blocked by `ENOBUFS` are retried
+pub(crate) const BACKPRESSURE_RETRY: time::Duration = time::Duration::from_micros(200);
+
+/// Origin of a received frame, see `Socket::receive_tagged`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Received from the bus
+    Rx,
+
+    /// Echo of a frame transmitted by this socket
+    TxEcho,
+
+    /// Frame transmitted by another socket on this host, e.g. a gateway or
+    /// another application
+    Injected,
+}
+
 /// A socket for a CAN device.
 ///
 /// Will be closed upon deallocation. To close manually, use std::drop::Drop.
@@ -30,23 +184,32 @@ struct CanAddr {
 #[derive(Debug)]
 pub struct Socket {
     fd: c_int,
-    // filter_group: FilterGroup,
+    strategy: ReceiveStrategy,
+    monitor_drops: AtomicBool,
+    dropped: AtomicU32,
+    adaptive: Mutex<Adaptive>,
+    monitor_only: AtomicBool,
 }
 
 impl Socket {
     /// Open a named CAN device.
     pub fn new(ifname: &str) -> Result<Socket, SocketError> {
-        let ifname = CString::new(ifname).unwrap();
+        let ifname = CString::new(ifname).map_err(|_| SocketError::NoSuchDevice)?;
         let ifindex = unsafe { if_nametoindex(ifname.as_ptr()) };
         if ifindex == 0 {
-            return Err(SocketError::IOError(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid interface",
-            )));
+            return Err(SocketError::NoSuchDevice);
         }
         Socket::open_if(ifindex)
     }
 
+    /// Open a socket receiving from all CAN interfaces, like `candump any`.
+    ///
+    /// Use `receive_from` to learn which interface a frame arrived on.
+    /// Transmitting requires a bound interface and fails on such a socket.
+    pub fn open_any() -> Result<Socket, SocketError> {
+        Socket::open_if(0)
+    }
+
     /// Open CAN device by interface number.
     ///
     /// Opens a CAN device by kernel interface number.
@@ -88,10 +251,33 @@ impl Socket {
             return Err(SocketError::from(e));
         }
 
-        Ok(Socket {
-            fd: sock_fd,
-            // filter_group: FilterGroup::new(sock_fd),
-        })
+        Ok(Socket::from_fd(sock_fd))
+    }
+
+    /// Create two connected sockets, e.g. for tests without a CAN device.
+    ///
+    /// Frames transmitted on one socket are received on the other. The pair
+    /// is backed by a local `SOCK_SEQPACKET` socket pair instead of a CAN
+    /// interface, so CAN specific options like filters fail with an error.
+    pub fn pair() -> io::Result<(Socket, Socket)> {
+        let mut fds = [0; 2];
+        let rv = unsafe { socketpair(AF_UNIX, SOCK_SEQPACKET | SOCK_CLOEXEC, 0, fds.as_mut_ptr()) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok((Socket::from_fd(fds[0]), Socket::from_fd(fds[1])))
+    }
+
+    fn from_fd(fd: c_int) -> Socket {
+        Socket {
+            fd,
+            strategy: ReceiveStrategy::Blocking,
+            monitor_drops: AtomicBool::new(false),
+            dropped: AtomicU32::new(0),
+            adaptive: Mutex::new(Adaptive::new()),
+            monitor_only: AtomicBool::new(false),
+        }
     }
 
     fn close(&mut self) -> io::Result<()> {
@@ -104,6 +290,78 @@ impl Socket {
         Ok(())
     }
 
+    /// Index of the interface the socket is bound to, 0 for all interfaces
+    pub fn interface_index(&self) -> io::Result<c_uint> {
+        let mut addr: sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = size_of::<sockaddr_storage>() as socklen_t;
+        let rv = unsafe {
+            getsockname(
+                self.fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut len,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if addr.ss_family != AF_CAN as sa_family_t {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a CAN socket",
+            ));
+        }
+        let addr =
+            unsafe { ptr::read_unaligned(&addr as *const sockaddr_storage as *const CanAddr) };
+        Ok(addr.if_index as c_uint)
+    }
+
+    /// Name of the interface the socket is bound to, e.g. `can0`
+    pub fn interface_name(&self) -> io::Result<String> {
+        let if_index = self.interface_index()?;
+        if if_index == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Socket is bound to all interfaces",
+            ));
+        }
+        Socket::interface_name_of(if_index)
+    }
+
+    /// Name of the interface with index `if_index`, e.g. to resolve the
+    /// interfaces reported by `receive_from`
+    pub fn interface_name_of(if_index: c_uint) -> io::Result<String> {
+        let mut name = [0 as c_char; IF_NAMESIZE];
+        if unsafe { if_indextoname(if_index, name.as_mut_ptr()) }.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Refuse all further transmissions on this socket with
+    /// `SocketError::MonitorOnly`.
+    ///
+    /// Intended for diagnostic tools which must never write to the bus they
+    /// observe. Can not be undone. See `into_monitor_only` for a socket
+    /// which can not even express a transmission.
+    pub fn set_monitor_only(&self) {
+        self.monitor_only.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the socket refuses to transmit
+    pub fn is_monitor_only(&self) -> bool {
+        self.monitor_only.load(Ordering::Relaxed)
+    }
+
+    /// Turn the socket into a receive-only socket, refusing transmissions
+    /// also through `RxSocket::get_ref`.
+    pub fn into_monitor_only(self) -> RxSocket {
+        self.set_monitor_only();
+        RxSocket {
+            socket: Arc::new(self),
+        }
+    }
+
     /// Change socket to non-blocking mode
     pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
         // retrieve current flags
@@ -135,11 +393,70 @@ impl Socket {
         self.set_socket_option(self.fd, SOL_SOCKET, SO_RCVTIMEO, &c_timeval_new(duration))
     }
 
+    /// Read timeout of the socket, `None` if reads block indefinitely
+    pub fn read_timeout(&self) -> io::Result<Option<time::Duration>> {
+        let mut tv = c_timeval_new(time::Duration::ZERO);
+        let mut len = size_of::<timeval>() as socklen_t;
+        let rv = unsafe {
+            getsockopt(
+                self.fd,
+                SOL_SOCKET,
+                SO_RCVTIMEO,
+                &mut tv as *mut timeval as *mut c_void,
+                &mut len,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let timeout = time::Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+        Ok(Some(timeout).filter(|timeout| !timeout.is_zero()))
+    }
+
     /// Sets the write timeout on the socket
     pub fn set_write_timeout(&self, duration: time::Duration) -> io::Result<()> {
         self.set_socket_option(self.fd, SOL_SOCKET, SO_SNDTIMEO, &c_timeval_new(duration))
     }
 
+    /// Sets the size of the kernel receive buffer (`SO_RCVBUF`) in bytes.
+    ///
+    /// A larger buffer queues more frames while the application is busy,
+    /// instead of dropping them. The kernel doubles the value for its
+    /// bookkeeping and limits it to `net.core.rmem_max`; use
+    /// `recv_buffer_size` to read back the effective size.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        let size = c_int::try_from(size).unwrap_or(c_int::MAX);
+        self.set_socket_option(self.fd, SOL_SOCKET, SO_RCVBUF, &size)
+    }
+
+    /// Size of the kernel receive buffer in bytes, as used by the kernel
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        let mut size: c_int = 0;
+        let mut len = size_of::<c_int>() as socklen_t;
+        let rv = unsafe {
+            getsockopt(
+                self.fd,
+                SOL_SOCKET,
+                SO_RCVBUF,
+                &mut size as *mut c_int as *mut c_void,
+                &mut len,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size as usize)
+    }
+
+    /// Sets the strategy used by `receive` to wait for frames.
+    ///
+    /// Read timeouts and non-blocking mode apply to the blocking read only,
+    /// so a busy polling `receive` can take up to the spin duration longer
+    /// than the read timeout.
+    pub fn set_receive_strategy(&mut self, strategy: ReceiveStrategy) {
+        self.strategy = strategy;
+    }
+
     /// Sets the error mask on the socket.
     ///
     /// By default (`ERR_MASK_NONE`) no error conditions are reported as
@@ -150,6 +467,16 @@ impl Socket {
         self.set_socket_option(self.fd, SOL_CAN_RAW, CAN_RAW_ERR_FILTER, &mask)
     }
 
+    /// Receive only error frames, e.g. for a diagnostics task.
+    ///
+    /// Installs an empty filter set, which drops all data and remote frames,
+    /// and enables all error conditions. Undo with `clear_filters` and
+    /// `set_error_mask(0)`.
+    pub fn subscribe_errors_only(&self) -> io::Result<()> {
+        self.set_filters(&[])?;
+        self.set_error_mask(CAN_ERR_MASK)
+    }
+
     /// Enable or disable loopback.
     ///
     /// By default, loopback is enabled, causing other applications that open
@@ -169,6 +496,106 @@ impl Socket {
         self.set_socket_option(self.fd, SOL_CAN_RAW, CAN_RAW_RECV_OWN_MSGS, &recv_own_msgs)
     }
 
+    /// Sets the filters on the socket.
+    ///
+    /// Only frames matching at least one of the filters are received (or all
+    /// of them, if join filters are enabled). An empty slice disables the
+    /// reception of data frames entirely. At most `CAN_RAW_FILTER_MAX` (512)
+    /// filters can be set.
+    pub fn set_filters(&self, filters: &[Filter]) -> io::Result<()> {
+        if filters.len() > CAN_RAW_FILTER_MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many filters",
+            ));
+        }
+
+        let rv = unsafe {
+            setsockopt(
+                self.fd,
+                SOL_CAN_RAW,
+                CAN_RAW_FILTER,
+                filters.as_ptr() as *const c_void,
+                std::mem::size_of_val(filters) as socklen_t,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Removes all filters, so that all frames are received again.
+    ///
+    /// This is the default state of a newly opened socket.
+    pub fn clear_filters(&self) -> io::Result<()> {
+        self.set_filters(&[Filter::accept_all()])
+    }
+
+    /// Sets the filters of a `FilterGroup` together with its join setting.
+    ///
+    /// These are two socket options, set one after the other. They are
+    /// ordered so that the socket accepts at least the frames of the
+    /// previous or of the new group in between, so no frame accepted by
+    /// both is dropped while the filters are replaced.
+    pub fn set_filter_group(&self, group: &FilterGroup) -> io::Result<()> {
+        group.install(
+            |join| self.set_join_filters(join),
+            |filters| self.set_filters(filters),
+        )
+    }
+
+    /// Filters currently installed on the socket.
+    ///
+    /// A newly opened socket reports a single filter accepting all frames.
+    pub fn filters(&self) -> io::Result<Vec<Filter>> {
+        let mut filters = vec![Filter::accept_all(); CAN_RAW_FILTER_MAX as usize];
+        let mut len = std::mem::size_of_val(filters.as_slice()) as socklen_t;
+        let rv = unsafe {
+            getsockopt(
+                self.fd,
+                SOL_CAN_RAW,
+                CAN_RAW_FILTER,
+                filters.as_mut_ptr() as *mut c_void,
+                &mut len,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        filters.truncate(len as usize / size_of::<Filter>());
+        Ok(filters)
+    }
+
+    /// Whether join filters are enabled, see `set_join_filters`
+    pub fn join_filters(&self) -> io::Result<bool> {
+        let mut join: c_int = 0;
+        let mut len = size_of::<c_int>() as socklen_t;
+        let rv = unsafe {
+            getsockopt(
+                self.fd,
+                SOL_CAN_RAW,
+                CAN_RAW_JOIN_FILTERS,
+                &mut join as *mut c_int as *mut c_void,
+                &mut len,
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(join != 0)
+    }
+
+    /// Currently installed filters together with the join setting, e.g. to
+    /// restore them later using `set_filter_group`.
+    pub fn filter_group(&self) -> io::Result<FilterGroup> {
+        let group = self
+            .filters()?
+            .into_iter()
+            .fold(FilterGroup::new(), FilterGroup::filter);
+        Ok(group.join(self.join_filters()?))
+    }
+
     /// Enable or disable join filters.
     ///
     /// By default a frame is accepted if it matches any of the filters set
@@ -179,7 +606,484 @@ impl Socket {
         self.set_socket_option(self.fd, SOL_CAN_RAW, CAN_RAW_JOIN_FILTERS, &join_filters)
     }
 
-    fn set_socket_option<T>(
+    /// Enable or disable CAN FD frames.
+    ///
+    /// By default only classic frames can be sent and received. Once enabled,
+    /// `transmit_fd` can be used and `receive_any` returns both classic and
+    /// CAN FD frames. Fails if the interface does not support CAN FD.
+    pub fn set_fd_frames(&self, enabled: bool) -> io::Result<()> {
+        let fd_frames: c_int = if enabled { 1 } else { 0 };
+        self.set_socket_option(self.fd, SOL_CAN_RAW, CAN_RAW_FD_FRAMES, &fd_frames)
+    }
+
+    /// Enable or disable CAN XL frames.
+    ///
+    /// Once enabled, `transmit_xl` can be used and `receive_xl` returns
+    /// classic, CAN FD and CAN XL frames. Fails if the kernel or the
+    /// interface does not support CAN XL.
+    pub fn set_xl_frames(&self, enabled: bool) -> io::Result<()> {
+        let xl_frames: c_int = if enabled { 1 } else { 0 };
+        self.set_socket_option(self.fd, SOL_CAN_RAW, CAN_RAW_XL_FRAMES, &xl_frames)
+    }
+
+    /// Enable or disable reporting of dropped frames (`SO_RXQ_OVFL`).
+    ///
+    /// Once enabled, every received frame carries the number of frames the
+    /// kernel dropped on this socket because its receive queue was full,
+    /// which is available from `dropped`.
+    pub fn set_drop_monitoring(&self, enabled: bool) -> io::Result<()> {
+        let rxq_ovfl: c_int = if enabled { 1 } else { 0 };
+        self.set_socket_option(self.fd, SOL_SOCKET, SO_RXQ_OVFL, &rxq_ovfl)?;
+        self.monitor_drops.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Number of frames dropped by the kernel since the socket was opened,
+    /// as reported with the last received frame.
+    ///
+    /// Stays at zero unless enabled using `set_drop_monitoring`. Compare
+    /// against a previous value to detect receive overruns.
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Select the source of the timestamps returned by `receive_timestamped`.
+    pub fn set_timestamping(&self, mode: TimestampMode) -> io::Result<()> {
+        let flags: c_uint = match mode {
+            TimestampMode::Disabled => 0,
+            TimestampMode::Software => SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE,
+            TimestampMode::Hardware => {
+                SOF_TIMESTAMPING_RX_HARDWARE
+                    | SOF_TIMESTAMPING_RAW_HARDWARE
+                    | SOF_TIMESTAMPING_RX_SOFTWARE
+                    | SOF_TIMESTAMPING_SOFTWARE
+            }
+        };
+        self.set_socket_option(self.fd, SOL_SOCKET, SO_TIMESTAMPING, &flags)
+    }
+
+    /// Receive a classic frame if one is pending, without blocking.
+    ///
+    /// Uses `MSG_DONTWAIT`, so the socket can stay in blocking mode. Frames
+    /// already fetched by `ReceiveStrategy::Adaptive` are returned first.
+    pub fn try_receive(&mut self) -> Result<Option<Frame>, SocketError> {
+        match self.recv_one(MSG_DONTWAIT) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(self.error(e)),
+        }
+    }
+
+    /// Receive a classic frame together with its timestamp.
+    ///
+    /// The timestamp is `None` unless enabled using `set_timestamping`.
+    /// Hardware timestamps are preferred over kernel timestamps.
+    pub fn receive_timestamped(&mut self) -> Result<(Frame, Option<Timestamp>), SocketError> {
+        let msg = self.recv_msg(0).map_err(|e| self.error(e))?;
+        Ok((msg.frame, msg.timestamp))
+    }
+
+    /// Receive a classic frame together with the index of the interface it
+    /// arrived on.
+    ///
+    /// Mostly useful on sockets opened with `open_any`, to monitor several
+    /// buses with a single socket. Use `interface_name_of` to resolve the
+    /// index.
+    pub fn receive_from(&mut self) -> Result<(Frame, c_uint), SocketError> {
+        let msg = self.recv_msg(0).map_err(|e| self.error(e))?;
+        Ok((msg.frame, msg.if_index))
+    }
+
+    /// Receive a classic frame together with its origin.
+    ///
+    /// Echoes of frames sent by this socket are only received if enabled
+    /// using `set_recv_own_msgs`, frames sent by other local sockets only if
+    /// loopback is enabled (default).
+    pub fn receive_tagged(&mut self) -> Result<(Frame, Direction), SocketError> {
+        let msg = self.recv_msg(0).map_err(|e| self.error(e))?;
+        let direction = if msg.flags & MSG_CONFIRM != 0 {
+            Direction::TxEcho
+        } else if msg.flags & MSG_DONTROUTE != 0 {
+            Direction::Injected
+        } else {
+            Direction::Rx
+        };
+        Ok((msg.frame, direction))
+    }
+
+    /// Receive a single classic frame using `recvmsg`, together with its
+    /// timestamp, source interface and the message flags. Frames left from
+    /// a batch are returned first.
+    fn recv_msg(&self, flags: c_int) -> io::Result<ReceivedMsg> {
+        if let Some(msg) = self.batched() {
+            return Ok(msg);
+        }
+        let mut frame = Frame::default();
+        let mut addr: sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut control = [0u64; 16];
+        let mut iov = iovec {
+            iov_base: &mut frame as *mut Frame as *mut c_void,
+            iov_len: size_of::<Frame>(),
+        };
+        let mut hdr: msghdr = unsafe { std::mem::zeroed() };
+        hdr.msg_name = &mut addr as *mut sockaddr_storage as *mut c_void;
+        hdr.msg_namelen = size_of::<sockaddr_storage>() as socklen_t;
+        hdr.msg_iov = &mut iov;
+        hdr.msg_iovlen = 1;
+        hdr.msg_control = control.as_mut_ptr() as *mut c_void;
+        hdr.msg_controllen = size_of::<[u64; 16]>() as _;
+
+        let nbytes = unsafe { recvmsg(self.fd, &mut hdr, flags) };
+        let frame = classic_frame(nbytes, frame)?;
+        if let Some(dropped) = dropped(&hdr) {
+            self.dropped.store(dropped, Ordering::Relaxed);
+        }
+        Ok(received_msg(frame, &hdr, &addr))
+    }
+
+    /// Take the next frame left from a batch of `ReceiveStrategy::Adaptive`
+    fn batched(&self) -> Option<ReceivedMsg> {
+        self.adaptive.lock().unwrap().batch.pop_front()
+    }
+
+    /// Transmit a frame without blocking, using `MSG_DONTWAIT`.
+    ///
+    /// Fails with `SocketError::TxQueueFull` instead of waiting if the
+    /// frame can not be queued, so applications can implement their own
+    /// backpressure while the socket stays in blocking mode.
+    pub fn transmit_nonblocking(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        self.send_frame(frame, MSG_DONTWAIT)
+            .map_err(|e| match SocketError::from(e) {
+                SocketError::WouldBlock => SocketError::TxQueueFull,
+                e => e,
+            })
+    }
+
+    /// Transmit a frame at the wall-clock time `at`, blocking until then.
+    ///
+    /// Use `next_boundary` to align transmissions, e.g. to the start of a
+    /// second. Keep a `WallClockTimer` around instead for repeated
+    /// transmissions.
+    pub fn transmit_at(&mut self, frame: &Frame, at: time::SystemTime) -> Result<(), SocketError> {
+        WallClockTimer::new()?.wait_until(at)?;
+        self.send_frame(frame, 0).map_err(|e| self.error(e))
+    }
+
+    /// Transmit a burst of frames, waiting out transmit queue backpressure.
+    ///
+    /// When the device queue is full (`ENOBUFS`), transmission of a frame is
+    /// retried for up to `timeout` before it is given up as expired. Returns
+    /// the outcome of every frame, in order.
+    pub fn transmit_all(
+        &mut self,
+        frames: &[Frame],
+        timeout: time::Duration,
+    ) -> Vec<TransmitOutcome> {
+        frames
+            .iter()
+            .map(|frame| {
+                let start = time::Instant::now();
+                loop {
+                    match self.send_frame(frame, MSG_DONTWAIT) {
+                        Ok(()) => return TransmitOutcome::Sent,
+                        Err(e)
+                            if e.raw_os_error() == Some(ENOBUFS)
+                                || e.kind() == io::ErrorKind::WouldBlock =>
+                        {
+                            let elapsed = start.elapsed();
+                            if elapsed >= timeout {
+                                return TransmitOutcome::Expired;
+                            }
+                            std::thread::sleep((timeout - elapsed).min(BACKPRESSURE_RETRY));
+                        }
+                        Err(e) => return TransmitOutcome::Dropped(e),
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Transmit a CAN FD frame.
+    ///
+    /// Requires CAN FD frames to be enabled using `set_fd_frames`.
+    pub fn transmit_fd(&mut self, frame: &FdFrame) -> Result<(), SocketError> {
+        if self.is_monitor_only() {
+            return Err(SocketError::MonitorOnly);
+        }
+        let write_rv = unsafe {
+            let frame_ptr = frame as *const FdFrame;
+            write(self.fd, frame_ptr as *const c_void, size_of::<FdFrame>())
+        };
+
+        if write_rv as usize != size_of::<FdFrame>() {
+            return Err(self.error(io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Receive a classic or CAN FD frame.
+    ///
+    /// CAN FD frames are only received if enabled using `set_fd_frames`.
+    pub fn receive_any(&mut self) -> Result<AnyFrame, SocketError> {
+        if let Some(msg) = self.batched() {
+            return Ok(AnyFrame::Classic(msg.frame));
+        }
+        let mut frame = FdFrame::default();
+        let nbytes = unsafe {
+            let frame_ptr = &mut frame as *mut FdFrame;
+            read(self.fd, frame_ptr as *mut c_void, size_of::<FdFrame>())
+        };
+        if nbytes < 0 {
+            return Err(self.error(io::Error::last_os_error()));
+        }
+
+        match nbytes as usize {
+            n if n == size_of::<FdFrame>() => Ok(AnyFrame::Fd(frame)),
+            n if n == size_of::<Frame>() => {
+                let classic = unsafe { *(&frame as *const FdFrame as *const Frame) };
+                Ok(AnyFrame::Classic(classic))
+            }
+            _ => Err(SocketError::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected frame size",
+            ))),
+        }
+    }
+
+    /// Transmit a CAN XL frame.
+    ///
+    /// Requires CAN XL frames to be enabled using `set_xl_frames`.
+    pub fn transmit_xl(&mut self, frame: &XlFrame) -> Result<(), SocketError> {
+        if self.is_monitor_only() {
+            return Err(SocketError::MonitorOnly);
+        }
+        let mtu = frame.mtu();
+        let write_rv = unsafe { write(self.fd, frame as *const XlFrame as *const c_void, mtu) };
+
+        if write_rv as usize != mtu {
+            return Err(self.error(io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Receive a classic, CAN FD or CAN XL frame.
+    ///
+    /// CAN XL frames are only received if enabled using `set_xl_frames`.
+    pub fn receive_xl(&mut self) -> Result<XlAnyFrame, SocketError> {
+        if let Some(msg) = self.batched() {
+            return Ok(XlAnyFrame::Classic(msg.frame));
+        }
+        let mut frame = Box::<XlFrame>::default();
+        let nbytes = unsafe {
+            let frame_ptr = &mut *frame as *mut XlFrame;
+            read(self.fd, frame_ptr as *mut c_void, size_of::<XlFrame>())
+        };
+        if nbytes < 0 {
+            return Err(self.error(io::Error::last_os_error()));
+        }
+
+        // the CAN XL flags overlap the length of classic and CAN FD frames
+        let raw = &*frame as *const XlFrame as *const u8;
+        let xl = unsafe { *raw.add(4) } & CANXL_XLF != 0;
+        match nbytes as usize {
+            // the payload announced in the header has to be read completely
+            n if xl && frame.is_complete(n) => Ok(XlAnyFrame::Xl(frame)),
+            n if !xl && n == size_of::<FdFrame>() => {
+                Ok(XlAnyFrame::Fd(unsafe { ptr::read(raw as *const FdFrame) }))
+            }
+            n if !xl && n == size_of::<Frame>() => Ok(XlAnyFrame::Classic(unsafe {
+                ptr::read(raw as *const Frame)
+            })),
+            _ => Err(SocketError::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected frame size",
+            ))),
+        }
+    }
+
+    /// Split the socket into owned receive and transmit halves, which can be
+    /// moved to different threads.
+    ///
+    /// The socket is closed once both halves are dropped.
+    pub fn split(self) -> (RxSocket, TxSocket) {
+        let socket = Arc::new(self);
+        (
+            RxSocket {
+                socket: socket.clone(),
+            },
+            TxSocket { socket },
+        )
+    }
+
+    /// Map an error of a socket operation. `EAGAIN` on a blocking socket
+    /// means a read or write timeout expired.
+    pub(crate) fn error(&self, e: io::Error) -> SocketError {
+        match SocketError::from(e) {
+            SocketError::WouldBlock if !self.is_nonblocking() => SocketError::Timeout,
+            e => e,
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        let flags = unsafe { fcntl(self.fd, F_GETFL) };
+        flags != -1 && flags & O_NONBLOCK != 0
+    }
+
+    /// Receive a classic frame according to the receive strategy.
+    pub(crate) fn receive_frame(&self) -> io::Result<Frame> {
+        match self.strategy {
+            ReceiveStrategy::Blocking => self.recv_one(0),
+            ReceiveStrategy::BusyPoll(spin) => match self.spin(spin) {
+                Some(rv) => rv,
+                None => self.recv_one(0),
+            },
+            ReceiveStrategy::Adaptive => self.receive_adaptive(),
+        }
+    }
+
+    /// Spin on non-blocking reads for up to `spin`. Returns `None` if no
+    /// frame arrived.
+    fn spin(&self, spin: time::Duration) -> Option<io::Result<Frame>> {
+        let start = time::Instant::now();
+        loop {
+            match self.recv_one(MSG_DONTWAIT) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= spin {
+                        return None;
+                    }
+                    std::hint::spin_loop();
+                }
+                rv => return Some(rv),
+            }
+        }
+    }
+
+    fn receive_adaptive(&self) -> io::Result<Frame> {
+        let mut adaptive = self.adaptive.lock().unwrap();
+        if let Some(msg) = adaptive.batch.pop_front() {
+            return Ok(msg.frame);
+        }
+        let interval = adaptive.interval;
+
+        if interval < ADAPTIVE_BATCH_INTERVAL && !self.monitor_drops.load(Ordering::Relaxed) {
+            match self.recv_batch(&mut adaptive.batch) {
+                Ok(0) => {}
+                Ok(n) => {
+                    adaptive.record(n);
+                    return Ok(adaptive.batch.pop_front().unwrap().frame);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        drop(adaptive);
+
+        let spun = if interval < ADAPTIVE_SPIN_INTERVAL {
+            let spin = time::Duration::from_secs_f64(2.0 * interval);
+            self.spin(spin)
+        } else {
+            None
+        };
+        let frame = match spun {
+            Some(rv) => rv?,
+            None => self.recv_one(0)?,
+        };
+        self.adaptive.lock().unwrap().record(1);
+        Ok(frame)
+    }
+
+    /// Fetch the frames already queued on the socket using `recvmmsg`,
+    /// without blocking, and append them with their metadata to `batch`.
+    /// Returns the number of frames appended.
+    fn recv_batch(&self, batch: &mut VecDeque<ReceivedMsg>) -> io::Result<usize> {
+        let mut frames = [Frame::default(); ADAPTIVE_BATCH_SIZE];
+        let mut addrs: [sockaddr_storage; ADAPTIVE_BATCH_SIZE] = unsafe { std::mem::zeroed() };
+        let mut controls = [[0u64; 16]; ADAPTIVE_BATCH_SIZE];
+        let mut iovecs: [iovec; ADAPTIVE_BATCH_SIZE] = unsafe { std::mem::zeroed() };
+        let mut headers: [mmsghdr; ADAPTIVE_BATCH_SIZE] = unsafe { std::mem::zeroed() };
+        for (i, hdr) in headers.iter_mut().enumerate() {
+            iovecs[i].iov_base = &mut frames[i] as *mut Frame as *mut c_void;
+            iovecs[i].iov_len = size_of::<Frame>();
+            hdr.msg_hdr.msg_name = &mut addrs[i] as *mut sockaddr_storage as *mut c_void;
+            hdr.msg_hdr.msg_namelen = size_of::<sockaddr_storage>() as socklen_t;
+            hdr.msg_hdr.msg_iov = &mut iovecs[i];
+            hdr.msg_hdr.msg_iovlen = 1;
+            hdr.msg_hdr.msg_control = controls[i].as_mut_ptr() as *mut c_void;
+            hdr.msg_hdr.msg_controllen = size_of::<[u64; 16]>() as _;
+        }
+
+        let count = unsafe {
+            recvmmsg(
+                self.fd,
+                headers.as_mut_ptr(),
+                ADAPTIVE_BATCH_SIZE as c_uint,
+                MSG_DONTWAIT,
+                ptr::null_mut(),
+            )
+        };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // skip anything but complete classic frames
+        let mut n = 0;
+        for i in 0..count as usize {
+            if headers[i].msg_len as usize == size_of::<Frame>() && frames[i].is_classic() {
+                batch.push_back(received_msg(frames[i], &headers[i].msg_hdr, &addrs[i]));
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Receive a single classic frame, using `recvmsg` only if the drop
+    /// counter has to be read.
+    pub(crate) fn recv_one(&self, flags: c_int) -> io::Result<Frame> {
+        if let Some(msg) = self.batched() {
+            return Ok(msg.frame);
+        }
+        if self.monitor_drops.load(Ordering::Relaxed) {
+            Ok(self.recv_msg(flags)?.frame)
+        } else {
+            self.recv_frame(flags)
+        }
+    }
+
+    /// Send a single classic frame, passing `flags` to `send`.
+    pub(crate) fn send_frame(&self, frame: &Frame, flags: c_int) -> io::Result<()> {
+        if self.is_monitor_only() {
+            return Err(MonitorOnly::error());
+        }
+        let nbytes = unsafe {
+            let frame_ptr = frame as *const Frame;
+            send(
+                self.fd,
+                frame_ptr as *const c_void,
+                size_of::<Frame>(),
+                flags,
+            )
+        };
+
+        if nbytes as usize != size_of::<Frame>() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Receive a single classic frame, passing `flags` to `recv`.
+    pub(crate) fn recv_frame(&self, flags: c_int) -> io::Result<Frame> {
+        let mut frame = Frame::default();
+        let nbytes = unsafe {
+            let frame_ptr = &mut frame as *mut Frame;
+            recv(self.fd, frame_ptr as *mut c_void, size_of::<Frame>(), flags)
+        };
+
+        classic_frame(nbytes, frame)
+    }
+
+    pub(crate) fn set_socket_option<T>(
         &self,
         fd: c_int,
         level: c_int,
@@ -213,31 +1117,206 @@ impl embedded_can::blocking::Can for Socket {
         // a comparison
         // debug!("Sending: {:?}", frame);
 
-        let write_rv = unsafe {
-            let frame_ptr = frame as *const Frame;
-            write(self.fd, frame_ptr as *const c_void, size_of::<Frame>())
-        };
+        self.send_frame(frame, 0).map_err(|e| self.error(e))
+    }
 
-        if write_rv as usize != size_of::<Frame>() {
-            return Err(SocketError::from(io::Error::last_os_error()));
-        }
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        self.receive_frame().map_err(|e| self.error(e))
+    }
+}
 
-        Ok(())
+/// Non-blocking access. Requires the socket to be switched to non-blocking
+/// mode using `set_nonblocking`, otherwise the calls block like the
+/// `blocking::Can` implementation.
+impl embedded_can::nb::Can for Socket {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, Self::Error> {
+        // the kernel queues frames itself, so no frame is ever displaced
+        self.send_frame(frame, 0).map(|_| None).map_err(would_block)
     }
 
-    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
-        let mut frame = Frame::default();
-        let nbytes = unsafe {
-            let frame_ptr = &mut frame as *mut Frame;
-            read(self.fd, frame_ptr as *mut c_void, size_of::<Frame>())
-        };
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        self.recv_one(0).map_err(would_block)
+    }
+}
 
-        if nbytes as usize != size_of::<Frame>() {
-            let e = io::Error::last_os_error();
-            return Err(SocketError::IOError(e));
+/// Check the outcome of reading a single classic frame.
+///
+/// Frames of unexpected size or with an invalid length, e.g. CAN FD frames
+/// truncated to the size of a classic frame, fail with `InvalidData`.
+fn classic_frame(nbytes: isize, frame: Frame) -> io::Result<Frame> {
+    if nbytes < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if nbytes as usize != size_of::<Frame>() || !frame.is_classic() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a classic CAN frame",
+        ));
+    }
+    Ok(frame)
+}
+
+/// Collect the metadata of a frame received with `recvmsg` or `recvmmsg`
+fn received_msg(frame: Frame, hdr: &msghdr, addr: &sockaddr_storage) -> ReceivedMsg {
+    // socket pairs report no address
+    let if_index = if hdr.msg_namelen as usize >= size_of::<CanAddr>()
+        && addr.ss_family == AF_CAN as sa_family_t
+    {
+        let addr =
+            unsafe { ptr::read_unaligned(addr as *const sockaddr_storage as *const CanAddr) };
+        addr.if_index as c_uint
+    } else {
+        0
+    };
+    ReceivedMsg {
+        frame,
+        timestamp: timestamp(hdr),
+        if_index,
+        flags: hdr.msg_flags,
+    }
+}
+
+/// Extract the timestamp from the control messages of a received frame
+fn timestamp(hdr: &msghdr) -> Option<Timestamp> {
+    let duration = |ts: &timespec| time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    unsafe {
+        let mut cmsg: *mut cmsghdr = CMSG_FIRSTHDR(hdr);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == SOL_SOCKET && (*cmsg).cmsg_type == SCM_TIMESTAMPING {
+                // software, deprecated and raw hardware timestamp
+                let ts = ptr::read_unaligned(CMSG_DATA(cmsg) as *const [timespec; 3]);
+                if ts[2].tv_sec != 0 || ts[2].tv_nsec != 0 {
+                    return Some(Timestamp::Hardware(duration(&ts[2])));
+                }
+                if ts[0].tv_sec != 0 || ts[0].tv_nsec != 0 {
+                    return Some(Timestamp::Software(duration(&ts[0])));
+                }
+            }
+            cmsg = CMSG_NXTHDR(hdr, cmsg);
         }
+    }
+    None
+}
 
-        Ok(frame)
+/// Extract the `SO_RXQ_OVFL` drop counter from the control messages
+fn dropped(hdr: &msghdr) -> Option<u32> {
+    unsafe {
+        let mut cmsg: *mut cmsghdr = CMSG_FIRSTHDR(hdr);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == SOL_SOCKET && (*cmsg).cmsg_type == SO_RXQ_OVFL {
+                return Some(ptr::read_unaligned(CMSG_DATA(cmsg) as *const u32));
+            }
+            cmsg = CMSG_NXTHDR(hdr, cmsg);
+        }
+    }
+    None
+}
+
+/// Map `EAGAIN`/`EWOULDBLOCK` to `nb::Error::WouldBlock`
+fn would_block(e: io::Error) -> nb::Error<SocketError> {
+    if e.kind() == io::ErrorKind::WouldBlock {
+        nb::Error::WouldBlock
+    } else {
+        nb::Error::Other(e.into())
+    }
+}
+
+/// Receiving half of a `Socket`, created by `Socket::split`, or a
+/// receive-only socket created by `Socket::into_monitor_only`
+#[derive(Debug)]
+pub struct RxSocket {
+    socket: Arc<Socket>,
+}
+
+impl RxSocket {
+    /// Receive a frame, using the strategy the socket was configured with.
+    pub fn receive(&mut self) -> Result<Frame, SocketError> {
+        self.socket
+            .receive_frame()
+            .map_err(|e| self.socket.error(e))
+    }
+
+    /// Access the underlying socket, e.g. to change socket options.
+    pub fn get_ref(&self) -> &Socket {
+        &self.socket
+    }
+}
+
+/// Transmitting half of a `Socket`, created by `Socket::split`
+#[derive(Debug)]
+pub struct TxSocket {
+    socket: Arc<Socket>,
+}
+
+impl TxSocket {
+    /// Transmit a frame.
+    pub fn transmit(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        self.socket
+            .send_frame(frame, 0)
+            .map_err(|e| self.socket.error(e))
+    }
+
+    /// Access the underlying socket, e.g. to change socket options.
+    pub fn get_ref(&self) -> &Socket {
+        &self.socket
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the descriptor stays open as long as the socket is alive
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+impl FromRawFd for Socket {
+    /// Take ownership of a raw CAN socket file descriptor.
+    ///
+    /// The descriptor has to be a bound `CAN_RAW` socket.
+    unsafe fn from_raw_fd(fd: RawFd) -> Socket {
+        Socket::from_fd(fd)
+    }
+}
+
+impl IntoRawFd for Socket {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.fd;
+        std::mem::forget(self);
+        fd
+    }
+}
+
+#[cfg(feature = "mio")]
+impl mio::event::Source for Socket {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd).deregister(registry)
     }
 }
 
@@ -247,7 +1326,7 @@ impl Drop for Socket {
     }
 }
 
-fn c_timeval_new(t: time::Duration) -> timeval {
+pub(crate) fn c_timeval_new(t: time::Duration) -> timeval {
     timeval {
         tv_sec: t.as_secs() as time_t,
         tv_usec: t.subsec_micros() as suseconds_t,
@@ -263,6 +1342,222 @@ mod tests {
         assert!(Socket::new("invalid").is_err());
     }
 
+    #[test]
+    fn test_interface_of_pair() {
+        use embedded_can::{Frame, StandardId};
+
+        let (a, mut b) = Socket::pair().unwrap();
+        assert!(a.interface_index().is_err());
+
+        // no source interface is reported
+        let frame = Frame::new(StandardId::new(0x123).unwrap(), &[]).unwrap();
+        a.send_frame(&frame, 0).unwrap();
+        assert_eq!(b.receive_from().unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_try_receive() {
+        use embedded_can::blocking::Can;
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        assert!(a.try_receive().unwrap().is_none());
+        b.transmit(&crate::Frame::new(0x123, &[1], false, false).unwrap())
+            .unwrap();
+        assert_eq!(a.try_receive().unwrap().unwrap().data(), &[1]);
+        assert!(a.try_receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_transmit_nonblocking() {
+        use crate::SocketError;
+
+        let (mut a, _b) = Socket::pair().unwrap();
+        let frame = crate::Frame::new(0x123, &[1], false, false).unwrap();
+        // nobody reads from the other end, so the queue fills up
+        let mut rv = Ok(());
+        for _ in 0..100_000 {
+            rv = a.transmit_nonblocking(&frame);
+            if rv.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(rv, Err(SocketError::TxQueueFull)));
+    }
+
+    #[test]
+    fn test_xl_pair() {
+        use crate::{XlAnyFrame, XlFrame};
+        use embedded_can::blocking::Can;
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        let frame = XlFrame::new(0x123, &[0x55; 300]).unwrap().with_sdt(0x03);
+        b.transmit_xl(&frame).unwrap();
+        match a.receive_xl().unwrap() {
+            XlAnyFrame::Xl(received) => {
+                assert_eq!(received.priority(), 0x123);
+                assert_eq!(received.sdt(), 0x03);
+                assert_eq!(received.data(), frame.data());
+            }
+            other => panic!("unexpected frame {:?}", other),
+        }
+
+        b.transmit(&crate::Frame::new(0x7FF, &[1, 2], false, false).unwrap())
+            .unwrap();
+        assert!(matches!(a.receive_xl().unwrap(), XlAnyFrame::Classic(_)));
+    }
+
+    #[test]
+    fn test_invalid_frame() {
+        use embedded_can::blocking::Can;
+        use std::os::unix::io::AsRawFd;
+
+        // CAN FD frame truncated to the size of a classic frame
+        let (mut a, b) = Socket::pair().unwrap();
+        let mut raw = [0u8; 16];
+        raw[4] = 12;
+        let rv = unsafe { libc::write(b.as_raw_fd(), raw.as_ptr() as *const _, raw.len()) };
+        assert_eq!(rv, 16);
+        assert!(a.receive().is_err());
+
+        // CAN XL header announcing more payload than was read
+        let mut raw = [0u8; 16];
+        raw[4] = crate::xl::CANXL_XLF;
+        raw[6..8].copy_from_slice(&100u16.to_ne_bytes());
+        let rv = unsafe { libc::write(b.as_raw_fd(), raw.as_ptr() as *const _, raw.len()) };
+        assert_eq!(rv, 16);
+        match a.receive_xl() {
+            Err(crate::SocketError::IOError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // neither a classic nor a CAN FD frame
+        let rv = unsafe { libc::write(b.as_raw_fd(), raw.as_ptr() as *const _, 12) };
+        assert_eq!(rv, 12);
+        match a.receive_any() {
+            Err(crate::SocketError::IOError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_monitor_only() {
+        use crate::SocketError;
+        use embedded_can::{blocking::Can, Frame, StandardId};
+
+        let (mut a, b) = Socket::pair().unwrap();
+        let frame = Frame::new(StandardId::new(0x123).unwrap(), &[]).unwrap();
+        a.set_monitor_only();
+        assert!(matches!(a.transmit(&frame), Err(SocketError::MonitorOnly)));
+
+        let rx = b.into_monitor_only();
+        assert!(rx.get_ref().is_monitor_only());
+        let error = rx.get_ref().send_frame(&frame, 0).unwrap_err();
+        assert!(matches!(SocketError::from(error), SocketError::MonitorOnly));
+    }
+
+    #[test]
+    fn test_pair_echo() {
+        use embedded_can::{blocking::Can, Frame, StandardId};
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        let echo = std::thread::spawn(move || {
+            let frame = b.receive().unwrap();
+            b.transmit(&frame).unwrap();
+        });
+
+        let frame = Frame::new(StandardId::new(0x123).unwrap(), &[0xDE, 0xAD]).unwrap();
+        a.transmit(&frame).unwrap();
+        let echoed = a.receive().unwrap();
+        assert_eq!(echoed.id(), frame.id());
+        assert_eq!(echoed.data(), frame.data());
+        echo.join().unwrap();
+    }
+
+    #[test]
+    fn test_transmit_all() {
+        use embedded_can::blocking::Can;
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        let frames: Vec<crate::Frame> = (0..16)
+            .map(|id| crate::Frame::new(id, &[id as u8], false, false).unwrap())
+            .collect();
+        let outcomes = a.transmit_all(&frames, std::time::Duration::from_millis(10));
+        assert_eq!(outcomes.len(), 16);
+        assert!(outcomes
+            .iter()
+            .all(|o| matches!(o, crate::TransmitOutcome::Sent)));
+        for n in 0..16 {
+            assert_eq!(b.receive().unwrap().data(), &[n]);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_receive() {
+        use embedded_can::blocking::Can;
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        b.set_receive_strategy(crate::ReceiveStrategy::Adaptive);
+        for n in 0..100 {
+            a.transmit(&crate::Frame::new(0x100, &[n], false, false).unwrap())
+                .unwrap();
+        }
+        for n in 0..100 {
+            assert_eq!(b.receive().unwrap().data(), &[n]);
+        }
+        // the queued frames were picked up at a high rate
+        assert!(b.adaptive.lock().unwrap().interval < super::ADAPTIVE_SPIN_INTERVAL);
+    }
+
+    #[test]
+    fn test_adaptive_batch_drained() {
+        use embedded_can::blocking::Can;
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        b.set_receive_strategy(crate::ReceiveStrategy::Adaptive);
+        b.adaptive.lock().unwrap().interval = 0.0;
+        for n in 0..4 {
+            a.transmit(&crate::Frame::new(0x100, &[n], false, false).unwrap())
+                .unwrap();
+        }
+        // the first receive fetches all frames, the others are taken from
+        // the batch in order
+        assert_eq!(b.receive().unwrap().data(), &[0]);
+        assert_eq!(b.adaptive.lock().unwrap().batch.len(), 3);
+        let (frame, direction) = b.receive_tagged().unwrap();
+        assert_eq!((frame.data(), direction), (&[1][..], super::Direction::Rx));
+        match b.receive_any().unwrap() {
+            crate::AnyFrame::Classic(frame) => assert_eq!(frame.data(), &[2]),
+            other => panic!("unexpected frame {:?}", other),
+        }
+        assert_eq!(b.try_receive().unwrap().unwrap().data(), &[3]);
+        assert!(b.try_receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_recv_buffer_size() {
+        let (a, _b) = Socket::pair().unwrap();
+        a.set_recv_buffer_size(64 * 1024).unwrap();
+        // the kernel doubles the requested size
+        assert!(a.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[test]
+    fn test_transmit_at() {
+        use embedded_can::{blocking::Can, Frame, StandardId};
+        use std::time::{Duration, SystemTime};
+
+        let (mut a, mut b) = Socket::pair().unwrap();
+        let frame = Frame::new(StandardId::new(0x123).unwrap(), &[1]).unwrap();
+        let at = SystemTime::now() + Duration::from_millis(20);
+        a.transmit_at(&frame, at).unwrap();
+        b.receive().unwrap();
+        assert!(SystemTime::now() >= at);
+    }
+
     #[cfg(feature = "vcan0")]
     mod vcan {
         use crate::Socket;
@@ -283,6 +1578,13 @@ mod tests {
             socket.set_error_mask(ERR_MASK_NONE).unwrap();
         }
 
+        #[test]
+        fn vcan0_errors_only() {
+            let socket = Socket::new(VCAN0).unwrap();
+            socket.subscribe_errors_only().unwrap();
+            assert!(socket.filters().unwrap().is_empty());
+        }
+
         #[test]
         fn vcan0_enable_own_loopback() {
             let id = Id::Standard(StandardId::new(0x123).unwrap());
@@ -300,6 +1602,144 @@ mod tests {
             assert_eq!(frame.data(), data);
         }
 
+        #[test]
+        fn vcan0_fd_loopback() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_fd_frames(true).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+
+            let frame = crate::FdFrame::new(0x123, &[0x55; 20], true, false).unwrap();
+            socket.transmit_fd(&frame).unwrap();
+
+            match socket.receive_any().unwrap() {
+                crate::AnyFrame::Fd(received) => assert_eq!(received.data(), frame.data()),
+                other => panic!("unexpected frame: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn vcan0_filters() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+            socket
+                .set_filters(&[crate::Filter::standard(0x123)])
+                .unwrap();
+
+            let dropped = Frame::new(StandardId::new(0x124).unwrap(), &[1]).unwrap();
+            let accepted = Frame::new(StandardId::new(0x123).unwrap(), &[2]).unwrap();
+            socket.transmit(&dropped).unwrap();
+            socket.transmit(&accepted).unwrap();
+            assert_eq!(socket.receive().unwrap().data(), &[2]);
+            assert_eq!(socket.filters().unwrap(), [crate::Filter::standard(0x123)]);
+
+            socket.clear_filters().unwrap();
+            let group = socket.filter_group().unwrap();
+            assert_eq!(group.filters(), [crate::Filter::accept_all()]);
+            assert!(!group.is_join());
+        }
+
+        #[test]
+        fn vcan0_busy_poll() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+            socket.set_receive_strategy(crate::ReceiveStrategy::BusyPoll(
+                std::time::Duration::from_millis(1),
+            ));
+
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+            socket.transmit(&frame).unwrap();
+            assert_eq!(socket.receive().unwrap().data(), &[1, 2]);
+        }
+
+        #[test]
+        fn vcan0_split() {
+            let socket = Socket::new(VCAN0).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+            let (mut rx, mut tx) = socket.split();
+
+            let receiver = std::thread::spawn(move || rx.receive().unwrap());
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[3]).unwrap();
+            tx.transmit(&frame).unwrap();
+            assert_eq!(receiver.join().unwrap().data(), &[3]);
+        }
+
+        #[test]
+        fn vcan0_nb_would_block() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_nonblocking(true).unwrap();
+
+            match embedded_can::nb::Can::receive(&mut socket) {
+                Err(nb::Error::WouldBlock) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn vcan0_timestamping() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+            socket
+                .set_timestamping(crate::TimestampMode::Software)
+                .unwrap();
+
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[4]).unwrap();
+            socket.transmit(&frame).unwrap();
+            match socket.receive_timestamped().unwrap() {
+                (frame, Some(crate::Timestamp::Software(ts))) => {
+                    assert_eq!(frame.data(), &[4]);
+                    assert!(ts.as_secs() > 0);
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn vcan0_direction() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_recv_own_msgs(true).unwrap();
+            let mut other = Socket::new(VCAN0).unwrap();
+
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[5]).unwrap();
+            socket.transmit(&frame).unwrap();
+            other.transmit(&frame).unwrap();
+            let (_, first) = socket.receive_tagged().unwrap();
+            let (_, second) = socket.receive_tagged().unwrap();
+            assert_eq!(first, crate::Direction::TxEcho);
+            assert_eq!(second, crate::Direction::Injected);
+        }
+
+        #[test]
+        fn vcan0_interface() {
+            let socket = Socket::new(VCAN0).unwrap();
+            assert_ne!(socket.interface_index().unwrap(), 0);
+            assert_eq!(socket.interface_name().unwrap(), VCAN0);
+        }
+
+        #[test]
+        fn vcan0_receive_from_any() {
+            let mut any = Socket::open_any().unwrap();
+            let mut other = Socket::new(VCAN0).unwrap();
+
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[5]).unwrap();
+            other.transmit(&frame).unwrap();
+            let (received, if_index) = any.receive_from().unwrap();
+            assert_eq!(received.data(), &[5]);
+            assert_eq!(if_index, other.interface_index().unwrap());
+            assert_eq!(Socket::interface_name_of(if_index).unwrap(), VCAN0);
+        }
+
+        #[test]
+        fn vcan0_drop_monitoring() {
+            let mut socket = Socket::new(VCAN0).unwrap();
+            socket.set_drop_monitoring(true).unwrap();
+            let mut other = Socket::new(VCAN0).unwrap();
+
+            let frame = Frame::new(StandardId::new(0x123).unwrap(), &[5]).unwrap();
+            other.transmit(&frame).unwrap();
+            socket.receive().unwrap();
+            assert_eq!(socket.dropped(), 0);
+        }
+
         #[test]
         fn vcan0_test_nonblocking() {
             let mut socket = Socket::new(VCAN0).unwrap();
@@ -307,12 +1747,9 @@ mod tests {
 
             // no timeout set: should return immediately
             match socket.receive() {
-                Ok(_) => assert!(false),
-                Err(e) => match e {
-                    crate::SocketError::IOError(err)
-                        if err.kind() == std::io::ErrorKind::WouldBlock => {}
-                    _ => assert!(false),
-                },
+                Ok(_) => panic!("receive should not succeed"),
+                Err(crate::SocketError::WouldBlock) => {}
+                Err(e) => panic!("unexpected error: {:?}", e),
             }
         }
     }
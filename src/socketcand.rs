@@ -0,0 +1,296 @@
+use crate::{Frame, SocketError};
+use embedded_can::Frame as _;
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// CAN bus exported by a socketcand server
+///
+/// Speaks the ASCII protocol of socketcand over TCP in raw mode: the bus is
+/// opened on connect, afterwards every frame on the remote bus is received
+/// and frames are transmitted with `< send >`. Implements the same
+/// `embedded_can` traits as `Socket`.
+///
+/// socketcand has no notion of remote frames, transmitting one fails with
+/// an `InvalidInput` error.
+///
+/// ```no_run
+/// use candev::{Frame, SocketCand};
+/// use embedded_can::{blocking::Can, Frame as _};
+///
+/// let mut can = SocketCand::connect("192.168.1.10:29536", "can0").unwrap();
+/// can.transmit(&Frame::new(0x123, &[1, 2, 3], false, false).unwrap()).unwrap();
+/// let frame = can.receive().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SocketCand {
+    reader: BufReader<TcpStream>,
+    /// Partially received message
+    rx: Vec<u8>,
+    /// Messages not written completely in non-blocking mode
+    tx: Vec<u8>,
+    nonblocking: bool,
+}
+
+impl SocketCand {
+    /// Connect to a socketcand server and open `bus` in raw mode
+    pub fn connect<A: ToSocketAddrs>(addr: A, bus: &str) -> Result<SocketCand, SocketError> {
+        let stream = TcpStream::connect(addr)?;
+        // frames are small, don't wait for more data to send
+        stream.set_nodelay(true)?;
+        let mut can = SocketCand {
+            reader: BufReader::new(stream),
+            rx: Vec::new(),
+            tx: Vec::new(),
+            nonblocking: false,
+        };
+
+        can.expect("hi")?;
+        can.send_message(&format!("< open {} >", bus))?;
+        can.expect("ok")?;
+        can.send_message("< rawmode >")?;
+        can.expect("ok")?;
+        Ok(can)
+    }
+
+    /// Sets the read timeout of the connection
+    pub fn set_read_timeout(&self, duration: Duration) -> io::Result<()> {
+        self.reader.get_ref().set_read_timeout(Some(duration))
+    }
+
+    /// Change the connection to non-blocking mode
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        self.reader.get_ref().set_nonblocking(nonblocking)?;
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Access the TCP connection
+    pub fn get_ref(&self) -> &TcpStream {
+        self.reader.get_ref()
+    }
+
+    /// Receive the next message, without the angle brackets
+    fn message(&mut self) -> Result<String, SocketError> {
+        loop {
+            let n = self
+                .reader
+                .read_until(b'>', &mut self.rx)
+                .map_err(|e| self.error(e))?;
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if self.rx.last() != Some(&b'>') {
+                continue;
+            }
+
+            let rx = std::mem::take(&mut self.rx);
+            // skip anything between messages
+            let start = match rx.iter().rposition(|&c| c == b'<') {
+                Some(start) => start + 1,
+                None => continue,
+            };
+            let message = String::from_utf8_lossy(&rx[start..rx.len() - 1]);
+            return Ok(message.trim().to_owned());
+        }
+    }
+
+    /// Receive the next message, which has to be `expected`
+    fn expect(&mut self, expected: &str) -> Result<(), SocketError> {
+        let message = self.message()?;
+        if message != expected {
+            return Err(protocol_error(&message));
+        }
+        Ok(())
+    }
+
+    /// Write a message, buffering what could not be written in non-blocking
+    /// mode
+    fn send_message(&mut self, message: &str) -> Result<(), SocketError> {
+        self.tx.extend_from_slice(message.as_bytes());
+        match self.flush() {
+            Err(SocketError::WouldBlock) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Write the buffered messages
+    fn flush(&mut self) -> Result<(), SocketError> {
+        while !self.tx.is_empty() {
+            match self.reader.get_ref().write(&self.tx) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(n) => {
+                    self.tx.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Map an error of the connection. A read timeout on a blocking
+    /// connection is reported as `Timeout`, like on `Socket`.
+    fn error(&self, e: io::Error) -> SocketError {
+        match SocketError::from(e) {
+            SocketError::WouldBlock if !self.nonblocking => SocketError::Timeout,
+            e => e,
+        }
+    }
+
+    fn transmit_frame(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        if frame.is_remote_frame() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "socketcand does not support remote frames",
+            )
+            .into());
+        }
+        self.send_message(&send_message(frame))
+    }
+
+    fn receive_frame(&mut self) -> Result<Frame, SocketError> {
+        loop {
+            let message = self.message()?;
+            let mut fields = message.split_whitespace();
+            match fields.next() {
+                Some("frame") => {
+                    return parse_frame(fields).ok_or_else(|| protocol_error(&message))
+                }
+                Some("error") => return Err(protocol_error(&message)),
+                // e.g. the acknowledgement of a mode change
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Transmit a frame, blocking until it was written to the connection
+impl embedded_can::blocking::Can for SocketCand {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), SocketError> {
+        self.flush()?;
+        self.transmit_frame(frame)?;
+        self.flush()
+    }
+
+    fn receive(&mut self) -> Result<Frame, SocketError> {
+        self.receive_frame()
+    }
+}
+
+/// Non-blocking access. Requires the connection to be switched to
+/// non-blocking mode using `set_nonblocking`. A frame is accepted once the
+/// previous one was written completely.
+impl embedded_can::nb::Can for SocketCand {
+    type Frame = Frame;
+    type Error = SocketError;
+
+    fn transmit(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, SocketError> {
+        self.flush().map_err(would_block)?;
+        self.transmit_frame(frame)?;
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> nb::Result<Frame, SocketError> {
+        self.receive_frame().map_err(would_block)
+    }
+}
+
+fn would_block(e: SocketError) -> nb::Error<SocketError> {
+    match e {
+        SocketError::WouldBlock => nb::Error::WouldBlock,
+        e => nb::Error::Other(e),
+    }
+}
+
+fn protocol_error(message: &str) -> SocketError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected socketcand message `{}`", message),
+    )
+    .into()
+}
+
+/// `< send id dlc data* >`, with 8 digits for extended ids
+fn send_message(frame: &Frame) -> String {
+    let mut message = if frame.is_extended() {
+        format!("< send {:08X} {}", frame.raw_id(), frame.dlc())
+    } else {
+        format!("< send {:03X} {}", frame.raw_id(), frame.dlc())
+    };
+    for byte in frame.data() {
+        write!(message, " {:02X}", byte).unwrap();
+    }
+    message.push_str(" >");
+    message
+}
+
+/// Parse the fields of `< frame id seconds.useconds data >`, the data given
+/// as consecutive hex digits
+fn parse_frame<'a>(mut fields: impl Iterator<Item = &'a str>) -> Option<Frame> {
+    let id = fields.next()?;
+    let _timestamp = fields.next()?;
+    let data: String = fields.collect();
+    format!("{}#{}", id, data).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SocketCand;
+    use crate::Frame;
+    use embedded_can::{blocking::Can, Frame as _};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn test_socketcand() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut read = || {
+                let mut buf = Vec::new();
+                reader.read_until(b'>', &mut buf).unwrap();
+                String::from_utf8(buf).unwrap()
+            };
+
+            stream.write_all(b"< hi >").unwrap();
+            assert_eq!(read(), "< open vcan0 >");
+            stream.write_all(b"< ok >").unwrap();
+            assert_eq!(read(), "< rawmode >");
+            stream
+                .write_all(b"< ok >< frame 123 1.000000 DEADBEEF >< frame 18FEF100 1.5 >")
+                .unwrap();
+            assert_eq!(read(), "< send 123 2 01 02 >");
+            assert_eq!(read(), "< send 00000042 0 >");
+        });
+
+        let mut can = SocketCand::connect(addr, "vcan0").unwrap();
+        let frame = can.receive().unwrap();
+        assert_eq!(frame.raw_id(), 0x123);
+        assert_eq!(frame.data(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+        let frame = can.receive().unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.raw_id(), 0x18FEF100);
+        assert_eq!(frame.data(), &[]);
+
+        can.transmit(&Frame::new(0x123, &[1, 2], false, false).unwrap())
+            .unwrap();
+        let extended = Frame::builder().id(0x42).extended().build().unwrap();
+        can.transmit(&extended).unwrap();
+        assert!(can
+            .transmit(&Frame::new(0x123, &[0; 2], true, false).unwrap())
+            .is_err());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{Frame, QueueStats};
+use embedded_can::blocking::Can;
+use std::{
+    collections::VecDeque,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+};
+
+/// Frame copied to a `Tap`
+#[derive(Debug, Copy, Clone)]
+pub enum TapFrame {
+    /// Frame received from the bus
+    Received(Frame),
+    /// Frame transmitted by the primary consumer
+    Transmitted(Frame),
+}
+
+/// Passive observer of the traffic of a `Tapped` device or a `Mux`
+///
+/// Copies of all frames are queued, up to the capacity given when the tap
+/// was attached. The oldest copies are dropped if the tap is not read fast
+/// enough, so a slow tap never holds back the primary consumer. Dropping
+/// the tap detaches it.
+#[derive(Debug)]
+pub struct Tap {
+    queue: Arc<TapQueue>,
+}
+
+#[derive(Debug)]
+struct TapQueue {
+    frames: Mutex<VecDeque<TapFrame>>,
+    ready: Condvar,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl Tap {
+    /// Take the next copied frame, if any
+    pub fn try_receive(&self) -> Option<TapFrame> {
+        self.queue.frames.lock().unwrap().pop_front()
+    }
+
+    /// Wait for the next copied frame
+    pub fn receive(&self) -> TapFrame {
+        let mut frames = self.queue.frames.lock().unwrap();
+        loop {
+            if let Some(frame) = frames.pop_front() {
+                return frame;
+            }
+            frames = self.queue.ready.wait(frames).unwrap();
+        }
+    }
+
+    /// Number of queued frames
+    pub fn pending(&self) -> usize {
+        self.queue.frames.lock().unwrap().len()
+    }
+
+    /// Queue occupancy and copies dropped because the tap was not read fast
+    /// enough
+    pub fn stats(&self) -> QueueStats {
+        let pending = self.pending();
+        QueueStats {
+            pending,
+            capacity: self.queue.capacity,
+            memory: pending * size_of::<TapFrame>(),
+            dropped: self.queue.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Attached taps, shared by the tapped device
+#[derive(Debug, Default)]
+pub(crate) struct Taps {
+    queues: Mutex<Vec<Weak<TapQueue>>>,
+}
+
+impl Taps {
+    /// Attach a new tap queueing up to `capacity` frames
+    pub(crate) fn attach(&self, capacity: usize) -> Tap {
+        let queue = Arc::new(TapQueue {
+            frames: Mutex::new(VecDeque::with_capacity(capacity)),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+            dropped: AtomicU64::new(0),
+        });
+        self.queues.lock().unwrap().push(Arc::downgrade(&queue));
+        Tap { queue }
+    }
+
+    /// Copy `frame` to all attached taps, forgetting detached ones
+    pub(crate) fn copy(&self, frame: TapFrame) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                let mut frames = queue.frames.lock().unwrap();
+                if frames.len() == queue.capacity {
+                    frames.pop_front();
+                    queue.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                frames.push_back(frame);
+                queue.ready.notify_one();
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Wraps a `Can` implementation, e.g. a `Socket`, and copies every frame
+/// transmitted and received through it to the attached taps.
+#[derive(Debug)]
+pub struct Tapped<T> {
+    inner: T,
+    taps: Taps,
+}
+
+impl<T: Can<Frame = Frame>> Tapped<T> {
+    /// Wrap `inner`, initially without taps
+    pub fn new(inner: T) -> Tapped<T> {
+        Tapped {
+            inner,
+            taps: Taps::default(),
+        }
+    }
+
+    /// Attach a tap queueing up to `capacity` frames
+    pub fn tap(&self, capacity: usize) -> Tap {
+        self.taps.attach(capacity)
+    }
+
+    /// Access the wrapped device
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Return the wrapped device, detaching all taps
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Can<Frame = Frame>> Can for Tapped<T> {
+    type Frame = Frame;
+    type Error = T::Error;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+        self.inner.transmit(frame)?;
+        self.taps.copy(TapFrame::Transmitted(*frame));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Frame, Self::Error> {
+        let frame = self.inner.receive()?;
+        self.taps.copy(TapFrame::Received(frame));
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TapFrame, Tapped};
+    use crate::{Frame, Socket};
+    use embedded_can::blocking::Can;
+
+    #[test]
+    fn test_tapped() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let mut tapped = Tapped::new(a);
+        let tap = tapped.tap(2);
+
+        tapped
+            .transmit(&Frame::new(0x100, &[1], false, false).unwrap())
+            .unwrap();
+        b.receive().unwrap();
+        b.transmit(&Frame::new(0x200, &[2], false, false).unwrap())
+            .unwrap();
+        assert_eq!(tapped.receive().unwrap().data(), &[2]);
+
+        assert!(matches!(tap.receive(), TapFrame::Transmitted(f) if f.data() == [1]));
+        assert!(matches!(tap.try_receive(), Some(TapFrame::Received(f)) if f.data() == [2]));
+        assert!(tap.try_receive().is_none());
+
+        for _ in 0..3 {
+            tapped
+                .transmit(&Frame::new(0x100, &[], false, false).unwrap())
+                .unwrap();
+        }
+        let stats = tap.stats();
+        assert_eq!((stats.pending, stats.capacity, stats.dropped), (2, 2, 1));
+
+        // detached taps are no longer fed
+        drop(tap);
+        tapped
+            .transmit(&Frame::new(0x100, &[], false, false).unwrap())
+            .unwrap();
+        assert!(tapped.taps.queues.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{Interface, InterfaceError, Socket, SocketError};
+use libc::{EEXIST, EOPNOTSUPP};
+use std::{
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// Counter making the names of the buses of a process unique
+static NEXT_BUS: AtomicU32 = AtomicU32::new(0);
+
+/// Virtual CAN interface for integration tests, deleted on drop
+///
+/// Every bus is a new vcan interface with a name unique to the process and
+/// the bus, so tests can run in parallel against real kernel sockets:
+///
+/// ```no_run
+/// use candev::{Frame, TestBus};
+/// use embedded_can::blocking::Can;
+///
+/// let bus = match TestBus::new() {
+///     Some(bus) => bus,
+///     // not permitted to create interfaces
+///     None => return,
+/// };
+/// let mut a = bus.socket().unwrap();
+/// let mut b = bus.socket().unwrap();
+/// a.transmit(&Frame::new(0x123, &[1], false, false).unwrap()).unwrap();
+/// assert_eq!(b.receive().unwrap().data(), &[1]);
+/// ```
+///
+/// Creating interfaces requires `CAP_NET_ADMIN` and the `vcan` kernel
+/// module. The buses are created in the network namespace of the process,
+/// they are not isolated from other programs.
+#[derive(Debug)]
+pub struct TestBus {
+    interface: Interface,
+}
+
+impl TestBus {
+    /// Create a bus, or `None` if the process is not permitted to create
+    /// interfaces or the kernel does not support vcan, so the test can be
+    /// skipped.
+    ///
+    /// # Panics
+    ///
+    /// On other errors, see `create`.
+    pub fn new() -> Option<TestBus> {
+        match TestBus::create() {
+            Ok(bus) => Some(bus),
+            Err(InterfaceError::PermissionDenied) => None,
+            Err(InterfaceError::IOError(e)) if e.raw_os_error() == Some(EOPNOTSUPP) => None,
+            Err(e) => panic!("failed to create test bus: {}", e),
+        }
+    }
+
+    /// Create a bus and bring it up
+    pub fn create() -> Result<TestBus, InterfaceError> {
+        loop {
+            // at most 15 characters
+            let n = NEXT_BUS.fetch_add(1, Ordering::Relaxed);
+            let name = format!("tbus{:x}_{:x}", process::id(), n);
+            let interface = match Interface::create_vcan(&name) {
+                Ok(interface) => interface,
+                // left over by an earlier process with the same id
+                Err(InterfaceError::IOError(e)) if e.raw_os_error() == Some(EEXIST) => continue,
+                Err(e) => return Err(e),
+            };
+            let bus = TestBus { interface };
+            bus.interface.bring_up()?;
+            return Ok(bus);
+        }
+    }
+
+    /// The vcan interface
+    pub fn interface(&self) -> &Interface {
+        &self.interface
+    }
+
+    /// Interface name
+    pub fn name(&self) -> &str {
+        self.interface.name()
+    }
+
+    /// Open a socket on the bus
+    pub fn socket(&self) -> Result<Socket, SocketError> {
+        Socket::open_if(self.interface.index())
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.interface.clone().delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestBus;
+    use crate::{Frame, Interface, InterfaceError};
+    use embedded_can::blocking::Can;
+
+    #[test]
+    fn test_bus() {
+        let bus = match TestBus::new() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let other = TestBus::create().unwrap();
+        assert_ne!(bus.name(), other.name());
+
+        let mut a = bus.socket().unwrap();
+        let mut b = bus.socket().unwrap();
+        a.transmit(&Frame::new(0x123, &[1], false, false).unwrap())
+            .unwrap();
+        assert_eq!(b.receive().unwrap().data(), &[1]);
+
+        let name = bus.name().to_string();
+        drop((a, b, bus));
+        assert!(matches!(
+            Interface::new(&name),
+            Err(InterfaceError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{CanId, Frame};
+use embedded_can::{blocking::Can, Frame as _};
+use libc::{CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_RTR_FLAG};
+use std::collections::HashMap;
+
+/// Receive-side id translation table
+///
+/// Normalizes the ids of received frames, e.g. stripping the J1939 priority
+/// bits or mapping supplier specific ids to internal ones:
+///
+/// ```
+/// use candev::{CanId, IdMap};
+///
+/// let map = IdMap::new()
+///     .clear_extended_bits(0x1C00_0000)
+///     .map(CanId::Extended(0x00FE_F100), CanId::Standard(0x101));
+/// assert_eq!(map.translate_id(CanId::Extended(0x18FE_F100)), CanId::Standard(0x101));
+/// ```
+///
+/// Bits are cleared first, then the exact mappings are looked up with the
+/// resulting id. Error frames are never translated.
+#[derive(Debug, Clone, Default)]
+pub struct IdMap {
+    clear_extended: u32,
+    ids: HashMap<CanId, CanId>,
+}
+
+impl IdMap {
+    /// Create an empty table, which leaves all ids unchanged
+    pub fn new() -> IdMap {
+        IdMap::default()
+    }
+
+    /// Translate the id `from` to `to`
+    pub fn map(mut self, from: CanId, to: CanId) -> IdMap {
+        self.ids.insert(from, to);
+        self
+    }
+
+    /// Clear the bits set in `mask` from all extended ids, e.g. 0x1C000000
+    /// for the J1939 priority.
+    pub fn clear_extended_bits(mut self, mask: u32) -> IdMap {
+        self.clear_extended |= mask & CAN_EFF_MASK;
+        self
+    }
+
+    /// Translated id of `id`
+    pub fn translate_id(&self, id: CanId) -> CanId {
+        let id = match id {
+            CanId::Extended(raw) => CanId::Extended(raw & !self.clear_extended),
+            id => id,
+        };
+        self.ids.get(&id).copied().unwrap_or(id)
+    }
+
+    /// Copy of `frame` with its id translated, keeping the RTR flag
+    pub fn translate(&self, frame: &Frame) -> Frame {
+        if frame.is_error() {
+            return *frame;
+        }
+        let id = match self.translate_id(CanId::from(frame.id())) {
+            CanId::Standard(id) => id as u32,
+            CanId::Extended(id) => id | CAN_EFF_FLAG,
+        };
+        frame.with_can_id(id | (frame.can_id() & (CAN_RTR_FLAG | CAN_ERR_FLAG)))
+    }
+}
+
+/// Translates the ids of received frames
+///
+/// Wraps a `Can` implementation and applies an `IdMap` to every received
+/// frame, so the rest of the application works with normalized ids.
+/// Transmitted frames are passed on unchanged.
+#[derive(Debug)]
+pub struct Translated<T> {
+    inner: T,
+    map: IdMap,
+}
+
+impl<T: Can<Frame = Frame>> Translated<T> {
+    /// Translate the frames received on `inner` using `map`
+    pub fn new(inner: T, map: IdMap) -> Translated<T> {
+        Translated { inner, map }
+    }
+
+    /// Access the translation table
+    pub fn map(&self) -> &IdMap {
+        &self.map
+    }
+
+    /// Access the wrapped device
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Return the wrapped device
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Can<Frame = Frame>> Can for Translated<T> {
+    type Frame = Frame;
+    type Error = T::Error;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+        self.inner.transmit(frame)
+    }
+
+    fn receive(&mut self) -> Result<Frame, Self::Error> {
+        Ok(self.map.translate(&self.inner.receive()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdMap, Translated};
+    use crate::{CanId, Frame, Socket};
+    use embedded_can::{blocking::Can, Frame as _};
+
+    #[test]
+    fn test_translate() {
+        let (a, mut b) = Socket::pair().unwrap();
+        let map = IdMap::new()
+            .clear_extended_bits(0x1C00_0000)
+            .map(CanId::Standard(0x7E8), CanId::Standard(0x100));
+        let mut translated = Translated::new(a, map);
+
+        for id in [0x18FEF100, 0x0CFEF100] {
+            b.transmit(&Frame::new(id, &[1], false, false).unwrap())
+                .unwrap();
+            let frame = translated.receive().unwrap();
+            assert_eq!(CanId::from(frame.id()), CanId::Extended(0x00FEF100));
+            assert_eq!(frame.data(), &[1]);
+        }
+
+        let remote = Frame::new(0x7E8, &[0; 3], true, false).unwrap();
+        let frame = translated.map().translate(&remote);
+        assert_eq!(CanId::from(frame.id()), CanId::Standard(0x100));
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.dlc(), 3);
+    }
+}
@@ -0,0 +1,472 @@
+use crate::{IsoTpSocket, SocketError};
+use std::{error, fmt, io, time::Duration};
+
+/// Default time a server has to answer a request (P2)
+const DEFAULT_P2: Duration = Duration::from_millis(50);
+
+/// Default time a server has to answer after a response pending (P2*)
+const DEFAULT_P2_EXTENDED: Duration = Duration::from_millis(5000);
+
+/// Shortest timeout used, a zero timeout would disable the read timeout
+const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Service id of negative responses
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+
+const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+const ECU_RESET: u8 = 0x11;
+const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+const ROUTINE_CONTROL: u8 = 0x31;
+const TESTER_PRESENT: u8 = 0x3E;
+
+/// Negative response codes of ISO 14229-1
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Nrc {
+    /// 0x10, the request was rejected without a more specific reason
+    GeneralReject,
+    /// 0x11, the service is not supported
+    ServiceNotSupported,
+    /// 0x12, the sub-function is not supported
+    SubFunctionNotSupported,
+    /// 0x13, the request has the wrong length or format
+    IncorrectMessageLength,
+    /// 0x14, the response would exceed the transport protocol limit
+    ResponseTooLong,
+    /// 0x21, the server is busy, the request should be repeated later
+    BusyRepeatRequest,
+    /// 0x22, a precondition of the request is not met
+    ConditionsNotCorrect,
+    /// 0x24, the request was sent in the wrong order
+    RequestSequenceError,
+    /// 0x31, a parameter is out of range, e.g. an unknown identifier
+    RequestOutOfRange,
+    /// 0x33, the server is not unlocked for the request
+    SecurityAccessDenied,
+    /// 0x35, the key of a security access is wrong
+    InvalidKey,
+    /// 0x36, too many failed security access attempts
+    ExceededNumberOfAttempts,
+    /// 0x37, the delay after failed security access attempts is running
+    RequiredTimeDelayNotExpired,
+    /// 0x70, the upload or download can not be accepted
+    UploadDownloadNotAccepted,
+    /// 0x72, erasing or programming memory failed
+    GeneralProgrammingFailure,
+    /// 0x78, the request was received, the response follows later
+    ResponsePending,
+    /// 0x7E, the sub-function is not supported in the active session
+    SubFunctionNotSupportedInActiveSession,
+    /// 0x7F, the service is not supported in the active session
+    ServiceNotSupportedInActiveSession,
+
+    /// Code without a variant, e.g. manufacturer specific
+    Other(u8),
+}
+
+impl From<u8> for Nrc {
+    fn from(code: u8) -> Nrc {
+        match code {
+            0x10 => Nrc::GeneralReject,
+            0x11 => Nrc::ServiceNotSupported,
+            0x12 => Nrc::SubFunctionNotSupported,
+            0x13 => Nrc::IncorrectMessageLength,
+            0x14 => Nrc::ResponseTooLong,
+            0x21 => Nrc::BusyRepeatRequest,
+            0x22 => Nrc::ConditionsNotCorrect,
+            0x24 => Nrc::RequestSequenceError,
+            0x31 => Nrc::RequestOutOfRange,
+            0x33 => Nrc::SecurityAccessDenied,
+            0x35 => Nrc::InvalidKey,
+            0x36 => Nrc::ExceededNumberOfAttempts,
+            0x37 => Nrc::RequiredTimeDelayNotExpired,
+            0x70 => Nrc::UploadDownloadNotAccepted,
+            0x72 => Nrc::GeneralProgrammingFailure,
+            0x78 => Nrc::ResponsePending,
+            0x7E => Nrc::SubFunctionNotSupportedInActiveSession,
+            0x7F => Nrc::ServiceNotSupportedInActiveSession,
+            code => Nrc::Other(code),
+        }
+    }
+}
+
+impl From<Nrc> for u8 {
+    fn from(nrc: Nrc) -> u8 {
+        match nrc {
+            Nrc::GeneralReject => 0x10,
+            Nrc::ServiceNotSupported => 0x11,
+            Nrc::SubFunctionNotSupported => 0x12,
+            Nrc::IncorrectMessageLength => 0x13,
+            Nrc::ResponseTooLong => 0x14,
+            Nrc::BusyRepeatRequest => 0x21,
+            Nrc::ConditionsNotCorrect => 0x22,
+            Nrc::RequestSequenceError => 0x24,
+            Nrc::RequestOutOfRange => 0x31,
+            Nrc::SecurityAccessDenied => 0x33,
+            Nrc::InvalidKey => 0x35,
+            Nrc::ExceededNumberOfAttempts => 0x36,
+            Nrc::RequiredTimeDelayNotExpired => 0x37,
+            Nrc::UploadDownloadNotAccepted => 0x70,
+            Nrc::GeneralProgrammingFailure => 0x72,
+            Nrc::ResponsePending => 0x78,
+            Nrc::SubFunctionNotSupportedInActiveSession => 0x7E,
+            Nrc::ServiceNotSupportedInActiveSession => 0x7F,
+            Nrc::Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for Nrc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Nrc::Other(code) => write!(f, "negative response 0x{:02X}", code),
+            nrc => write!(f, "{:?} (0x{:02X})", nrc, u8::from(*nrc)),
+        }
+    }
+}
+
+/// Errors of UDS requests
+#[derive(Debug)]
+pub enum UdsError {
+    /// The server did not answer in time or the socket failed
+    IOError(io::Error),
+
+    /// Opening or configuring the socket failed
+    Socket(SocketError),
+
+    /// The server rejected the request
+    NegativeResponse(Nrc),
+
+    /// The response does not match the request
+    InvalidResponse,
+}
+
+impl From<io::Error> for UdsError {
+    fn from(e: io::Error) -> UdsError {
+        UdsError::IOError(e)
+    }
+}
+
+impl From<SocketError> for UdsError {
+    fn from(e: SocketError) -> UdsError {
+        UdsError::Socket(e)
+    }
+}
+
+impl fmt::Display for UdsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UdsError::IOError(e) => write!(f, "UDS request failed: {}", e),
+            UdsError::Socket(e) => write!(f, "UDS request failed: {}", e),
+            UdsError::NegativeResponse(nrc) => write!(f, "{}", nrc),
+            UdsError::InvalidResponse => write!(f, "invalid UDS response"),
+        }
+    }
+}
+
+impl error::Error for UdsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            UdsError::IOError(e) => Some(e),
+            UdsError::Socket(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Diagnostic session
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Session {
+    /// 0x01, the session active after power on
+    Default,
+    /// 0x02, session for reprogramming the server
+    Programming,
+    /// 0x03, session unlocking additional diagnostic functions
+    Extended,
+
+    /// Manufacturer or supplier specific session
+    Other(u8),
+}
+
+impl From<Session> for u8 {
+    fn from(session: Session) -> u8 {
+        match session {
+            Session::Default => 0x01,
+            Session::Programming => 0x02,
+            Session::Extended => 0x03,
+            Session::Other(id) => id,
+        }
+    }
+}
+
+/// Kind of ECU reset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetType {
+    /// 0x01, simulate a power cycle
+    Hard,
+    /// 0x02, simulate switching the ignition off and on
+    KeyOffOn,
+    /// 0x03, restart the application
+    Soft,
+}
+
+impl From<ResetType> for u8 {
+    fn from(reset: ResetType) -> u8 {
+        match reset {
+            ResetType::Hard => 0x01,
+            ResetType::KeyOffOn => 0x02,
+            ResetType::Soft => 0x03,
+        }
+    }
+}
+
+/// Routine control sub-function
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoutineControl {
+    /// 0x01, start the routine
+    Start,
+    /// 0x02, stop the running routine
+    Stop,
+    /// 0x03, request the results of the routine
+    RequestResults,
+}
+
+impl From<RoutineControl> for u8 {
+    fn from(control: RoutineControl) -> u8 {
+        match control {
+            RoutineControl::Start => 0x01,
+            RoutineControl::Stop => 0x02,
+            RoutineControl::RequestResults => 0x03,
+        }
+    }
+}
+
+/// UDS (ISO 14229) client
+///
+/// Sends requests to a single server over an `IsoTpSocket`. While the
+/// server answers with response pending (0x78), the client keeps waiting
+/// up to the extended timeout P2* for the final response.
+///
+/// ```no_run
+/// use candev::{CanId, IsoTpSocket, Session, UdsClient};
+///
+/// let socket = IsoTpSocket::new("can0", CanId::Standard(0x7E8), CanId::Standard(0x7E0)).unwrap();
+/// let mut uds = UdsClient::new(socket);
+/// uds.session_control(Session::Extended).unwrap();
+/// let version = uds.read_data(0xF189).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UdsClient {
+    socket: IsoTpSocket,
+    p2: Duration,
+    p2_extended: Duration,
+}
+
+impl UdsClient {
+    /// Send requests on `socket`, which is connected to a single server
+    pub fn new(socket: IsoTpSocket) -> UdsClient {
+        UdsClient {
+            socket,
+            p2: DEFAULT_P2,
+            p2_extended: DEFAULT_P2_EXTENDED,
+        }
+    }
+
+    /// Set the timeouts for the first response (P2) and for the final
+    /// response after a response pending (P2*).
+    ///
+    /// Both are updated by the timing parameters of a session control
+    /// response. Timeouts below 1 ms are raised to 1 ms.
+    pub fn timeouts(mut self, p2: Duration, p2_extended: Duration) -> Self {
+        self.p2 = p2.max(MIN_TIMEOUT);
+        self.p2_extended = p2_extended.max(MIN_TIMEOUT);
+        self
+    }
+
+    /// Send a request of `service` and return the response data following
+    /// the echoed service id.
+    pub fn request(&mut self, service: u8, data: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![service];
+        request.extend_from_slice(data);
+        self.socket.send(&request)?;
+
+        let mut buf = [0u8; 4095];
+        let mut timeout = self.p2;
+        loop {
+            self.socket.set_read_timeout(timeout)?;
+            let len = self.socket.recv(&mut buf)?;
+            match response_data(service, &buf[..len])? {
+                Some(data) => return Ok(data.to_vec()),
+                None => timeout = self.p2_extended,
+            }
+        }
+    }
+
+    /// Switch to another diagnostic session, adopting the timing parameters
+    /// of the response
+    pub fn session_control(&mut self, session: Session) -> Result<(), UdsError> {
+        let response = self.request(DIAGNOSTIC_SESSION_CONTROL, &[session.into()])?;
+        let timing = sub_function_data(session.into(), &response)?;
+        if let [p2_hi, p2_lo, p2x_hi, p2x_lo, ..] = *timing {
+            let p2 = Duration::from_millis(u16::from_be_bytes([p2_hi, p2_lo]) as u64);
+            // in units of 10 ms
+            let p2_extended =
+                Duration::from_millis(u16::from_be_bytes([p2x_hi, p2x_lo]) as u64 * 10);
+            self.p2 = p2.max(MIN_TIMEOUT);
+            self.p2_extended = p2_extended.max(MIN_TIMEOUT);
+        }
+        Ok(())
+    }
+
+    /// Keep the current session alive
+    pub fn tester_present(&mut self) -> Result<(), UdsError> {
+        let response = self.request(TESTER_PRESENT, &[0x00])?;
+        sub_function_data(0x00, &response)?;
+        Ok(())
+    }
+
+    /// Reset the server
+    pub fn ecu_reset(&mut self, reset: ResetType) -> Result<(), UdsError> {
+        let response = self.request(ECU_RESET, &[reset.into()])?;
+        sub_function_data(reset.into(), &response)?;
+        Ok(())
+    }
+
+    /// Read the data record of `did`
+    pub fn read_data(&mut self, did: u16) -> Result<Vec<u8>, UdsError> {
+        let response = self.request(READ_DATA_BY_IDENTIFIER, &did.to_be_bytes())?;
+        identifier_data(did, &response).map(|data| data.to_vec())
+    }
+
+    /// Write the data record of `did`
+    pub fn write_data(&mut self, did: u16, data: &[u8]) -> Result<(), UdsError> {
+        let mut request = did.to_be_bytes().to_vec();
+        request.extend_from_slice(data);
+        let response = self.request(WRITE_DATA_BY_IDENTIFIER, &request)?;
+        identifier_data(did, &response)?;
+        Ok(())
+    }
+
+    /// Start, stop or request the results of routine `id`, returning the
+    /// routine status record
+    pub fn routine_control(
+        &mut self,
+        control: RoutineControl,
+        id: u16,
+        data: &[u8],
+    ) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![control.into()];
+        request.extend_from_slice(&id.to_be_bytes());
+        request.extend_from_slice(data);
+        let response = self.request(ROUTINE_CONTROL, &request)?;
+        let status = sub_function_data(control.into(), &response)?;
+        identifier_data(id, status).map(|data| data.to_vec())
+    }
+
+    /// Access the socket
+    pub fn get_ref(&self) -> &IsoTpSocket {
+        &self.socket
+    }
+
+    /// Return the socket
+    pub fn into_inner(self) -> IsoTpSocket {
+        self.socket
+    }
+}
+
+/// Data of a positive response to `service`, after the echoed service id.
+///
+/// Returns `None` for a response pending.
+fn response_data(service: u8, response: &[u8]) -> Result<Option<&[u8]>, UdsError> {
+    match response {
+        [NEGATIVE_RESPONSE, s, code, ..] if *s == service => match Nrc::from(*code) {
+            Nrc::ResponsePending => Ok(None),
+            nrc => Err(UdsError::NegativeResponse(nrc)),
+        },
+        [s, data @ ..] if *s == service | 0x40 => Ok(Some(data)),
+        _ => Err(UdsError::InvalidResponse),
+    }
+}
+
+/// Data following the echoed sub-function
+fn sub_function_data(sub_function: u8, data: &[u8]) -> Result<&[u8], UdsError> {
+    match data {
+        [s, rest @ ..] if *s == sub_function => Ok(rest),
+        _ => Err(UdsError::InvalidResponse),
+    }
+}
+
+/// Data following the echoed 16 bit identifier
+fn identifier_data(id: u16, data: &[u8]) -> Result<&[u8], UdsError> {
+    match data {
+        [hi, lo, rest @ ..] if u16::from_be_bytes([*hi, *lo]) == id => Ok(rest),
+        _ => Err(UdsError::InvalidResponse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{identifier_data, response_data, sub_function_data, Nrc, UdsError};
+
+    #[test]
+    fn test_nrc() {
+        assert_eq!(Nrc::from(0x31), Nrc::RequestOutOfRange);
+        assert_eq!(Nrc::from(0xF0), Nrc::Other(0xF0));
+        for code in 0..=0xFF {
+            assert_eq!(u8::from(Nrc::from(code)), code);
+        }
+        assert_eq!(
+            Nrc::ConditionsNotCorrect.to_string(),
+            "ConditionsNotCorrect (0x22)"
+        );
+    }
+
+    #[test]
+    fn test_responses() {
+        let data = response_data(0x22, &[0x62, 0xF1, 0x90, b'W'])
+            .unwrap()
+            .unwrap();
+        assert_eq!(identifier_data(0xF190, data).unwrap(), b"W");
+        assert!(identifier_data(0xF191, data).is_err());
+
+        assert_eq!(response_data(0x22, &[0x7F, 0x22, 0x78]).unwrap(), None);
+        assert!(matches!(
+            response_data(0x22, &[0x7F, 0x22, 0x31]),
+            Err(UdsError::NegativeResponse(Nrc::RequestOutOfRange))
+        ));
+        assert!(matches!(
+            response_data(0x22, &[0x50, 0x01]),
+            Err(UdsError::InvalidResponse)
+        ));
+        assert_eq!(
+            sub_function_data(0x03, &[0x03, 0x00, 0x32]).unwrap(),
+            &[0x00, 0x32]
+        );
+    }
+
+    #[cfg(feature = "vcan0")]
+    #[test]
+    fn vcan0_response_pending() {
+        use super::UdsClient;
+        use crate::{CanId, IsoTpSocket};
+
+        let server =
+            IsoTpSocket::new("vcan0", CanId::Standard(0x7E0), CanId::Standard(0x7E8)).unwrap();
+        let client =
+            IsoTpSocket::new("vcan0", CanId::Standard(0x7E8), CanId::Standard(0x7E0)).unwrap();
+        let mut uds = UdsClient::new(client);
+        let responder = std::thread::spawn(move || {
+            let mut buf = [0u8; 8];
+            let len = server.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], &[0x31, 0x01, 0xFF, 0x00]);
+            server.send(&[0x7F, 0x31, 0x78]).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            server.send(&[0x71, 0x01, 0xFF, 0x00, 0x00]).unwrap();
+        });
+        let status = uds
+            .routine_control(super::RoutineControl::Start, 0xFF00, &[])
+            .unwrap();
+        responder.join().unwrap();
+        assert_eq!(status, [0x00]);
+    }
+}
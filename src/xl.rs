@@ -0,0 +1,195 @@
+use crate::{ConstructionError, FdFrame, Frame};
+use libc::CAN_SFF_MASK;
+use std::fmt;
+
+/// Maximum payload of a CAN XL frame
+const CANXL_MAX_DLEN: usize = 2048;
+
+/// Size of the `canxl_frame` header preceding the payload
+pub(crate) const CANXL_HDR_SIZE: usize = 12;
+
+/// `CANXL_XLF` from `linux/can.h`, set in every CAN XL frame
+pub(crate) const CANXL_XLF: u8 = 0x80;
+
+/// `CANXL_SEC` from `linux/can.h`
+const CANXL_SEC: u8 = 0x01;
+
+const CANXL_VCID_OFFSET: u32 = 16;
+
+/// CAN XL frame
+///
+/// Uses the same memory layout as the kernel `canxl_frame` struct. Only the
+/// header and the valid part of the payload are exchanged with the socket.
+#[derive(Clone)]
+#[repr(C)]
+pub struct XlFrame {
+    /// 11 bit priority and the virtual CAN network id
+    prio: u32,
+    flags: u8,
+    /// SDU type
+    sdt: u8,
+    len: u16,
+    /// acceptance field
+    af: u32,
+    data: [u8; CANXL_MAX_DLEN],
+}
+
+impl Default for XlFrame {
+    fn default() -> XlFrame {
+        XlFrame {
+            prio: 0,
+            flags: CANXL_XLF,
+            sdt: 0,
+            len: 1,
+            af: 0,
+            data: [0; CANXL_MAX_DLEN],
+        }
+    }
+}
+
+impl XlFrame {
+    /// Create a new CAN XL frame with the 11 bit `priority`.
+    ///
+    /// CAN XL frames carry at least one byte, an empty payload is padded
+    /// with a zero.
+    pub fn new(priority: u16, data: &[u8]) -> Result<XlFrame, ConstructionError> {
+        if priority as u32 > CAN_SFF_MASK {
+            return Err(ConstructionError::IDTooLarge);
+        }
+        if data.len() > CANXL_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData);
+        }
+
+        let mut frame = XlFrame {
+            prio: priority as u32,
+            len: data.len().max(1) as u16,
+            ..XlFrame::default()
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Ok(frame)
+    }
+
+    /// Set the SDU type, describing the content of the payload
+    pub fn with_sdt(mut self, sdt: u8) -> XlFrame {
+        self.sdt = sdt;
+        self
+    }
+
+    /// Set the acceptance field, e.g. the addressing information of the
+    /// higher layer protocol
+    pub fn with_af(mut self, af: u32) -> XlFrame {
+        self.af = af;
+        self
+    }
+
+    /// Set the virtual CAN network id
+    pub fn with_vcid(mut self, vcid: u8) -> XlFrame {
+        self.prio = (self.prio & CAN_SFF_MASK) | (vcid as u32) << CANXL_VCID_OFFSET;
+        self
+    }
+
+    /// Set the simple extended content (SEC) flag
+    pub fn with_sec(mut self, sec: bool) -> XlFrame {
+        if sec {
+            self.flags |= CANXL_SEC;
+        } else {
+            self.flags &= !CANXL_SEC;
+        }
+        self
+    }
+
+    /// 11 bit priority used for arbitration
+    pub fn priority(&self) -> u16 {
+        (self.prio & CAN_SFF_MASK) as u16
+    }
+
+    /// Virtual CAN network id
+    pub fn vcid(&self) -> u8 {
+        (self.prio >> CANXL_VCID_OFFSET) as u8
+    }
+
+    /// SDU type
+    pub fn sdt(&self) -> u8 {
+        self.sdt
+    }
+
+    /// Acceptance field
+    pub fn af(&self) -> u32 {
+        self.af
+    }
+
+    /// Check if the simple extended content flag is set
+    pub fn is_sec(&self) -> bool {
+        self.flags & CANXL_SEC != 0
+    }
+
+    /// Payload of the frame
+    pub fn data(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(CANXL_MAX_DLEN)]
+    }
+
+    /// Number of bytes exchanged with the socket, header and payload
+    pub(crate) fn mtu(&self) -> usize {
+        CANXL_HDR_SIZE + self.data().len()
+    }
+
+    /// Check if `nbytes` read from a socket hold the header and the whole
+    /// payload announced by it
+    pub(crate) fn is_complete(&self, nbytes: usize) -> bool {
+        let len = self.len as usize;
+        (1..=CANXL_MAX_DLEN).contains(&len) && CANXL_HDR_SIZE + len <= nbytes
+    }
+}
+
+impl fmt::Debug for XlFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("XlFrame")
+            .field("priority", &self.priority())
+            .field("vcid", &self.vcid())
+            .field("sdt", &self.sdt)
+            .field("af", &self.af)
+            .field("sec", &self.is_sec())
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+/// Frame received on a socket with CAN XL frames enabled
+#[derive(Debug, Clone)]
+pub enum XlAnyFrame {
+    /// Classic CAN frame
+    Classic(Frame),
+    /// CAN FD frame
+    Fd(FdFrame),
+    /// CAN XL frame
+    Xl(Box<XlFrame>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{XlFrame, CANXL_HDR_SIZE};
+    use std::mem::size_of;
+
+    #[test]
+    fn test_xl_frame() {
+        assert_eq!(size_of::<XlFrame>(), CANXL_HDR_SIZE + 2048);
+
+        let frame = XlFrame::new(0x123, &[0xAA; 100])
+            .unwrap()
+            .with_vcid(5)
+            .with_sdt(0x03)
+            .with_af(0xDEADBEEF)
+            .with_sec(true);
+        assert_eq!(frame.priority(), 0x123);
+        assert_eq!(frame.vcid(), 5);
+        assert_eq!(frame.sdt(), 0x03);
+        assert_eq!(frame.af(), 0xDEADBEEF);
+        assert!(frame.is_sec());
+        assert_eq!(frame.data().len(), 100);
+        assert_eq!(frame.mtu(), 112);
+
+        assert_eq!(XlFrame::new(0, &[]).unwrap().data(), &[0]);
+        assert!(XlFrame::new(0x800, &[1]).is_err());
+        assert!(XlFrame::new(0, &[0; 2049]).is_err());
+    }
+}